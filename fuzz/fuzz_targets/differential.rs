@@ -0,0 +1,14 @@
+//! Executes an arbitrary opcode from an arbitrary machine state on both the emulator and
+//! the reference machine, which should agree on the result
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/reference/mod.rs"]
+mod reference;
+
+fuzz_target!(|data: &[u8]| {
+    let mut emulator = reference::emulator_from_bytes(data);
+    if let Err(difference) = reference::check_step(&mut emulator) {
+        panic!("{}", difference);
+    }
+});
@@ -0,0 +1,11 @@
+//! Executes an arbitrary opcode from an arbitrary machine state, which should never panic
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/reference/mod.rs"]
+mod reference;
+
+fuzz_target!(|data: &[u8]| {
+    let mut emulator = reference::emulator_from_bytes(data);
+    let _ = emulator.step();
+});
@@ -0,0 +1,15 @@
+//! Decodes arbitrary opcodes with arbitrary quirks, which should either succeed or return
+//! an error
+#![no_main]
+use chip8::chip8::quirks::Quirks;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: [u8; 3]| {
+    let quirks = Quirks {
+        shift_vy: data[2] & 1 == 1,
+        load_store_increments_i: data[2] & 2 == 2,
+        jump_vx: data[2] & 4 == 4,
+        logic_resets_vf: data[2] & 8 == 8,
+    };
+    let _ = chip8::instructions::parse((data[0], data[1]), &quirks);
+});
@@ -0,0 +1,27 @@
+//! Runs an arbitrary ROM for a while, which should never panic
+#![no_main]
+use chip8::chip8::memory::{MAX_SIZE, PROGRAM_START};
+use chip8::chip8::{display::Display, keyboard::HeadlessKeyboard, State};
+use chip8::{config::Color, emulator::Emulator};
+use libfuzzer_sys::fuzz_target;
+
+/// The most instructions a ROM is run for, so looping ROMs still finish
+const MAX_CYCLES: u64 = 10_000;
+
+fuzz_target!(|program: &[u8]| {
+    // Anything longer than memory can't be loaded in the first place
+    if program.len() > MAX_SIZE - PROGRAM_START {
+        return;
+    }
+    let display = Display::new(Color::white(), Color::black());
+    let state = State::new(program, Box::new(HeadlessKeyboard::new()), display);
+    let mut emulator = Emulator::new(state, false);
+    for cycle in 1..=MAX_CYCLES {
+        if emulator.step().is_err() {
+            break;
+        }
+        if cycle % 9 == 0 {
+            emulator.end_frame();
+        }
+    }
+});
@@ -0,0 +1,184 @@
+/* A C API for the chip8 emulator. Generated from src/ffi.rs by cbindgen, so don't edit it. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// What a call did
+typedef enum Chip8Status {
+  CHIP8_STATUS_OK = 0,
+  // A pointer argument was null
+  CHIP8_STATUS_NULL_POINTER,
+  // The ROM doesn't fit in memory
+  CHIP8_STATUS_ROM_TOO_LARGE,
+  // The program executed something that isn't an instruction
+  CHIP8_STATUS_INVALID_INSTRUCTION,
+  // An instruction failed, e.g. RET with nothing on the stack
+  CHIP8_STATUS_EXECUTION_FAILED,
+  // The program counter ran off the end of memory
+  CHIP8_STATUS_PROGRAM_COUNTER_OUT_OF_BOUNDS,
+  // An address, register value or key is out of range
+  CHIP8_STATUS_OUT_OF_RANGE,
+  // There's no quirk profile with that name
+  CHIP8_STATUS_UNKNOWN_PROFILE,
+  // The buffer is too small for the state
+  CHIP8_STATUS_BUFFER_TOO_SMALL,
+  // The state wasn't saved by `chip8_save_state`, or is corrupt
+  CHIP8_STATUS_INVALID_STATE,
+  // The memory layout doesn't fit, e.g. the font runs past the end of memory
+  CHIP8_STATUS_INVALID_LAYOUT,
+} Chip8Status;
+
+// A machine and the keys held on it. C only sees a pointer to it.
+typedef struct Chip8Machine Chip8Machine;
+
+// A side panel showing the machine's registers, timers, stack and memory while it runs
+typedef struct Overlay Overlay;
+
+// The registers, as read by `chip8_get_registers` and written by `chip8_set_registers`
+typedef struct Chip8Registers {
+  uint8_t v[16];
+  uint16_t i;
+  uint16_t pc;
+  uint8_t delay_timer;
+  uint8_t sound_timer;
+} Chip8Registers;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a machine with no program loaded. Free it with `chip8_free`.
+struct Chip8Machine *chip8_new(void);
+
+// Frees a machine created by `chip8_new`. Null is ignored.
+//
+// # Safety
+// `machine` must be null or come from `chip8_new`, and not be used afterwards.
+void chip8_free(struct Chip8Machine *machine);
+
+// Loads `length` bytes of ROM where the memory layout starts programs (0x200 unless
+// `chip8_set_memory_layout` says otherwise), resetting the machine
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `rom` must point to `length` bytes.
+enum Chip8Status chip8_load_rom(struct Chip8Machine *machine, const uint8_t *rom, size_t length);
+
+// Sets how memory is laid out for ROMs loaded afterwards: `size` bytes of it (up to
+// 0x10000), with programs loaded at `program_start` and the font at `font_address`
+//
+// # Safety
+// `machine` must come from `chip8_new`.
+enum Chip8Status chip8_set_memory_layout(struct Chip8Machine *machine,
+                                         size_t size,
+                                         size_t program_start,
+                                         size_t font_address);
+
+// Switches to a quirk profile (default, cosmac-vip or super-chip), which also applies
+// to ROMs loaded later
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `profile` must be a NUL-terminated string.
+enum Chip8Status chip8_set_quirks(struct Chip8Machine *machine, const char *profile);
+
+// Holds or releases one of the 16 keys (0-F)
+//
+// # Safety
+// `machine` must come from `chip8_new`.
+enum Chip8Status chip8_set_key(struct Chip8Machine *machine, uint8_t key, bool pressed);
+
+// Executes up to `cycles` instructions. Waiting for a key (LD VX, K) with none held
+// uses up a cycle without doing anything, so the caller is never blocked.
+//
+// # Safety
+// `machine` must come from `chip8_new`.
+enum Chip8Status chip8_run_cycles(struct Chip8Machine *machine, uint32_t cycles);
+
+// Ends a frame, counting down the timers. Call it sixty times a second.
+//
+// # Safety
+// `machine` must come from `chip8_new`.
+enum Chip8Status chip8_end_frame(struct Chip8Machine *machine);
+
+// Returns the display as 64x32 bytes, row by row, 1 where a pixel is lit.
+// The pointer is valid until the next `chip8_load_rom` or `chip8_free`.
+//
+// # Safety
+// `machine` must be null or come from `chip8_new`.
+const uint8_t *chip8_framebuffer(const struct Chip8Machine *machine);
+
+// Returns whether the beeper should sound, i.e. the sound timer is running
+//
+// # Safety
+// `machine` must be null or come from `chip8_new`.
+bool chip8_is_beeping(const struct Chip8Machine *machine);
+
+// Copies the registers into `registers`
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `registers` must be writable.
+enum Chip8Status chip8_get_registers(const struct Chip8Machine *machine,
+                                     struct Chip8Registers *registers);
+
+// Sets the registers from `registers`. The program counter must be in memory.
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `registers` must be readable.
+enum Chip8Status chip8_set_registers(struct Chip8Machine *machine,
+                                     const struct Chip8Registers *registers);
+
+// Copies `length` bytes of memory starting at `address` into `buffer`
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `buffer` must have room for `length` bytes.
+enum Chip8Status chip8_read_memory(struct Chip8Machine *machine,
+                                   uint16_t address,
+                                   uint8_t *buffer,
+                                   size_t length);
+
+// Copies `length` bytes from `data` into memory starting at `address`
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `data` must point to `length` bytes.
+enum Chip8Status chip8_write_memory(struct Chip8Machine *machine,
+                                    uint16_t address,
+                                    const uint8_t *data,
+                                    size_t length);
+
+// Returns the number of bytes `chip8_save_state` writes, which depends on the size of
+// the machine's memory, or 0 if `machine` is null
+//
+// # Safety
+// `machine` must be null or come from `chip8_new`.
+size_t chip8_state_size(const struct Chip8Machine *machine);
+
+// Saves the whole machine (except the keys held) into `buffer`, which needs
+// `chip8_state_size(machine)` bytes
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `buffer` must have room for `capacity` bytes.
+enum Chip8Status chip8_save_state(struct Chip8Machine *machine, uint8_t *buffer, size_t capacity);
+
+// Restores the machine to a state saved by `chip8_save_state`
+//
+// # Safety
+// `machine` must come from `chip8_new`, and `data` must point to `length` bytes.
+enum Chip8Status chip8_load_state(struct Chip8Machine *machine, const uint8_t *data, size_t length);
+
+// Returns why the last call on the machine failed, or null if none has.
+// The string is valid until the next call that fails.
+//
+// # Safety
+// `machine` must be null or come from `chip8_new`.
+const char *chip8_last_error(const struct Chip8Machine *machine);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
@@ -0,0 +1,799 @@
+use crate::chip8::{memory::MemoryLayout, quirks::Quirks};
+use crate::instructions;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Returns the assembly for an opcode, in the syntax of Cowgod's Chip-8 technical reference.
+/// Bytes that aren't an instruction are shown as `DW` (a data word).
+pub fn disassemble(opcode: (u8, u8)) -> String {
+    let op = u16::from_be_bytes([opcode.0, opcode.1]);
+    let (x, y, n, nn, nnn) = (
+        opcode.0 & 0xF,
+        opcode.1 >> 4,
+        opcode.1 & 0xF,
+        opcode.1,
+        op & 0xFFF,
+    );
+    match (op >> 12, n) {
+        _ if op == 0x00E0 => String::from("CLS"),
+        _ if op == 0x00EE => String::from("RET"),
+        (0x0, _) => format!("SYS {:#05X}", nnn),
+        (0x1, _) => format!("JP {:#05X}", nnn),
+        (0x2, _) => format!("CALL {:#05X}", nnn),
+        (0x3, _) => format!("SE V{:X}, {:#04X}", x, nn),
+        (0x4, _) => format!("SNE V{:X}, {:#04X}", x, nn),
+        (0x5, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, _) => format!("LD V{:X}, {:#04X}", x, nn),
+        (0x7, _) => format!("ADD V{:X}, {:#04X}", x, nn),
+        (0x8, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _) => format!("LD I, {:#05X}", nnn),
+        (0xB, _) => format!("JP V0, {:#05X}", nnn),
+        (0xC, _) => format!("RND V{:X}, {:#04X}", x, nn),
+        (0xD, _) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE, _) if nn == 0x9E => format!("SKP V{:X}", x),
+        (0xE, _) if nn == 0xA1 => format!("SKNP V{:X}", x),
+        (0xF, _) => match nn {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => format!("DW {:#06X}", op),
+        },
+        _ => format!("DW {:#06X}", op),
+    }
+}
+
+/// How an instruction passes control on
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Flow {
+    /// Continues with the next instruction
+    Next,
+    /// Continues with the next instruction, or skips it (SE, SNE, SKP and SKNP)
+    Skip,
+    /// Jumps to an address (JP, and SYS, which the emulator treats as a jump)
+    Jump(usize),
+    /// Calls the subroutine at an address and continues with the next instruction when it returns
+    Call(usize),
+    /// Returns from a subroutine
+    Return,
+    /// Jumps to an address plus a register (BNNN), which can't be followed without running
+    /// the program
+    Indirect { base: usize, register: u8 },
+    /// Isn't an instruction
+    Invalid,
+}
+
+impl Flow {
+    /// Returns how the instruction decoded from an opcode passes control on
+    pub fn of(opcode: (u8, u8), quirks: &Quirks) -> Flow {
+        if instructions::parse(opcode, quirks).is_err() {
+            return Flow::Invalid;
+        }
+        let op = u16::from_be_bytes([opcode.0, opcode.1]);
+        let nnn = usize::from(op & 0xFFF);
+        match op >> 12 {
+            0x0 if op == 0x00E0 => Flow::Next,
+            0x0 if op == 0x00EE => Flow::Return,
+            0x0 | 0x1 => Flow::Jump(nnn),
+            0x2 => Flow::Call(nnn),
+            0x3 | 0x4 | 0x5 | 0x9 | 0xE => Flow::Skip,
+            0xB => Flow::Indirect {
+                base: nnn,
+                register: if quirks.jump_vx { opcode.0 & 0xF } else { 0 },
+            },
+            _ => Flow::Next,
+        }
+    }
+}
+
+/// A range of addresses, from `start` up to but not including `end`
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A decoded instruction
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Line {
+    pub address: usize,
+    /// The opcode in hex, e.g. "A22A"
+    pub opcode: String,
+    /// The disassembled instruction, e.g. "LD I, 0x22A"
+    pub assembly: String,
+}
+
+/// The ways control can pass from one block to another
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    /// Running on into the next block
+    Next,
+    /// Skipping the instruction after a skip instruction
+    Skip,
+    Jump,
+    Call,
+    /// Carrying on after a call, once the subroutine returns
+    AfterCall,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct Edge {
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions that's only entered at the first one and only left after the last one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Block {
+    pub start: usize,
+    /// The address just past the last instruction
+    pub end: usize,
+    pub instructions: Vec<Line>,
+    pub successors: Vec<Edge>,
+}
+
+/// The code reached by calling an address
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Subroutine {
+    pub entry: usize,
+    /// The addresses of the CALL instructions that call it
+    pub callers: Vec<usize>,
+    /// The starts of the blocks reached from its entry before returning
+    pub blocks: Vec<usize>,
+    /// Whether any of its blocks return
+    pub returns: bool,
+}
+
+/// A BNNN instruction, whose target depends on a register
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct IndirectJump {
+    pub address: usize,
+    pub base: usize,
+    pub register: u8,
+}
+
+/// An instruction that writes to memory at a known address
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct MemoryWrite {
+    /// The address of the instruction
+    pub address: usize,
+    /// The memory written to
+    pub target: Region,
+}
+
+/// What can be found out about a program without running it.
+///
+/// Code is found by following control flow from the start of the program (recursive
+/// descent). The value of I is followed through the control-flow graph from LD I
+/// instructions, to find the memory that DRW reads sprites from and that LD B and LD [I]
+/// write to. It's only known where every path there sets it to the same address, and
+/// isn't known after a call returns.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Analysis {
+    /// The address the program starts at
+    pub entry: usize,
+    /// The control-flow graph
+    pub blocks: Vec<Block>,
+    pub subroutines: Vec<Subroutine>,
+    /// Jumps that can't be followed, so any code they lead to is missing from the graph
+    pub indirect_jumps: Vec<IndirectJump>,
+    /// The addresses of bytes reached as code that aren't instructions
+    pub invalid_instructions: Vec<usize>,
+    /// Addresses control passes to outside the program (e.g. past its end)
+    pub external_targets: Vec<usize>,
+    /// Bytes of the program that are read or written as data
+    pub data: Vec<Region>,
+    /// Bytes of the program that are neither reached as code nor known to be used as data
+    pub unreachable: Vec<Region>,
+    /// Writes to memory where I is known
+    pub memory_writes: Vec<MemoryWrite>,
+    /// Writes to memory holding code
+    pub self_modifying_writes: Vec<MemoryWrite>,
+    /// The addresses of instructions that write to memory where I isn't known
+    pub unresolved_writes: Vec<usize>,
+}
+
+impl Analysis {
+    /// Analyzes a program loaded where `layout` says, which is where it starts running
+    pub fn new(program: &[u8], layout: &MemoryLayout, quirks: &Quirks) -> Self {
+        let origin = layout.program_start;
+        let end = origin + program.len();
+        let opcode_at = |address: usize| (program[address - origin], program[address + 1 - origin]);
+
+        // Follow control flow from the entry to find every instruction reached
+        let mut code: BTreeMap<usize, Flow> = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut external_targets = BTreeSet::new();
+        let mut pending = vec![origin];
+        leaders.insert(origin);
+        while let Some(address) = pending.pop() {
+            if code.contains_key(&address) {
+                continue;
+            }
+            if address < origin || address + 2 > end {
+                external_targets.insert(address);
+                continue;
+            }
+            let flow = Flow::of(opcode_at(address), quirks);
+            code.insert(address, flow);
+            let next = address + 2;
+            match flow {
+                Flow::Next => pending.push(next),
+                Flow::Skip => {
+                    leaders.extend(&[next, next + 2]);
+                    pending.extend(&[next, next + 2]);
+                }
+                Flow::Jump(target) => {
+                    leaders.insert(target);
+                    pending.push(target);
+                }
+                Flow::Call(target) => {
+                    leaders.extend(&[target, next]);
+                    pending.extend(&[target, next]);
+                }
+                Flow::Return | Flow::Indirect { .. } | Flow::Invalid => {}
+            }
+        }
+
+        // Split the instructions into blocks at jump targets and after changes in control flow
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut flows: Vec<Flow> = Vec::new();
+        for (&address, &flow) in code.iter() {
+            let continues = match (blocks.last(), flows.last()) {
+                (Some(block), Some(Flow::Next)) => {
+                    block.end == address && !leaders.contains(&address)
+                }
+                _ => false,
+            };
+            if !continues {
+                blocks.push(Block {
+                    start: address,
+                    end: address,
+                    instructions: Vec::new(),
+                    successors: Vec::new(),
+                });
+                flows.push(flow);
+            }
+            let opcode = opcode_at(address);
+            let block = blocks.last_mut().unwrap();
+            block.instructions.push(Line {
+                address,
+                opcode: format!("{:02X}{:02X}", opcode.0, opcode.1),
+                assembly: disassemble(opcode),
+            });
+            block.end = address + 2;
+            *flows.last_mut().unwrap() = flow;
+        }
+        for (block, flow) in blocks.iter_mut().zip(flows.iter()) {
+            let edge = |to, kind| Edge { to, kind };
+            block.successors = match *flow {
+                Flow::Next => vec![edge(block.end, EdgeKind::Next)],
+                Flow::Skip => vec![
+                    edge(block.end, EdgeKind::Next),
+                    edge(block.end + 2, EdgeKind::Skip),
+                ],
+                Flow::Jump(target) => vec![edge(target, EdgeKind::Jump)],
+                Flow::Call(target) => vec![
+                    edge(target, EdgeKind::Call),
+                    edge(block.end, EdgeKind::AfterCall),
+                ],
+                Flow::Return | Flow::Indirect { .. } | Flow::Invalid => Vec::new(),
+            };
+        }
+
+        let mut indirect_jumps = Vec::new();
+        let mut invalid_instructions = Vec::new();
+        let mut callers: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (&address, &flow) in code.iter() {
+            match flow {
+                Flow::Indirect { base, register } => indirect_jumps.push(IndirectJump {
+                    address,
+                    base,
+                    register,
+                }),
+                Flow::Invalid => invalid_instructions.push(address),
+                Flow::Call(target) => callers.entry(target).or_default().push(address),
+                _ => {}
+            }
+        }
+        let subroutines = callers
+            .into_iter()
+            .map(|(entry, callers)| Self::subroutine(&blocks, &flows, entry, callers))
+            .collect();
+
+        // Find the memory read and written through I
+        let code_bytes: BTreeSet<usize> = code.keys().flat_map(|x| vec![*x, x + 1]).collect();
+        let mut data_bytes = BTreeSet::new();
+        let mut memory_writes = Vec::new();
+        let mut self_modifying_writes = Vec::new();
+        let mut unresolved_writes = Vec::new();
+        let i_at_starts = Self::i_at_starts(&blocks, quirks, &opcode_at);
+        for (block, &i_at_start) in blocks.iter().zip(i_at_starts.iter()) {
+            let mut i = i_at_start;
+            for line in block.instructions.iter() {
+                let opcode = opcode_at(line.address);
+                let access = Access::of(opcode);
+                match (access, i) {
+                    (Access::Read(length), Some(start)) | (Access::Write(length), Some(start)) => {
+                        let target: Vec<usize> =
+                            (start..start + length).map(|x| x % layout.size).collect();
+                        if let Access::Write(_) = access {
+                            let write = MemoryWrite {
+                                address: line.address,
+                                target: Region {
+                                    start,
+                                    end: start + length,
+                                },
+                            };
+                            memory_writes.push(write);
+                            if target.iter().any(|x| code_bytes.contains(x)) {
+                                self_modifying_writes.push(write);
+                            }
+                        }
+                        data_bytes.extend(
+                            target
+                                .into_iter()
+                                .filter(|x| (origin..end).contains(x) && !code_bytes.contains(x)),
+                        );
+                    }
+                    (Access::Write(_), None) => unresolved_writes.push(line.address),
+                    _ => {}
+                }
+                i = Access::next_i(opcode, i, quirks);
+            }
+        }
+        let unreachable = Self::regions(
+            (origin..end).filter(|x| !code_bytes.contains(x) && !data_bytes.contains(x)),
+        );
+
+        Analysis {
+            entry: origin,
+            blocks,
+            subroutines,
+            indirect_jumps,
+            invalid_instructions,
+            external_targets: external_targets.into_iter().collect(),
+            data: Self::regions(data_bytes.into_iter()),
+            unreachable,
+            memory_writes,
+            self_modifying_writes,
+            unresolved_writes,
+        }
+    }
+
+    /// Returns the value of I at the start of each block, where it's known
+    fn i_at_starts(
+        blocks: &[Block],
+        quirks: &Quirks,
+        opcode_at: &dyn Fn(usize) -> (u8, u8),
+    ) -> Vec<Option<usize>> {
+        #[derive(Copy, Clone, PartialEq)]
+        enum Value {
+            /// No path to the block has been followed yet
+            Unset,
+            Known(usize),
+            Unknown,
+        }
+        let index: BTreeMap<usize, usize> = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.start, index))
+            .collect();
+        let mut values = vec![Value::Unset; blocks.len()];
+        let mut pending = Vec::new();
+        if !blocks.is_empty() {
+            values[0] = Value::Unknown;
+            pending.push(0);
+        }
+        while let Some(block) = pending.pop() {
+            let mut i = match values[block] {
+                Value::Known(address) => Some(address),
+                _ => None,
+            };
+            for line in blocks[block].instructions.iter() {
+                i = Access::next_i(opcode_at(line.address), i, quirks);
+            }
+            for edge in blocks[block].successors.iter() {
+                let target = match index.get(&edge.to) {
+                    Some(target) => *target,
+                    None => continue,
+                };
+                // The subroutine could have changed I by the time it returns
+                let incoming = match (edge.kind, i) {
+                    (EdgeKind::AfterCall, _) | (_, None) => Value::Unknown,
+                    (_, Some(address)) => Value::Known(address),
+                };
+                let merged = match (values[target], incoming) {
+                    (Value::Unset, value) => value,
+                    (value, incoming) if value == incoming => value,
+                    _ => Value::Unknown,
+                };
+                if merged != values[target] {
+                    values[target] = merged;
+                    pending.push(target);
+                }
+            }
+        }
+        values
+            .into_iter()
+            .map(|x| match x {
+                Value::Known(address) => Some(address),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Finds the blocks reached from a subroutine's entry, following calls inside it
+    /// to where they return to rather than into the subroutines they call
+    fn subroutine(
+        blocks: &[Block],
+        flows: &[Flow],
+        entry: usize,
+        callers: Vec<usize>,
+    ) -> Subroutine {
+        let reached = Self::reach(blocks, entry);
+        Subroutine {
+            entry,
+            callers,
+            returns: reached.iter().any(|x| flows[*x] == Flow::Return),
+            blocks: reached.into_iter().map(|x| blocks[x].start).collect(),
+        }
+    }
+
+    /// Returns the indices of the blocks reached from `address`, following calls to where
+    /// they return to rather than into the subroutines they call
+    fn reach(blocks: &[Block], address: usize) -> BTreeSet<usize> {
+        let index: BTreeMap<usize, usize> = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.start, index))
+            .collect();
+        let mut reached = BTreeSet::new();
+        let mut pending = vec![address];
+        while let Some(start) = pending.pop() {
+            if let Some(&block) = index.get(&start) {
+                if reached.insert(block) {
+                    pending.extend(
+                        blocks[block]
+                            .successors
+                            .iter()
+                            .filter(|edge| edge.kind != EdgeKind::Call)
+                            .map(|edge| edge.to),
+                    );
+                }
+            }
+        }
+        reached
+    }
+
+    /// Returns the blocks reached from `address` (e.g. the entry or a subroutine's entry),
+    /// following calls to where they return to rather than into the subroutines they call
+    pub fn reached_from(&self, address: usize) -> Vec<&Block> {
+        Self::reach(&self.blocks, address)
+            .into_iter()
+            .map(|x| &self.blocks[x])
+            .collect()
+    }
+
+    /// Merges sorted addresses into regions of consecutive addresses
+    fn regions(addresses: impl Iterator<Item = usize>) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        for address in addresses {
+            match regions.last_mut() {
+                Some(region) if region.end == address => region.end += 1,
+                _ => regions.push(Region {
+                    start: address,
+                    end: address + 1,
+                }),
+            }
+        }
+        regions
+    }
+
+    /// Returns the analysis as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Returns the control-flow graph in the Graphviz DOT language.
+    /// Subroutine entries are drawn in bold, calls are dashed and indirect jumps are dotted.
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec![
+            String::from("digraph program {"),
+            String::from("    node [shape=box fontname=\"monospace\"];"),
+        ];
+        let entries: BTreeSet<usize> = self.subroutines.iter().map(|x| x.entry).collect();
+        for block in self.blocks.iter() {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|x| format!("{:03X}  {}  {}\\l", x.address, x.opcode, x.assembly))
+                .collect();
+            let style = if entries.contains(&block.start) {
+                " style=bold"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "    b{:03X} [label=\"{}\"{}];",
+                block.start, label, style
+            ));
+            for edge in block.successors.iter() {
+                let attributes = match edge.kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Call => " [label=\"call\" style=dashed]",
+                    EdgeKind::AfterCall => " [label=\"after call\"]",
+                };
+                lines.push(format!(
+                    "    b{:03X} -> b{:03X}{};",
+                    block.start, edge.to, attributes
+                ));
+            }
+        }
+        for jump in self.indirect_jumps.iter() {
+            let block = self
+                .blocks
+                .iter()
+                .find(|x| (x.start..x.end).contains(&jump.address))
+                .unwrap();
+            lines.push(format!(
+                "    indirect{:03X} [shape=plaintext label=\"{:#05X} + V{:X}\"];",
+                jump.address, jump.base, jump.register
+            ));
+            lines.push(format!(
+                "    b{:03X} -> indirect{:03X} [style=dotted];",
+                block.start, jump.address
+            ));
+        }
+        for address in self.external_targets.iter() {
+            lines.push(format!(
+                "    b{:03X} [shape=plaintext label=\"{:#05X}\\n(outside the program)\"];",
+                address, address
+            ));
+        }
+        lines.push(String::from("}"));
+        lines.join("\n") + "\n"
+    }
+}
+
+/// How an instruction uses I
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Access {
+    /// Sets I to an address
+    SetI(usize),
+    /// Changes I in a way that depends on a register
+    ChangeI,
+    /// Reads a number of bytes from memory at I
+    Read(usize),
+    /// Writes a number of bytes to memory at I
+    Write(usize),
+    None,
+}
+
+impl Access {
+    /// Returns the value of I after an instruction, if it's known
+    fn next_i(opcode: (u8, u8), i: Option<usize>, quirks: &Quirks) -> Option<usize> {
+        match (Access::of(opcode), opcode.1) {
+            (Access::SetI(address), _) => Some(address),
+            (Access::ChangeI, _) => None,
+            // FX55 and FX65 move I on past the registers with this quirk
+            (Access::Read(length), 0x65) | (Access::Write(length), 0x55)
+                if quirks.load_store_increments_i && opcode.0 >> 4 == 0xF =>
+            {
+                i.map(|x| x + length)
+            }
+            _ => i,
+        }
+    }
+
+    fn of(opcode: (u8, u8)) -> Access {
+        let x = usize::from(opcode.0 & 0xF);
+        match (opcode.0 >> 4, opcode.1) {
+            (0xA, _) => Access::SetI(usize::from(opcode.0 & 0xF) << 8 | usize::from(opcode.1)),
+            (0xD, n) => Access::Read(usize::from(n & 0xF)),
+            (0xF, 0x1E) | (0xF, 0x29) => Access::ChangeI,
+            (0xF, 0x33) => Access::Write(3),
+            (0xF, 0x55) => Access::Write(x + 1),
+            (0xF, 0x65) => Access::Read(x + 1),
+            _ => Access::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(disassemble((0x00, 0xE0)), "CLS");
+        assert_eq!(disassemble((0xA2, 0x2A)), "LD I, 0x22A");
+        assert_eq!(disassemble((0x8A, 0xBE)), "SHL VA, VB");
+        assert_eq!(disassemble((0xD0, 0x15)), "DRW V0, V1, 5");
+        assert_eq!(disassemble((0xF3, 0x65)), "LD V3, [I]");
+        assert_eq!(disassemble((0x5A, 0xB1)), "DW 0x5AB1");
+        // Every opcode the emulator can't decode is shown as data, and no others are
+        for op in 0..=0xFFFFu16 {
+            let [high, low] = op.to_be_bytes();
+            assert_eq!(
+                disassemble((high, low)).starts_with("DW"),
+                instructions::parse((high, low), &Quirks::default()).is_err(),
+                "{:04X}",
+                op
+            );
+        }
+    }
+
+    /// A program with a subroutine, a skip, an indirect jump, sprite data,
+    /// a self-modifying write and some unreachable bytes
+    const PROGRAM: [u8; 28] = [
+        0x22, 0x0A, // 200: CALL 0x20A
+        0x30, 0x01, // 202: SE V0, 0x01
+        0x12, 0x00, // 204: JP 0x200
+        0xB2, 0x00, // 206: JP V0, 0x200
+        0x12, 0x12, // 208: JP 0x212 (unreachable)
+        0xA2, 0x18, // 20A: LD I, 0x218
+        0xD0, 0x12, // 20C: DRW V0, V1, 2
+        0xA2, 0x02, // 20E: LD I, 0x202
+        0xF1, 0x55, // 210: LD [I], V1
+        0x00, 0xEE, // 212: RET
+        0xFF, 0xFF, // 214: (unreachable)
+        0xFF, 0xFF, // 216: (unreachable)
+        0x81, 0x42, // 218: sprite
+        0x00, 0x00, // 21A: (unreachable)
+    ];
+
+    #[test]
+    fn test_blocks() {
+        let analysis = Analysis::new(&PROGRAM, &MemoryLayout::default(), &Quirks::default());
+        let starts: Vec<usize> = analysis.blocks.iter().map(|x| x.start).collect();
+        assert_eq!(starts, vec![0x200, 0x202, 0x204, 0x206, 0x20A]);
+        assert_eq!(
+            analysis.blocks[0].successors,
+            vec![
+                Edge {
+                    to: 0x20A,
+                    kind: EdgeKind::Call
+                },
+                Edge {
+                    to: 0x202,
+                    kind: EdgeKind::AfterCall
+                }
+            ]
+        );
+        assert_eq!(analysis.blocks[1].successors[1].kind, EdgeKind::Skip);
+        assert_eq!(analysis.blocks[4].end, 0x214);
+        assert_eq!(analysis.blocks[4].instructions[1].assembly, "DRW V0, V1, 2");
+        assert_eq!(
+            analysis.indirect_jumps,
+            vec![IndirectJump {
+                address: 0x206,
+                base: 0x200,
+                register: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_subroutines() {
+        let analysis = Analysis::new(&PROGRAM, &MemoryLayout::default(), &Quirks::default());
+        let reached: Vec<usize> = analysis
+            .reached_from(0x200)
+            .iter()
+            .map(|x| x.start)
+            .collect();
+        assert_eq!(reached, vec![0x200, 0x202, 0x204, 0x206]);
+        assert_eq!(
+            analysis.subroutines,
+            vec![Subroutine {
+                entry: 0x20A,
+                callers: vec![0x200],
+                blocks: vec![0x20A],
+                returns: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_memory() {
+        let analysis = Analysis::new(&PROGRAM, &MemoryLayout::default(), &Quirks::default());
+        assert_eq!(
+            analysis.data,
+            vec![Region {
+                start: 0x218,
+                end: 0x21A
+            }]
+        );
+        assert_eq!(
+            analysis.unreachable,
+            vec![
+                Region {
+                    start: 0x208,
+                    end: 0x20A
+                },
+                Region {
+                    start: 0x214,
+                    end: 0x218
+                },
+                Region {
+                    start: 0x21A,
+                    end: 0x21C
+                }
+            ]
+        );
+        assert_eq!(
+            analysis.self_modifying_writes,
+            vec![MemoryWrite {
+                address: 0x210,
+                target: Region {
+                    start: 0x202,
+                    end: 0x204
+                }
+            }]
+        );
+        assert_eq!(analysis.memory_writes, analysis.self_modifying_writes);
+        assert!(analysis.unresolved_writes.is_empty());
+    }
+
+    #[test]
+    fn test_edges_of_program() {
+        // Runs off the end of the program, and reaches a byte that isn't an instruction
+        let analysis = Analysis::new(
+            &[0x30, 0x00, 0x50, 0x01],
+            &MemoryLayout::default(),
+            &Quirks::default(),
+        );
+        assert_eq!(analysis.invalid_instructions, vec![0x202]);
+        assert_eq!(analysis.external_targets, vec![0x204]);
+        assert_eq!(
+            Analysis::new(&[], &MemoryLayout::default(), &Quirks::default()).blocks,
+            vec![]
+        );
+        // Programs for the ETI-660 start at 0x600
+        let layout = MemoryLayout {
+            program_start: 0x600,
+            ..MemoryLayout::default()
+        };
+        let analysis = Analysis::new(&[0x16, 0x00], &layout, &Quirks::default());
+        assert_eq!(analysis.entry, 0x600);
+        assert!(analysis.external_targets.is_empty());
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = Analysis::new(&PROGRAM, &MemoryLayout::default(), &Quirks::default()).to_dot();
+        assert!(dot.starts_with("digraph program {\n"));
+        assert!(dot.contains("    b200 -> b20A [label=\"call\" style=dashed];\n"));
+        assert!(dot.contains("b20A [label=\"20A  A218  LD I, 0x218\\l"));
+        assert!(dot.contains("    b206 -> indirect206 [style=dotted];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_json() {
+        let json: serde_json::Value = serde_json::from_str(
+            &Analysis::new(&PROGRAM, &MemoryLayout::default(), &Quirks::default()).to_json(),
+        )
+        .unwrap();
+        assert_eq!(json["entry"], 0x200);
+        assert_eq!(json["blocks"][0]["successors"][1]["kind"], "after_call");
+        assert_eq!(json["unreachable"][0]["start"], 0x208);
+    }
+}
@@ -0,0 +1,460 @@
+use crate::chip8::{display::Display, State};
+use crate::recording::Recorder;
+use std::{
+    convert::TryFrom,
+    fmt,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Error for when a screenshot or recording can't be written
+#[derive(Debug)]
+pub struct CaptureError(String);
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(error: io::Error) -> Self {
+        CaptureError(error.to_string())
+    }
+}
+
+impl From<png::EncodingError> for CaptureError {
+    fn from(error: png::EncodingError) -> Self {
+        CaptureError(error.to_string())
+    }
+}
+
+impl From<gif::EncodingError> for CaptureError {
+    fn from(error: gif::EncodingError) -> Self {
+        CaptureError(error.to_string())
+    }
+}
+
+/// Returns a path in the working directory named after the current time,
+/// e.g. `chip8-1584576000.png`
+pub fn timestamped_path(extension: &str) -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+    unused_path(Path::new(""), &format!("chip8-{}", seconds), extension)
+}
+
+/// Returns the path of a file in `directory` called `name`, or if there already is one,
+/// `name` numbered from 2 up, e.g. `chip8-1584576000-2.png`
+fn unused_path(directory: &Path, name: &str, extension: &str) -> PathBuf {
+    let mut path = directory.join(format!("{}.{}", name, extension));
+    let mut number = 1;
+    while path.exists() {
+        number += 1;
+        path = directory.join(format!("{}-{}.{}", name, number, extension));
+    }
+    path
+}
+
+/// Drops the alpha channel from the display's colored pixels.
+/// The window draws the display without blending, so colors show as opaque whatever
+/// their alpha, and captures keep only the colors to match.
+pub fn to_rgb(colored_pixels: &[u8]) -> Vec<u8> {
+    colored_pixels
+        .chunks(4)
+        .flat_map(|x| x[..3].iter().cloned())
+        .collect()
+}
+
+/// Scales an image made of `channels`-byte pixels up by `factor` in each direction
+pub fn scale(pixels: &[u8], channels: usize, width: usize, factor: usize) -> Vec<u8> {
+    let mut result: Vec<u8> = Vec::with_capacity(pixels.len() * factor * factor);
+    for row in pixels.chunks(width * channels) {
+        let mut scaled_row: Vec<u8> = Vec::with_capacity(row.len() * factor);
+        for pixel in row.chunks(channels) {
+            for _ in 0..factor {
+                scaled_row.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..factor {
+            result.extend_from_slice(&scaled_row);
+        }
+    }
+    result
+}
+
+/// Saves the display's colored pixels as a PNG, with every chip-8 pixel
+/// drawn as a `pixel_size` by `pixel_size` square
+pub fn save_png(path: &Path, colored_pixels: &[u8], pixel_size: u32) -> Result<(), CaptureError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(
+        file,
+        Display::WIDTH as u32 * pixel_size,
+        Display::HEIGHT as u32 * pixel_size,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data = scale(
+        &to_rgb(colored_pixels),
+        3,
+        Display::WIDTH,
+        pixel_size as usize,
+    );
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+/// A frame that hasn't been written to the GIF yet, because the frames after it
+/// might be identical and extend how long it is shown for
+struct PendingFrame {
+    rgb: Vec<u8>,
+    /// How long the frame is shown for, in hundredths of a second
+    duration: f64,
+}
+
+/// Records frames of the display to an animated GIF.
+///
+/// GIF delays are measured in hundredths of a second and most viewers slow down
+/// frames shorter than two hundredths, so identical consecutive frames are merged
+/// and frames shown for less than that are superseded by the frame after them.
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    pixel_size: usize,
+    width: u16,
+    height: u16,
+    /// How long each emulator frame lasts, in hundredths of a second
+    frame_duration: f64,
+    pending: Option<PendingFrame>,
+    /// Rounding error carried over from the delays already written
+    carry: f64,
+}
+
+impl GifRecorder {
+    /// The shortest delay (in hundredths of a second) viewers reliably honour
+    const MIN_DELAY: f64 = 2.0;
+    /// The longest delay a GIF frame can have
+    const MAX_DELAY: f64 = u16::MAX as f64;
+
+    /// Creates a GIF at `path` that plays back at `frames_per_second`
+    pub fn create(
+        path: &Path,
+        pixel_size: u32,
+        frames_per_second: u32,
+    ) -> Result<Self, CaptureError> {
+        // GIFs are at most 65535 pixels wide and high
+        let size = |pixels: usize| {
+            u16::try_from(pixels * pixel_size as usize).map_err(|_| {
+                CaptureError(format!(
+                    "A pixel size of {} is too large for a GIF",
+                    pixel_size
+                ))
+            })
+        };
+        let (width, height) = (size(Display::WIDTH)?, size(Display::HEIGHT)?);
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(GifRecorder {
+            encoder,
+            pixel_size: pixel_size as usize,
+            width,
+            height,
+            frame_duration: 100.0 / f64::from(frames_per_second),
+            pending: None,
+            carry: 0.0,
+        })
+    }
+
+    /// Adds the display's colored pixels as the next frame
+    pub fn add_frame(&mut self, colored_pixels: &[u8]) -> Result<(), CaptureError> {
+        let rgb = to_rgb(colored_pixels);
+        let frame_duration = self.frame_duration;
+        match self.pending.as_mut() {
+            Some(pending) if pending.rgb == rgb => {
+                pending.duration += frame_duration;
+                return Ok(());
+            }
+            Some(pending) if pending.duration < Self::MIN_DELAY => {
+                pending.rgb = rgb;
+                pending.duration += frame_duration;
+                return Ok(());
+            }
+            _ => {}
+        }
+        self.flush()?;
+        self.pending = Some(PendingFrame {
+            rgb,
+            duration: frame_duration,
+        });
+        Ok(())
+    }
+
+    /// Writes the last frame and closes the file
+    pub fn finish(mut self) -> Result<(), CaptureError> {
+        self.flush()?;
+        self.encoder.into_inner()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), CaptureError> {
+        let pending = match self.pending.take() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let duration = pending.duration + self.carry;
+        let mut delay = duration.round().max(Self::MIN_DELAY);
+        self.carry = duration - delay;
+
+        let mut frame = match index_colors(&pending.rgb) {
+            Some((indices, palette)) => gif::Frame::from_palette_pixels(
+                self.width,
+                self.height,
+                scale(&indices, 1, Display::WIDTH, self.pixel_size),
+                palette,
+                None,
+            ),
+            None => gif::Frame::from_rgb_speed(
+                self.width,
+                self.height,
+                &scale(&pending.rgb, 3, Display::WIDTH, self.pixel_size),
+                10,
+            ),
+        };
+        // Frames held longer than a GIF delay can be are repeated, leaving at least the
+        // minimum delay for the last one
+        while delay > Self::MAX_DELAY {
+            let part = (delay - Self::MIN_DELAY).min(Self::MAX_DELAY);
+            frame.delay = part as u16;
+            self.encoder.write_frame(&frame)?;
+            delay -= part;
+        }
+        frame.delay = delay as u16;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+}
+
+/// Converts RGB pixels to palette indices and a palette.
+/// Returns None if there are too many colors to fit in a GIF palette.
+fn index_colors(rgb: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut palette: Vec<&[u8]> = Vec::new();
+    let mut indices: Vec<u8> = Vec::with_capacity(rgb.len() / 3);
+    for pixel in rgb.chunks(3) {
+        let index = match palette.iter().position(|x| *x == pixel) {
+            Some(index) => index,
+            None => {
+                if palette.len() == 256 {
+                    return None;
+                }
+                palette.push(pixel);
+                palette.len() - 1
+            }
+        };
+        indices.push(index as u8);
+    }
+    Some((indices, palette.concat()))
+}
+
+/// Saves screenshots and records GIFs and videos of the frames shown by an emulator.
+///
+/// GIFs can either be toggled on and off (e.g. by a hotkey) or scheduled
+/// to cover a span of frames ahead of time (e.g. from the command line).
+pub struct Capturer {
+    pixel_size: u32,
+    frames_per_second: u32,
+    gif: Option<GifRecorder>,
+    /// A recording to start at the first frame number and stop after the second
+    scheduled_gif: Option<(PathBuf, u64, Option<u64>)>,
+    recorder: Option<Recorder>,
+}
+
+impl Capturer {
+    pub fn new(pixel_size: u32, frames_per_second: u32) -> Self {
+        Capturer {
+            pixel_size,
+            frames_per_second,
+            gif: None,
+            scheduled_gif: None,
+            recorder: None,
+        }
+    }
+
+    /// Saves the current frame as a PNG
+    pub fn screenshot(&self, path: &Path, display: &Display) -> Result<(), CaptureError> {
+        save_png(path, &display.colored_pixels, self.pixel_size)
+    }
+
+    /// Records `length` frames (or every frame, if there is no length)
+    /// to a GIF at `path`, starting at frame number `start`
+    pub fn schedule_gif(&mut self, path: PathBuf, start: u64, length: Option<u64>) {
+        self.scheduled_gif = Some((path, start, length.map(|x| start + x)));
+    }
+
+    /// Starts recording a GIF at `path` if one isn't being recorded, otherwise stops recording.
+    /// Returns true if a recording was started.
+    pub fn toggle_gif(&mut self, path: &Path) -> Result<bool, CaptureError> {
+        match self.gif.take() {
+            Some(gif) => {
+                gif.finish()?;
+                Ok(false)
+            }
+            None => {
+                self.gif = Some(GifRecorder::create(
+                    path,
+                    self.pixel_size,
+                    self.frames_per_second,
+                )?);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Records every frame from now on to a video and audio file at `path` (see `Recorder`)
+    pub fn record(&mut self, path: &Path) -> Result<(), CaptureError> {
+        self.recorder = Some(Recorder::create(path, self.pixel_size)?);
+        Ok(())
+    }
+
+    /// Records frame number `frame` to the GIF and video being recorded, if there are any.
+    /// Any scheduled GIF recording is started or stopped first.
+    pub fn capture_frame(&mut self, frame: u64, state: &State) -> Result<(), CaptureError> {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_frame(&state.display, state.timers.sound_timer)?;
+        }
+        if let Some((path, start, end)) = self.scheduled_gif.take() {
+            let finished = match end {
+                Some(end) => frame >= end,
+                None => false,
+            };
+            if finished {
+                if let Some(gif) = self.gif.take() {
+                    gif.finish()?;
+                }
+            } else {
+                if frame == start && self.gif.is_none() {
+                    self.toggle_gif(&path)?;
+                }
+                self.scheduled_gif = Some((path, start, end));
+            }
+        }
+        match self.gif.as_mut() {
+            Some(gif) => gif.add_frame(&state.display.colored_pixels),
+            None => Ok(()),
+        }
+    }
+
+    /// Finishes any GIF or video still being recorded
+    pub fn finish(&mut self) -> Result<(), CaptureError> {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish()?;
+        }
+        match self.gif.take() {
+            Some(gif) => gif.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Color;
+    use std::{env, fs};
+
+    #[test]
+    fn test_scale() {
+        let pixels = [1, 2, 3, 4];
+        assert_eq!(
+            scale(&pixels, 1, 2, 2),
+            vec![1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]
+        );
+    }
+
+    #[test]
+    fn test_index_colors() {
+        let rgb = [0, 0, 0, 255, 255, 255, 0, 0, 0];
+        let (indices, palette) = index_colors(&rgb).unwrap();
+        assert_eq!(indices, vec![0, 1, 0]);
+        assert_eq!(palette, vec![0, 0, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_unused_path() {
+        let directory = env::temp_dir().join("chip8_test_unused_path");
+        fs::create_dir_all(&directory).unwrap();
+        let first = unused_path(&directory, "chip8-1", "png");
+        assert_eq!(first, directory.join("chip8-1.png"));
+        File::create(&first).unwrap();
+        let second = unused_path(&directory, "chip8-1", "png");
+        assert_eq!(second, directory.join("chip8-1-2.png"));
+        File::create(&second).unwrap();
+        assert_eq!(
+            unused_path(&directory, "chip8-1", "png"),
+            directory.join("chip8-1-3.png")
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_save_png() {
+        let mut display = Display::new(Color::white(), Color::black());
+        display.xor(1, 0, 1);
+        let path = env::temp_dir().join("chip8_test_save_png.png");
+        save_png(&path, &display.colored_pixels, 2).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((info.width, info.height), (128, 64));
+        assert_eq!(&data[..3], &[0, 0, 0]);
+        assert_eq!(&data[6..9], &[255, 255, 255]);
+    }
+
+    #[test]
+    fn test_scheduled_gif() {
+        let mut state = State::mock(&[]);
+        let path = env::temp_dir().join("chip8_test_scheduled_gif.gif");
+        let mut capturer = Capturer::new(1, 60);
+        capturer.schedule_gif(path.clone(), 2, Some(6));
+        for frame in 0..10 {
+            state.display.xor(frame as usize, 0, 1);
+            capturer.capture_frame(frame, &state).unwrap();
+        }
+        capturer.finish().unwrap();
+        let decoder = gif::DecodeOptions::new();
+        let mut reader = decoder.read_info(File::open(&path).unwrap()).unwrap();
+        let mut frames = 0;
+        while reader.read_next_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        fs::remove_file(&path).unwrap();
+        // Six frames at 60fps, merged in pairs to honour the minimum delay
+        assert_eq!(frames, 3);
+        assert!(GifRecorder::create(&path, 1024, 60).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_long_gif_frames() {
+        let path = env::temp_dir().join("chip8_test_long_gif_frames.gif");
+        let display = Display::new(Color::white(), Color::black());
+        // At 1 frame a second, 700 identical frames last 70000 hundredths of a second
+        let mut recorder = GifRecorder::create(&path, 1, 1).unwrap();
+        for _ in 0..700 {
+            recorder.add_frame(&display.colored_pixels).unwrap();
+        }
+        recorder.finish().unwrap();
+        let decoder = gif::DecodeOptions::new();
+        let mut reader = decoder.read_info(File::open(&path).unwrap()).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = reader.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(delays, vec![65535, 4465]);
+    }
+}
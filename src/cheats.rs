@@ -0,0 +1,539 @@
+use crate::chip8::{memory::MemoryLayout, State};
+use crate::config;
+use crate::database::program_hash;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Error for when a cheat or cheat list can't be read or written
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheatError(String);
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<io::Error> for CheatError {
+    fn from(error: io::Error) -> Self {
+        CheatError(error.to_string())
+    }
+}
+
+/// Parses a byte or address written in decimal, or in hex with a 0x prefix
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Somewhere a cheat can search for and freeze values
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    /// A byte of memory, e.g. `0x2F3`
+    Memory(usize),
+    /// A V register, e.g. `VA`
+    Register(usize),
+}
+
+impl Target {
+    /// Every byte of the state's memory, then every V register
+    pub fn all(state: &State) -> impl Iterator<Item = Target> {
+        (0..state.memory.ram().len())
+            .map(Target::Memory)
+            .chain((0..16).map(Target::Register))
+    }
+
+    /// Checks that the target is in the state's memory, which can be smaller than the
+    /// addresses a target can be parsed from
+    pub fn check(self, state: &State) -> Result<Target, CheatError> {
+        let size = state.memory.ram().len();
+        match self {
+            Target::Memory(address) if address >= size => Err(CheatError(format!(
+                "{} is past the end of memory at {:#05X}",
+                self,
+                size - 1
+            ))),
+            _ => Ok(self),
+        }
+    }
+
+    /// Reads the target's value, which is 0 for an address past the end of memory
+    pub fn read(self, state: &State) -> u8 {
+        match self {
+            Target::Memory(address) => state.memory.ram().get(address).copied().unwrap_or(0),
+            Target::Register(x) => state.registers.v_registers[x],
+        }
+    }
+
+    /// Writes the target's value, unless it's an address past the end of memory
+    pub fn write(self, state: &mut State, value: u8) {
+        match self {
+            Target::Memory(address) => {
+                if let Some(byte) = state.memory.ram_mut().get_mut(address) {
+                    *byte = value;
+                }
+            }
+            Target::Register(x) => state.registers.v_registers[x] = value,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Memory(address) => write!(f, "{:#05X}", address),
+            Target::Register(x) => write!(f, "V{:X}", x),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = CheatError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let target = match text.strip_prefix('V').or_else(|| text.strip_prefix('v')) {
+            Some(x) => u8::from_str_radix(x, 16)
+                .ok()
+                .filter(|x| *x < 16)
+                .map(|x| Target::Register(x as usize)),
+            None => parse_number(text)
+                .filter(|x| *x < MemoryLayout::LARGEST)
+                .map(Target::Memory),
+        };
+        target.ok_or_else(|| {
+            CheatError(format!(
+                "{} should be a memory address below {:#X} or a V register from V0 to VF",
+                text,
+                MemoryLayout::LARGEST
+            ))
+        })
+    }
+}
+
+/// Parses a value to search for or freeze
+fn parse_value(text: &str) -> Result<u8, CheatError> {
+    parse_number(text.trim())
+        .filter(|x| *x <= 0xFF)
+        .map(|x| x as u8)
+        .ok_or_else(|| CheatError(format!("{} should be a byte from 0 to 255", text.trim())))
+}
+
+/// A value frozen in place, written as `target=value` (e.g. `0x2F3=9` or `VE=3`)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cheat {
+    pub target: Target,
+    pub value: u8,
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.target, self.value)
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = CheatError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts = text.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(target), Some(value)) => Ok(Cheat {
+                target: target.parse()?,
+                value: parse_value(value)?,
+            }),
+            _ => Err(CheatError(format!(
+                "{} should look like address=value",
+                text.trim()
+            ))),
+        }
+    }
+}
+
+/// The cheats a program runs with, which are re-applied at the end of every frame
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    /// Returns where the cheats for a program are saved: `cheats/<SHA-1>.txt` in the
+    /// config directory, if there is one
+    pub fn default_path(program: &[u8]) -> Option<PathBuf> {
+        Some(
+            config::config_dir()?
+                .join("cheats")
+                .join(format!("{}.txt", program_hash(program))),
+        )
+    }
+
+    /// Reads a cheat list: one `target=value` per line, with `#` starting a comment
+    pub fn load(path: &Path) -> Result<Self, CheatError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Saves the cheat list to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), CheatError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(fs::write(path, self.to_string())?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    /// Adds a cheat, replacing any other cheat for the same target
+    pub fn add(&mut self, cheat: Cheat) {
+        self.remove(cheat.target);
+        self.cheats.push(cheat);
+    }
+
+    /// Removes the cheat for a target, returning whether there was one
+    pub fn remove(&mut self, target: Target) -> bool {
+        let count = self.cheats.len();
+        self.cheats.retain(|x| x.target != target);
+        self.cheats.len() != count
+    }
+
+    /// Writes every cheat's value into the state
+    pub fn apply(&self, state: &mut State) {
+        for cheat in self.cheats.iter() {
+            cheat.target.write(state, cheat.value);
+        }
+    }
+}
+
+impl fmt::Display for Cheats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cheat in self.cheats.iter() {
+            writeln!(f, "{}", cheat)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Cheats {
+    type Err = CheatError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut cheats = Cheats::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if !line.is_empty() {
+                let cheat = line
+                    .parse()
+                    .map_err(|e| CheatError(format!("Line {}: {}", index + 1, e)))?;
+                cheats.add(cheat);
+            }
+        }
+        Ok(cheats)
+    }
+}
+
+/// How a search narrows down its candidates, comparing each one's value now to its
+/// value at the last search
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Comparison {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Comparison {
+    fn matches(self, before: u8, now: u8) -> bool {
+        match self {
+            Comparison::Equal(value) => now == value,
+            Comparison::Changed => now != before,
+            Comparison::Unchanged => now == before,
+            Comparison::Increased => now > before,
+            Comparison::Decreased => now < before,
+        }
+    }
+}
+
+/// Finds where a program keeps a value (e.g. the number of lives) by comparing
+/// memory and the V registers between snapshots
+pub struct CheatSearch {
+    /// The targets that have matched every comparison so far, with their values at the last one
+    candidates: Vec<(Target, u8)>,
+}
+
+impl CheatSearch {
+    /// Starts a search with every byte of memory and every V register as a candidate
+    pub fn new(state: &State) -> Self {
+        CheatSearch {
+            candidates: Target::all(state).map(|x| (x, x.read(state))).collect(),
+        }
+    }
+
+    /// Keeps the candidates whose value matches the comparison and remembers their values now
+    pub fn filter(&mut self, state: &State, comparison: Comparison) {
+        self.candidates = self
+            .candidates
+            .iter()
+            .map(|(target, before)| (*target, *before, target.read(state)))
+            .filter(|(_, before, now)| comparison.matches(*before, *now))
+            .map(|(target, _, now)| (target, now))
+            .collect();
+    }
+
+    /// The targets left, with their values at the last comparison
+    pub fn candidates(&self) -> &[(Target, u8)] {
+        &self.candidates
+    }
+}
+
+/// Searches and freezes values for commands typed while a program runs
+pub struct CheatConsole {
+    search: Option<CheatSearch>,
+    /// Where `save` writes the cheats
+    path: Option<PathBuf>,
+}
+
+impl CheatConsole {
+    /// The number of candidates `list` shows
+    const LISTED: usize = 20;
+
+    pub const HELP: &'static str = "Commands:
+  search                 Start a new search over memory and the V registers
+  equal VALUE            Keep the candidates equal to VALUE
+  changed, unchanged     Keep the candidates that changed or didn't since the last search
+  increased, decreased   Keep the candidates that went up or down since the last search
+  list                   Show the candidates left
+  freeze TARGET [VALUE]  Hold TARGET (e.g. 0x2F3 or VA) at VALUE, or at its current value
+  unfreeze TARGET        Stop holding TARGET
+  cheats                 Show the frozen values
+  save                   Save the frozen values for next time";
+
+    pub fn new(path: Option<PathBuf>) -> Self {
+        CheatConsole { search: None, path }
+    }
+
+    /// Runs a command, returning what to tell the user
+    pub fn run(
+        &mut self,
+        command: &str,
+        state: &mut State,
+        cheats: &mut Cheats,
+    ) -> Result<String, CheatError> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let comparison = match words.as_slice() {
+            [] => return Ok(String::new()),
+            ["search"] => {
+                self.search = Some(CheatSearch::new(state));
+                return Ok(format!(
+                    "Searching {} candidates",
+                    Target::all(state).count()
+                ));
+            }
+            ["equal", value] => Comparison::Equal(parse_value(value)?),
+            ["changed"] => Comparison::Changed,
+            ["unchanged"] => Comparison::Unchanged,
+            ["increased"] => Comparison::Increased,
+            ["decreased"] => Comparison::Decreased,
+            ["list"] => return self.list(),
+            ["freeze", target] => {
+                let target = target.parse::<Target>()?.check(state)?;
+                return Ok(self.freeze(state, cheats, target, target.read(state)));
+            }
+            ["freeze", target, value] => {
+                let target = target.parse::<Target>()?.check(state)?;
+                return Ok(self.freeze(state, cheats, target, parse_value(value)?));
+            }
+            ["unfreeze", target] => {
+                let target: Target = target.parse()?;
+                return Ok(if cheats.remove(target) {
+                    format!("Unfroze {}", target)
+                } else {
+                    format!("{} isn't frozen", target)
+                });
+            }
+            ["cheats"] if cheats.is_empty() => return Ok("Nothing is frozen".to_string()),
+            ["cheats"] => return Ok(cheats.to_string().trim_end().to_string()),
+            ["save"] => {
+                let path = self
+                    .path
+                    .as_ref()
+                    .ok_or_else(|| CheatError("There's nowhere to save the cheats".to_string()))?;
+                cheats.save(path)?;
+                return Ok(format!("Saved the cheats to {}", path.display()));
+            }
+            ["help"] => return Ok(Self::HELP.to_string()),
+            _ => {
+                return Err(CheatError(format!(
+                    "Unknown command {}. Type help for the commands.",
+                    command.trim()
+                )))
+            }
+        };
+        let search = self.search.get_or_insert_with(|| CheatSearch::new(state));
+        search.filter(state, comparison);
+        Ok(match search.candidates().len() {
+            1 => "1 candidate left".to_string(),
+            count => format!("{} candidates left", count),
+        })
+    }
+
+    fn list(&self) -> Result<String, CheatError> {
+        let search = self
+            .search
+            .as_ref()
+            .ok_or_else(|| CheatError("Start a search first".to_string()))?;
+        let candidates = search.candidates();
+        let mut lines: Vec<String> = candidates
+            .iter()
+            .take(Self::LISTED)
+            .map(|(target, value)| format!("{}: {}", target, value))
+            .collect();
+        if candidates.len() > Self::LISTED {
+            lines.push(format!("... and {} more", candidates.len() - Self::LISTED));
+        }
+        if lines.is_empty() {
+            lines.push("No candidates left".to_string());
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn freeze(&self, state: &mut State, cheats: &mut Cheats, target: Target, value: u8) -> String {
+        let cheat = Cheat { target, value };
+        cheat.target.write(state, cheat.value);
+        cheats.add(cheat);
+        format!("Froze {}", cheat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_parse_cheats() {
+        let cheats: Cheats = "# Lives\n0x2F3=9\nva = 0xFF # Score\n\n512=1\n0x2F3=3"
+            .parse()
+            .unwrap();
+        assert_eq!(cheats.to_string(), "VA=255\n0x200=1\n0x2F3=3\n");
+        assert_eq!(
+            "VG=1".parse::<Cheat>(),
+            Err(CheatError(
+                "VG should be a memory address below 0x10000 or a V register from V0 to VF"
+                    .to_string()
+            ))
+        );
+        assert!("0x10000=1".parse::<Cheat>().is_err());
+        assert!("0x200=256".parse::<Cheat>().is_err());
+        assert_eq!(
+            "0x200\n0x201=1".parse::<Cheats>(),
+            Err(CheatError(
+                "Line 1: 0x200 should look like address=value".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = env::temp_dir().join("chip8_test_cheats").join("cheats.txt");
+        let cheats: Cheats = "0x2F3=9\nVE=3".parse().unwrap();
+        cheats.save(&path).unwrap();
+        assert_eq!(Cheats::load(&path), Ok(cheats));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(Cheats::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let mut state = State::mock(&[]);
+        state.memory.ram_mut()[0x300] = 3;
+        state.registers.v_registers[5] = 3;
+        let mut search = CheatSearch::new(&state);
+        search.filter(&state, Comparison::Equal(3));
+        assert_eq!(
+            search.candidates(),
+            &[(Target::Memory(0x300), 3), (Target::Register(5), 3)]
+        );
+        state.memory.ram_mut()[0x300] = 2;
+        search.filter(&state, Comparison::Unchanged);
+        assert_eq!(search.candidates(), &[(Target::Register(5), 3)]);
+
+        let mut search = CheatSearch::new(&state);
+        state.memory.ram_mut()[0x300] = 1;
+        state.memory.ram_mut()[0x301] = 1;
+        search.filter(&state, Comparison::Decreased);
+        assert_eq!(search.candidates(), &[(Target::Memory(0x300), 1)]);
+        search.filter(&state, Comparison::Increased);
+        assert!(search.candidates().is_empty());
+    }
+
+    #[test]
+    fn test_console() {
+        let mut state = State::mock(&[]);
+        let mut cheats = Cheats::default();
+        let mut console = CheatConsole::new(None);
+        let mut run = |command: &str, state: &mut State| console.run(command, state, &mut cheats);
+        state.memory.ram_mut()[0xF00] = 3;
+        assert_eq!(
+            run("equal 3", &mut state),
+            Ok("1 candidate left".to_string())
+        );
+        state.memory.ram_mut()[0xF00] = 2;
+        assert_eq!(
+            run("decreased", &mut state),
+            Ok("1 candidate left".to_string())
+        );
+        assert_eq!(run("list", &mut state), Ok("0xF00: 2".to_string()));
+        assert_eq!(
+            run("freeze 0xF00 9", &mut state),
+            Ok("Froze 0xF00=9".to_string())
+        );
+        assert_eq!(state.memory.ram()[0xF00], 9);
+        assert_eq!(run("freeze v1", &mut state), Ok("Froze V1=0".to_string()));
+        assert_eq!(run("cheats", &mut state), Ok("0xF00=9\nV1=0".to_string()));
+        assert_eq!(
+            run("unfreeze 0xF00", &mut state),
+            Ok("Unfroze 0xF00".to_string())
+        );
+        assert!(run("save", &mut state).is_err());
+        assert!(run("jump", &mut state).is_err());
+    }
+
+    #[test]
+    fn test_smaller_memory() {
+        let layout = MemoryLayout {
+            size: 0x800,
+            ..MemoryLayout::default()
+        };
+        let mut state = State::mock_with_layout(&[], layout);
+        state.memory.ram_mut()[0x7FF] = 5;
+        let mut search = CheatSearch::new(&state);
+        search.filter(&state, Comparison::Equal(5));
+        assert_eq!(search.candidates(), &[(Target::Memory(0x7FF), 5)]);
+
+        let mut cheats: Cheats = "0x900=1".parse().unwrap();
+        cheats.apply(&mut state);
+        assert_eq!(Target::Memory(0x900).read(&state), 0);
+        let mut console = CheatConsole::new(None);
+        assert_eq!(
+            console.run("freeze 0x900 1", &mut state, &mut cheats),
+            Err(CheatError(
+                "0x900 is past the end of memory at 0x7FF".to_string()
+            ))
+        );
+        assert_eq!(
+            console.run("search", &mut state, &mut cheats),
+            Ok("Searching 2064 candidates".to_string())
+        );
+    }
+}
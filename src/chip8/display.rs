@@ -45,6 +45,22 @@ impl Display {
         result
     }
 
+    /// Changes the colors of active and inactive pixels, recoloring the pixels already drawn
+    pub fn set_colors(&mut self, active_color: Color, inactive_color: Color) {
+        self.active_color = active_color;
+        self.inactive_color = inactive_color;
+        for y in 0..Self::HEIGHT {
+            for x in 0..Self::WIDTH {
+                let color = if self.pixels[y][x] == 1 {
+                    active_color
+                } else {
+                    inactive_color
+                };
+                self.set_colored_pixel(x, y, color);
+            }
+        }
+    }
+
     fn set_colored_pixel(&mut self, x: usize, y: usize, color: Color) {
         self.colored_pixels[(Self::WIDTH * y + x) * 4] = color.0;
         self.colored_pixels[(Self::WIDTH * y + x) * 4 + 1] = color.1;
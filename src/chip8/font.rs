@@ -0,0 +1,249 @@
+use std::{fmt, fs, path::Path};
+
+/// Error for when a font can't be read or isn't a valid set of sprites
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontError(String);
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The sprites for the hex digits that FX29 points I at, one after the other
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    /// The number of bytes, and so rows, in each glyph
+    pub height: usize,
+    glyphs: Vec<u8>,
+}
+
+impl Font {
+    /// The names of the built-in fonts
+    pub const PRESETS: [&'static str; 6] = [
+        "default",
+        "cosmac-vip",
+        "dream-6800",
+        "eti-660",
+        "super-chip",
+        "super-chip-large",
+    ];
+
+    /// The most glyphs a font can have, one for each hex digit
+    pub const MAX_GLYPHS: usize = 16;
+
+    /// Creates a font from glyphs of `height` bytes each, one after the other.
+    /// Fonts can have fewer than 16 glyphs, like the SUPER-CHIP large font.
+    pub fn new(glyphs: &[u8], height: usize) -> Result<Font, FontError> {
+        if height == 0 || height > 15 {
+            return Err(FontError(format!(
+                "Glyphs should be from 1 to 15 rows high, not {}",
+                height
+            )));
+        }
+        if glyphs.is_empty() || !glyphs.len().is_multiple_of(height) {
+            return Err(FontError(format!(
+                "A font of {}-row glyphs can't be {} bytes long",
+                height,
+                glyphs.len()
+            )));
+        }
+        if glyphs.len() / height > Self::MAX_GLYPHS {
+            return Err(FontError(format!(
+                "A font can have at most {} glyphs, not {}",
+                Self::MAX_GLYPHS,
+                glyphs.len() / height
+            )));
+        }
+        Ok(Font {
+            height,
+            glyphs: glyphs.to_vec(),
+        })
+    }
+
+    /// Reads a font from a binary file of glyphs of `height` bytes each
+    pub fn from_file(path: &Path, height: usize) -> Result<Font, FontError> {
+        let glyphs = fs::read(path).map_err(|e| FontError(e.to_string()))?;
+        Font::new(&glyphs, height)
+    }
+
+    /// Returns the built-in font with the given name, if there is one
+    pub fn preset(name: &str) -> Option<Font> {
+        let font = match name {
+            "default" => Font::from_glyphs(&DEFAULT),
+            // The font in the COSMAC VIP's interpreter
+            "cosmac-vip" => Font::from_glyphs(&COSMAC_VIP),
+            // The 3 pixel wide fonts of the DREAM 6800 and ETI-660 monitors
+            "dream-6800" => Font::from_glyphs(&DREAM_6800),
+            "eti-660" => Font::from_glyphs(&ETI_660),
+            // The small and large fonts of SUPER-CHIP 1.1; the large one only has digits
+            "super-chip" => Font::from_glyphs(&SUPER_CHIP),
+            "super-chip-large" => Font::from_glyphs(&SUPER_CHIP_LARGE),
+            _ => return None,
+        };
+        Some(font)
+    }
+
+    fn from_glyphs<const N: usize>(glyphs: &[[u8; N]]) -> Font {
+        Font {
+            height: N,
+            glyphs: glyphs.iter().flatten().cloned().collect(),
+        }
+    }
+
+    /// The bytes of all glyphs, one after the other
+    pub fn bytes(&self) -> &[u8] {
+        &self.glyphs
+    }
+
+    /// The number of bytes the font takes up in memory
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font::from_glyphs(&DEFAULT)
+    }
+}
+
+/// The font most interpreters use
+const DEFAULT: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
+    [0x20, 0x60, 0x20, 0x20, 0x70], // 1
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // 2
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // 3
+    [0x90, 0x90, 0xF0, 0x10, 0x10], // 4
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // 5
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // 6
+    [0xF0, 0x10, 0x20, 0x40, 0x40], // 7
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // 9
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xE0, 0x90, 0x90, 0x90, 0xE0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
+
+const COSMAC_VIP: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
+    [0x60, 0x20, 0x20, 0x20, 0x70], // 1
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // 2
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // 3
+    [0xA0, 0xA0, 0xF0, 0x20, 0x20], // 4
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // 5
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // 6
+    [0xF0, 0x10, 0x10, 0x10, 0x10], // 7
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // 8
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // 9
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xF0, 0x50, 0x70, 0x50, 0xF0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xF0, 0x50, 0x50, 0x50, 0xF0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
+
+const DREAM_6800: [[u8; 5]; 16] = [
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0], // 0
+    [0x40, 0x40, 0x40, 0x40, 0x40], // 1
+    [0xE0, 0x20, 0xE0, 0x80, 0xE0], // 2
+    [0xE0, 0x20, 0xE0, 0x20, 0xE0], // 3
+    [0x80, 0xA0, 0xA0, 0xE0, 0x20], // 4
+    [0xE0, 0x80, 0xE0, 0x20, 0xE0], // 5
+    [0xE0, 0x80, 0xE0, 0xA0, 0xE0], // 6
+    [0xE0, 0x20, 0x20, 0x20, 0x20], // 7
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xE0], // 8
+    [0xE0, 0xA0, 0xE0, 0x20, 0xE0], // 9
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xA0], // A
+    [0xC0, 0xA0, 0xE0, 0xA0, 0xC0], // B
+    [0xE0, 0x80, 0x80, 0x80, 0xE0], // C
+    [0xC0, 0xA0, 0xA0, 0xA0, 0xC0], // D
+    [0xE0, 0x80, 0xE0, 0x80, 0xE0], // E
+    [0xE0, 0x80, 0xC0, 0x80, 0x80], // F
+];
+
+const ETI_660: [[u8; 5]; 16] = [
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0], // 0
+    [0x20, 0x20, 0x20, 0x20, 0x20], // 1
+    [0xE0, 0x20, 0xE0, 0x80, 0xE0], // 2
+    [0xE0, 0x20, 0xE0, 0x20, 0xE0], // 3
+    [0xA0, 0xA0, 0xE0, 0x20, 0x20], // 4
+    [0xE0, 0x80, 0xE0, 0x20, 0xE0], // 5
+    [0xE0, 0x80, 0xE0, 0xA0, 0xE0], // 6
+    [0xE0, 0x20, 0x20, 0x20, 0x20], // 7
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xE0], // 8
+    [0xE0, 0xA0, 0xE0, 0x20, 0xE0], // 9
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xA0], // A
+    [0x80, 0x80, 0xE0, 0xA0, 0xE0], // B
+    [0xE0, 0x80, 0x80, 0x80, 0xE0], // C
+    [0x20, 0x20, 0xE0, 0xA0, 0xE0], // D
+    [0xE0, 0x80, 0xE0, 0x80, 0xE0], // E
+    [0xE0, 0x80, 0xC0, 0x80, 0x80], // F
+];
+
+const SUPER_CHIP: [[u8; 5]; 16] = [
+    [0x60, 0xA0, 0xA0, 0xA0, 0xC0], // 0
+    [0x40, 0xC0, 0x40, 0x40, 0xE0], // 1
+    [0xC0, 0x20, 0x40, 0x80, 0xE0], // 2
+    [0xC0, 0x20, 0x40, 0x20, 0xC0], // 3
+    [0x20, 0xA0, 0xE0, 0x20, 0x20], // 4
+    [0xE0, 0x80, 0xC0, 0x20, 0xC0], // 5
+    [0x40, 0x80, 0xC0, 0xA0, 0x40], // 6
+    [0xE0, 0x20, 0x60, 0x40, 0x40], // 7
+    [0x40, 0xA0, 0x40, 0xA0, 0x40], // 8
+    [0x40, 0xA0, 0x60, 0x20, 0x40], // 9
+    [0x40, 0xA0, 0xE0, 0xA0, 0xA0], // A
+    [0xC0, 0xA0, 0xC0, 0xA0, 0xC0], // B
+    [0x60, 0x80, 0x80, 0x80, 0x60], // C
+    [0xC0, 0xA0, 0xA0, 0xA0, 0xC0], // D
+    [0xE0, 0x80, 0xC0, 0x80, 0xE0], // E
+    [0xE0, 0x80, 0xC0, 0x80, 0x80], // F
+];
+
+const SUPER_CHIP_LARGE: [[u8; 10]; 10] = [
+    [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C], // 0
+    [0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C], // 1
+    [0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF], // 2
+    [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C], // 3
+    [0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06], // 4
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C], // 5
+    [0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C], // 6
+    [0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60], // 7
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C], // 8
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C], // 9
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        for name in Font::PRESETS.iter() {
+            let font = Font::preset(name).unwrap();
+            assert!(font.len().is_multiple_of(font.height));
+        }
+        assert_eq!(Font::preset("default"), Some(Font::default()));
+        assert_eq!(Font::preset("super-chip-large").unwrap().height, 10);
+        assert_eq!(Font::preset("nope"), None);
+    }
+
+    #[test]
+    fn test_new() {
+        let font = Font::new(&[0xFF; 16 * 8], 8).unwrap();
+        assert_eq!(font.height, 8);
+        assert_eq!(font.len(), 128);
+        assert!(Font::new(&[0xFF; 12], 5).is_err());
+        assert!(Font::new(&[0xFF; 17 * 5], 5).is_err());
+        assert!(Font::new(&[0xFF; 16], 16).is_err());
+        assert!(Font::new(&[], 5).is_err());
+    }
+}
@@ -1,8 +1,11 @@
+#[cfg(feature = "sdl")]
 use sdl2::{
     event::Event,
     keyboard::{Keycode, Scancode},
     EventPump,
 };
+use std::{cell::Cell, rc::Rc};
+#[cfg(feature = "sdl")]
 use std::{cell::RefCell, collections::HashMap, thread, time::Duration};
 
 #[cfg(test)]
@@ -18,17 +21,56 @@ pub trait Keyboard {
     fn is_key_pressed(&self, key: u8) -> bool;
     fn wait_for_key_press(&self) -> u8;
     fn is_quit(&self) -> bool;
+    /// Returns the hotkeys pressed since the last call, oldest first
+    fn take_hotkeys(&self) -> Vec<Hotkey>;
+}
+
+/// Emulator functions bound to keys outside of the chip-8 keyboard
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Hotkey {
+    /// Save the current frame as a PNG (F12)
+    Screenshot,
+    /// Start or stop recording frames to an animated GIF (F10)
+    ToggleGif,
+    /// Switch between fullscreen and windowed mode (F11)
+    ToggleFullscreen,
+    /// Switch to the next palette (F9)
+    NextPalette,
+    /// Show or hide the debug overlay (F1)
+    ToggleOverlay,
+    /// Go back to the launcher's menu of programs, if the program was chosen from it (F2)
+    Launcher,
+}
+
+#[cfg(feature = "sdl")]
+impl Hotkey {
+    /// Returns the hotkey bound to an SDL keycode, if there is one
+    pub fn from_keycode(keycode: Keycode) -> Option<Hotkey> {
+        match keycode {
+            Keycode::F12 => Some(Hotkey::Screenshot),
+            Keycode::F10 => Some(Hotkey::ToggleGif),
+            Keycode::F11 => Some(Hotkey::ToggleFullscreen),
+            Keycode::F9 => Some(Hotkey::NextPalette),
+            Keycode::F1 => Some(Hotkey::ToggleOverlay),
+            Keycode::F2 => Some(Hotkey::Launcher),
+            _ => None,
+        }
+    }
 }
 
 /// A struct that implements the Keyboard trait using the SDL2 library
+#[cfg(feature = "sdl")]
 pub struct SDLKeyboard {
     /// Map from u8 to SDL2 Keycodes
     u8_to_keycode: [Keycode; 16],
     /// Map from SDL2 Keycodes to u8s
     keycode_to_u8: HashMap<Keycode, u8>,
     event_source: RefCell<EventPump>,
+    /// Hotkeys seen while polling for events that haven't been taken yet
+    hotkeys: RefCell<Vec<Hotkey>>,
 }
 
+#[cfg(feature = "sdl")]
 impl SDLKeyboard {
     pub fn new(events: EventPump, u8_to_keycode: [Keycode; 16]) -> Self {
         let keycode_to_u8: HashMap<Keycode, u8> = u8_to_keycode
@@ -40,13 +82,20 @@ impl SDLKeyboard {
             u8_to_keycode,
             keycode_to_u8,
             event_source: RefCell::new(events),
+            hotkeys: RefCell::new(Vec::new()),
         }
     }
 }
 
+#[cfg(feature = "sdl")]
 impl Keyboard for SDLKeyboard {
     fn is_key_pressed(&self, key: u8) -> bool {
-        let scancode = Scancode::from_keycode(self.u8_to_keycode[usize::from(key)]).unwrap();
+        // Programs can ask about any byte, but only 0-F are keys
+        let keycode = match self.u8_to_keycode.get(usize::from(key)) {
+            Some(keycode) => *keycode,
+            None => return false,
+        };
+        let scancode = Scancode::from_keycode(keycode).unwrap();
         self.event_source
             .borrow()
             .keyboard_state()
@@ -79,9 +128,121 @@ impl Keyboard for SDLKeyboard {
                 } => {
                     return true;
                 }
+                Event::KeyDown {
+                    keycode: Some(x),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(hotkey) = Hotkey::from_keycode(x) {
+                        self.hotkeys.borrow_mut().push(hotkey);
+                    }
+                }
                 _ => {}
             }
         }
         false
     }
+
+    fn take_hotkeys(&self) -> Vec<Hotkey> {
+        self.hotkeys.replace(Vec::new())
+    }
+}
+
+/// A keyboard with no keys pressed, used when running without a window
+#[derive(Default)]
+pub struct HeadlessKeyboard;
+
+impl HeadlessKeyboard {
+    pub fn new() -> Self {
+        HeadlessKeyboard
+    }
+}
+
+impl Keyboard for HeadlessKeyboard {
+    fn is_key_pressed(&self, _: u8) -> bool {
+        false
+    }
+
+    /// Nobody is around to press a key, so this resolves to key 0 immediately
+    /// rather than blocking forever
+    fn wait_for_key_press(&self) -> u8 {
+        0
+    }
+
+    fn is_quit(&self) -> bool {
+        false
+    }
+
+    fn take_hotkeys(&self) -> Vec<Hotkey> {
+        Vec::new()
+    }
+}
+
+/// A keyboard whose keys are held and released by whatever embeds the emulator, e.g.
+/// a web page or a C program. Clones share the same keys, so the embedder keeps one
+/// and gives another to the State.
+#[derive(Default, Clone)]
+pub struct SharedKeyboard {
+    /// Bit N is set while key N is held
+    keys: Rc<Cell<u16>>,
+}
+
+impl SharedKeyboard {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Holds or releases a key. Anything but 0-F is ignored.
+    pub fn set_key(&self, key: u8, pressed: bool) {
+        if key < 16 {
+            let keys = self.keys.get() & !(1 << key);
+            self.keys.set(keys | u16::from(pressed) << key);
+        }
+    }
+
+    /// Returns whether any key is held
+    pub fn any_held(&self) -> bool {
+        self.keys.get() != 0
+    }
+}
+
+impl Keyboard for SharedKeyboard {
+    fn is_key_pressed(&self, key: u8) -> bool {
+        key < 16 && self.keys.get() >> key & 1 == 1
+    }
+
+    /// Returns the lowest key held. Embedders can't be blocked while waiting for a key,
+    /// so they shouldn't execute LD VX, K until one is held.
+    fn wait_for_key_press(&self) -> u8 {
+        self.keys.get().trailing_zeros().min(15) as u8
+    }
+
+    fn is_quit(&self) -> bool {
+        false
+    }
+
+    fn take_hotkeys(&self) -> Vec<Hotkey> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_keyboard() {
+        let keyboard = SharedKeyboard::new();
+        let handle = keyboard.clone();
+        assert!(!keyboard.any_held());
+        handle.set_key(0xA, true);
+        handle.set_key(0x3, true);
+        handle.set_key(0x10, true);
+        assert!(keyboard.is_key_pressed(0xA));
+        assert!(!keyboard.is_key_pressed(0x10));
+        assert_eq!(keyboard.wait_for_key_press(), 0x3);
+        handle.set_key(0x3, false);
+        assert_eq!(keyboard.wait_for_key_press(), 0xA);
+        assert!(keyboard.any_held());
+    }
 }
@@ -1,6 +1,147 @@
-/// Represents the RAM in Chip-8
+use super::font::Font;
+use serde::Deserialize;
+use std::{fmt, ops::Range};
+
+/// Error for when a program or the font doesn't fit in memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutError(String);
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Where things are in memory, which differs between interpreters: ETI-660 programs
+/// start at 0x600, and some interpreters keep the font at 0x50
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MemoryLayout {
+    /// The number of bytes of memory
+    pub size: usize,
+    /// Where programs are loaded, and where they start running
+    pub program_start: usize,
+    /// Where the font's sprites are
+    pub font_address: usize,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        MemoryLayout {
+            size: MAX_SIZE,
+            program_start: PROGRAM_START,
+            font_address: 0,
+        }
+    }
+}
+
+impl MemoryLayout {
+    /// The most memory I can reach
+    pub const LARGEST: usize = 0x10000;
+
+    /// The addresses the program counter can be at: as far as it reaches, or to the end
+    /// of memory if that's sooner
+    pub fn code(&self) -> Range<usize> {
+        0..self.size.min(MAX_SIZE)
+    }
+
+    /// Where the interpreter lived on the original machines, below the program
+    pub fn interpreter(&self) -> Range<usize> {
+        0..self.program_start
+    }
+
+    /// Checks that a program and a font fit in memory without overlapping, and that
+    /// the program starts where the program counter can reach
+    pub fn check(&self, program: &[u8], font: &Font) -> Result<(), LayoutError> {
+        if self.size == 0 || self.size > Self::LARGEST {
+            return Err(LayoutError(format!(
+                "The memory size should be from 1 to {:#X} bytes, not {:#X}",
+                Self::LARGEST,
+                self.size
+            )));
+        }
+        if self.program_start >= MAX_SIZE {
+            return Err(LayoutError(format!(
+                "Programs should start below {:#X}, not at {:#X}",
+                MAX_SIZE, self.program_start
+            )));
+        }
+        if self.font_address + font.len() > self.size {
+            return Err(LayoutError(format!(
+                "The font at {:#X} doesn't fit in {:#X} bytes of memory",
+                self.font_address, self.size
+            )));
+        }
+        if self.program_start + program.len() > self.size {
+            return Err(LayoutError(format!(
+                "The program is {} bytes, but only {} fit after {:#X}",
+                program.len(),
+                self.size.saturating_sub(self.program_start),
+                self.program_start
+            )));
+        }
+        let fonts = self.font_address..self.font_address + font.len();
+        if overlaps(&fonts, &self.program(program.len())) {
+            return Err(LayoutError(format!(
+                "The font at {:#X} to {:#X} overlaps the program at {:#X}",
+                fonts.start, fonts.end, self.program_start
+            )));
+        }
+        Ok(())
+    }
+
+    /// Where a program of `length` bytes is loaded
+    fn program(&self, length: usize) -> Range<usize> {
+        self.program_start..self.program_start + length
+    }
+}
+
+/// Returns whether two ranges of addresses share any
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// What the addresses past the end of memory lead to
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrapping {
+    /// They wrap around to the start
+    #[default]
+    Wrap,
+    /// Only the first N bytes exist, and they repeat through the rest of the address
+    /// space, as on a COSMAC VIP with 2K of RAM
+    Mirror(usize),
+    /// Nothing: reads give 0 and writes are ignored
+    Unmapped,
+}
+
+/// Identifies a callback added with `Memory::watch`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchId(usize);
+
+/// A callback for writes to a range of addresses
+struct Watch {
+    id: WatchId,
+    addresses: Range<usize>,
+    callback: Box<dyn FnMut(usize, u8)>,
+}
+
+/// Represents the RAM in Chip-8.
+///
+/// Instructions go through `read` and `write`, which apply the wrapping policy, the
+/// read-only regions and the write watches. Debuggers and other tools that need the
+/// bytes as they are use `ram` and `ram_mut` instead.
 pub struct Memory {
-    pub ram: Vec<u8>,
+    ram: Vec<u8>,
+    /// What the addresses past the end of memory lead to
+    pub wrapping: Wrapping,
+    layout: MemoryLayout,
+    font: Font,
+    /// Where the program was loaded, which the font mustn't be loaded over
+    program: Range<usize>,
+    read_only: Vec<Range<usize>>,
+    watches: Vec<Watch>,
+    next_watch: usize,
 }
 
 impl Memory {
@@ -10,139 +151,148 @@ impl Memory {
     ///
     /// * `program` - a vector of bytes representing the program to load
     pub fn new(program: &[u8]) -> Memory {
-        let mut ram: Vec<u8> = vec![0; MAX_SIZE];
-        let flat_sprites = SPRITES.iter().flatten().cloned().collect::<Vec<u8>>();
-        ram.splice(..flat_sprites.len(), flat_sprites);
+        Self::with_layout(program, MemoryLayout::default())
+    }
+
+    /// Returns a Memory struct with sprites and a program loaded where `layout` says.
+    /// Panics if they don't fit, which `MemoryLayout::check` catches first.
+    pub fn with_layout(program: &[u8], layout: MemoryLayout) -> Memory {
+        let font = Font::default();
+        if let Err(e) = layout.check(program, &font) {
+            panic!("{}", e);
+        }
+        let mut ram: Vec<u8> = vec![0; layout.size];
+        ram.splice(
+            layout.font_address..layout.font_address + font.len(),
+            font.bytes().iter().cloned(),
+        );
         ram.splice(
-            PROGRAM_START..PROGRAM_START + program.len(),
+            layout.program_start..layout.program_start + program.len(),
             program.iter().cloned(),
         );
-        Memory { ram }
+        Memory {
+            ram,
+            wrapping: Wrapping::default(),
+            layout,
+            font,
+            program: layout.program(program.len()),
+            read_only: Vec::new(),
+            watches: Vec::new(),
+            next_watch: 0,
+        }
+    }
+
+    /// Where things are in memory
+    pub fn layout(&self) -> MemoryLayout {
+        self.layout
+    }
+
+    /// The font FX29 points I at
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Replaces the font at the layout's font address with another one, unless it
+    /// would run past the end of memory or into the program
+    pub fn load_font(&mut self, font: Font) -> Result<(), LayoutError> {
+        let start = self.layout.font_address;
+        if start + font.len() > self.ram.len() {
+            return Err(LayoutError(format!(
+                "The font is {} bytes, but only {} fit after {:#X}",
+                font.len(),
+                self.ram.len() - start,
+                start
+            )));
+        }
+        if overlaps(&(start..start + font.len()), &self.program) {
+            return Err(LayoutError(format!(
+                "The font is {} bytes, so it would run into the program at {:#X}",
+                font.len(),
+                self.program.start
+            )));
+        }
+        self.ram[start..start + self.font.len()].fill(0);
+        self.ram[start..start + font.len()].copy_from_slice(font.bytes());
+        self.font = font;
+        Ok(())
+    }
+
+    /// The bytes in memory, without going through the bus
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// The bytes in memory, to change without going through the bus: read-only
+    /// regions can be changed and watches aren't called
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    /// Returns the byte an address leads to under the wrapping policy, if any
+    pub fn resolve(&self, address: usize) -> Option<usize> {
+        match self.wrapping {
+            Wrapping::Wrap => Some(address % self.ram.len()),
+            Wrapping::Mirror(size) => Some(address % size.clamp(1, self.ram.len())),
+            Wrapping::Unmapped => Some(address).filter(|x| *x < self.ram.len()),
+        }
+    }
+
+    /// Reads the byte at an address
+    pub fn read(&self, address: usize) -> u8 {
+        self.resolve(address).map_or(0, |x| self.ram[x])
+    }
+
+    /// Writes a byte to an address and calls the watches on it, unless it's read-only
+    pub fn write(&mut self, address: usize, value: u8) {
+        let address = match self.resolve(address) {
+            Some(address) if !self.is_read_only(address) => address,
+            _ => return,
+        };
+        self.ram[address] = value;
+        for watch in self.watches.iter_mut() {
+            if watch.addresses.contains(&address) {
+                (watch.callback)(address, value);
+            }
+        }
+    }
+
+    /// Makes writes to a range of addresses do nothing
+    pub fn protect(&mut self, addresses: Range<usize>) {
+        self.read_only.push(addresses);
+    }
+
+    pub fn is_read_only(&self, address: usize) -> bool {
+        self.read_only.iter().any(|x| x.contains(&address))
+    }
+
+    /// Calls `callback` with the address and value of every write to a range of addresses
+    pub fn watch<F>(&mut self, addresses: Range<usize>, callback: F) -> WatchId
+    where
+        F: FnMut(usize, u8) + 'static,
+    {
+        let id = WatchId(self.next_watch);
+        self.next_watch += 1;
+        self.watches.push(Watch {
+            id,
+            addresses,
+            callback: Box::new(callback),
+        });
+        id
+    }
+
+    /// Removes a callback added with `watch`
+    pub fn unwatch(&mut self, id: WatchId) {
+        self.watches.retain(|x| x.id != id);
     }
 }
 
-/// Maximum size of the RAM in Chip-8
+/// The size of the RAM in Chip-8 by default, which is also as far as the program
+/// counter reaches
 pub const MAX_SIZE: usize = 4096;
 
-/// The starting index of a Chip-8 program
+/// The starting index of a Chip-8 program by default
 pub const PROGRAM_START: usize = 512;
 
-/// The sprites stored in Chip-8 memory
-const SPRITES: [[u8; 5]; 16] = [
-    [
-        0b1111_0000,
-        0b1001_0000,
-        0b1001_0000,
-        0b1001_0000,
-        0b1111_0000,
-    ], // 0
-    [
-        0b0010_0000,
-        0b0110_0000,
-        0b0010_0000,
-        0b0010_0000,
-        0b0111_0000,
-    ], // 1
-    [
-        0b1111_0000,
-        0b0001_0000,
-        0b1111_0000,
-        0b1000_0000,
-        0b1111_0000,
-    ], // 2
-    [
-        0b1111_0000,
-        0b0001_0000,
-        0b1111_0000,
-        0b0001_0000,
-        0b1111_0000,
-    ], // 3
-    [
-        0b1001_0000,
-        0b1001_0000,
-        0b1111_0000,
-        0b0001_0000,
-        0b0001_0000,
-    ], // 4
-    [
-        0b1111_0000,
-        0b1000_0000,
-        0b1111_0000,
-        0b0001_0000,
-        0b1111_0000,
-    ], // 5
-    [
-        0b1111_0000,
-        0b1000_0000,
-        0b1111_0000,
-        0b1001_0000,
-        0b1111_0000,
-    ], // 6
-    [
-        0b1111_0000,
-        0b0001_0000,
-        0b0010_0000,
-        0b0100_0000,
-        0b0100_0000,
-    ], // 7
-    [
-        0b1111_0000,
-        0b1001_0000,
-        0b1111_0000,
-        0b1001_0000,
-        0b1111_0000,
-    ], // 8
-    [
-        0b1111_0000,
-        0b1001_0000,
-        0b1111_0000,
-        0b0001_0000,
-        0b1111_0000,
-    ], // 9
-    [
-        0b1111_0000,
-        0b1001_0000,
-        0b1111_0000,
-        0b1001_0000,
-        0b1001_0000,
-    ], // A
-    [
-        0b1110_0000,
-        0b1001_0000,
-        0b1110_0000,
-        0b1001_0000,
-        0b1110_0000,
-    ], // B
-    [
-        0b1111_0000,
-        0b1000_0000,
-        0b1000_0000,
-        0b1000_0000,
-        0b1111_0000,
-    ], // C
-    [
-        0b1110_0000,
-        0b1001_0000,
-        0b1001_0000,
-        0b1001_0000,
-        0b1110_0000,
-    ], // D
-    [
-        0b1111_0000,
-        0b1000_0000,
-        0b1111_0000,
-        0b1000_0000,
-        0b1111_0000,
-    ], // E
-    [
-        0b1111_0000,
-        0b1000_0000,
-        0b1111_0000,
-        0b1000_0000,
-        0b1000_0000,
-    ], // F
-];
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,9 +301,8 @@ mod tests {
     /// Test that sprites are loaded correctly
     fn test_sprites() {
         let mem = Memory::new(&[]);
-        let flat_sprites = SPRITES.iter().flatten().cloned().collect::<Vec<u8>>();
-        for (index, element) in flat_sprites.iter().enumerate() {
-            assert_eq!(*element, mem.ram[index]);
+        for (index, element) in Font::default().bytes().iter().enumerate() {
+            assert_eq!(*element, mem.ram()[index]);
         }
     }
 
@@ -163,7 +312,149 @@ mod tests {
         let program = &[5, 24, 32, 4, 16, 50];
         let mem = Memory::new(program);
         for (index, element) in program.iter().enumerate() {
-            assert_eq!(*element, mem.ram[PROGRAM_START + index]);
+            assert_eq!(*element, mem.ram()[PROGRAM_START + index]);
         }
     }
+
+    #[test]
+    fn test_wrapping() {
+        let mut mem = Memory::new(&[]);
+        mem.write(MAX_SIZE + 0x300, 7);
+        assert_eq!(mem.ram()[0x300], 7);
+        assert_eq!(mem.read(0x300 + 2 * MAX_SIZE), 7);
+        mem.wrapping = Wrapping::Mirror(0x800);
+        assert_eq!(mem.read(0xB00), 7);
+        mem.write(0xB01, 8);
+        assert_eq!(mem.ram()[0x301], 8);
+        mem.wrapping = Wrapping::Unmapped;
+        mem.write(MAX_SIZE + 0x300, 9);
+        assert_eq!(mem.read(MAX_SIZE + 0x300), 0);
+        assert_eq!(mem.ram()[0x300], 7);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut mem = Memory::new(&[]);
+        mem.protect(mem.layout().interpreter());
+        mem.write(0x000, 0xFF);
+        mem.write(0x200, 0xFF);
+        assert_eq!(mem.read(0x000), 0xF0);
+        assert_eq!(mem.read(0x200), 0xFF);
+        mem.ram_mut()[0x000] = 0xAA;
+        assert_eq!(mem.read(0x000), 0xAA);
+    }
+
+    #[test]
+    fn test_watch() {
+        use std::{cell::RefCell, rc::Rc};
+        let mut mem = Memory::new(&[]);
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let w = writes.clone();
+        let id = mem.watch(0x300..0x302, move |address, value| {
+            w.borrow_mut().push((address, value))
+        });
+        mem.write(0x2FF, 1);
+        mem.write(0x301, 2);
+        mem.write(MAX_SIZE + 0x300, 3);
+        mem.protect(0x300..0x301);
+        mem.write(0x300, 4);
+        mem.unwatch(id);
+        mem.write(0x301, 5);
+        assert_eq!(*writes.borrow(), vec![(0x301, 2), (0x300, 3)]);
+    }
+
+    #[test]
+    fn test_load_font() {
+        let mut mem = Memory::with_layout(
+            &[],
+            MemoryLayout {
+                font_address: 0x50,
+                ..MemoryLayout::default()
+            },
+        );
+        let large = Font::preset("super-chip-large").unwrap();
+        mem.load_font(large.clone()).unwrap();
+        assert_eq!(mem.ram()[0x50..0x50 + 100], *large.bytes());
+        mem.load_font(Font::preset("eti-660").unwrap()).unwrap();
+        assert_eq!(mem.ram()[0x50..0x55], [0xE0, 0xA0, 0xA0, 0xA0, 0xE0]);
+        assert_eq!(mem.ram()[0xA0..0xB4], [0; 20]);
+        assert_eq!(mem.font().height, 5);
+        let mut mem = Memory::with_layout(
+            &[],
+            MemoryLayout {
+                size: 0x100,
+                font_address: 0xB0,
+                program_start: 0x100,
+            },
+        );
+        assert!(mem.load_font(large).is_err());
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = MemoryLayout {
+            size: 0x1000,
+            program_start: 0x600,
+            font_address: 0x50,
+        };
+        let mem = Memory::with_layout(&[1, 2], layout);
+        assert_eq!(mem.ram()[0x50..0x55], Font::default().bytes()[..5]);
+        assert_eq!(mem.ram()[0x000], 0);
+        assert_eq!(mem.ram()[0x600..0x602], [1, 2]);
+        let font = Font::default();
+        assert!(layout.check(&[0; 0xA00], &font).is_ok());
+        assert_eq!(
+            layout.check(&[0; 0xA01], &font),
+            Err(LayoutError(
+                "The program is 2561 bytes, but only 2560 fit after 0x600".to_string()
+            ))
+        );
+        let layout = MemoryLayout {
+            size: 0x800,
+            font_address: 0x7C0,
+            ..MemoryLayout::default()
+        };
+        assert!(layout.check(&[], &font).is_err());
+        let layout = MemoryLayout {
+            program_start: 0x1000,
+            size: 0x2000,
+            ..MemoryLayout::default()
+        };
+        assert!(layout.check(&[], &font).is_err());
+    }
+
+    #[test]
+    fn test_font_and_program_apart() {
+        let font = Font::default();
+        let large = Font::preset("super-chip-large").unwrap();
+        let layout = MemoryLayout {
+            program_start: 0,
+            ..MemoryLayout::default()
+        };
+        assert!(layout.check(&[], &font).is_ok());
+        assert_eq!(
+            layout.check(&[0x12, 0x00], &font),
+            Err(LayoutError(
+                "The font at 0x0 to 0x50 overlaps the program at 0x0".to_string()
+            ))
+        );
+        let layout = MemoryLayout {
+            font_address: 0x1C0,
+            ..MemoryLayout::default()
+        };
+        assert!(layout.check(&[0x12, 0x00], &font).is_err());
+        let layout = MemoryLayout {
+            font_address: 0x1B0,
+            ..MemoryLayout::default()
+        };
+        assert!(layout.check(&[0x12, 0x00], &font).is_ok());
+        assert!(layout.check(&[0x12, 0x00], &large).is_err());
+
+        // A font that would reach into the program isn't loaded, and nothing is zeroed
+        let mut mem = Memory::with_layout(&[0x12, 0x00], layout);
+        assert!(mem.load_font(large).is_err());
+        assert_eq!(mem.ram()[0x200..0x202], [0x12, 0x00]);
+        assert_eq!(mem.ram()[0x1B0..0x1B5], font.bytes()[..5]);
+        assert_eq!(*mem.font(), font);
+    }
 }
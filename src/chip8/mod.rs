@@ -1,13 +1,17 @@
 pub mod display;
+pub mod font;
 pub mod keyboard;
 pub mod memory;
+pub mod quirks;
 pub mod registers;
+pub mod snapshot;
 pub mod stack;
 pub mod timers;
 
 use display::Display;
 use keyboard::Keyboard;
-use memory::Memory;
+use memory::{Memory, MemoryLayout};
+use quirks::Quirks;
 use registers::Registers;
 use stack::Stack;
 use timers::Timers;
@@ -21,24 +25,54 @@ pub struct State {
     pub stack: Stack,
     pub program_counter: usize,
     pub keyboard: Box<dyn Keyboard>,
+    /// How instructions that differ between interpreters behave
+    pub quirks: Quirks,
 }
 
 impl State {
+    /// Creates a new State struct with the program loaded and the program counter at its start
+    pub fn new(program: &[u8], keyboard: Box<dyn Keyboard>, display: Display) -> Self {
+        Self::with_layout(program, keyboard, display, MemoryLayout::default())
+    }
+
+    /// Creates a new State struct with the program and font loaded where `layout` says,
+    /// and the program counter at the program's start
+    pub fn with_layout(
+        program: &[u8],
+        keyboard: Box<dyn Keyboard>,
+        display: Display,
+        layout: MemoryLayout,
+    ) -> Self {
+        State {
+            display,
+            memory: Memory::with_layout(program, layout),
+            registers: Registers::new(),
+            timers: Timers::new(),
+            stack: Stack::new(),
+            program_counter: layout.program_start,
+            keyboard,
+            quirks: Quirks::default(),
+        }
+    }
+
     /// Creates a new State struct with no IO (no display, no keyboard).
     /// Used for testing purposes only!
     #[cfg(test)]
     pub fn mock(program: &[u8]) -> Self {
+        Self::mock_with_layout(program, MemoryLayout::default())
+    }
+
+    /// Creates a new State struct with no IO and the program loaded where `layout` says.
+    /// Used for testing purposes only!
+    #[cfg(test)]
+    pub fn mock_with_layout(program: &[u8], layout: MemoryLayout) -> Self {
         use crate::config::Color;
         use keyboard::MockKeyboard;
-        use memory::PROGRAM_START;
-        State {
-            display: Display::new(Color::white(), Color::black()),
-            memory: Memory::new(program),
-            registers: Registers::new(),
-            timers: Timers::new(),
-            stack: Stack::new(),
-            program_counter: PROGRAM_START,
-            keyboard: Box::new(MockKeyboard::new()),
-        }
+        State::with_layout(
+            program,
+            Box::new(MockKeyboard::new()),
+            Display::new(Color::white(), Color::black()),
+            layout,
+        )
     }
 }
@@ -0,0 +1,70 @@
+use serde::Deserialize;
+
+/// Behaviors that differ between Chip-8 interpreters, which programs written for one
+/// interpreter can depend on.
+/// The defaults are this emulator's original behavior, which most modern ROMs expect.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VY into VX, instead of shifting VX in place
+    pub shift_vy: bool,
+    /// FX55 and FX65 leave I pointing just past the last register stored or loaded
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to NNN + VX (where X is the first digit of NNN), instead of NNN + V0
+    pub jump_vx: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+}
+
+impl Quirks {
+    /// The names of the built-in quirk profiles
+    pub const PROFILES: [&'static str; 3] = ["default", "cosmac-vip", "super-chip"];
+
+    /// Returns the built-in quirk profile with the given name, if there is one
+    pub fn profile(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(Quirks::default()),
+            // The original interpreter on the COSMAC VIP
+            "cosmac-vip" => Some(Quirks {
+                shift_vy: true,
+                load_store_increments_i: true,
+                jump_vx: false,
+                logic_resets_vf: true,
+            }),
+            // SUPER-CHIP 1.1 on the HP 48 calculators
+            "super-chip" => Some(Quirks {
+                shift_vy: false,
+                load_store_increments_i: false,
+                jump_vx: true,
+                logic_resets_vf: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        for name in Quirks::PROFILES.iter() {
+            assert!(Quirks::profile(name).is_some());
+        }
+        assert_eq!(Quirks::profile("default"), Some(Quirks::default()));
+        assert_eq!(Quirks::profile("nope"), None);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let quirks: Quirks = serde_json::from_str(r#"{"shift_vy": true}"#).unwrap();
+        assert_eq!(
+            quirks,
+            Quirks {
+                shift_vy: true,
+                ..Quirks::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use super::{display::Display, memory::MAX_SIZE, quirks::Quirks, State};
+use std::fmt;
+
+/// The bytes every snapshot starts with
+const MAGIC: &[u8; 4] = b"CH8S";
+
+/// The version of the snapshot format, which changes whenever the layout does
+const VERSION: u8 = 1;
+
+/// The number of return addresses the stack holds
+const STACK_SIZE: usize = 16;
+
+/// The size of a snapshot in bytes before memory:
+/// the magic and version, PC, I, V0-VF, the timers, the stack depth and return addresses,
+/// the quirks and the display (one bit per pixel)
+const HEADER_SIZE: usize =
+    4 + 1 + 2 + 2 + 16 + 2 + 1 + STACK_SIZE * 2 + 1 + Display::WIDTH * Display::HEIGHT / 8;
+
+/// The size of a snapshot in bytes of a machine with the usual amount of memory
+pub const SNAPSHOT_SIZE: usize = HEADER_SIZE + MAX_SIZE;
+
+/// Error for when a snapshot can't be restored
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    /// The snapshot isn't as long as the machine's snapshots (the second size)
+    WrongSize(usize, usize),
+    /// The snapshot doesn't start with the magic bytes
+    NotASnapshot,
+    /// The snapshot was saved in another version of the format
+    UnsupportedVersion(u8),
+    /// A value in the snapshot is out of range, e.g. the program counter
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::WrongSize(size, expected) => write!(
+                f,
+                "The snapshot is {} bytes, but should be {}",
+                size, expected
+            ),
+            SnapshotError::NotASnapshot => write!(f, "The data isn't a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "The snapshot is version {}, but only version {} is supported",
+                version, VERSION
+            ),
+            SnapshotError::InvalidValue(name) => write!(f, "The snapshot's {} is invalid", name),
+        }
+    }
+}
+
+impl Quirks {
+    fn to_bits(self) -> u8 {
+        self.shift_vy as u8
+            | (self.load_store_increments_i as u8) << 1
+            | (self.jump_vx as u8) << 2
+            | (self.logic_resets_vf as u8) << 3
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        Quirks {
+            shift_vy: bits & 1 != 0,
+            load_store_increments_i: bits & 2 != 0,
+            jump_vx: bits & 4 != 0,
+            logic_resets_vf: bits & 8 != 0,
+        }
+    }
+}
+
+impl State {
+    /// The size of this machine's snapshots in bytes, which is SNAPSHOT_SIZE unless it
+    /// has more or less memory than usual
+    pub fn snapshot_size(&self) -> usize {
+        HEADER_SIZE + self.memory.ram().len()
+    }
+
+    /// Saves everything about the machine except the keyboard, the display's colors and
+    /// the memory layout, in a fixed-size format that `restore` reads back
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.snapshot_size());
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.extend_from_slice(&(self.program_counter as u16).to_be_bytes());
+        data.extend_from_slice(&self.registers.i_register.to_be_bytes());
+        data.extend_from_slice(&self.registers.v_registers);
+        data.push(self.timers.delay_timer);
+        data.push(self.timers.sound_timer);
+        let stack = self.stack.contents();
+        data.push(stack.len() as u8);
+        for index in 0..STACK_SIZE {
+            let address = stack.get(index).copied().unwrap_or(0) as u16;
+            data.extend_from_slice(&address.to_be_bytes());
+        }
+        data.push(self.quirks.to_bits());
+        for row in self.display.pixels.iter() {
+            for pixels in row.chunks(8) {
+                data.push(pixels.iter().fold(0, |byte, pixel| byte << 1 | pixel));
+            }
+        }
+        data.extend_from_slice(self.memory.ram());
+        data
+    }
+
+    /// Restores the machine to a snapshot saved by `snapshot`.
+    /// If the snapshot is invalid, the state is left as it was.
+    pub fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        if data.len() != self.snapshot_size() {
+            return Err(SnapshotError::WrongSize(data.len(), self.snapshot_size()));
+        }
+        if &data[..4] != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        if data[4] != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(data[4]));
+        }
+        let word =
+            |offset: usize| usize::from(u16::from_be_bytes([data[offset], data[offset + 1]]));
+        let program_counter = word(5);
+        if program_counter >= self.memory.layout().code().end {
+            return Err(SnapshotError::InvalidValue("program counter"));
+        }
+        let depth = usize::from(data[27]);
+        if depth > STACK_SIZE {
+            return Err(SnapshotError::InvalidValue("stack depth"));
+        }
+
+        self.program_counter = program_counter;
+        self.registers.i_register = word(7) as u16;
+        self.registers.v_registers.copy_from_slice(&data[9..25]);
+        self.timers.delay_timer = data[25];
+        self.timers.sound_timer = data[26];
+        while self.stack.pop().is_ok() {}
+        for index in 0..depth {
+            // There's room, since the depth was checked
+            self.stack.push(word(28 + index * 2)).unwrap();
+        }
+        let mut offset = 28 + STACK_SIZE * 2;
+        self.quirks = Quirks::from_bits(data[offset]);
+        offset += 1;
+        self.display.clear();
+        for y in 0..Display::HEIGHT {
+            for x in 0..Display::WIDTH {
+                let byte = data[offset + (y * Display::WIDTH + x) / 8];
+                self.display.xor(x, y, byte >> (7 - x % 8) & 1);
+            }
+        }
+        offset += Display::WIDTH * Display::HEIGHT / 8;
+        self.memory.ram_mut().copy_from_slice(&data[offset..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let mut state = State::mock(&[0x12, 0x34]);
+        state.program_counter = 0x246;
+        state.registers.i_register = 0x321;
+        state.registers.v_registers[0xA] = 7;
+        state.timers.delay_timer = 9;
+        state.stack.push(0x202).unwrap();
+        state.stack.push(0x3FE).unwrap();
+        state.quirks = Quirks::profile("cosmac-vip").unwrap();
+        state.display.xor(0, 0, 1);
+        state.display.xor(63, 31, 1);
+        state.memory.ram_mut()[0xFFF] = 0xAB;
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.len(), SNAPSHOT_SIZE);
+
+        let mut restored = State::mock(&[]);
+        restored.display.xor(5, 5, 1);
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.program_counter, 0x246);
+        assert_eq!(restored.registers.i_register, 0x321);
+        assert_eq!(restored.registers.v_registers[0xA], 7);
+        assert_eq!(restored.timers.delay_timer, 9);
+        assert_eq!(restored.stack.contents(), &[0x202, 0x3FE]);
+        assert_eq!(restored.quirks, state.quirks);
+        assert_eq!(&restored.display.pixels[..], &state.display.pixels[..]);
+        assert_eq!(
+            restored.display.colored_pixels[..],
+            state.display.colored_pixels[..]
+        );
+        assert_eq!(restored.memory.ram(), state.memory.ram());
+        assert_eq!(restored.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_restore_errors() {
+        let mut state = State::mock(&[]);
+        let snapshot = state.snapshot();
+        assert_eq!(
+            state.restore(&snapshot[1..]),
+            Err(SnapshotError::WrongSize(SNAPSHOT_SIZE - 1, SNAPSHOT_SIZE))
+        );
+        let mut invalid = snapshot.clone();
+        invalid[0] = b'X';
+        assert_eq!(state.restore(&invalid), Err(SnapshotError::NotASnapshot));
+        let mut invalid = snapshot.clone();
+        invalid[4] = 2;
+        assert_eq!(
+            state.restore(&invalid),
+            Err(SnapshotError::UnsupportedVersion(2))
+        );
+        let mut invalid = snapshot.clone();
+        invalid[27] = 17;
+        invalid[5] = 0x01;
+        assert_eq!(
+            state.restore(&invalid),
+            Err(SnapshotError::InvalidValue("stack depth"))
+        );
+        assert_eq!(state.program_counter, 0x200);
+        invalid[5] = 0x10;
+        assert_eq!(
+            state.restore(&invalid),
+            Err(SnapshotError::InvalidValue("program counter"))
+        );
+    }
+}
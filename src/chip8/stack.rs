@@ -32,6 +32,11 @@ impl Stack {
         Ok(self.stack[self.stack_pointer])
     }
 
+    /// Returns the values on the stack, from the bottom to the top
+    pub fn contents(&self) -> &[usize] {
+        &self.stack[..self.stack_pointer]
+    }
+
     /// Returns the value on the top of the stack if is not empty
     /// Otherwise, it returns none
     #[cfg(test)]
@@ -43,7 +48,7 @@ impl Stack {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StackError {
     Full,
     Empty,
@@ -65,6 +70,15 @@ mod tests {
         assert_eq!(Some(3), stack.top());
     }
 
+    #[test]
+    fn test_contents() {
+        let mut stack = Stack::new();
+        assert!(stack.contents().is_empty());
+        stack.push(3).unwrap();
+        stack.push(5).unwrap();
+        assert_eq!(stack.contents(), &[3, 5]);
+    }
+
     #[test]
     #[should_panic]
     fn test_pop_panic() {
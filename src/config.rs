@@ -1,17 +1,113 @@
+use crate::chip8::{
+    font::Font,
+    memory::{Memory, MemoryLayout, Wrapping},
+    quirks::Quirks,
+};
+use crate::database::{RomDatabase, RomInfo};
+use crate::filters::FilterKind;
+use crate::octo::CartridgeOptions;
+use crate::palette::Palette;
+use crate::window::Scaling;
+#[cfg(feature = "sdl")]
 use sdl2::keyboard::Keycode;
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer};
 use serde_json;
-use std::{collections::HashMap, fs::File};
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    path::{Path, PathBuf},
+};
 
 /// Represents the JSON config file
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Config {
     pub ticks_per_frame: u8,
     pub frames_per_second: u32,
     pub pixel_size: u32,
     pub active_color: Color,
     pub inactive_color: Color,
+    /// SDL key names for each chip-8 key, which only the window uses
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
     keyboard: HashMap<String, String>,
+    /// The post-processing applied to each frame before it is shown
+    #[serde(default)]
+    pub filter: FilterKind,
+    /// The fraction of an unlit pixel's brightness kept each frame by the persistence filter
+    #[serde(default = "Config::default_persistence")]
+    pub persistence: f32,
+    /// How the display is scaled to fit the window
+    #[serde(default)]
+    pub scaling: Scaling,
+    /// The palette to start with. Overrides active_color and inactive_color if given.
+    #[serde(default)]
+    palette: Option<PaletteSetting>,
+    /// How instructions that differ between interpreters behave
+    #[serde(default)]
+    quirks: Option<QuirksSetting>,
+    /// What addresses past the end of memory lead to
+    #[serde(default)]
+    pub wrapping: Wrapping,
+    /// Whether writes to the interpreter's area, which holds the font, are ignored
+    #[serde(default)]
+    pub protect_interpreter: bool,
+    /// Where things are in memory. Overrides the ROM database if given.
+    #[serde(default)]
+    memory: Option<MemoryLayout>,
+    /// A ROM database to add to the built-in one
+    #[serde(default)]
+    rom_database: Option<String>,
+    /// The font for the hex digits
+    #[serde(default)]
+    font: Option<FontSetting>,
+    /// The directory of programs the launcher lists when no program is given
+    #[serde(default)]
+    pub rom_directory: Option<String>,
+}
+
+/// A palette in the config file: either the name of a built-in palette, or a list of
+/// the colors of the background and of lit pixels
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum PaletteSetting {
+    Preset(String),
+    Colors(Vec<Color>),
+}
+
+/// A font in the config file: either the name of a built-in font, or a binary file of
+/// glyphs and how many rows each has (5 if not given)
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum FontSetting {
+    Preset(String),
+    File {
+        file: String,
+        #[serde(default = "default_glyph_height")]
+        height: usize,
+    },
+}
+
+fn default_glyph_height() -> usize {
+    5
+}
+
+/// Quirks in the config file: either the name of a built-in profile, or the individual
+/// quirks (the ones not given are off)
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum QuirksSetting {
+    Profile(String),
+    Custom(Quirks),
+}
+
+/// Returns the directory settings are saved in between sessions: `chip8-rust` in the
+/// user's config directory, if there is one
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|x| Path::new(&x).join(".config")))?;
+    Some(config_dir.join("chip8-rust"))
 }
 
 impl Config {
@@ -22,6 +118,10 @@ impl Config {
             .unwrap_or_else(|_| panic!("Could not deserialize file at {}", path))
     }
 
+    fn default_persistence() -> f32 {
+        0.6
+    }
+
     /// Returns the default key mappings for the keys not given in the config file
     fn default_keyboard() -> HashMap<String, String> {
         [
@@ -47,8 +147,107 @@ impl Config {
         .collect()
     }
 
+    /// Returns the palette to start with
+    pub fn get_palette(&self) -> Palette {
+        match &self.palette {
+            None => Palette::two_color("config", self.active_color, self.inactive_color),
+            Some(PaletteSetting::Preset(name)) => Palette::preset(name).unwrap_or_else(|| {
+                panic!(
+                    "Could not find a palette named {}. Please use one of {:?}!",
+                    name,
+                    Palette::PRESETS
+                )
+            }),
+            Some(PaletteSetting::Colors(colors)) => match colors[..] {
+                [inactive, active] => Palette::two_color("config", active, inactive),
+                _ => panic!("A palette should have 2 colors, not {}!", colors.len()),
+            },
+        }
+    }
+
+    /// Returns the quirks to run programs with
+    pub fn get_quirks(&self) -> Quirks {
+        match &self.quirks {
+            None => Quirks::default(),
+            Some(QuirksSetting::Profile(name)) => Quirks::profile(name).unwrap_or_else(|| {
+                panic!(
+                    "Could not find a quirk profile named {}. Please use one of {:?}!",
+                    name,
+                    Quirks::PROFILES
+                )
+            }),
+            Some(QuirksSetting::Custom(quirks)) => *quirks,
+        }
+    }
+
+    /// Returns the built-in ROM database, with the one in the config file added
+    pub fn get_database(&self) -> RomDatabase {
+        let mut database = RomDatabase::builtin();
+        if let Some(path) = &self.rom_database {
+            let extra = RomDatabase::from_file(Path::new(path))
+                .unwrap_or_else(|e| panic!("Could not read the ROM database at {}: {}", path, e));
+            database.extend(extra);
+        }
+        database
+    }
+
+    /// Returns where things should be in memory for a program, given what the ROM
+    /// database knows about it
+    pub fn get_memory_layout(&self, info: Option<&RomInfo>) -> MemoryLayout {
+        self.memory
+            .or_else(|| info.and_then(|x| x.memory))
+            .unwrap_or_default()
+    }
+
+    /// Returns the font to load into memory
+    pub fn get_font(&self) -> Font {
+        match &self.font {
+            None => Font::default(),
+            Some(FontSetting::Preset(name)) => Font::preset(name).unwrap_or_else(|| {
+                panic!(
+                    "Could not find a font named {}. Please use one of {:?}!",
+                    name,
+                    Font::PRESETS
+                )
+            }),
+            Some(FontSetting::File { file, height }) => Font::from_file(Path::new(file), *height)
+                .unwrap_or_else(|e| panic!("Could not read the font at {}: {}", file, e)),
+        }
+    }
+
+    /// Sets up the font, wrapping policy and read-only regions of a machine's memory
+    pub fn configure_memory(&self, memory: &mut Memory) {
+        if let Err(e) = memory.load_font(self.get_font()) {
+            panic!("Could not load the font: {}", e);
+        }
+        memory.wrapping = self.wrapping;
+        if self.protect_interpreter {
+            memory.protect(memory.layout().interpreter());
+        }
+    }
+
+    /// Applies the settings an Octo cartridge came with, over the ones in the file
+    pub fn apply_cartridge(&mut self, options: &CartridgeOptions) {
+        self.quirks = Some(QuirksSetting::Custom(options.quirks()));
+        if let Some(name) = options.font() {
+            self.font = Some(FontSetting::Preset(String::from(name)));
+        }
+        if let Some(tickrate) = options.tickrate {
+            self.ticks_per_frame = tickrate.clamp(1, u8::MAX.into()) as u8;
+        }
+        if let Some(color) = options.fill_color {
+            self.active_color = color;
+            self.palette = None;
+        }
+        if let Some(color) = options.background_color {
+            self.inactive_color = color;
+            self.palette = None;
+        }
+    }
+
     /// Returns an array of keycodes representing the keyboard mapping (for SDL)
     /// The index of an element is its chip8 keycode, the element itself is the SDL keycode
+    #[cfg(feature = "sdl")]
     pub fn get_keyboard(&self) -> [Keycode; 16] {
         let mut result = [Keycode::Num2; 16];
         let default_keyboard = Self::default_keyboard();
@@ -69,10 +268,47 @@ impl Config {
     }
 }
 
-#[derive(Deserialize, Copy, Clone)]
+/// An RGBA color.
+/// In the config file, colors are either [r, g, b, a] arrays or "#RRGGBB" / "#RRGGBBAA" strings.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Color(pub u8, pub u8, pub u8, pub u8);
 
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum ColorSetting {
+            Array(u8, u8, u8, u8),
+            Hex(String),
+        }
+        match ColorSetting::deserialize(deserializer)? {
+            ColorSetting::Array(r, g, b, a) => Ok(Color(r, g, b, a)),
+            ColorSetting::Hex(hex) => Color::from_hex(&hex).ok_or_else(|| {
+                de::Error::custom(format!(
+                    "{} is not a color, use \"#RRGGBB\" or \"#RRGGBBAA\"",
+                    hex
+                ))
+            }),
+        }
+    }
+}
+
 impl Color {
+    /// Parses a "#RRGGBB" or "#RRGGBBAA" string. Colors without an alpha are opaque.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#')?;
+        if !(digits.len() == 6 || digits.len() == 8) || !digits.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+        let alpha = if digits.len() == 8 {
+            channel(6)?
+        } else {
+            u8::MAX
+        };
+        Some(Color(channel(0)?, channel(2)?, channel(4)?, alpha))
+    }
+
     pub fn black() -> Self {
         Color(
             u8::min_value(),
@@ -102,6 +338,17 @@ impl Default for Config {
             active_color: Color::white(),
             inactive_color: Color::black(),
             keyboard: Self::default_keyboard(),
+            filter: FilterKind::None,
+            persistence: Self::default_persistence(),
+            scaling: Scaling::Aspect,
+            palette: None,
+            quirks: None,
+            wrapping: Wrapping::Wrap,
+            protect_interpreter: false,
+            memory: None,
+            rom_database: None,
+            font: None,
+            rom_directory: None,
         }
     }
 }
@@ -111,8 +358,135 @@ mod tests {
     use super::*;
 
     #[test]
+    #[cfg(feature = "sdl")]
     fn test_get_default_keyboard() {
         let config: Config = Default::default();
         assert_eq!(config.get_keyboard().len(), 16);
     }
+
+    #[test]
+    fn test_filter_defaults() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "ticks_per_frame": 9,
+                "frames_per_second": 60,
+                "pixel_size": 20,
+                "active_color": [63, 191, 127, 1],
+                "inactive_color": [38, 114, 76, 1],
+                "keyboard": {}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.filter, FilterKind::None);
+        let config: Config = serde_json::from_str(
+            r#"{
+                "ticks_per_frame": 9,
+                "frames_per_second": 60,
+                "pixel_size": 20,
+                "active_color": [63, 191, 127, 1],
+                "inactive_color": [38, 114, 76, 1],
+                "keyboard": {},
+                "filter": "crt"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.filter, FilterKind::Crt);
+    }
+
+    #[test]
+    fn test_color_from_hex() {
+        assert_eq!(Color::from_hex("#3FBF7F"), Some(Color(63, 191, 127, 255)));
+        assert_eq!(Color::from_hex("#3fbf7f01"), Some(Color(63, 191, 127, 1)));
+        assert_eq!(Color::from_hex("3FBF7F"), None);
+        assert_eq!(Color::from_hex("#3FBF7"), None);
+        assert_eq!(Color::from_hex("#GGBF7F"), None);
+    }
+
+    #[test]
+    fn test_color_deserialize() {
+        let colors: Vec<Color> = serde_json::from_str(r##"[[1, 2, 3, 4], "#010203"]"##).unwrap();
+        assert_eq!(colors, vec![Color(1, 2, 3, 4), Color(1, 2, 3, 255)]);
+        assert!(serde_json::from_str::<Color>(r#""red""#).is_err());
+    }
+
+    #[test]
+    fn test_get_palette() {
+        let mut config: Config = Default::default();
+        assert_eq!(config.get_palette().active(), Color::white());
+        config.palette = Some(PaletteSetting::Preset(String::from("lcd")));
+        assert_eq!(config.get_palette(), Palette::preset("lcd").unwrap());
+        config.palette = Some(PaletteSetting::Colors(vec![Color::black(), Color::white()]));
+        assert_eq!(
+            config.get_palette().colors,
+            [Color::black(), Color::white()]
+        );
+    }
+
+    #[test]
+    fn test_get_quirks() {
+        let mut config: Config = Default::default();
+        assert_eq!(config.get_quirks(), Quirks::default());
+        config.quirks = serde_json::from_str(r#""cosmac-vip""#).unwrap();
+        assert_eq!(config.get_quirks(), Quirks::profile("cosmac-vip").unwrap());
+        config.quirks = serde_json::from_str(r#"{"jump_vx": true}"#).unwrap();
+        assert!(config.get_quirks().jump_vx);
+        assert!(!config.get_quirks().shift_vy);
+    }
+
+    #[test]
+    fn test_configure_memory() {
+        let config = Config {
+            wrapping: serde_json::from_str(r#"{"mirror": 2048}"#).unwrap(),
+            protect_interpreter: true,
+            ..Default::default()
+        };
+        let mut memory = Memory::new(&[]);
+        config.configure_memory(&mut memory);
+        assert_eq!(memory.wrapping, Wrapping::Mirror(2048));
+        assert!(memory.is_read_only(0x050));
+        assert!(!memory.is_read_only(0x200));
+    }
+
+    #[test]
+    fn test_apply_cartridge() {
+        let mut config: Config = Default::default();
+        let options: CartridgeOptions = serde_json::from_str(
+            r##"{"tickrate": 500, "fillColor": "#FF6600", "jumpQuirks": true, "fontStyle": "eti660"}"##,
+        )
+        .unwrap();
+        config.apply_cartridge(&options);
+        assert_eq!(config.ticks_per_frame, 255);
+        assert_eq!(config.get_palette().active(), Color(0xFF, 0x66, 0x00, 0xFF));
+        assert!(config.get_quirks().jump_vx);
+        assert_eq!(config.get_font(), Font::preset("eti-660").unwrap());
+    }
+
+    #[test]
+    fn test_get_font() {
+        let mut config: Config = Default::default();
+        assert_eq!(config.get_font(), Font::default());
+        config.font = serde_json::from_str(r#""cosmac-vip""#).unwrap();
+        assert_eq!(config.get_font(), Font::preset("cosmac-vip").unwrap());
+        let path = std::env::temp_dir().join("chip8-rust-test-font.bin");
+        std::fs::write(&path, [0xFF; 16 * 6]).unwrap();
+        config.font =
+            serde_json::from_value(serde_json::json!({"file": path, "height": 6})).unwrap();
+        let font = config.get_font();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(font.height, 6);
+        assert_eq!(font.len(), 96);
+    }
+
+    #[test]
+    fn test_get_memory_layout() {
+        let mut config: Config = Default::default();
+        let info: RomInfo =
+            serde_json::from_str(r#"{"title": "Game", "memory": {"program_start": 1536}}"#)
+                .unwrap();
+        assert_eq!(config.get_memory_layout(None), MemoryLayout::default());
+        assert_eq!(config.get_memory_layout(Some(&info)).program_start, 0x600);
+        config.memory = serde_json::from_str(r#"{"font_address": 80}"#).unwrap();
+        let layout = config.get_memory_layout(Some(&info));
+        assert_eq!((layout.program_start, layout.font_address), (0x200, 0x50));
+    }
 }
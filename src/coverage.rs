@@ -0,0 +1,309 @@
+use crate::analysis::{disassemble, Analysis, Flow};
+use crate::chip8::{memory::MemoryLayout, quirks::Quirks};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::{fs, io, path::Path};
+
+/// How often a skip instruction (SE, SNE, SKP or SKNP) went each way
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Branch {
+    /// The number of times it skipped the next instruction
+    pub skipped: u64,
+    /// The number of times it continued with the next instruction
+    pub continued: u64,
+}
+
+/// Keeps track of which instructions of a program are executed, and which way its
+/// skip instructions go, so tests can tell what they don't exercise.
+///
+/// The instructions a program could execute are found by static analysis, and
+/// anything executed that the analysis couldn't find (e.g. through `JP V0, addr`) is
+/// added to them.
+pub struct Coverage {
+    /// The opcodes of the instructions found by static analysis, by address
+    code: BTreeMap<usize, (u8, u8)>,
+    quirks: Quirks,
+    /// The number of times the instruction at each address the program counter can
+    /// reach was executed
+    counts: Vec<u64>,
+    /// The opcode last executed at each address
+    opcodes: Vec<(u8, u8)>,
+    /// Which way each skip instruction that was executed went, by address
+    branches: BTreeMap<usize, Branch>,
+}
+
+impl Coverage {
+    /// Measures the coverage of a program loaded into memory laid out as `layout`
+    pub fn new(program: &[u8], layout: &MemoryLayout, quirks: &Quirks) -> Self {
+        let analysis = Analysis::new(program, layout, quirks);
+        let code = analysis
+            .blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter())
+            .map(|line| {
+                let offset = line.address - analysis.entry;
+                (line.address, (program[offset], program[offset + 1]))
+            })
+            .collect();
+        Coverage {
+            code,
+            quirks: *quirks,
+            counts: vec![0; layout.code().end],
+            opcodes: vec![(0, 0); layout.code().end],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    /// Counts the instruction that was just executed at `address`, leaving the program
+    /// counter at `next`
+    pub fn record(&mut self, address: usize, opcode: (u8, u8), next: usize) {
+        let index = address % self.counts.len();
+        self.counts[index] += 1;
+        self.opcodes[index] = opcode;
+        if Flow::of(opcode, &self.quirks) == Flow::Skip {
+            let branch = self.branches.entry(address).or_default();
+            if next == address + 4 {
+                branch.skipped += 1;
+            } else {
+                branch.continued += 1;
+            }
+        }
+    }
+
+    /// Returns how many times the instruction at an address was executed
+    pub fn count(&self, address: usize) -> u64 {
+        self.counts[address % self.counts.len()]
+    }
+
+    /// Returns which way the skip instruction at an address went, if it was executed
+    pub fn branch(&self, address: usize) -> Option<Branch> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Returns the address and opcode of every instruction, in address order
+    fn instructions(&self) -> Vec<(usize, (u8, u8))> {
+        let mut instructions = self.code.clone();
+        for address in (0..self.counts.len()).filter(|x| self.counts[*x] > 0) {
+            instructions.insert(address, self.opcodes[address]);
+        }
+        instructions.into_iter().collect()
+    }
+
+    /// Returns a one-line summary of the instructions executed and the branches taken
+    pub fn summary(&self) -> String {
+        let instructions = self.instructions();
+        let executed = instructions
+            .iter()
+            .filter(|(address, _)| self.count(*address) > 0)
+            .count();
+        let skips = instructions
+            .iter()
+            .filter(|(_, opcode)| Flow::of(*opcode, &self.quirks) == Flow::Skip)
+            .count();
+        let taken: usize = self
+            .branches
+            .values()
+            .map(|x| (x.skipped > 0) as usize + (x.continued > 0) as usize)
+            .sum();
+        format!(
+            "Executed {} of {} instructions ({:.1}%) and took {} of {} branches ({:.1}%)",
+            executed,
+            instructions.len(),
+            100.0 * executed as f64 / instructions.len().max(1) as f64,
+            taken,
+            skips * 2,
+            100.0 * taken as f64 / (skips * 2).max(1) as f64
+        )
+    }
+
+    /// Returns the disassembly of the program with the number of times each instruction
+    /// was executed, marking those never executed with `#####` like gcov does.
+    /// Each instruction is on its own line, which the lcov report refers to.
+    pub fn listing(&self) -> String {
+        let mut listing = String::new();
+        for (address, opcode) in self.instructions() {
+            let count = match self.count(address) {
+                0 => String::from("#####"),
+                count => count.to_string(),
+            };
+            write!(
+                listing,
+                "{:>10}  {:03X}: {:02X}{:02X}  {}",
+                count,
+                address,
+                opcode.0,
+                opcode.1,
+                disassemble(opcode)
+            )
+            .unwrap();
+            if let Some(branch) = self.branch(address) {
+                write!(
+                    listing,
+                    "  (skipped {}, continued {})",
+                    branch.skipped, branch.continued
+                )
+                .unwrap();
+            }
+            listing.push('\n');
+        }
+        listing
+    }
+
+    /// Returns an lcov tracefile for the listing at `listing_path`, with a line for each
+    /// instruction and a pair of branches for each skip instruction
+    pub fn lcov(&self, listing_path: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", listing_path);
+        let (mut branches, mut branches_hit) = (0, 0);
+        let (mut lines, mut lines_hit) = (0, 0);
+        for (index, (address, opcode)) in self.instructions().into_iter().enumerate() {
+            let line = index + 1;
+            if Flow::of(opcode, &self.quirks) == Flow::Skip {
+                let taken = match self.branch(address) {
+                    Some(branch) => [branch.skipped.to_string(), branch.continued.to_string()],
+                    None => [String::from("-"), String::from("-")],
+                };
+                for (number, taken) in taken.iter().enumerate() {
+                    writeln!(lcov, "BRDA:{},0,{},{}", line, number, taken).unwrap();
+                    branches += 1;
+                    if taken != "-" && taken != "0" {
+                        branches_hit += 1;
+                    }
+                }
+            }
+            let count = self.count(address);
+            writeln!(lcov, "DA:{},{}", line, count).unwrap();
+            lines += 1;
+            if count > 0 {
+                lines_hit += 1;
+            }
+        }
+        writeln!(lcov, "BRF:{}\nBRH:{}", branches, branches_hit).unwrap();
+        writeln!(lcov, "LF:{}\nLH:{}\nend_of_record", lines, lines_hit).unwrap();
+        lcov
+    }
+
+    /// Writes the annotated listing to `<path>.lst` and the lcov report to `<path>.info`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let listing_path = path.with_extension("lst");
+        fs::write(&listing_path, self.listing())?;
+        fs::write(
+            path.with_extension("info"),
+            self.lcov(&listing_path.to_string_lossy()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::State;
+    use crate::emulator::Emulator;
+
+    const PROGRAM: [u8; 10] = [
+        0x70, 0x01, // 200: ADD V0, 1
+        0x30, 0x02, // 202: SE V0, 2
+        0x12, 0x00, // 204: JP 0x200
+        0x12, 0x06, // 206: JP 0x206
+        0x61, 0x00, // 208: LD V1, 0 (never reached)
+    ];
+
+    /// Runs a program for `steps` instructions, measuring its coverage
+    fn cover(program: &[u8], steps: usize) -> Coverage {
+        cover_with_layout(program, MemoryLayout::default(), steps)
+    }
+
+    /// Measures the coverage of a program like `cover`, in memory laid out as `layout`
+    fn cover_with_layout(program: &[u8], layout: MemoryLayout, steps: usize) -> Coverage {
+        let mut emulator = Emulator::new(State::mock_with_layout(program, layout), false);
+        emulator.coverage = Some(Coverage::new(program, &layout, &Quirks::default()));
+        for _ in 0..steps {
+            emulator.step().unwrap();
+        }
+        emulator.coverage.unwrap()
+    }
+
+    #[test]
+    fn test_record() {
+        let coverage = cover(&PROGRAM, 6);
+        assert_eq!(coverage.count(0x200), 2);
+        assert_eq!(coverage.count(0x206), 1);
+        assert_eq!(
+            coverage.branch(0x202),
+            Some(Branch {
+                skipped: 1,
+                continued: 1
+            })
+        );
+        assert_eq!(coverage.branch(0x200), None);
+        assert_eq!(
+            coverage.summary(),
+            "Executed 4 of 4 instructions (100.0%) and took 2 of 2 branches (100.0%)"
+        );
+    }
+
+    #[test]
+    fn test_listing() {
+        let coverage = cover(&PROGRAM, 2);
+        assert_eq!(
+            coverage.listing(),
+            concat!(
+                "         1  200: 7001  ADD V0, 0x01\n",
+                "         1  202: 3002  SE V0, 0x02  (skipped 0, continued 1)\n",
+                "     #####  204: 1200  JP 0x200\n",
+                "     #####  206: 1206  JP 0x206\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_lcov() {
+        let coverage = cover(&PROGRAM, 2);
+        assert_eq!(
+            coverage.lcov("game.lst"),
+            concat!(
+                "TN:\nSF:game.lst\n",
+                "DA:1,1\n",
+                "BRDA:2,0,0,0\nBRDA:2,0,1,1\nDA:2,1\n",
+                "DA:3,0\nDA:4,0\n",
+                "BRF:2\nBRH:1\nLF:4\nLH:2\nend_of_record\n"
+            )
+        );
+        let unexecuted = Coverage::new(&PROGRAM, &MemoryLayout::default(), &Quirks::default());
+        assert!(unexecuted.lcov("game.lst").contains("BRDA:2,0,0,-\n"));
+    }
+
+    #[test]
+    fn test_memory_layout() {
+        let layout = MemoryLayout {
+            program_start: 0x600,
+            ..MemoryLayout::default()
+        };
+        let program = [
+            0x70, 0x01, // 600: ADD V0, 1
+            0x16, 0x00, // 602: JP 0x600
+        ];
+        let coverage = cover_with_layout(&program, layout, 3);
+        assert_eq!(
+            coverage.listing(),
+            concat!(
+                "         2  600: 7001  ADD V0, 0x01\n",
+                "         1  602: 1600  JP 0x600\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_code_outside_the_analysis() {
+        let program = [
+            0x60, 0x04, // 200: LD V0, 4
+            0xB2, 0x02, // 202: JP V0, 0x202
+            0x00, 0xE0, // 204: never executed
+            0x12, 0x06, // 206: JP 0x206
+        ];
+        let coverage = cover(&program, 3);
+        assert!(coverage
+            .listing()
+            .ends_with("         1  206: 1206  JP 0x206\n"));
+        assert!(!coverage.listing().contains("204:"));
+    }
+}
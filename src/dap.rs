@@ -0,0 +1,1155 @@
+use crate::chip8::{
+    display::Display, keyboard::HeadlessKeyboard, memory::MemoryLayout, quirks::Quirks, State,
+};
+use crate::config::Config;
+use crate::emulator::Emulator;
+use crate::rom::{self, Rom, RomError};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// The location of an instruction in the program's source code
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub path: String,
+    pub line: u64,
+}
+
+/// Maps the addresses of a program back to the assembler source it was built from.
+///
+/// Line map files have one entry per line: a hex address followed by either
+/// `PATH:LINE` for the source line of the instruction at that address, or a label
+/// for a symbol. Blank lines and lines starting with `#` are ignored:
+///
+/// ```text
+/// # pong.8o
+/// 200 main
+/// 200 pong.8o:12
+/// 202 pong.8o:13
+/// ```
+#[derive(Debug, Default)]
+pub struct LineMap {
+    lines: BTreeMap<usize, SourceLine>,
+    symbols: BTreeMap<usize, String>,
+}
+
+impl LineMap {
+    pub fn parse(text: &str) -> Result<LineMap, String> {
+        let mut map = LineMap::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || format!("Line {} of the line map is malformed: {}", index + 1, line);
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let address = parts
+                .next()
+                .and_then(|x| usize::from_str_radix(x.trim_start_matches("0x"), 16).ok())
+                .ok_or_else(error)?;
+            let target = parts.next().map(str::trim).ok_or_else(error)?;
+            match target.rfind(':') {
+                Some(colon) => {
+                    let line = target[colon + 1..].parse().map_err(|_| error())?;
+                    let path = String::from(&target[..colon]);
+                    map.lines.insert(address, SourceLine { path, line });
+                }
+                None => {
+                    map.symbols.insert(address, String::from(target));
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Reads a line map file. Relative source paths in it are relative to its directory.
+    pub fn from_file(path: &str) -> Result<LineMap, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the line map at {}: {}", path, e))?;
+        let mut map = LineMap::parse(&text)?;
+        let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        for line in map.lines.values_mut() {
+            line.path = directory.join(&line.path).to_string_lossy().into_owned();
+        }
+        Ok(map)
+    }
+
+    /// Returns the source line of the instruction at `address`, if it has one
+    pub fn line(&self, address: usize) -> Option<&SourceLine> {
+        self.lines.get(&address)
+    }
+
+    /// Returns the lowest address of an instruction on a source line
+    pub fn address(&self, path: &str, line: u64) -> Option<usize> {
+        self.lines
+            .iter()
+            .find(|(_, x)| x.line == line && paths_match(&x.path, path))
+            .map(|(address, _)| *address)
+    }
+
+    /// Returns the closest symbol at or before `address`
+    pub fn symbol(&self, address: usize) -> Option<&str> {
+        self.symbols
+            .range(..=address)
+            .next_back()
+            .map(|(_, x)| x.as_str())
+    }
+}
+
+/// Returns true if two paths refer to the same file. Line maps often contain relative
+/// paths while debuggers send absolute ones, so one only has to end with the other.
+fn paths_match(a: &str, b: &str) -> bool {
+    let (short, long) = if a.len() < b.len() { (a, b) } else { (b, a) };
+    long.ends_with(short)
+        && (long.len() == short.len() || long[..long.len() - short.len()].ends_with(['/', '\\']))
+}
+
+/// Reads one Debug Adapter Protocol message. Returns None at the end of the input.
+pub fn read_message(reader: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length: usize = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Message has no Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes one Debug Adapter Protocol message
+pub fn write_message(writer: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    let mut packet = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    packet.extend(body.as_bytes());
+    writer.write_all(&packet)?;
+    writer.flush()
+}
+
+/// Reads messages on another thread, so requests like `pause` can be handled while
+/// the program is running
+pub fn spawn_reader<R: BufRead + Send + 'static>(mut reader: R) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (index, x)| {
+            value | (u32::from(*x) << (16 - 8 * index))
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                result.push(BASE64[((value >> (18 - 6 * index)) & 0x3F) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn from_base64(text: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut value = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|x| *x != b'=') {
+        value = (value << 6 | BASE64.iter().position(|x| *x == c)? as u32) & 0xFF_FFFF;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((value >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+/// Parses a memory reference like `0x200` plus an offset into an address
+fn parse_address(reference: &str, offset: i64) -> Option<usize> {
+    let base = i64::from_str_radix(reference.trim_start_matches("0x"), 16).ok()?;
+    let address = base + offset;
+    if address < 0 {
+        None
+    } else {
+        Some(address as usize)
+    }
+}
+
+/// The variables references of the scopes shown for every stack frame
+const REGISTERS_SCOPE: u64 = 1;
+const TIMERS_SCOPE: u64 = 2;
+const STACK_SCOPE: u64 = 3;
+
+/// The program has one thread, which is always this one
+const THREAD_ID: u64 = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum StepMode {
+    /// Stop at the next line, even if it's in a subroutine
+    In,
+    /// Stop at the next line in the same subroutine or its caller
+    Over,
+    /// Stop once the current subroutine returns
+    Out,
+}
+
+/// A step the debugger asked for, which runs until it is done
+#[derive(Debug, Clone)]
+struct Step {
+    mode: StepMode,
+    /// The line the step started on. Steps without one only go one instruction.
+    start_line: Option<SourceLine>,
+    /// The number of return addresses on the stack when the step started
+    depth: usize,
+}
+
+impl Step {
+    fn is_done(&self, state: &State, line_map: &LineMap) -> bool {
+        let depth = state.stack.contents().len();
+        match self.mode {
+            StepMode::Out => return depth < self.depth,
+            StepMode::Over if depth > self.depth => return false,
+            _ => {}
+        }
+        match &self.start_line {
+            None => true,
+            Some(start) => match line_map.line(state.program_counter) {
+                Some(line) => line != start,
+                None => false,
+            },
+        }
+    }
+}
+
+/// What the server should do after answering a request
+#[derive(Debug, PartialEq)]
+enum Action {
+    Continue,
+    Stop,
+}
+
+/// A Debug Adapter Protocol server, which lets editors debug chip-8 programs.
+///
+/// Programs are launched by the editor and run without a window, like headless runs,
+/// with the settings of the config file and those the program came with.
+/// Stepping works by source line when a line map is given, and by instruction otherwise.
+pub struct DapServer<W: Write> {
+    requests: Receiver<Value>,
+    output: W,
+    /// The sequence number of the next message sent
+    seq: u64,
+    config: Config,
+    /// The quirks to run with whatever the config file or the program say, if any
+    quirks: Option<Quirks>,
+    /// The number of instructions executed each frame by the launched program
+    ticks_per_frame: u8,
+    emulator: Option<Emulator>,
+    line_map: LineMap,
+    /// The breakpoint addresses set in each source file
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    instruction_breakpoints: HashSet<usize>,
+    stop_on_entry: bool,
+    running: bool,
+    /// The step in progress, if the program is running because of one
+    step: Option<Step>,
+    /// Whether to ignore a breakpoint at the next instruction, so resuming from one works
+    skip_breakpoint: bool,
+    /// The number of instructions executed in the current frame
+    ticks: u8,
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(
+        requests: Receiver<Value>,
+        output: W,
+        config: Config,
+        quirks: Option<Quirks>,
+    ) -> Self {
+        DapServer {
+            requests,
+            output,
+            seq: 1,
+            ticks_per_frame: config.ticks_per_frame,
+            config,
+            quirks,
+            emulator: None,
+            line_map: LineMap::default(),
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: HashSet::new(),
+            stop_on_entry: false,
+            running: false,
+            step: None,
+            skip_breakpoint: false,
+            ticks: 0,
+        }
+    }
+
+    /// Answers requests until the editor disconnects, running the program in between
+    pub fn serve(&mut self) -> io::Result<()> {
+        loop {
+            let request = if self.running {
+                match self.requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match self.requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+            match request {
+                Some(request) => {
+                    if self.handle_request(&request)? == Action::Stop {
+                        return Ok(());
+                    }
+                }
+                None => self.run_frame()?,
+            }
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        write_message(&mut self.output, &message)
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send_stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.send_event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )
+    }
+
+    fn handle_request(&mut self, request: &Value) -> io::Result<Action> {
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsSteppingGranularity": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => Ok(json!({})),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "chip8" }] })),
+            "stackTrace" => self.with_emulator(|server, emulator| server.stack_trace(emulator)),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_SCOPE, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS_SCOPE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_SCOPE, "expensive": false },
+            ] })),
+            "variables" => self.with_emulator(|_, emulator| {
+                Ok(json!({ "variables": variables(emulator, arguments["variablesReference"].as_u64()) }))
+            }),
+            "readMemory" => {
+                self.with_emulator(|_, emulator| read_memory(emulator, arguments))
+            }
+            "writeMemory" => {
+                let emulator = self.emulator.as_mut();
+                match emulator {
+                    Some(emulator) => write_memory(emulator, arguments),
+                    None => Err(String::from("No program has been launched")),
+                }
+            }
+            "continue" => {
+                self.resume(None);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let mode = match command {
+                    "next" => StepMode::Over,
+                    "stepIn" => StepMode::In,
+                    _ => StepMode::Out,
+                };
+                let by_instruction = arguments["granularity"] == "instruction";
+                self.start_step(mode, by_instruction);
+                Ok(json!({}))
+            }
+            "pause" => Ok(json!({})),
+            "disconnect" | "terminate" => Ok(json!({})),
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+        let response = match result {
+            Ok(body) => json!({
+                "type": "response",
+                "request_seq": request["seq"],
+                "success": true,
+                "command": command,
+                "body": body,
+            }),
+            Err(message) => json!({
+                "type": "response",
+                "request_seq": request["seq"],
+                "success": false,
+                "command": command,
+                "message": message,
+            }),
+        };
+        let success = response["success"] == true;
+        self.send(response)?;
+
+        // Some requests have to be followed by events once they've been answered
+        match command {
+            "launch" if success => self.send_event("initialized", json!({}))?,
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.send_stopped("entry")?;
+                } else {
+                    self.resume(None);
+                }
+            }
+            "pause" if self.running => {
+                self.running = false;
+                self.step = None;
+                self.send_stopped("pause")?;
+            }
+            "terminate" => {
+                self.send_event("terminated", json!({}))?;
+                return Ok(Action::Stop);
+            }
+            "disconnect" => return Ok(Action::Stop),
+            _ => {}
+        }
+        Ok(Action::Continue)
+    }
+
+    /// Calls `f` with the launched emulator, or fails if nothing has been launched yet
+    fn with_emulator<F>(&mut self, f: F) -> Result<Value, String>
+    where
+        F: FnOnce(&Self, &Emulator) -> Result<Value, String>,
+    {
+        match &self.emulator {
+            Some(emulator) => f(self, emulator),
+            None => Err(String::from("No program has been launched")),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or("The launch configuration needs a program")?;
+        let entry = arguments["entry"].as_str();
+        let memory: Option<MemoryLayout> = match arguments.get("memory") {
+            Some(memory) => Some(
+                serde_json::from_value(memory.clone())
+                    .map_err(|e| format!("Invalid memory layout: {}", e))?,
+            ),
+            None => None,
+        };
+        // Source is assembled where programs start before the database can be looked up
+        let origin = memory
+            .unwrap_or_else(|| self.config.get_memory_layout(None))
+            .program_start;
+        let rom = rom::read(path)
+            .and_then(|bytes| {
+                Rom::load(
+                    &bytes,
+                    path,
+                    origin,
+                    &mut |entries: &[String]| match entry {
+                        Some(name) => entries.iter().position(|x| x == name).ok_or_else(|| {
+                            RomError::new(&format!("The archive has no entry named {}", name))
+                        }),
+                        None => Err(RomError::new(
+                            "The archive has several programs, so choose one with entry",
+                        )),
+                    },
+                )
+            })
+            .map_err(|e| format!("Could not load the program at {}: {}", path, e))?;
+        if let Some(line_map) = arguments["lineMap"].as_str() {
+            self.line_map = LineMap::from_file(line_map)?;
+        }
+        self.stop_on_entry = arguments["stopOnEntry"] == true;
+
+        // The config file's settings, overridden by the program's and then the launch's
+        let mut config = self.config.clone();
+        if let Some(options) = &rom.options {
+            config.apply_cartridge(options);
+        }
+        let quirks = self.quirks.unwrap_or_else(|| config.get_quirks());
+        let layout = memory.unwrap_or_else(|| {
+            config.get_memory_layout(config.get_database().lookup(&rom.program))
+        });
+        layout
+            .check(&rom.program, &config.get_font())
+            .map_err(|e| e.to_string())?;
+        let palette = config.get_palette();
+        let display = Display::new(palette.active(), palette.inactive());
+        let keyboard = Box::new(HeadlessKeyboard::new());
+        let mut state = State::with_layout(&rom.program, keyboard, display, layout);
+        state.quirks = quirks;
+        config.configure_memory(&mut state.memory);
+        self.ticks_per_frame = config.ticks_per_frame;
+        self.emulator = Some(Emulator::new(state, false));
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+        let path = arguments["source"]["path"].as_str().unwrap_or("");
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or(0);
+            match self.line_map.address(path, line) {
+                Some(address) => {
+                    addresses.push(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("0x{:03X}", address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "There is no instruction on this line",
+                })),
+            }
+        }
+        self.source_breakpoints
+            .insert(String::from(path), addresses);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        self.instruction_breakpoints.clear();
+        let code_end = self
+            .emulator
+            .as_ref()
+            .map_or(MemoryLayout::default(), |x| x.state.memory.layout())
+            .code()
+            .end;
+        let mut breakpoints = Vec::new();
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for breakpoint in requested {
+            let address = breakpoint["instructionReference"]
+                .as_str()
+                .and_then(|x| parse_address(x, breakpoint["offset"].as_i64().unwrap_or(0)))
+                .filter(|x| *x < code_end);
+            if let Some(address) = address {
+                self.instruction_breakpoints.insert(address);
+            }
+            breakpoints.push(json!({ "verified": address.is_some() }));
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn is_breakpoint(&self, address: usize) -> bool {
+        self.instruction_breakpoints.contains(&address)
+            || self
+                .source_breakpoints
+                .values()
+                .any(|addresses| addresses.contains(&address))
+    }
+
+    fn stack_trace(&self, emulator: &Emulator) -> Result<Value, String> {
+        let state = &emulator.state;
+        // The current instruction, then the calls that led to it
+        let addresses = std::iter::once(state.program_counter)
+            .chain(state.stack.contents().iter().rev().cloned());
+        let frames: Vec<Value> = addresses
+            .enumerate()
+            .map(|(id, address)| {
+                let name = match self.line_map.symbol(address) {
+                    Some(symbol) => String::from(symbol),
+                    None => format!("0x{:03X}", address),
+                };
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:03X}", address),
+                });
+                if let Some(line) = self.line_map.line(address) {
+                    frame["line"] = json!(line.line);
+                    frame["source"] = json!({ "path": line.path });
+                }
+                frame
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    /// Starts running the program, stopping at the end of `step` if given
+    fn resume(&mut self, step: Option<Step>) {
+        if self.emulator.is_some() {
+            self.running = true;
+            self.step = step;
+            self.skip_breakpoint = true;
+        }
+    }
+
+    fn start_step(&mut self, mode: StepMode, by_instruction: bool) {
+        let state = match &self.emulator {
+            Some(emulator) => &emulator.state,
+            None => return,
+        };
+        let start_line = if by_instruction {
+            None
+        } else {
+            self.line_map.line(state.program_counter).cloned()
+        };
+        let step = Step {
+            mode,
+            start_line,
+            depth: state.stack.contents().len(),
+        };
+        self.resume(Some(step));
+    }
+
+    /// Runs the rest of the current frame, stopping early at breakpoints and finished steps
+    fn run_frame(&mut self) -> io::Result<()> {
+        let mut emulator = self.emulator.take().unwrap();
+        let result = self.run_frame_on(&mut emulator);
+        self.emulator = Some(emulator);
+        result
+    }
+
+    fn run_frame_on(&mut self, emulator: &mut Emulator) -> io::Result<()> {
+        loop {
+            if emulator.is_finished() {
+                self.running = false;
+                self.send_event("exited", json!({ "exitCode": 0 }))?;
+                return self.send_event("terminated", json!({}));
+            }
+            let pc = emulator.state.program_counter;
+            if !self.skip_breakpoint && self.is_breakpoint(pc) {
+                self.running = false;
+                self.step = None;
+                return self.send_stopped("breakpoint");
+            }
+            self.skip_breakpoint = false;
+            if let Err(error) = emulator.step() {
+                self.running = false;
+                self.step = None;
+                return self.send_event(
+                    "stopped",
+                    json!({
+                        "reason": "exception",
+                        "description": error.to_string(),
+                        "text": error.to_string(),
+                        "threadId": THREAD_ID,
+                        "allThreadsStopped": true,
+                    }),
+                );
+            }
+            self.ticks += 1;
+            let frame_ended = self.ticks >= self.ticks_per_frame;
+            if frame_ended {
+                self.ticks = 0;
+                emulator.end_frame();
+            }
+            let step_done = match &self.step {
+                Some(step) => step.is_done(&emulator.state, &self.line_map),
+                None => false,
+            };
+            if step_done {
+                self.running = false;
+                self.step = None;
+                return self.send_stopped("step");
+            }
+            if frame_ended {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn hex_variable(name: &str, value: usize, digits: usize) -> Value {
+    json!({
+        "name": name,
+        "value": format!("0x{:0width$X} ({})", value, value, width = digits),
+        "variablesReference": 0,
+    })
+}
+
+/// Returns the variables in one of the scopes
+fn variables(emulator: &Emulator, scope: Option<u64>) -> Vec<Value> {
+    let state = &emulator.state;
+    match scope {
+        Some(REGISTERS_SCOPE) => {
+            let mut result: Vec<Value> = state
+                .registers
+                .v_registers
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    hex_variable(&format!("V{:X}", index), usize::from(*value), 2)
+                })
+                .collect();
+            let mut i = hex_variable("I", usize::from(state.registers.i_register), 3);
+            i["memoryReference"] = json!(format!("0x{:03X}", state.registers.i_register));
+            result.push(i);
+            let mut pc = hex_variable("PC", state.program_counter, 3);
+            pc["memoryReference"] = json!(format!("0x{:03X}", state.program_counter));
+            result.push(pc);
+            result
+        }
+        Some(TIMERS_SCOPE) => vec![
+            hex_variable("DT", usize::from(state.timers.delay_timer), 2),
+            hex_variable("ST", usize::from(state.timers.sound_timer), 2),
+        ],
+        Some(STACK_SCOPE) => state
+            .stack
+            .contents()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, address)| {
+                let mut variable = hex_variable(&format!("[{}]", index), *address, 3);
+                variable["memoryReference"] = json!(format!("0x{:03X}", address));
+                variable
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn read_memory(emulator: &Emulator, arguments: &Value) -> Result<Value, String> {
+    let address = arguments["memoryReference"]
+        .as_str()
+        .and_then(|x| parse_address(x, arguments["offset"].as_i64().unwrap_or(0)))
+        .ok_or("Invalid memory reference")?;
+    let count = arguments["count"].as_u64().unwrap_or(0) as usize;
+    let ram = emulator.state.memory.ram();
+    let start = address.min(ram.len());
+    let end = address.saturating_add(count).min(ram.len());
+    Ok(json!({
+        "address": format!("0x{:03X}", address),
+        "data": to_base64(&ram[start..end]),
+        "unreadableBytes": count - (end - start),
+    }))
+}
+
+fn write_memory(emulator: &mut Emulator, arguments: &Value) -> Result<Value, String> {
+    let address = arguments["memoryReference"]
+        .as_str()
+        .and_then(|x| parse_address(x, arguments["offset"].as_i64().unwrap_or(0)))
+        .ok_or("Invalid memory reference")?;
+    let data = arguments["data"]
+        .as_str()
+        .and_then(from_base64)
+        .ok_or("Invalid data")?;
+    let ram = emulator.state.memory.ram_mut();
+    match address.checked_add(data.len()) {
+        Some(end) if end <= ram.len() => ram[address..end].copy_from_slice(&data),
+        _ => return Err(String::from("The data doesn't fit in memory")),
+    }
+    Ok(json!({ "bytesWritten": data.len() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, io::Cursor, path::PathBuf, sync::mpsc::Sender};
+
+    /// Collects the messages the server writes
+    struct Outbox(Sender<Vec<u8>>);
+
+    impl Write for Outbox {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0.send(buffer.to_vec()).unwrap();
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A minimal editor, talking to a server on another thread
+    struct Client {
+        requests: Sender<Value>,
+        messages: Receiver<Vec<u8>>,
+        seq: u64,
+    }
+
+    impl Client {
+        fn next_message(&mut self) -> Value {
+            let bytes = self.messages.recv().unwrap();
+            read_message(&mut Cursor::new(bytes)).unwrap().unwrap()
+        }
+
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            self.requests
+                .send(json!({
+                    "seq": self.seq,
+                    "type": "request",
+                    "command": command,
+                    "arguments": arguments,
+                }))
+                .unwrap();
+            let response = self.next_message();
+            assert_eq!(response["type"], "response");
+            assert_eq!(response["request_seq"], self.seq);
+            response
+        }
+
+        fn expect_event(&mut self, event: &str) -> Value {
+            let message = self.next_message();
+            assert_eq!(message["event"], event, "{}", message);
+            message["body"].clone()
+        }
+
+        fn variable(&mut self, scope: u64, name: &str) -> String {
+            let response = self.request("variables", json!({ "variablesReference": scope }));
+            let variables = response["body"]["variables"].as_array().unwrap().clone();
+            let variable = variables.iter().find(|x| x["name"] == name).unwrap();
+            String::from(variable["value"].as_str().unwrap())
+        }
+    }
+
+    /// Writes a test program and its line map, returning their paths
+    fn write_program(name: &str, program: &[u8], line_map: &str) -> (PathBuf, PathBuf) {
+        let directory = env::temp_dir().join(format!("chip8_test_dap_{}", name));
+        fs::create_dir_all(&directory).unwrap();
+        let program_path = directory.join("program.ch8");
+        let map_path = directory.join("program.map");
+        fs::write(&program_path, program).unwrap();
+        fs::write(&map_path, line_map).unwrap();
+        (program_path, map_path)
+    }
+
+    /// Runs a server while `session` talks to it from another thread
+    fn debug<F>(session: F)
+    where
+        F: FnOnce(&mut Client) + Send + 'static,
+    {
+        let mut config = Config::default();
+        config.ticks_per_frame = 2;
+        debug_with_config(config, session);
+    }
+
+    /// Runs a server with the settings of `config`, like `debug`
+    fn debug_with_config<F>(config: Config, session: F)
+    where
+        F: FnOnce(&mut Client) + Send + 'static,
+    {
+        let (requests, receiver) = mpsc::channel();
+        let (sender, messages) = mpsc::channel();
+        let client = thread::spawn(move || {
+            let mut client = Client {
+                requests,
+                messages,
+                seq: 0,
+            };
+            session(&mut client);
+        });
+        DapServer::new(receiver, Outbox(sender), config, None)
+            .serve()
+            .unwrap();
+        client.join().unwrap();
+    }
+
+    /// Initializes the server and launches `program` with `line_map`, stopped on entry
+    fn launch(client: &mut Client, name: &str, program: &[u8], line_map: &str) -> PathBuf {
+        let (program_path, map_path) = write_program(name, program, line_map);
+        let response = client.request("initialize", json!({ "adapterID": "chip8" }));
+        assert_eq!(response["body"]["supportsConfigurationDoneRequest"], true);
+        let response = client.request(
+            "launch",
+            json!({
+                "program": program_path.to_str().unwrap(),
+                "lineMap": map_path.to_str().unwrap(),
+                "stopOnEntry": true,
+            }),
+        );
+        assert_eq!(response["success"], true, "{}", response);
+        client.expect_event("initialized");
+        program_path.parent().unwrap().join("game.8o")
+    }
+
+    // main: LD V0, 1      (line 1)
+    //       CALL sub      (line 2)
+    //       JP main       (line 3)
+    // sub:  ADD V0, 1     (line 5)
+    //       RET           (line 6)
+    const PROGRAM: [u8; 10] = [0x60, 0x01, 0x22, 0x06, 0x12, 0x00, 0x70, 0x01, 0x00, 0xEE];
+    const LINE_MAP: &str = "# game.8o\n\
+                            200 main\n\
+                            200 game.8o:1\n\
+                            202 game.8o:2\n\
+                            204 game.8o:3\n\
+                            206 sub\n\
+                            206 game.8o:5\n\
+                            208 game.8o:6\n";
+
+    #[test]
+    fn test_line_map() {
+        let map = LineMap::parse(LINE_MAP).unwrap();
+        assert_eq!(
+            map.line(0x202),
+            Some(&SourceLine {
+                path: String::from("game.8o"),
+                line: 2
+            })
+        );
+        assert_eq!(map.line(0x203), None);
+        assert_eq!(map.address("/home/me/roms/game.8o", 5), Some(0x206));
+        assert_eq!(map.address("/home/me/roms/other_game.8o", 5), None);
+        assert_eq!(map.symbol(0x204), Some("main"));
+        assert_eq!(map.symbol(0x208), Some("sub"));
+        assert_eq!(map.symbol(0x100), None);
+        assert!(LineMap::parse("xyz game.8o:1").is_err());
+        assert!(LineMap::parse("200 game.8o:x").is_err());
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(buffer, b"Content-Length: 9\r\n\r\n{\"seq\":1}");
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "seq": 1 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_base64() {
+        for data in [&b""[..], b"a", b"ab", b"abc", b"\x00\xFF\x10\x20"].iter() {
+            assert_eq!(from_base64(&to_base64(data)).unwrap(), *data);
+        }
+        assert_eq!(to_base64(b"chip8"), "Y2hpcDg=");
+        assert_eq!(from_base64("!"), None);
+    }
+
+    #[test]
+    fn test_breakpoints_and_stack() {
+        debug(|client| {
+            let source = launch(client, "breakpoints", &PROGRAM, LINE_MAP);
+            let response = client.request(
+                "setBreakpoints",
+                json!({
+                    "source": { "path": source.to_str().unwrap() },
+                    "breakpoints": [{ "line": 5 }, { "line": 4 }],
+                }),
+            );
+            let breakpoints = &response["body"]["breakpoints"];
+            assert_eq!(breakpoints[0]["verified"], true);
+            assert_eq!(breakpoints[1]["verified"], false);
+            client.request("configurationDone", json!({}));
+            assert_eq!(client.expect_event("stopped")["reason"], "entry");
+
+            client.request("continue", json!({ "threadId": THREAD_ID }));
+            assert_eq!(client.expect_event("stopped")["reason"], "breakpoint");
+            let response = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+            let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[0]["name"], "sub");
+            assert_eq!(frames[0]["line"], 5);
+            assert_eq!(frames[0]["source"]["path"], source.to_str().unwrap());
+            assert_eq!(frames[1]["name"], "main");
+            assert_eq!(frames[1]["line"], 2);
+            assert_eq!(client.variable(REGISTERS_SCOPE, "V0"), "0x01 (1)");
+            assert_eq!(client.variable(REGISTERS_SCOPE, "PC"), "0x206 (518)");
+            assert_eq!(client.variable(STACK_SCOPE, "[0]"), "0x202 (514)");
+
+            // Hits the breakpoint again on the next loop
+            client.request("continue", json!({ "threadId": THREAD_ID }));
+            assert_eq!(client.expect_event("stopped")["reason"], "breakpoint");
+            assert_eq!(client.variable(REGISTERS_SCOPE, "V0"), "0x01 (1)");
+            client.request("disconnect", json!({}));
+        });
+    }
+
+    #[test]
+    fn test_stepping() {
+        debug(|client| {
+            launch(client, "stepping", &PROGRAM, LINE_MAP);
+            client.request("configurationDone", json!({}));
+            client.expect_event("stopped");
+            let line = |client: &mut Client| {
+                let response = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+                response["body"]["stackFrames"][0]["line"].as_u64().unwrap()
+            };
+
+            client.request("next", json!({ "threadId": THREAD_ID }));
+            assert_eq!(client.expect_event("stopped")["reason"], "step");
+            assert_eq!(line(client), 2);
+            // Steps over the subroutine
+            client.request("next", json!({ "threadId": THREAD_ID }));
+            client.expect_event("stopped");
+            assert_eq!(line(client), 3);
+            assert_eq!(client.variable(REGISTERS_SCOPE, "V0"), "0x02 (2)");
+
+            client.request("next", json!({ "threadId": THREAD_ID }));
+            client.expect_event("stopped");
+            client.request("next", json!({ "threadId": THREAD_ID }));
+            client.expect_event("stopped");
+            client.request("stepIn", json!({ "threadId": THREAD_ID }));
+            client.expect_event("stopped");
+            assert_eq!(line(client), 5);
+            client.request("stepOut", json!({ "threadId": THREAD_ID }));
+            client.expect_event("stopped");
+            assert_eq!(client.variable(REGISTERS_SCOPE, "PC"), "0x204 (516)");
+
+            client.request(
+                "stepIn",
+                json!({ "threadId": THREAD_ID, "granularity": "instruction" }),
+            );
+            client.expect_event("stopped");
+            assert_eq!(client.variable(REGISTERS_SCOPE, "PC"), "0x200 (512)");
+            client.request("terminate", json!({}));
+            client.expect_event("terminated");
+        });
+    }
+
+    #[test]
+    fn test_memory_and_pause() {
+        debug(|client| {
+            launch(client, "memory", &[0x12, 0x00], "");
+            client.request("configurationDone", json!({}));
+            client.expect_event("stopped");
+            let response = client.request(
+                "writeMemory",
+                json!({ "memoryReference": "0x300", "offset": 2, "data": to_base64(b"hi") }),
+            );
+            assert_eq!(response["body"]["bytesWritten"], 2);
+            let response = client.request(
+                "readMemory",
+                json!({ "memoryReference": "0x302", "count": 2 }),
+            );
+            assert_eq!(response["body"]["data"], to_base64(b"hi"));
+            let response = client.request(
+                "readMemory",
+                json!({ "memoryReference": "0xFFF", "count": 4 }),
+            );
+            assert_eq!(response["body"]["unreadableBytes"], 3);
+
+            // The program loops forever until it is paused
+            client.request("continue", json!({ "threadId": THREAD_ID }));
+            client.request("pause", json!({ "threadId": THREAD_ID }));
+            assert_eq!(client.expect_event("stopped")["reason"], "pause");
+            let response = client.request("unknownRequest", json!({}));
+            assert_eq!(response["success"], false);
+            client.request("disconnect", json!({}));
+        });
+    }
+
+    #[test]
+    fn test_config() {
+        let mut config = Config::default();
+        config.protect_interpreter = true;
+        debug_with_config(config, |client| {
+            // Hex text, which tries to write over the font
+            let program = b"60 05 A0 00 F0 55 12 06\n"; // LD V0, 5; LD I, 0; LD [I], V0
+            let (program_path, _) = write_program("config", program, "");
+            client.request("initialize", json!({ "adapterID": "chip8" }));
+            let response = client.request(
+                "launch",
+                json!({ "program": program_path.to_str().unwrap() }),
+            );
+            assert_eq!(response["success"], true, "{}", response);
+            client.expect_event("initialized");
+            client.request(
+                "setInstructionBreakpoints",
+                json!({ "breakpoints": [{ "instructionReference": "0x206" }] }),
+            );
+            client.request("configurationDone", json!({}));
+            assert_eq!(client.expect_event("stopped")["reason"], "breakpoint");
+            let response = client.request(
+                "readMemory",
+                json!({ "memoryReference": "0x0", "count": 1 }),
+            );
+            assert_eq!(response["body"]["data"], to_base64(&[0xF0]));
+            let response = client.request(
+                "readMemory",
+                json!({ "memoryReference": "0x200", "count": 2 }),
+            );
+            assert_eq!(response["body"]["data"], to_base64(&[0x60, 0x05]));
+            client.request("disconnect", json!({}));
+        });
+    }
+
+    #[test]
+    fn test_memory_layout() {
+        debug(|client| {
+            let (program_path, _) = write_program("layout", &[0x12, 0x00], "");
+            client.request("initialize", json!({ "adapterID": "chip8" }));
+            let response = client.request(
+                "launch",
+                json!({
+                    "program": program_path.to_str().unwrap(),
+                    "memory": { "size": 0x800 },
+                }),
+            );
+            assert_eq!(response["success"], true, "{}", response);
+            client.expect_event("initialized");
+            let response = client.request(
+                "readMemory",
+                json!({ "memoryReference": "0x7FF", "count": 4 }),
+            );
+            assert_eq!(response["body"]["unreadableBytes"], 3);
+            let response = client.request(
+                "writeMemory",
+                json!({ "memoryReference": "0x900", "data": to_base64(b"hi") }),
+            );
+            assert_eq!(response["success"], false);
+            let response = client.request(
+                "setInstructionBreakpoints",
+                json!({ "breakpoints": [
+                    { "instructionReference": "0x7FE" },
+                    { "instructionReference": "0x900" },
+                ] }),
+            );
+            assert_eq!(response["body"]["breakpoints"][0]["verified"], true);
+            assert_eq!(response["body"]["breakpoints"][1]["verified"], false);
+            client.request("disconnect", json!({}));
+        });
+    }
+}
@@ -0,0 +1,110 @@
+use crate::chip8::memory::MemoryLayout;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+/// Error for when a ROM database can't be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseError(String);
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Returns the SHA-1 of a program as lowercase hex, which identifies it whatever its file is called
+pub fn program_hash(program: &[u8]) -> String {
+    sha1_smol::Sha1::from(program).digest().to_string()
+}
+
+/// What's known about a program
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// Where the program expects things in memory, if that isn't the usual layout
+    #[serde(default)]
+    pub memory: Option<MemoryLayout>,
+}
+
+/// Programs' titles, descriptions and settings, by their SHA-1. It's a JSON object
+/// from hashes to entries such as `{"title": "Pong", "memory": {"program_start": 1536}}`.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    /// The programs in the repository's `roms` directory
+    pub fn builtin() -> Self {
+        serde_json::from_str(include_str!("../roms/database.json"))
+            .expect("The built-in ROM database should be valid")
+    }
+
+    /// Reads a database from a JSON file
+    pub fn from_file(path: &Path) -> Result<Self, DatabaseError> {
+        let text = fs::read_to_string(path).map_err(|e| DatabaseError(e.to_string()))?;
+        serde_json::from_str(&text).map_err(|e| DatabaseError(e.to_string()))
+    }
+
+    /// Adds the entries of another database, replacing any for the same programs
+    pub fn extend(&mut self, other: RomDatabase) {
+        self.roms.extend(
+            other
+                .roms
+                .into_iter()
+                .map(|(hash, info)| (hash.to_lowercase(), info)),
+        );
+    }
+
+    /// Looks up a program by its contents
+    pub fn lookup(&self, program: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&program_hash(program))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin() {
+        let database = RomDatabase::builtin();
+        let pong = include_bytes!("../roms/pong.rom");
+        assert_eq!(database.lookup(pong).unwrap().title, "Pong");
+        assert_eq!(database.lookup(pong).unwrap().memory, None);
+        assert_eq!(database.lookup(&[0x12, 0x00]), None);
+        assert_eq!(
+            program_hash(b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut database = RomDatabase::builtin();
+        let other: RomDatabase = serde_json::from_str(
+            r#"{
+                "B232EF880BD6060FB45FA6EFFED7EDF0AE95670E": {
+                    "title": "Pong (ETI-660)",
+                    "memory": {"program_start": 1536}
+                }
+            }"#,
+        )
+        .unwrap();
+        database.extend(other);
+        let pong = database.lookup(include_bytes!("../roms/pong.rom")).unwrap();
+        assert_eq!(pong.title, "Pong (ETI-660)");
+        assert_eq!(pong.description, "");
+        assert_eq!(
+            pong.memory,
+            Some(MemoryLayout {
+                program_start: 0x600,
+                ..MemoryLayout::default()
+            })
+        );
+        assert!(RomDatabase::from_file(Path::new("roms/nope.json")).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use crate::cheats::Cheats;
+use crate::chip8::State;
+use crate::coverage::Coverage;
+use crate::instructions::{self, ExecutionError, Instruction, InstructionError};
+use crate::profiler::Profiler;
+#[cfg(feature = "scripting")]
+use crate::scripting::Script;
+use std::fmt;
+
+/// Error for when the emulator can't run the next instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmulatorError {
+    /// The opcode at the address isn't an instruction
+    InvalidInstruction(usize, InstructionError),
+    /// The instruction at the address failed
+    ExecutionFailed(usize, ExecutionError),
+    /// The program counter doesn't point to a whole opcode in memory
+    ProgramCounterOutOfBounds(usize),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::InvalidInstruction(address, error) => {
+                write!(f, "{} at {:#05X}", error, address)
+            }
+            EmulatorError::ExecutionFailed(address, error) => {
+                write!(f, "{} at {:#05X}", error, address)
+            }
+            EmulatorError::ProgramCounterOutOfBounds(address) => {
+                write!(
+                    f,
+                    "The program counter ran off the end of memory at {:#X}",
+                    address
+                )
+            }
+        }
+    }
+}
+
+/// Runs a chip-8 program on a State, one instruction at a time.
+///
+/// This is shared by the SDL frontend and the headless runner, which only differ
+/// in how they present frames and read the keyboard.
+pub struct Emulator {
+    pub state: State,
+    /// Whether to print debugging information for every instruction executed
+    pub debug: bool,
+    /// The number of frames completed so far
+    pub frame: u64,
+    /// Counts what the program spends its time on, if it's being profiled
+    pub profiler: Option<Profiler>,
+    /// Keeps track of the instructions executed, if coverage is being measured
+    pub coverage: Option<Coverage>,
+    /// Values held in place at the end of every frame
+    pub cheats: Cheats,
+    /// Runs the hooks of a script, if one is loaded
+    #[cfg(feature = "scripting")]
+    pub script: Option<Script>,
+}
+
+impl Emulator {
+    pub fn new(state: State, debug: bool) -> Self {
+        Emulator {
+            state,
+            debug,
+            frame: 0,
+            profiler: None,
+            coverage: None,
+            cheats: Cheats::default(),
+            #[cfg(feature = "scripting")]
+            script: None,
+        }
+    }
+
+    /// Returns true once the program counter has run off the end of memory
+    pub fn is_finished(&self) -> bool {
+        self.state.program_counter + 1 >= self.state.memory.layout().code().end
+    }
+
+    /// Returns true once the program has finished or a script has quit
+    pub fn should_stop(&self) -> bool {
+        #[cfg(feature = "scripting")]
+        {
+            if let Some(script) = &self.script {
+                if script.exit_code().is_some() {
+                    return true;
+                }
+            }
+        }
+        self.is_finished()
+    }
+
+    /// Returns true if the next instruction is LD VX, K, which waits for a key press
+    pub fn is_waiting_for_key(&self) -> bool {
+        let pc = self.state.program_counter;
+        !self.is_finished()
+            && self.state.memory.read(pc) >> 4 == 0xF
+            && self.state.memory.read(pc + 1) == 0x0A
+    }
+
+    /// Reads the opcode pointed to by the program counter, executes it and
+    /// moves the program counter on to the next opcode.
+    /// If that fails, the state is left as it was before the instruction.
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        let pc: usize = self.state.program_counter;
+        if self.is_finished() {
+            return Err(EmulatorError::ProgramCounterOutOfBounds(pc));
+        }
+        if self.debug {
+            self.print_state();
+        }
+        let bytes: (u8, u8) = (self.state.memory.read(pc), self.state.memory.read(pc + 1));
+        let instruction: Box<dyn Instruction> = instructions::parse(bytes, &self.state.quirks)
+            .map_err(|error| EmulatorError::InvalidInstruction(pc, error))?;
+        if self.debug {
+            println!(
+                "Instruction: {:X} {:X} ({:?})",
+                bytes.0, bytes.1, instruction
+            );
+            println!("======================================================================");
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.before_step(&self.state, bytes);
+        }
+        instruction
+            .execute(&mut self.state)
+            .map_err(|error| EmulatorError::ExecutionFailed(pc, error))?;
+        self.state.program_counter = self.state.program_counter.wrapping_add(2);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.after_step(&self.state);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(pc, bytes, self.state.program_counter);
+        }
+        #[cfg(feature = "scripting")]
+        {
+            if let Some(script) = self.script.as_mut() {
+                script.after_step(&mut self.state);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes the current frame by decrementing the timers and re-applying the cheats
+    pub fn end_frame(&mut self) {
+        self.state.timers.decrement_timers();
+        self.cheats.apply(&mut self.state);
+        self.frame += 1;
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame();
+        }
+        #[cfg(feature = "scripting")]
+        {
+            if let Some(script) = self.script.as_mut() {
+                script.end_frame(&mut self.state, self.frame);
+            }
+        }
+    }
+
+    fn print_state(&self) {
+        println!("PC: {:?}", self.state.program_counter);
+        println!("{:?}", self.state.stack);
+        println!("{:?}", self.state.registers);
+        println!("{:?}", self.state.timers);
+        println!("Display:");
+        for row in self.state.display.pixels.iter() {
+            println!("{:?}", &row[..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{
+        memory::{MemoryLayout, MAX_SIZE},
+        stack::StackError,
+    };
+
+    #[test]
+    fn test_step() {
+        // LD V3, 0x2A; JP 0x200
+        let mut emulator = Emulator::new(State::mock(&[0x63, 0x2A, 0x12, 0x00]), false);
+        emulator.step().unwrap();
+        assert_eq!(emulator.state.registers.v_registers[3], 0x2A);
+        assert_eq!(emulator.state.program_counter, 0x202);
+        emulator.step().unwrap();
+        assert_eq!(emulator.state.program_counter, 0x200);
+    }
+
+    #[test]
+    fn test_step_errors() {
+        // RET with an empty stack; an invalid opcode
+        let mut emulator = Emulator::new(State::mock(&[0x00, 0xEE, 0xFF, 0xFF]), false);
+        assert_eq!(
+            emulator.step(),
+            Err(EmulatorError::ExecutionFailed(
+                0x200,
+                ExecutionError::Stack(StackError::Empty)
+            ))
+        );
+        assert_eq!(emulator.state.program_counter, 0x200);
+        emulator.state.program_counter = 0x202;
+        match emulator.step() {
+            Err(EmulatorError::InvalidInstruction(0x202, _)) => {}
+            x => panic!("Expected an invalid instruction, got {:?}", x),
+        }
+        emulator.state.program_counter = MAX_SIZE - 1;
+        assert_eq!(
+            emulator.step(),
+            Err(EmulatorError::ProgramCounterOutOfBounds(MAX_SIZE - 1))
+        );
+        let layout = MemoryLayout {
+            size: 0x800,
+            ..MemoryLayout::default()
+        };
+        let mut emulator = Emulator::new(State::mock_with_layout(&[], layout), false);
+        emulator.state.program_counter = 0x7FF;
+        assert!(emulator.is_finished());
+        emulator.state.program_counter = 0x7FE;
+        assert!(!emulator.is_finished());
+    }
+
+    #[test]
+    fn test_end_frame() {
+        let mut emulator = Emulator::new(State::mock(&[]), false);
+        emulator.state.timers.delay_timer = 2;
+        emulator.cheats = "0x300=9".parse().unwrap();
+        emulator.end_frame();
+        assert_eq!(emulator.state.timers.delay_timer, 1);
+        assert_eq!(emulator.state.memory.ram()[0x300], 9);
+        assert_eq!(emulator.frame, 1);
+        assert!(!emulator.is_finished());
+    }
+}
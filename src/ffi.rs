@@ -0,0 +1,690 @@
+//! A C API for embedding the emulator in other languages, built with the `ffi` feature.
+//!
+//! The API is declared in `include/chip8.h`, which is generated from this module.
+//! Every function takes the machine created by `chip8_new` and returns a
+//! `Chip8Status`, e.g.
+//!
+//! ```c
+//! Chip8Machine *machine = chip8_new();
+//! chip8_load_rom(machine, rom, rom_length);
+//! while (running) {
+//!     chip8_run_cycles(machine, 10);
+//!     chip8_end_frame(machine);
+//!     draw(chip8_framebuffer(machine));
+//! }
+//! chip8_free(machine);
+//! ```
+//!
+//! Functions that fail leave the machine as it was, and describe why in
+//! `chip8_last_error`.
+
+use crate::chip8::{
+    display::Display, font::Font, keyboard::SharedKeyboard, memory::MemoryLayout, quirks::Quirks,
+    State,
+};
+use crate::config::Config;
+use crate::emulator::{Emulator, EmulatorError};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::{ptr, slice};
+
+/// What a call did
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Chip8Status {
+    Ok = 0,
+    /// A pointer argument was null
+    NullPointer,
+    /// The ROM doesn't fit in memory
+    RomTooLarge,
+    /// The program executed something that isn't an instruction
+    InvalidInstruction,
+    /// An instruction failed, e.g. RET with nothing on the stack
+    ExecutionFailed,
+    /// The program counter ran off the end of memory
+    ProgramCounterOutOfBounds,
+    /// An address, register value or key is out of range
+    OutOfRange,
+    /// There's no quirk profile with that name
+    UnknownProfile,
+    /// The buffer is too small for the state
+    BufferTooSmall,
+    /// The state wasn't saved by `chip8_save_state`, or is corrupt
+    InvalidState,
+    /// The memory layout doesn't fit, e.g. the font runs past the end of memory
+    InvalidLayout,
+}
+
+/// A machine and the keys held on it. C only sees a pointer to it.
+pub struct Chip8Machine {
+    emulator: Emulator,
+    keys: SharedKeyboard,
+    quirks: Quirks,
+    /// Where ROMs are loaded and the rest of memory is laid out
+    layout: MemoryLayout,
+    /// Why the last call failed
+    error: Option<CString>,
+}
+
+/// The registers, as read by `chip8_get_registers` and written by `chip8_set_registers`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Chip8Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Chip8Machine {
+    fn emulator(
+        program: &[u8],
+        keys: &SharedKeyboard,
+        quirks: Quirks,
+        layout: MemoryLayout,
+    ) -> Emulator {
+        let palette = Config::default().get_palette();
+        let display = Display::new(palette.active(), palette.inactive());
+        let mut state = State::with_layout(program, Box::new(keys.clone()), display, layout);
+        state.quirks = quirks;
+        Emulator::new(state, false)
+    }
+
+    /// Remembers why a call failed, and returns its status
+    fn fail(&mut self, status: Chip8Status, message: String) -> Chip8Status {
+        // Messages are ours, so they never contain a NUL
+        self.error = Some(CString::new(message).unwrap());
+        status
+    }
+
+    /// Returns the memory from `address` to `address + length`, if it's in bounds
+    fn range(
+        &mut self,
+        address: u16,
+        length: usize,
+    ) -> Result<std::ops::Range<usize>, Chip8Status> {
+        let start = usize::from(address);
+        match start.checked_add(length) {
+            Some(end) if end <= self.emulator.state.memory.ram().len() => Ok(start..end),
+            _ => Err(self.fail(
+                Chip8Status::OutOfRange,
+                format!(
+                    "{} bytes from {:#05X} run past the end of memory",
+                    length, address
+                ),
+            )),
+        }
+    }
+}
+
+/// Creates a machine with no program loaded. Free it with `chip8_free`.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8Machine {
+    let keys = SharedKeyboard::new();
+    let machine = Chip8Machine {
+        emulator: Chip8Machine::emulator(&[], &keys, Quirks::default(), MemoryLayout::default()),
+        keys,
+        quirks: Quirks::default(),
+        layout: MemoryLayout::default(),
+        error: None,
+    };
+    Box::into_raw(Box::new(machine))
+}
+
+/// Frees a machine created by `chip8_new`. Null is ignored.
+///
+/// # Safety
+/// `machine` must be null or come from `chip8_new`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(machine: *mut Chip8Machine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+/// Loads `length` bytes of ROM where the memory layout starts programs (0x200 unless
+/// `chip8_set_memory_layout` says otherwise), resetting the machine
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `rom` must point to `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    machine: *mut Chip8Machine,
+    rom: *const u8,
+    length: usize,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    if rom.is_null() && length > 0 {
+        return machine.fail(Chip8Status::NullPointer, String::from("The ROM is null"));
+    }
+    let program = if length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(rom, length)
+    };
+    if let Err(e) = machine.layout.check(program, &Font::default()) {
+        return machine.fail(Chip8Status::RomTooLarge, e.to_string());
+    }
+    machine.emulator =
+        Chip8Machine::emulator(program, &machine.keys, machine.quirks, machine.layout);
+    Chip8Status::Ok
+}
+
+/// Sets how memory is laid out for ROMs loaded afterwards: `size` bytes of it (up to
+/// 0x10000), with programs loaded at `program_start` and the font at `font_address`
+///
+/// # Safety
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_memory_layout(
+    machine: *mut Chip8Machine,
+    size: usize,
+    program_start: usize,
+    font_address: usize,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    let layout = MemoryLayout {
+        size,
+        program_start,
+        font_address,
+    };
+    if let Err(e) = layout.check(&[], &Font::default()) {
+        return machine.fail(Chip8Status::InvalidLayout, e.to_string());
+    }
+    machine.layout = layout;
+    Chip8Status::Ok
+}
+
+/// Switches to a quirk profile (default, cosmac-vip or super-chip), which also applies
+/// to ROMs loaded later
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `profile` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_quirks(
+    machine: *mut Chip8Machine,
+    profile: *const c_char,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    if profile.is_null() {
+        return machine.fail(
+            Chip8Status::NullPointer,
+            String::from("The profile is null"),
+        );
+    }
+    let profile = CStr::from_ptr(profile).to_string_lossy();
+    match Quirks::profile(&profile) {
+        Some(quirks) => {
+            machine.quirks = quirks;
+            machine.emulator.state.quirks = quirks;
+            Chip8Status::Ok
+        }
+        None => machine.fail(
+            Chip8Status::UnknownProfile,
+            format!(
+                "There's no quirk profile named {}. Please use one of {:?}",
+                profile,
+                Quirks::PROFILES
+            ),
+        ),
+    }
+}
+
+/// Holds or releases one of the 16 keys (0-F)
+///
+/// # Safety
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(
+    machine: *mut Chip8Machine,
+    key: u8,
+    pressed: bool,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    if key >= 16 {
+        return machine.fail(
+            Chip8Status::OutOfRange,
+            format!("There's no key {:#X}", key),
+        );
+    }
+    machine.keys.set_key(key, pressed);
+    Chip8Status::Ok
+}
+
+/// Executes up to `cycles` instructions. Waiting for a key (LD VX, K) with none held
+/// uses up a cycle without doing anything, so the caller is never blocked.
+///
+/// # Safety
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_cycles(machine: *mut Chip8Machine, cycles: u32) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    for _ in 0..cycles {
+        if machine.emulator.is_waiting_for_key() && !machine.keys.any_held() {
+            continue;
+        }
+        if let Err(e) = machine.emulator.step() {
+            let status = match e {
+                EmulatorError::InvalidInstruction(..) => Chip8Status::InvalidInstruction,
+                EmulatorError::ExecutionFailed(..) => Chip8Status::ExecutionFailed,
+                EmulatorError::ProgramCounterOutOfBounds(..) => {
+                    Chip8Status::ProgramCounterOutOfBounds
+                }
+            };
+            return machine.fail(status, e.to_string());
+        }
+    }
+    Chip8Status::Ok
+}
+
+/// Ends a frame, counting down the timers. Call it sixty times a second.
+///
+/// # Safety
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_end_frame(machine: *mut Chip8Machine) -> Chip8Status {
+    match machine.as_mut() {
+        Some(machine) => {
+            machine.emulator.end_frame();
+            Chip8Status::Ok
+        }
+        None => Chip8Status::NullPointer,
+    }
+}
+
+/// Returns the display as 64x32 bytes, row by row, 1 where a pixel is lit.
+/// The pointer is valid until the next `chip8_load_rom` or `chip8_free`.
+///
+/// # Safety
+/// `machine` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(machine: *const Chip8Machine) -> *const u8 {
+    match machine.as_ref() {
+        Some(machine) => machine.emulator.state.display.pixels.as_ptr() as *const u8,
+        None => ptr::null(),
+    }
+}
+
+/// Returns whether the beeper should sound, i.e. the sound timer is running
+///
+/// # Safety
+/// `machine` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_is_beeping(machine: *const Chip8Machine) -> bool {
+    match machine.as_ref() {
+        Some(machine) => machine.emulator.state.timers.sound_timer > 0,
+        None => false,
+    }
+}
+
+/// Copies the registers into `registers`
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `registers` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_get_registers(
+    machine: *const Chip8Machine,
+    registers: *mut Chip8Registers,
+) -> Chip8Status {
+    let (machine, registers) = match (machine.as_ref(), registers.as_mut()) {
+        (Some(machine), Some(registers)) => (machine, registers),
+        _ => return Chip8Status::NullPointer,
+    };
+    let state = &machine.emulator.state;
+    *registers = Chip8Registers {
+        v: state.registers.v_registers,
+        i: state.registers.i_register,
+        pc: state.program_counter as u16,
+        delay_timer: state.timers.delay_timer,
+        sound_timer: state.timers.sound_timer,
+    };
+    Chip8Status::Ok
+}
+
+/// Sets the registers from `registers`. The program counter must be in memory.
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `registers` must be readable.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_registers(
+    machine: *mut Chip8Machine,
+    registers: *const Chip8Registers,
+) -> Chip8Status {
+    let (machine, registers) = match (machine.as_mut(), registers.as_ref()) {
+        (Some(machine), Some(registers)) => (machine, registers),
+        _ => return Chip8Status::NullPointer,
+    };
+    if usize::from(registers.pc) >= machine.emulator.state.memory.layout().code().end {
+        return machine.fail(
+            Chip8Status::OutOfRange,
+            format!("The program counter {:#X} is out of memory", registers.pc),
+        );
+    }
+    let state = &mut machine.emulator.state;
+    state.registers.v_registers = registers.v;
+    state.registers.i_register = registers.i;
+    state.program_counter = usize::from(registers.pc);
+    state.timers.delay_timer = registers.delay_timer;
+    state.timers.sound_timer = registers.sound_timer;
+    Chip8Status::Ok
+}
+
+/// Copies `length` bytes of memory starting at `address` into `buffer`
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `buffer` must have room for `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_read_memory(
+    machine: *mut Chip8Machine,
+    address: u16,
+    buffer: *mut u8,
+    length: usize,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    let range = match machine.range(address, length) {
+        Ok(range) => range,
+        Err(status) => return status,
+    };
+    if length > 0 {
+        if buffer.is_null() {
+            return machine.fail(Chip8Status::NullPointer, String::from("The buffer is null"));
+        }
+        slice::from_raw_parts_mut(buffer, length)
+            .copy_from_slice(&machine.emulator.state.memory.ram()[range]);
+    }
+    Chip8Status::Ok
+}
+
+/// Copies `length` bytes from `data` into memory starting at `address`
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `data` must point to `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_write_memory(
+    machine: *mut Chip8Machine,
+    address: u16,
+    data: *const u8,
+    length: usize,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    let range = match machine.range(address, length) {
+        Ok(range) => range,
+        Err(status) => return status,
+    };
+    if length > 0 {
+        if data.is_null() {
+            return machine.fail(Chip8Status::NullPointer, String::from("The data is null"));
+        }
+        machine.emulator.state.memory.ram_mut()[range]
+            .copy_from_slice(slice::from_raw_parts(data, length));
+    }
+    Chip8Status::Ok
+}
+
+/// Returns the number of bytes `chip8_save_state` writes, which depends on the size of
+/// the machine's memory, or 0 if `machine` is null
+///
+/// # Safety
+/// `machine` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_state_size(machine: *const Chip8Machine) -> usize {
+    match machine.as_ref() {
+        Some(machine) => machine.emulator.state.snapshot_size(),
+        None => 0,
+    }
+}
+
+/// Saves the whole machine (except the keys held) into `buffer`, which needs
+/// `chip8_state_size(machine)` bytes
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `buffer` must have room for `capacity` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    machine: *mut Chip8Machine,
+    buffer: *mut u8,
+    capacity: usize,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    if buffer.is_null() {
+        return machine.fail(Chip8Status::NullPointer, String::from("The buffer is null"));
+    }
+    let size = machine.emulator.state.snapshot_size();
+    if capacity < size {
+        return machine.fail(
+            Chip8Status::BufferTooSmall,
+            format!(
+                "The buffer is {} bytes, but the state needs {}",
+                capacity, size
+            ),
+        );
+    }
+    let snapshot = machine.emulator.state.snapshot();
+    slice::from_raw_parts_mut(buffer, size).copy_from_slice(&snapshot);
+    Chip8Status::Ok
+}
+
+/// Restores the machine to a state saved by `chip8_save_state`
+///
+/// # Safety
+/// `machine` must come from `chip8_new`, and `data` must point to `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(
+    machine: *mut Chip8Machine,
+    data: *const u8,
+    length: usize,
+) -> Chip8Status {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return Chip8Status::NullPointer,
+    };
+    if data.is_null() {
+        return machine.fail(Chip8Status::NullPointer, String::from("The state is null"));
+    }
+    let data = slice::from_raw_parts(data, length);
+    match machine.emulator.state.restore(data) {
+        Ok(()) => {
+            machine.quirks = machine.emulator.state.quirks;
+            Chip8Status::Ok
+        }
+        Err(e) => machine.fail(Chip8Status::InvalidState, e.to_string()),
+    }
+}
+
+/// Returns why the last call on the machine failed, or null if none has.
+/// The string is valid until the next call that fails.
+///
+/// # Safety
+/// `machine` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_last_error(machine: *const Chip8Machine) -> *const c_char {
+    match machine.as_ref().and_then(|machine| machine.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `test` on a machine with `program` loaded
+    fn with_machine(program: &[u8], test: impl FnOnce(*mut Chip8Machine)) {
+        let machine = chip8_new();
+        unsafe {
+            assert_eq!(
+                chip8_load_rom(machine, program.as_ptr(), program.len()),
+                Chip8Status::Ok
+            );
+            test(machine);
+            chip8_free(machine);
+        }
+    }
+
+    #[test]
+    fn test_run_and_registers() {
+        let program = [
+            0x60, 0x05, // 200: LD V0, 5
+            0xF0, 0x15, // 202: LD DT, V0
+            0xF1, 0x0A, // 204: LD V1, K
+            0x12, 0x06, // 206: JP 0x206
+        ];
+        with_machine(&program, |machine| unsafe {
+            assert_eq!(chip8_run_cycles(machine, 10), Chip8Status::Ok);
+            chip8_end_frame(machine);
+            let mut registers = Chip8Registers::default();
+            chip8_get_registers(machine, &mut registers);
+            assert_eq!(registers.pc, 0x204);
+            assert_eq!(registers.delay_timer, 4);
+
+            chip8_set_key(machine, 0xB, true);
+            chip8_run_cycles(machine, 1);
+            chip8_get_registers(machine, &mut registers);
+            assert_eq!((registers.pc, registers.v[1]), (0x206, 0xB));
+
+            registers.pc = 0x200;
+            registers.v[0xF] = 1;
+            assert_eq!(chip8_set_registers(machine, &registers), Chip8Status::Ok);
+            let mut read = Chip8Registers::default();
+            chip8_get_registers(machine, &mut read);
+            assert_eq!(read, registers);
+            registers.pc = 0x1000;
+            assert_eq!(
+                chip8_set_registers(machine, &registers),
+                Chip8Status::OutOfRange
+            );
+        });
+    }
+
+    #[test]
+    fn test_memory_and_errors() {
+        with_machine(&[0x50, 0x01], |machine| unsafe {
+            assert!(chip8_last_error(machine).is_null());
+            let data = [0xAB, 0xCD];
+            assert_eq!(
+                chip8_write_memory(machine, 0xFFE, data.as_ptr(), 2),
+                Chip8Status::Ok
+            );
+            let mut buffer = [0; 2];
+            chip8_read_memory(machine, 0xFFE, buffer.as_mut_ptr(), 2);
+            assert_eq!(buffer, data);
+            assert_eq!(
+                chip8_read_memory(machine, 0xFFF, buffer.as_mut_ptr(), 2),
+                Chip8Status::OutOfRange
+            );
+
+            assert_eq!(
+                chip8_run_cycles(machine, 1),
+                Chip8Status::InvalidInstruction
+            );
+            let error = CStr::from_ptr(chip8_last_error(machine));
+            assert!(error.to_str().unwrap().contains("0x200"));
+            assert_eq!(chip8_set_key(machine, 16, true), Chip8Status::OutOfRange);
+            assert_eq!(
+                chip8_run_cycles(ptr::null_mut(), 1),
+                Chip8Status::NullPointer
+            );
+        });
+    }
+
+    #[test]
+    fn test_memory_layout() {
+        let machine = chip8_new();
+        unsafe {
+            assert_eq!(
+                chip8_set_memory_layout(machine, 0x800, 0x1000, 0),
+                Chip8Status::InvalidLayout
+            );
+            assert_eq!(
+                chip8_set_memory_layout(machine, 0x800, 0x600, 0),
+                Chip8Status::Ok
+            );
+            let too_large = [0; 0x201];
+            assert_eq!(
+                chip8_load_rom(machine, too_large.as_ptr(), too_large.len()),
+                Chip8Status::RomTooLarge
+            );
+            let program = [0x16, 0x00]; // 600: JP 0x600
+            assert_eq!(
+                chip8_load_rom(machine, program.as_ptr(), program.len()),
+                Chip8Status::Ok
+            );
+            let mut registers = Chip8Registers::default();
+            chip8_get_registers(machine, &mut registers);
+            assert_eq!(registers.pc, 0x600);
+            let mut buffer = [0; 2];
+            assert_eq!(
+                chip8_read_memory(machine, 0x7FE, buffer.as_mut_ptr(), 2),
+                Chip8Status::Ok
+            );
+            assert_eq!(
+                chip8_read_memory(machine, 0x7FF, buffer.as_mut_ptr(), 2),
+                Chip8Status::OutOfRange
+            );
+            registers.pc = 0x800;
+            assert_eq!(
+                chip8_set_registers(machine, &registers),
+                Chip8Status::OutOfRange
+            );
+            let mut state = vec![0; chip8_state_size(machine)];
+            assert_eq!(
+                chip8_save_state(machine, state.as_mut_ptr(), state.len()),
+                Chip8Status::Ok
+            );
+            assert_eq!(
+                chip8_load_state(machine, state.as_ptr(), state.len()),
+                Chip8Status::Ok
+            );
+            chip8_free(machine);
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        with_machine(&[0x60, 0x07, 0x12, 0x02], |machine| unsafe {
+            let mut state = vec![0; chip8_state_size(machine)];
+            assert_eq!(
+                chip8_save_state(machine, state.as_mut_ptr(), state.len() - 1),
+                Chip8Status::BufferTooSmall
+            );
+            chip8_save_state(machine, state.as_mut_ptr(), state.len());
+            chip8_run_cycles(machine, 2);
+            assert_eq!(
+                chip8_load_state(machine, state.as_ptr(), state.len()),
+                Chip8Status::Ok
+            );
+            let mut registers = Chip8Registers::default();
+            chip8_get_registers(machine, &mut registers);
+            assert_eq!((registers.pc, registers.v[0]), (0x200, 0));
+            assert_eq!(
+                chip8_load_state(machine, state.as_ptr(), 3),
+                Chip8Status::InvalidState
+            );
+        });
+    }
+}
@@ -0,0 +1,303 @@
+use crate::chip8::display::Display;
+use serde::Deserialize;
+
+/// A post-processing stage applied to the display before each frame is shown.
+///
+/// Filters work on the display's colored pixels (RGBA) so they don't need to
+/// know which colors are in use, and use `Display::pixels` to tell lit pixels apart.
+pub trait Filter {
+    /// Returns the (width, height) of the images this filter produces
+    fn size(&self) -> (usize, usize);
+
+    /// Processes one frame, returning the RGBA image to show
+    fn apply(&mut self, display: &Display) -> &[u8];
+}
+
+/// The filters that can be selected in the config file
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    /// Show the display as is
+    #[default]
+    None,
+    /// Let unlit pixels fade out over a few frames, like phosphor on a CRT
+    Persistence,
+    /// Keep showing pixels that were lit in the previous frame
+    Deflicker,
+    /// Draw scanlines and bloom, like a CRT television
+    Crt,
+}
+
+impl FilterKind {
+    /// Creates the filter of this kind.
+    /// `persistence` is the fraction of a faded pixel's brightness kept each frame.
+    pub fn create(self, persistence: f32) -> Box<dyn Filter> {
+        match self {
+            FilterKind::None => Box::new(NoFilter::new()),
+            FilterKind::Persistence => Box::new(Persistence::new(persistence)),
+            FilterKind::Deflicker => Box::new(Deflicker::new()),
+            FilterKind::Crt => Box::new(Crt::new()),
+        }
+    }
+}
+
+/// Shows the display as is
+pub struct NoFilter {
+    output: Vec<u8>,
+}
+
+impl NoFilter {
+    pub fn new() -> Self {
+        NoFilter {
+            output: vec![0; Display::WIDTH * Display::HEIGHT * 4],
+        }
+    }
+}
+
+impl Default for NoFilter {
+    fn default() -> Self {
+        NoFilter::new()
+    }
+}
+
+impl Filter for NoFilter {
+    fn size(&self) -> (usize, usize) {
+        (Display::WIDTH, Display::HEIGHT)
+    }
+
+    fn apply(&mut self, display: &Display) -> &[u8] {
+        self.output.copy_from_slice(&display.colored_pixels);
+        &self.output
+    }
+}
+
+/// Lit pixels are shown immediately, but pixels that are turned off
+/// fade towards their new color instead of disappearing at once
+pub struct Persistence {
+    /// The fraction of the difference from the previous frame that is kept each frame
+    decay: f32,
+    /// The last frame shown, with fractional channels so slow fades don't get stuck
+    previous: Vec<f32>,
+    output: Vec<u8>,
+}
+
+impl Persistence {
+    pub fn new(decay: f32) -> Self {
+        Persistence {
+            decay: decay.clamp(0.0, 1.0),
+            previous: Vec::new(),
+            output: vec![0; Display::WIDTH * Display::HEIGHT * 4],
+        }
+    }
+}
+
+impl Filter for Persistence {
+    fn size(&self) -> (usize, usize) {
+        (Display::WIDTH, Display::HEIGHT)
+    }
+
+    fn apply(&mut self, display: &Display) -> &[u8] {
+        if self.previous.is_empty() {
+            self.previous = display
+                .colored_pixels
+                .iter()
+                .map(|x| f32::from(*x))
+                .collect();
+        }
+        for (index, current) in display.colored_pixels.iter().enumerate() {
+            let pixel = index / 4;
+            let current = f32::from(*current);
+            let value = if display.pixels[pixel / Display::WIDTH][pixel % Display::WIDTH] == 1 {
+                current
+            } else {
+                current + (self.previous[index] - current) * self.decay
+            };
+            self.previous[index] = value;
+            self.output[index] = value.round() as u8;
+        }
+        &self.output
+    }
+}
+
+/// Shows a pixel if it is lit in the current frame or the one before it.
+/// Sprites that are erased and redrawn every frame then stop flickering,
+/// at the cost of moving sprites leaving a one frame trail.
+pub struct Deflicker {
+    previous: Vec<u8>,
+    previous_pixels: [[u8; Display::WIDTH]; Display::HEIGHT],
+    output: Vec<u8>,
+}
+
+impl Deflicker {
+    pub fn new() -> Self {
+        Deflicker {
+            previous: vec![0; Display::WIDTH * Display::HEIGHT * 4],
+            previous_pixels: [[0; Display::WIDTH]; Display::HEIGHT],
+            output: vec![0; Display::WIDTH * Display::HEIGHT * 4],
+        }
+    }
+}
+
+impl Default for Deflicker {
+    fn default() -> Self {
+        Deflicker::new()
+    }
+}
+
+impl Filter for Deflicker {
+    fn size(&self) -> (usize, usize) {
+        (Display::WIDTH, Display::HEIGHT)
+    }
+
+    fn apply(&mut self, display: &Display) -> &[u8] {
+        for y in 0..Display::HEIGHT {
+            for x in 0..Display::WIDTH {
+                let start = (Display::WIDTH * y + x) * 4;
+                let source = if display.pixels[y][x] == 0 && self.previous_pixels[y][x] == 1 {
+                    &self.previous[start..start + 4]
+                } else {
+                    &display.colored_pixels[start..start + 4]
+                };
+                self.output[start..start + 4].copy_from_slice(source);
+            }
+        }
+        self.previous.copy_from_slice(&display.colored_pixels);
+        self.previous_pixels = display.pixels;
+        &self.output
+    }
+}
+
+/// Draws every chip-8 pixel as a block of `Crt::SCALE` rows with a dark scanline
+/// at the bottom, and lets lit pixels bloom into their neighbours
+pub struct Crt {
+    output: Vec<u8>,
+}
+
+impl Crt {
+    /// The number of output pixels per chip-8 pixel in each direction
+    const SCALE: usize = 3;
+
+    /// How bright scanlines are compared to the rest of the pixel
+    const SCANLINE: f32 = 0.55;
+
+    /// How much of the neighbouring pixels' color bleeds into each pixel
+    const BLOOM: f32 = 0.3;
+
+    pub fn new() -> Self {
+        Crt {
+            output: vec![0; Display::WIDTH * Display::HEIGHT * 4 * Self::SCALE * Self::SCALE],
+        }
+    }
+
+    /// Returns the average color of the pixels around (x, y), wrapping like the display does
+    fn neighbourhood(colored_pixels: &[u8], x: usize, y: usize) -> [f32; 4] {
+        let mut result = [0.0; 4];
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            let nx = (x as isize + dx).rem_euclid(Display::WIDTH as isize) as usize;
+            let ny = (y as isize + dy).rem_euclid(Display::HEIGHT as isize) as usize;
+            let start = (Display::WIDTH * ny + nx) * 4;
+            for (channel, value) in result.iter_mut().enumerate() {
+                *value += f32::from(colored_pixels[start + channel]) / 4.0;
+            }
+        }
+        result
+    }
+}
+
+impl Default for Crt {
+    fn default() -> Self {
+        Crt::new()
+    }
+}
+
+impl Filter for Crt {
+    fn size(&self) -> (usize, usize) {
+        (Display::WIDTH * Self::SCALE, Display::HEIGHT * Self::SCALE)
+    }
+
+    fn apply(&mut self, display: &Display) -> &[u8] {
+        let output_width = Display::WIDTH * Self::SCALE;
+        for y in 0..Display::HEIGHT {
+            for x in 0..Display::WIDTH {
+                let start = (Display::WIDTH * y + x) * 4;
+                let neighbourhood = Self::neighbourhood(&display.colored_pixels, x, y);
+                let mut color = [0.0; 4];
+                for (channel, value) in color.iter_mut().enumerate() {
+                    let own = f32::from(display.colored_pixels[start + channel]);
+                    *value =
+                        own.max(own * (1.0 - Self::BLOOM) + neighbourhood[channel] * Self::BLOOM);
+                }
+                for row in 0..Self::SCALE {
+                    let brightness = if row == Self::SCALE - 1 {
+                        Self::SCANLINE
+                    } else {
+                        1.0
+                    };
+                    for column in 0..Self::SCALE {
+                        let out_start =
+                            ((y * Self::SCALE + row) * output_width + x * Self::SCALE + column) * 4;
+                        for (channel, value) in color.iter().enumerate().take(3) {
+                            self.output[out_start + channel] =
+                                (value * brightness).round().min(255.0) as u8;
+                        }
+                        self.output[out_start + 3] = display.colored_pixels[start + 3];
+                    }
+                }
+            }
+        }
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Color;
+
+    #[test]
+    fn test_no_filter() {
+        let mut display = Display::new(Color::white(), Color::black());
+        display.xor(3, 4, 1);
+        let mut filter = NoFilter::new();
+        assert_eq!(filter.apply(&display), &display.colored_pixels[..]);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut display = Display::new(Color::white(), Color::black());
+        let mut filter = Persistence::new(0.5);
+        display.xor(0, 0, 1);
+        assert_eq!(filter.apply(&display)[0], 255);
+        display.xor(0, 0, 1);
+        assert_eq!(filter.apply(&display)[0], 128);
+        assert_eq!(filter.apply(&display)[0], 64);
+        display.xor(0, 0, 1);
+        assert_eq!(filter.apply(&display)[0], 255);
+    }
+
+    #[test]
+    fn test_deflicker() {
+        let mut display = Display::new(Color::white(), Color::black());
+        let mut filter = Deflicker::new();
+        display.xor(0, 0, 1);
+        assert_eq!(filter.apply(&display)[0], 255);
+        display.xor(0, 0, 1);
+        assert_eq!(filter.apply(&display)[0], 255);
+        assert_eq!(filter.apply(&display)[0], 0);
+    }
+
+    #[test]
+    fn test_crt() {
+        let mut display = Display::new(Color::white(), Color::black());
+        let mut filter = Crt::new();
+        display.xor(1, 1, 1);
+        let (width, height) = filter.size();
+        let output = filter.apply(&display);
+        assert_eq!(output.len(), width * height * 4);
+        let at = |x: usize, y: usize| output[(y * width + x) * 4];
+        assert_eq!(at(3, 3), 255);
+        assert_eq!(at(3, 5), 140);
+        assert!(at(0, 3) > 0);
+        assert_eq!(at(9, 9), 0);
+    }
+}
@@ -0,0 +1,733 @@
+use crate::emulator::{Emulator, EmulatorError};
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{self, Read, Write},
+    net::TcpStream,
+};
+
+/// The registers exposed to the debugger, in GDB's register numbering.
+/// Each entry is the register's name and its size in bytes.
+const REGISTERS: [(&str, usize); 21] = [
+    ("v0", 1),
+    ("v1", 1),
+    ("v2", 1),
+    ("v3", 1),
+    ("v4", 1),
+    ("v5", 1),
+    ("v6", 1),
+    ("v7", 1),
+    ("v8", 1),
+    ("v9", 1),
+    ("va", 1),
+    ("vb", 1),
+    ("vc", 1),
+    ("vd", 1),
+    ("ve", 1),
+    ("vf", 1),
+    ("i", 2),
+    ("sp", 1),
+    ("pc", 2),
+    ("dt", 1),
+    ("st", 1),
+];
+
+const I_REGISTER: usize = 16;
+const STACK_POINTER: usize = 17;
+const PROGRAM_COUNTER: usize = 18;
+const DELAY_TIMER: usize = 19;
+const SOUND_TIMER: usize = 20;
+
+/// Byte a client sends to interrupt a running program
+const INTERRUPT: u8 = 0x03;
+
+/// Returns the target description, which tells the debugger which registers there are
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chip8.core\">\n",
+    );
+    for (number, (name, size)) in REGISTERS.iter().enumerate() {
+        let kind = match number {
+            I_REGISTER => "data_ptr",
+            PROGRAM_COUNTER => "code_ptr\" generic=\"pc",
+            _ => "uint8",
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\" type=\"{}\"/>\n",
+            name,
+            size * 8,
+            number,
+            kind
+        ));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+/// Returns the memory map, which tells the debugger how much memory there is
+fn memory_map(size: usize) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE memory-map PUBLIC \"+//IDN gnu.org//DTD GDB Memory Map V1.0//EN\" \"http://sourceware.org/gdb/gdb-memory-map.dtd\">\n\
+         <memory-map>\n\
+         <memory type=\"ram\" start=\"0x0\" length=\"{:#x}\"/>\n\
+         </memory-map>\n",
+        size
+    )
+}
+
+/// Returns the GDB checksum of a packet's data
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, x| sum.wrapping_add(*x))
+}
+
+/// Escapes the bytes that have special meanings inside packets
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    for x in data {
+        match x {
+            b'#' | b'$' | b'}' | b'*' => result.extend(&[b'}', x ^ 0x20]),
+            _ => result.push(*x),
+        }
+    }
+    result
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|x| u8::from_str_radix(hex.get(x..x + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses the `address,length` arguments of memory packets, for `size` bytes of memory
+fn parse_range(arguments: &str, size: usize) -> Option<(usize, usize)> {
+    let mut parts = arguments.splitn(2, ',');
+    let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let length = usize::from_str_radix(parts.next()?, 16).ok()?;
+    if address.checked_add(length)? > size {
+        return None;
+    }
+    Some((address, length))
+}
+
+/// What the server should do after answering a packet
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Wait for the next packet
+    Continue,
+    /// Close the connection
+    Stop,
+}
+
+/// A GDB remote serial protocol server that debugs an emulator over TCP.
+///
+/// The program only runs while the debugger says so: it stops at breakpoints,
+/// after single steps and when the debugger interrupts it.
+pub struct GdbServer {
+    stream: TcpStream,
+    /// Bytes received but not handled yet
+    received: VecDeque<u8>,
+    breakpoints: HashSet<usize>,
+    /// Whether the client asked to stop acknowledging packets
+    no_ack: bool,
+    ticks_per_frame: u8,
+    /// The number of instructions executed in the current frame
+    ticks: u8,
+}
+
+impl GdbServer {
+    pub fn new(stream: TcpStream, ticks_per_frame: u8) -> Self {
+        // Packets are small and each one waits for a reply, so don't hold them back.
+        // This only affects latency, so it's fine if it can't be set.
+        stream.set_nodelay(true).ok();
+        GdbServer {
+            stream,
+            received: VecDeque::new(),
+            breakpoints: HashSet::new(),
+            no_ack: false,
+            ticks_per_frame,
+            ticks: 0,
+        }
+    }
+
+    /// Answers the debugger's packets until it detaches, kills the program or disconnects.
+    /// `on_frame` is called at the end of every frame the program runs.
+    pub fn serve(
+        &mut self,
+        emulator: &mut Emulator,
+        on_frame: &mut dyn FnMut(&mut Emulator),
+    ) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            if self.handle_packet(&packet, emulator, on_frame)? == Action::Stop {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads one byte, waiting for it if there isn't one yet.
+    /// Returns None once the client disconnects.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(x) = self.received.pop_front() {
+            return Ok(Some(x));
+        }
+        let mut buffer = [0; 1024];
+        let count = self.stream.read(&mut buffer)?;
+        self.received.extend(&buffer[1..count.max(1)]);
+        Ok(if count == 0 { None } else { Some(buffer[0]) })
+    }
+
+    /// Returns true if the client sent an interrupt, without waiting for one
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 1024];
+        let result = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(count) => self.received.extend(&buffer[..count]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        Ok(match self.received.iter().position(|x| *x == INTERRUPT) {
+            Some(index) => {
+                self.received.remove(index);
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Reads the next packet's data, acknowledging it.
+    /// Returns None once the client disconnects.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip acknowledgements and stray interrupts until a packet starts
+            match self.read_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(x) => data.push(x),
+                    None => return Ok(None),
+                }
+            }
+            let mut sum = [0; 2];
+            for x in sum.iter_mut() {
+                match self.read_byte()? {
+                    Some(y) => *x = y,
+                    None => return Ok(None),
+                }
+            }
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                == Some(checksum(&data));
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let data = escape(data.as_bytes());
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend(&data);
+        packet.extend(format!("#{:02x}", checksum(&data)).as_bytes());
+        self.stream.write_all(&packet)?;
+        self.stream.flush()
+    }
+
+    fn handle_packet(
+        &mut self,
+        packet: &str,
+        emulator: &mut Emulator,
+        on_frame: &mut dyn FnMut(&mut Emulator),
+    ) -> io::Result<Action> {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => String::from("S05"),
+            "g" => to_hex(&Self::read_registers(emulator)),
+            "G" => match from_hex(arguments) {
+                Some(ref values) if Self::write_registers(emulator, values) => String::from("OK"),
+                _ => String::from("E01"),
+            },
+            "p" => match usize::from_str_radix(arguments, 16) {
+                Ok(number) if number < REGISTERS.len() => {
+                    to_hex(&Self::read_register(emulator, number))
+                }
+                _ => String::from("E01"),
+            },
+            "P" => {
+                let mut parts = arguments.splitn(2, '=');
+                let number = parts.next().and_then(|x| usize::from_str_radix(x, 16).ok());
+                let value = parts.next().and_then(from_hex);
+                match (number, value) {
+                    (Some(number), Some(value))
+                        if Self::write_register(emulator, number, &value) =>
+                    {
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            "m" => match parse_range(arguments, emulator.state.memory.ram().len()) {
+                Some((address, length)) => {
+                    to_hex(&emulator.state.memory.ram()[address..address + length])
+                }
+                None => String::from("E01"),
+            },
+            "M" => {
+                let mut parts = arguments.splitn(2, ':');
+                let size = emulator.state.memory.ram().len();
+                let range = parts.next().and_then(|x| parse_range(x, size));
+                let data = parts.next().and_then(from_hex);
+                match (range, data) {
+                    (Some((address, length)), Some(data)) if data.len() == length => {
+                        emulator.state.memory.ram_mut()[address..address + length]
+                            .copy_from_slice(&data);
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            "Z" | "z" => self.handle_breakpoint(emulator, command == "Z", arguments),
+            "s" => self
+                .step(emulator, on_frame)
+                .unwrap_or_else(|| String::from("S05")),
+            "c" => self.resume(emulator, on_frame)?,
+            "D" => {
+                self.send("OK")?;
+                return Ok(Action::Stop);
+            }
+            "k" => return Ok(Action::Stop),
+            "H" => String::from("OK"),
+            "q" | "Q" => self.handle_query(emulator, packet),
+            _ => String::new(),
+        };
+        self.send(&reply)?;
+        Ok(Action::Continue)
+    }
+
+    /// Answers general query packets, or returns an empty reply for unsupported ones
+    fn handle_query(&mut self, emulator: &Emulator, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return String::from(
+                "PacketSize=4000;qXfer:features:read+;qXfer:memory-map:read+;QStartNoAckMode+;swbreak+",
+            );
+        }
+        if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+            return String::from("OK");
+        }
+        if let Some(arguments) = packet.strip_prefix("qXfer:") {
+            let document = if arguments.starts_with("features:read:target.xml:") {
+                target_xml()
+            } else if arguments.starts_with("memory-map:read::") {
+                memory_map(emulator.state.memory.ram().len())
+            } else {
+                return String::new();
+            };
+            let range = arguments.rsplit(':').next().unwrap_or("");
+            return match parse_transfer(range) {
+                Some((offset, length)) => {
+                    let start = offset.min(document.len());
+                    let end = start.saturating_add(length).min(document.len());
+                    let prefix = if end == document.len() { 'l' } else { 'm' };
+                    format!("{}{}", prefix, &document[start..end])
+                }
+                None => String::from("E01"),
+            };
+        }
+        match packet {
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            _ => String::new(),
+        }
+    }
+
+    fn handle_breakpoint(&mut self, emulator: &Emulator, insert: bool, arguments: &str) -> String {
+        let mut parts = arguments.split(',');
+        // Only software breakpoints are supported
+        if parts.next() != Some("0") {
+            return String::new();
+        }
+        match parts.next().and_then(|x| usize::from_str_radix(x, 16).ok()) {
+            Some(address) if emulator.state.memory.layout().code().contains(&address) => {
+                if insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                String::from("OK")
+            }
+            _ => String::from("E01"),
+        }
+    }
+
+    /// Executes one instruction, finishing the frame if it was the last one in it.
+    /// Returns the stop reply if the program can't continue.
+    fn step(
+        &mut self,
+        emulator: &mut Emulator,
+        on_frame: &mut dyn FnMut(&mut Emulator),
+    ) -> Option<String> {
+        if let Err(error) = emulator.step() {
+            return Some(stop_reply(&error));
+        }
+        self.ticks += 1;
+        if self.ticks >= self.ticks_per_frame {
+            self.ticks = 0;
+            on_frame(emulator);
+            emulator.end_frame();
+        }
+        if emulator.is_finished() {
+            return Some(String::from("W00"));
+        }
+        None
+    }
+
+    /// Runs the program until it reaches a breakpoint, finishes or is interrupted,
+    /// returning the stop reply
+    fn resume(
+        &mut self,
+        emulator: &mut Emulator,
+        on_frame: &mut dyn FnMut(&mut Emulator),
+    ) -> io::Result<String> {
+        // Step off the breakpoint the program is stopped at, if there is one
+        if let Some(reply) = self.step(emulator, on_frame) {
+            return Ok(reply);
+        }
+        loop {
+            if self.breakpoints.contains(&emulator.state.program_counter) {
+                return Ok(String::from("T05swbreak:;"));
+            }
+            // Checking the socket every instruction would be too slow
+            if self.ticks == 0 && self.poll_interrupt()? {
+                return Ok(String::from("S02"));
+            }
+            if let Some(reply) = self.step(emulator, on_frame) {
+                return Ok(reply);
+            }
+        }
+    }
+
+    fn read_register(emulator: &Emulator, number: usize) -> Vec<u8> {
+        let state = &emulator.state;
+        match number {
+            0..=15 => vec![state.registers.v_registers[number]],
+            I_REGISTER => state.registers.i_register.to_le_bytes().to_vec(),
+            STACK_POINTER => vec![state.stack.contents().len() as u8],
+            PROGRAM_COUNTER => (state.program_counter as u16).to_le_bytes().to_vec(),
+            DELAY_TIMER => vec![state.timers.delay_timer],
+            SOUND_TIMER => vec![state.timers.sound_timer],
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_registers(emulator: &Emulator) -> Vec<u8> {
+        (0..REGISTERS.len())
+            .flat_map(|x| Self::read_register(emulator, x))
+            .collect()
+    }
+
+    /// Writes a little endian value to a register, returning false if that's not possible.
+    /// The stack pointer can only be lowered, which pops values off the stack.
+    fn write_register(emulator: &mut Emulator, number: usize, value: &[u8]) -> bool {
+        let state = &mut emulator.state;
+        match REGISTERS.get(number) {
+            Some((_, size)) if *size == value.len() => {}
+            _ => return false,
+        }
+        match number {
+            0..=15 => state.registers.v_registers[number] = value[0],
+            I_REGISTER => state.registers.i_register = u16::from_le_bytes([value[0], value[1]]),
+            STACK_POINTER => {
+                let target = usize::from(value[0]);
+                if target > state.stack.contents().len() {
+                    return false;
+                }
+                while state.stack.contents().len() > target {
+                    state.stack.pop().unwrap();
+                }
+            }
+            PROGRAM_COUNTER => {
+                let address = usize::from(u16::from_le_bytes([value[0], value[1]]));
+                if !state.memory.layout().code().contains(&address) {
+                    return false;
+                }
+                state.program_counter = address;
+            }
+            DELAY_TIMER => state.timers.delay_timer = value[0],
+            SOUND_TIMER => state.timers.sound_timer = value[0],
+            _ => unreachable!(),
+        }
+        true
+    }
+
+    fn write_registers(emulator: &mut Emulator, values: &[u8]) -> bool {
+        let mut rest = values;
+        for (number, (_, size)) in REGISTERS.iter().enumerate() {
+            if rest.len() < *size {
+                return false;
+            }
+            let (value, remaining) = rest.split_at(*size);
+            // The stack pointer is skipped so writing back what was read always works
+            if number != STACK_POINTER && !Self::write_register(emulator, number, value) {
+                return false;
+            }
+            rest = remaining;
+        }
+        rest.is_empty()
+    }
+}
+
+/// Returns the stop reply for an instruction that failed, with the closest Unix signal
+fn stop_reply(error: &EmulatorError) -> String {
+    match error {
+        // SIGILL
+        EmulatorError::InvalidInstruction(..) => String::from("S04"),
+        // SIGSEGV
+        EmulatorError::ExecutionFailed(..) => String::from("S0b"),
+        EmulatorError::ProgramCounterOutOfBounds(_) => String::from("W00"),
+    }
+}
+
+/// Parses the `offset,length` of qXfer packets
+fn parse_transfer(range: &str) -> Option<(usize, usize)> {
+    let mut parts = range.splitn(2, ',');
+    let offset = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let length = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((offset, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{memory::MemoryLayout, State};
+    use std::{net::TcpListener, thread};
+
+    /// A minimal debugger client
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn read_reply(&mut self) -> String {
+            let mut byte = [0; 1];
+            let mut reply = Vec::new();
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                match byte[0] {
+                    b'+' if reply.is_empty() => continue,
+                    b'#' => break,
+                    x => reply.push(x),
+                }
+            }
+            let mut sum = [0; 2];
+            self.stream.read_exact(&mut sum).unwrap();
+            assert_eq!(reply[0], b'$');
+            assert_eq!(
+                u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap(),
+                checksum(&reply[1..])
+            );
+            String::from_utf8(reply[1..].to_vec()).unwrap()
+        }
+
+        fn send(&mut self, packet: &str) {
+            write!(
+                self.stream,
+                "${}#{:02x}",
+                packet,
+                checksum(packet.as_bytes())
+            )
+            .unwrap();
+        }
+
+        fn request(&mut self, packet: &str) -> String {
+            self.send(packet);
+            self.read_reply()
+        }
+    }
+
+    /// Debugs `program` with a server running two instructions per frame, while `session`
+    /// talks to it from another thread. Returns the emulator and the number of frames run.
+    fn debug<F>(program: &[u8], session: F) -> (Emulator, u64)
+    where
+        F: FnOnce(&mut Client) + Send + 'static,
+    {
+        debug_state(State::mock(program), session)
+    }
+
+    /// Debugs a program already loaded into `state`, like `debug`
+    fn debug_state<F>(state: State, session: F) -> (Emulator, u64)
+    where
+        F: FnOnce(&mut Client) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut client = Client { stream };
+            session(&mut client);
+            client.send("k");
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut emulator = Emulator::new(state, false);
+        let mut frames = 0;
+        GdbServer::new(stream, 2)
+            .serve(&mut emulator, &mut |_| frames += 1)
+            .unwrap();
+        client.join().unwrap();
+        (emulator, frames)
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        // LD V3, 0x2A
+        let (emulator, _) = debug(&[0x63, 0x2A], |client| {
+            assert!(client.request("qSupported:swbreak+").contains("swbreak+"));
+            assert_eq!(client.request("?"), "S05");
+            let registers = client.request("g");
+            assert_eq!(registers.len(), 23 * 2);
+            // V0-VF, then I, SP, PC (0x200, little endian), DT and ST
+            assert_eq!(&registers[32..], "00000000020000");
+            assert_eq!(client.request("m200,2"), "632a");
+            assert_eq!(client.request("M300,2:abcd"), "OK");
+            assert_eq!(client.request("m300,2"), "abcd");
+            assert_eq!(client.request("mfff,2"), "E01");
+            assert_eq!(client.request("P10=0003"), "OK");
+            assert_eq!(client.request("p10"), "0003");
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("p3"), "2a");
+            assert_eq!(client.request("p12"), "0202");
+            let registers = client.request("g");
+            assert_eq!(client.request(&format!("G{}", registers)), "OK");
+            assert_eq!(client.request("vMustReplyEmpty"), "");
+        });
+        assert_eq!(emulator.state.registers.i_register, 0x300);
+        assert_eq!(emulator.state.memory.ram()[0x300], 0xAB);
+    }
+
+    #[test]
+    fn test_target_description() {
+        debug(&[], |client| {
+            let mut xml = String::new();
+            loop {
+                let reply = client.request(&format!(
+                    "qXfer:features:read:target.xml:{:x},80",
+                    xml.len()
+                ));
+                xml.push_str(&reply[1..]);
+                if reply.starts_with('l') {
+                    break;
+                }
+                assert!(reply.starts_with('m'));
+            }
+            assert_eq!(xml, target_xml());
+            assert!(xml.contains(
+                "<reg name=\"pc\" bitsize=\"16\" regnum=\"18\" type=\"code_ptr\" generic=\"pc\"/>"
+            ));
+            assert!(client
+                .request("qXfer:memory-map:read::0,1000")
+                .contains("length=\"0x1000\""));
+        });
+    }
+
+    #[test]
+    fn test_memory_layout() {
+        let layout = MemoryLayout {
+            size: 0x800,
+            ..MemoryLayout::default()
+        };
+        debug_state(State::mock_with_layout(&[], layout), |client| {
+            assert_eq!(client.request("m7fe,2"), "0000");
+            assert_eq!(client.request("m800,2"), "E01");
+            assert_eq!(client.request("M900,1:ab"), "E01");
+            assert_eq!(client.request("Z0,900,2"), "E01");
+            assert_eq!(client.request("P12=0009"), "E01");
+            assert!(client
+                .request("qXfer:memory-map:read::0,1000")
+                .contains("length=\"0x800\""));
+        });
+        let layout = MemoryLayout {
+            size: 0x10000,
+            ..MemoryLayout::default()
+        };
+        let (emulator, _) = debug_state(State::mock_with_layout(&[], layout), |client| {
+            assert_eq!(client.request("M8000,1:ab"), "OK");
+            assert_eq!(client.request("mffff,1"), "00");
+            assert_eq!(client.request("Z0,1000,2"), "E01");
+        });
+        assert_eq!(emulator.state.memory.ram()[0x8000], 0xAB);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // ADD V0, 1; ADD V0, 1; JP 0x200
+        let (emulator, frames) = debug(&[0x70, 0x01, 0x70, 0x01, 0x12, 0x00], |client| {
+            assert_eq!(client.request("Z0,202,2"), "OK");
+            assert_eq!(client.request("c"), "T05swbreak:;");
+            assert_eq!(client.request("p0"), "01");
+            assert_eq!(client.request("c"), "T05swbreak:;");
+            assert_eq!(client.request("p0"), "03");
+            assert_eq!(client.request("z0,202,2"), "OK");
+            assert_eq!(client.request("Z1,202,2"), "");
+        });
+        // Two instructions run each frame
+        assert_eq!(frames, 2);
+        assert_eq!(emulator.frame, 2);
+    }
+
+    #[test]
+    fn test_faults() {
+        // RET with an empty stack, then an invalid opcode
+        debug(&[0x00, 0xEE, 0xFF, 0xFF], |client| {
+            assert_eq!(client.request("s"), "S0b");
+            assert_eq!(client.request("p12"), "0002");
+            assert_eq!(client.request("P12=0202"), "OK");
+            assert_eq!(client.request("c"), "S04");
+        });
+    }
+
+    #[test]
+    fn test_interrupt() {
+        // JP 0x200
+        debug(&[0x12, 0x00], |client| {
+            client.send("c");
+            client.stream.write_all(&[INTERRUPT]).unwrap();
+            assert_eq!(client.read_reply(), "S02");
+            assert_eq!(client.request("p12"), "0002");
+        });
+    }
+
+    #[test]
+    fn test_escape_and_hex() {
+        assert_eq!(escape(b"a#b}"), b"a}\x03b}]");
+        assert_eq!(from_hex("0aff"), Some(vec![0x0A, 0xFF]));
+        assert_eq!(from_hex("0a0"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(parse_range("ffe,2", 0x1000), Some((0xFFE, 2)));
+        assert_eq!(parse_range("fff,2", 0x1000), None);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::overflow_op;
 use crate::variables::{Read, Write};
 use std::fmt::Debug;
@@ -32,9 +32,10 @@ where
     T: Write<S> + Read<S>,
     U: Read<S>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         let (result, _): (S, bool) = self.0.read(state).overflowing_add(&self.1.read(state));
         self.0.write(state, result);
+        Ok(())
     }
 }
 
@@ -65,10 +66,11 @@ where
     T: Write<S> + Read<S>,
     U: Read<S>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         let (result, carry): (S, bool) = self.0.read(state).overflowing_add(&self.1.read(state));
-        state.registers.v_registers[0xF] = u8::from(carry);
         self.0.write(state, result);
+        state.registers.v_registers[0xF] = u8::from(carry);
+        Ok(())
     }
 }
 
@@ -96,7 +98,7 @@ mod tests {
         state.registers.v_registers[12] = 3;
         state.registers.i_register = 45;
         let add = ADD::new(I, V(B4(12)));
-        add.execute(&mut state);
+        add.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[12], 3);
         assert_eq!(state.registers.i_register, 48);
     }
@@ -107,7 +109,7 @@ mod tests {
         state.registers.v_registers[12] = 3;
         state.registers.v_registers[3] = 253;
         let addf = ADDF::new(V(B4(3)), V(B4(12)));
-        addf.execute(&mut state);
+        addf.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[12], 3);
         assert_eq!(state.registers.v_registers[3], 0);
         assert_eq!(state.registers.v_registers[0xF], 1);
@@ -119,7 +121,7 @@ mod tests {
         state.registers.v_registers[4] = 3;
         state.registers.v_registers[7] = 252;
         let addf = ADDF::new(V(B4(7)), V(B4(4)));
-        addf.execute(&mut state);
+        addf.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[4], 3);
         assert_eq!(state.registers.v_registers[7], 255);
         assert_eq!(state.registers.v_registers[0xF], 0);
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
 
 /// Represents the AND instruction (sets AND.0 = AND.0 | AND.1)
@@ -13,8 +13,9 @@ where
     T: Write<u8> + Read<u8>,
     U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         self.0.write(state, self.0.read(state) & self.1.read(state));
+        Ok(())
     }
 }
 
@@ -29,7 +30,7 @@ mod tests {
         state.registers.v_registers[3] = 0b1001_1001;
         state.registers.v_registers[5] = 0b1010_1011;
         let and = AND(V(B4(3)), V(B4(5)));
-        and.execute(&mut state);
+        and.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[3], 0b1000_1001);
         assert_eq!(state.registers.v_registers[5], 0b1010_1011);
     }
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{jump_target, ExecutionError, Instruction, State};
 use crate::variables::Read;
 
 /// Represents the CALL instruction (call subroutine at CALL.0)
@@ -6,15 +6,17 @@ use crate::variables::Read;
 pub struct CALL<T: Read<usize>>(pub T);
 
 impl<T: Read<usize>> Instruction for CALL<T> {
-    fn execute(&self, state: &mut State) {
-        state.stack.push(state.program_counter).unwrap();
-        state.program_counter = self.0.read(state) - 2;
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
+        state.stack.push(state.program_counter)?;
+        state.program_counter = jump_target(state, self.0.read(state));
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chip8::{memory::PROGRAM_START, stack::StackError};
     use crate::variables::{nibble::B4, tribble::B12};
 
     #[test]
@@ -22,9 +24,23 @@ mod tests {
         let mut state = State::mock(&[]);
         let program_counter = state.program_counter;
         let call = CALL(B12(B4(0b1000), B4(0b0010), B4(0b0001)));
-        call.execute(&mut state);
+        call.execute(&mut state).unwrap();
         state.program_counter += 2;
         assert_eq!(state.stack.top().unwrap(), program_counter);
         assert_eq!(state.program_counter, 0b1000_0010_0001);
     }
+
+    #[test]
+    fn test_call_full_stack() {
+        let mut state = State::mock(&[]);
+        for _ in 0..16 {
+            state.stack.push(0x200).unwrap();
+        }
+        let call = CALL(B12(B4(0b1000), B4(0b0010), B4(0b0001)));
+        assert_eq!(
+            call.execute(&mut state),
+            Err(ExecutionError::Stack(StackError::Full))
+        );
+        assert_eq!(state.program_counter, PROGRAM_START);
+    }
 }
@@ -1,11 +1,12 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 
 /// Represents the CLS instruction (clears the display)
 #[derive(Debug)]
 pub struct CLS;
 
 impl Instruction for CLS {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         state.display.clear();
+        Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::chip8::display::Display;
 use crate::variables::Read;
 
@@ -16,12 +16,14 @@ where
     T: Read<usize>,
     U: Read<usize>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         let x = self.0.read(state);
         let y = self.1.read(state);
         let mut vf = 0;
         for i in 0..self.2.read(state) {
-            let byte: u8 = state.memory.ram[usize::from(state.registers.i_register) + i];
+            let byte: u8 = state
+                .memory
+                .read(usize::from(state.registers.i_register) + i);
             for j in 0..8 {
                 let bit = (byte & (1 << (7 - j))) >> (7 - j);
                 vf |= state
@@ -30,6 +32,7 @@ where
             }
         }
         state.registers.v_registers[0xF] = vf;
+        Ok(())
     }
 }
 
@@ -44,7 +47,7 @@ mod tests {
         state.display.pixels[0][0] = 1;
         state.display.pixels[1][1] = 1;
         let drw = DRW(V(B4(0)), V(B4(1)), B4(5));
-        drw.execute(&mut state);
+        drw.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[0xF], 1);
         let image = [
             [0, 1, 1, 1, 0, 0, 0, 0],
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{jump_target, ExecutionError, Instruction, State};
 use crate::variables::Read;
 
 /// Represents the JP instruction (jump to the location at JP.0 + JP.1)
@@ -13,22 +13,52 @@ where
     T: Read<usize>,
     U: Read<usize>,
 {
-    fn execute(&self, state: &mut State) {
-        state.program_counter = self.1.read(state).wrapping_add(self.0.read(state)) - 2;
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
+        state.program_counter = jump_target(state, self.1.read(state) + self.0.read(state));
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::variables::{nibble::B4, tribble::B12};
+    use crate::chip8::memory::MemoryLayout;
+    use crate::variables::{nibble::B4, tribble::B12, vregister::V};
 
     #[test]
     fn test_jp() {
         let mut state = State::mock(&[]);
         let jp = JP(B4(0), B12(B4(0b0000), B4(0b1001), B4(0b0010)));
-        jp.execute(&mut state);
+        jp.execute(&mut state).unwrap();
         state.program_counter += 2;
         assert_eq!(state.program_counter, 0b0000_1001_0010);
     }
+
+    #[test]
+    fn test_jp_wraps() {
+        let mut state = State::mock(&[]);
+        state.registers.v_registers[0] = 0x10;
+        // 0xFF8 + 0x10 wraps around to 0x008
+        let jp = JP(V(B4(0)), B12(B4(0xF), B4(0xF), B4(0x8)));
+        jp.execute(&mut state).unwrap();
+        state.program_counter = state.program_counter.wrapping_add(2);
+        assert_eq!(state.program_counter, 0x008);
+        let jp = JP(B4(0), B12(B4(0), B4(0), B4(0)));
+        jp.execute(&mut state).unwrap();
+        state.program_counter = state.program_counter.wrapping_add(2);
+        assert_eq!(state.program_counter, 0);
+
+        // With less memory than the program counter reaches, jumps wrap around sooner
+        let mut state = State::mock_with_layout(
+            &[],
+            MemoryLayout {
+                size: 0x800,
+                ..MemoryLayout::default()
+            },
+        );
+        let jp = JP(B4(0), B12(B4(0x9), B4(0), B4(0)));
+        jp.execute(&mut state).unwrap();
+        state.program_counter = state.program_counter.wrapping_add(2);
+        assert_eq!(state.program_counter, 0x100);
+    }
 }
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
 use std::{fmt::Debug, marker::PhantomData};
 
@@ -27,8 +27,9 @@ where
     T: Write<S>,
     U: Read<S>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         self.0.write(state, self.1.read(state));
+        Ok(())
     }
 }
 
@@ -41,7 +42,7 @@ mod tests {
     fn test_ld() {
         let mut state = State::mock(&[]);
         let ld = LD::new(V(B4(11)), B8::from(92));
-        ld.execute(&mut state);
+        ld.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[11], 92);
     }
 }
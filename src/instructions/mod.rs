@@ -10,6 +10,7 @@ mod or;
 mod ret;
 mod rnd;
 mod se;
+mod seq;
 mod shl;
 mod shr;
 mod sknp;
@@ -19,7 +20,7 @@ mod sub;
 mod sys;
 mod xor;
 
-use super::chip8::State;
+use super::chip8::{quirks::Quirks, stack::StackError, State};
 use crate::variables::{
     bcd::BCD, byte::B8, delay_timer::DT, font::F, iregister::I, key::K, memory_at::AT, nibble::B4,
     range::RANGE, sound_timer::ST, tribble::B12, vregister::V,
@@ -35,31 +36,98 @@ use or::OR;
 use ret::RET;
 use rnd::RND;
 use se::SE;
+use seq::SEQ;
 use shl::SHL;
 use shr::SHR;
 use sknp::SKNP;
 use skp::SKP;
 use sne::SNE;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use sub::{SUB, SUBN};
 use sys::SYS;
 use xor::XOR;
 
+#[cfg(target_arch = "wasm32")]
+pub use rnd::seed_random;
+
 /// A trait for instructions.
 ///
 /// Defines one method, `execute(&self, &mut State)` because
-/// instructions should be able to be executed in the context of a State struct
+/// instructions should be able to be executed in the context of a State struct.
+/// If an instruction fails, it leaves the state as it was.
 pub trait Instruction: Debug {
-    fn execute(&self, state: &mut State);
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError>;
 }
 
 /// Error for when an instruction can't be parsed
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct InstructionError(String);
 
-pub fn parse(instruction: (u8, u8)) -> Result<Box<dyn Instruction>, InstructionError> {
+impl fmt::Display for InstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Error for when an instruction can't be executed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionError {
+    /// CALL with a full stack, or RET with an empty one
+    Stack(StackError),
+}
+
+impl From<StackError> for ExecutionError {
+    fn from(error: StackError) -> Self {
+        ExecutionError::Stack(error)
+    }
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecutionError::Stack(StackError::Full) => write!(f, "Stack overflow"),
+            ExecutionError::Stack(StackError::Empty) => write!(f, "Returned with an empty stack"),
+        }
+    }
+}
+
+/// Returns the program counter to set for the emulator to continue at `address`.
+/// Addresses past the end of the memory the program counter reaches wrap around to
+/// the start.
+/// The emulator moves the program counter on by 2 after each instruction, so this is
+/// 2 less than `address` (wrapping, so jumping to 0 works).
+fn jump_target(state: &State, address: usize) -> usize {
+    (address % state.memory.layout().code().end).wrapping_sub(2)
+}
+
+/// Parses an opcode into an instruction.
+/// Instructions that differ between interpreters are built according to `quirks`.
+pub fn parse(
+    instruction: (u8, u8),
+    quirks: &Quirks,
+) -> Result<Box<dyn Instruction>, InstructionError> {
     let (first, second): (B4, B4) = B4::from_u8(instruction.0);
     let (third, fourth): (B4, B4) = B4::from_u8(instruction.1);
+    let shift_source = if quirks.shift_vy { third } else { second };
+    let jump_offset = if quirks.jump_vx { second } else { B4(0) };
+    let logic = |operation: Box<dyn Instruction>| -> Box<dyn Instruction> {
+        if quirks.logic_resets_vf {
+            Box::new(SEQ(vec![
+                operation,
+                Box::new(LD::new(V(B4(0xF)), B8::from(0))),
+            ]))
+        } else {
+            operation
+        }
+    };
+    let load_store = |operation: Box<dyn Instruction>, x: u8| -> Box<dyn Instruction> {
+        if quirks.load_store_increments_i {
+            let count = B12(B4(0), B4((x + 1) >> 4), B4((x + 1) & 0xF));
+            Box::new(SEQ(vec![operation, Box::new(ADD::new(I, count))]))
+        } else {
+            operation
+        }
+    };
     match (first, second, third, fourth) {
         (B4(0x0), B4(0x0), B4(0xE), B4(0x0)) => Ok(Box::new(CLS)),
         (B4(0x0), B4(0x0), B4(0xE), B4(0xE)) => Ok(Box::new(RET)),
@@ -72,17 +140,17 @@ pub fn parse(instruction: (u8, u8)) -> Result<Box<dyn Instruction>, InstructionE
         (B4(0x6), _, _, _) => Ok(Box::new(LD::new(V(second), B8(third, fourth)))),
         (B4(0x7), _, _, _) => Ok(Box::new(ADD::new(V(second), B8(third, fourth)))),
         (B4(0x8), _, _, B4(0x0)) => Ok(Box::new(LD::new(V(second), V(third)))),
-        (B4(0x8), _, _, B4(0x1)) => Ok(Box::new(OR(V(second), V(third)))),
-        (B4(0x8), _, _, B4(0x2)) => Ok(Box::new(AND(V(second), V(third)))),
-        (B4(0x8), _, _, B4(0x3)) => Ok(Box::new(XOR(V(second), V(third)))),
+        (B4(0x8), _, _, B4(0x1)) => Ok(logic(Box::new(OR(V(second), V(third))))),
+        (B4(0x8), _, _, B4(0x2)) => Ok(logic(Box::new(AND(V(second), V(third))))),
+        (B4(0x8), _, _, B4(0x3)) => Ok(logic(Box::new(XOR(V(second), V(third))))),
         (B4(0x8), _, _, B4(0x4)) => Ok(Box::new(ADDF::new(V(second), V(third)))),
         (B4(0x8), _, _, B4(0x5)) => Ok(Box::new(SUB(V(second), V(third)))),
-        (B4(0x8), _, _, B4(0x6)) => Ok(Box::new(SHR(V(second)))),
+        (B4(0x8), _, _, B4(0x6)) => Ok(Box::new(SHR(V(second), V(shift_source)))),
         (B4(0x8), _, _, B4(0x7)) => Ok(Box::new(SUBN(V(second), V(third)))),
-        (B4(0x8), _, _, B4(0xE)) => Ok(Box::new(SHL(V(second)))),
+        (B4(0x8), _, _, B4(0xE)) => Ok(Box::new(SHL(V(second), V(shift_source)))),
         (B4(0x9), _, _, B4(0x0)) => Ok(Box::new(SNE(V(second), V(third)))),
         (B4(0xA), _, _, _) => Ok(Box::new(LD::new(I, B12(second, third, fourth)))),
-        (B4(0xB), _, _, _) => Ok(Box::new(JP(V(B4(0)), B12(second, third, fourth)))),
+        (B4(0xB), _, _, _) => Ok(Box::new(JP(V(jump_offset), B12(second, third, fourth)))),
         (B4(0xC), _, _, _) => Ok(Box::new(RND(V(second), B8(third, fourth)))),
         (B4(0xD), _, _, _) => Ok(Box::new(DRW(V(second), V(third), fourth))),
         (B4(0xE), _, B4(0x9), B4(0xE)) => Ok(Box::new(SKP(V(second)))),
@@ -104,7 +172,7 @@ pub fn parse(instruction: (u8, u8)) -> Result<Box<dyn Instruction>, InstructionE
                     .collect::<Vec<AT<I>>>(),
             );
             let registers = RANGE((0u8..=x).map(|y| V(B4(y))).collect::<Vec<V<B4>>>());
-            Ok(Box::new(LD::new(memory_at, registers)))
+            Ok(load_store(Box::new(LD::new(memory_at, registers)), x))
         }
         (B4(0xF), B4(x), B4(0x6), B4(0x5)) => {
             let memory_at = RANGE(
@@ -113,7 +181,7 @@ pub fn parse(instruction: (u8, u8)) -> Result<Box<dyn Instruction>, InstructionE
                     .collect::<Vec<AT<I>>>(),
             );
             let registers = RANGE((0u8..=x).map(|y| V(B4(y))).collect::<Vec<V<B4>>>());
-            Ok(Box::new(LD::new(registers, memory_at)))
+            Ok(load_store(Box::new(LD::new(registers, memory_at)), x))
         }
         _ => Err(InstructionError(format!(
             "Could not parse instruction: {:?}",
@@ -121,3 +189,70 @@ pub fn parse(instruction: (u8, u8)) -> Result<Box<dyn Instruction>, InstructionE
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(opcode: (u8, u8), quirks: Quirks, state: &mut State) {
+        parse(opcode, &quirks).unwrap().execute(state).unwrap();
+    }
+
+    #[test]
+    fn test_parse_shift_quirk() {
+        let mut state = State::mock(&[]);
+        state.registers.v_registers[1] = 0b0000_0010;
+        state.registers.v_registers[2] = 0b0000_1000;
+        run((0x81, 0x26), Quirks::default(), &mut state);
+        assert_eq!(state.registers.v_registers[1], 0b0000_0001);
+        let quirks = Quirks {
+            shift_vy: true,
+            ..Quirks::default()
+        };
+        run((0x81, 0x2E), quirks, &mut state);
+        assert_eq!(state.registers.v_registers[1], 0b0001_0000);
+    }
+
+    #[test]
+    fn test_parse_jump_quirk() {
+        let mut state = State::mock(&[]);
+        state.registers.v_registers[0] = 0x01;
+        state.registers.v_registers[3] = 0x10;
+        run((0xB3, 0x00), Quirks::default(), &mut state);
+        assert_eq!(state.program_counter.wrapping_add(2), 0x301);
+        let quirks = Quirks {
+            jump_vx: true,
+            ..Quirks::default()
+        };
+        run((0xB3, 0x00), quirks, &mut state);
+        assert_eq!(state.program_counter.wrapping_add(2), 0x310);
+    }
+
+    #[test]
+    fn test_parse_logic_quirk() {
+        let mut state = State::mock(&[]);
+        state.registers.v_registers[0xF] = 1;
+        run((0x81, 0x21), Quirks::default(), &mut state);
+        assert_eq!(state.registers.v_registers[0xF], 1);
+        let quirks = Quirks {
+            logic_resets_vf: true,
+            ..Quirks::default()
+        };
+        run((0x81, 0x22), quirks, &mut state);
+        assert_eq!(state.registers.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn test_parse_load_store_quirk() {
+        let mut state = State::mock(&[]);
+        state.registers.i_register = 0x300;
+        run((0xF2, 0x55), Quirks::default(), &mut state);
+        assert_eq!(state.registers.i_register, 0x300);
+        let quirks = Quirks {
+            load_store_increments_i: true,
+            ..Quirks::default()
+        };
+        run((0xFF, 0x65), quirks, &mut state);
+        assert_eq!(state.registers.i_register, 0x310);
+    }
+}
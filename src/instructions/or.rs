@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
 
 /// Represents the OR instruction (sets OR.0 = OR.0 | OR.1)
@@ -13,8 +13,9 @@ where
     T: Write<u8> + Read<u8>,
     U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         self.0.write(state, self.0.read(state) | self.1.read(state));
+        Ok(())
     }
 }
 
@@ -29,7 +30,7 @@ mod tests {
         state.registers.v_registers[1] = 0b1011_0001;
         state.registers.v_registers[13] = 0b1000_0010;
         let or = OR(V(B4(1)), V(B4(13)));
-        or.execute(&mut state);
+        or.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[13], 0b1000_0010);
         assert_eq!(state.registers.v_registers[1], 0b1011_0011);
     }
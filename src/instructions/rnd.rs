@@ -1,6 +1,33 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
-use rand::random;
+#[cfg(target_arch = "wasm32")]
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+#[cfg(target_arch = "wasm32")]
+use std::cell::RefCell;
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+}
+
+/// Returns a random byte from the OS's random number generator
+#[cfg(not(target_arch = "wasm32"))]
+fn random_byte() -> u8 {
+    rand::random()
+}
+
+/// Returns a random byte from a generator seeded by `seed_random`, since WebAssembly
+/// has no OS random number generator
+#[cfg(target_arch = "wasm32")]
+fn random_byte() -> u8 {
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+/// Reseeds the random number generator RND uses in WebAssembly
+#[cfg(target_arch = "wasm32")]
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
 
 /// Represents the RND instruction (set RND.0 to a random byte & RND.1)
 #[derive(Debug)]
@@ -14,8 +41,9 @@ where
     T: Write<u8>,
     U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
-        self.0.write(state, random::<u8>() & self.1.read(state));
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
+        self.0.write(state, random_byte() & self.1.read(state));
+        Ok(())
     }
 }
 
@@ -29,7 +57,7 @@ mod tests {
         let mut state = State::mock(&[]);
         state.registers.v_registers[3] = 4;
         let rnd = RND(V(B4(3)), B8::from(0));
-        rnd.execute(&mut state);
+        rnd.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[3], 0);
     }
 }
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::Read;
 
 /// Represents the SE instruction (skip the next instruction if SE.0 == SE.1)
@@ -6,10 +6,11 @@ use crate::variables::Read;
 pub struct SE<T: Read<u8>, U: Read<u8>>(pub T, pub U);
 
 impl<T: Read<u8>, U: Read<u8>> Instruction for SE<T, U> {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         if self.0.read(state) == self.1.read(state) {
             state.program_counter += 2;
         }
+        Ok(())
     }
 }
 
@@ -24,7 +25,7 @@ mod tests {
         state.registers.v_registers[4] = 98;
         let se = SE(V(B4(4)), B8::from(98));
         let program_counter = state.program_counter;
-        se.execute(&mut state);
+        se.execute(&mut state).unwrap();
         assert_eq!(state.program_counter, program_counter + 2);
     }
 
@@ -35,7 +36,7 @@ mod tests {
         state.registers.v_registers[2] = 44;
         let se = SE(V(B4(11)), V(B4(2)));
         let program_counter = state.program_counter;
-        se.execute(&mut state);
+        se.execute(&mut state).unwrap();
         assert_eq!(state.program_counter, program_counter);
     }
 }
@@ -0,0 +1,39 @@
+use super::{ExecutionError, Instruction, State};
+
+/// Represents a sequence of instructions executed one after another as a single instruction.
+/// Used to build the quirk variants of instructions that have side effects on top of
+/// their usual behavior (e.g. resetting VF after OR).
+/// Only the last instruction may fail, so a failure leaves the state as it was.
+#[derive(Debug)]
+pub struct SEQ(pub Vec<Box<dyn Instruction>>);
+
+impl Instruction for SEQ {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
+        for instruction in self.0.iter() {
+            instruction.execute(state)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::{ld::LD, or::OR};
+    use crate::variables::{byte::B8, nibble::B4, vregister::V};
+
+    #[test]
+    fn test_seq() {
+        let mut state = State::mock(&[]);
+        state.registers.v_registers[1] = 0b0000_0011;
+        state.registers.v_registers[2] = 0b0000_0100;
+        state.registers.v_registers[0xF] = 1;
+        let seq = SEQ(vec![
+            Box::new(OR(V(B4(1)), V(B4(2)))),
+            Box::new(LD::new(V(B4(0xF)), B8(B4(0), B4(0)))),
+        ]);
+        seq.execute(&mut state).unwrap();
+        assert_eq!(state.registers.v_registers[1], 0b0000_0111);
+        assert_eq!(state.registers.v_registers[0xF], 0);
+    }
+}
@@ -1,20 +1,23 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
 
-/// Represents the SHL instruction (bitwise left-shift on the contents of SHL.0)
+/// Represents the SHL instruction (sets SHL.0 = SHL.1 shifted left by one bit)
 #[derive(Debug)]
-pub struct SHL<T>(pub T)
+pub struct SHL<T, U>(pub T, pub U)
 where
-    T: Read<u8> + Write<u8>;
+    T: Write<u8>,
+    U: Read<u8>;
 
-impl<T> Instruction for SHL<T>
+impl<T, U> Instruction for SHL<T, U>
 where
-    T: Read<u8> + Write<u8>,
+    T: Write<u8>,
+    U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
-        let val = self.0.read(state);
-        state.registers.v_registers[0xF] = (val & 0b1000_0000) >> 7;
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
+        let val = self.1.read(state);
         self.0.write(state, val << 1);
+        state.registers.v_registers[0xF] = (val & 0b1000_0000) >> 7;
+        Ok(())
     }
 }
 
@@ -27,8 +30,8 @@ mod tests {
     fn test_shl_no_overflow() {
         let mut state = State::mock(&[]);
         state.registers.v_registers[5] = 0b0101_0111;
-        let shl = SHL(V(B4(5)));
-        shl.execute(&mut state);
+        let shl = SHL(V(B4(5)), V(B4(5)));
+        shl.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[5], 0b1010_1110);
         assert_eq!(state.registers.v_registers[0xF], 0);
     }
@@ -37,8 +40,8 @@ mod tests {
     fn test_shl_overflow() {
         let mut state = State::mock(&[]);
         state.registers.v_registers[8] = 0b1100_0101;
-        let shl = SHL(V(B4(8)));
-        shl.execute(&mut state);
+        let shl = SHL(V(B4(8)), V(B4(8)));
+        shl.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[8], 0b1000_1010);
         assert_eq!(state.registers.v_registers[0xF], 1);
     }
@@ -1,20 +1,23 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
 
-/// Represents the SHR instruction (bitwise right-shift on the contents of SHR.0)
+/// Represents the SHR instruction (sets SHR.0 = SHR.1 shifted right by one bit)
 #[derive(Debug)]
-pub struct SHR<T>(pub T)
+pub struct SHR<T, U>(pub T, pub U)
 where
-    T: Read<u8> + Write<u8>;
+    T: Write<u8>,
+    U: Read<u8>;
 
-impl<T> Instruction for SHR<T>
+impl<T, U> Instruction for SHR<T, U>
 where
-    T: Read<u8> + Write<u8>,
+    T: Write<u8>,
+    U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
-        let val = self.0.read(state);
-        state.registers.v_registers[0xF] = val & 1;
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
+        let val = self.1.read(state);
         self.0.write(state, val >> 1);
+        state.registers.v_registers[0xF] = val & 1;
+        Ok(())
     }
 }
 
@@ -27,8 +30,8 @@ mod tests {
     fn test_shr_no_overflow() {
         let mut state = State::mock(&[]);
         state.registers.v_registers[5] = 0b1001_0110;
-        let shr = SHR(V(B4(5)));
-        shr.execute(&mut state);
+        let shr = SHR(V(B4(5)), V(B4(5)));
+        shr.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[5], 0b0100_1011);
         assert_eq!(state.registers.v_registers[0xF], 0);
     }
@@ -37,9 +40,31 @@ mod tests {
     fn test_shr_overflow() {
         let mut state = State::mock(&[]);
         state.registers.v_registers[8] = 0b1100_0101;
-        let shr = SHR(V(B4(8)));
-        shr.execute(&mut state);
+        let shr = SHR(V(B4(8)), V(B4(8)));
+        shr.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[8], 0b0110_0010);
         assert_eq!(state.registers.v_registers[0xF], 1);
     }
+
+    #[test]
+    fn test_shr_vf() {
+        // The flag is written last, so it wins over the result
+        let mut state = State::mock(&[]);
+        state.registers.v_registers[0xF] = 0b0000_0011;
+        let shr = SHR(V(B4(0xF)), V(B4(0xF)));
+        shr.execute(&mut state).unwrap();
+        assert_eq!(state.registers.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_shr_other_register() {
+        let mut state = State::mock(&[]);
+        state.registers.v_registers[2] = 0b1111_1111;
+        state.registers.v_registers[3] = 0b0000_0101;
+        let shr = SHR(V(B4(2)), V(B4(3)));
+        shr.execute(&mut state).unwrap();
+        assert_eq!(state.registers.v_registers[2], 0b0000_0010);
+        assert_eq!(state.registers.v_registers[3], 0b0000_0101);
+        assert_eq!(state.registers.v_registers[0xF], 1);
+    }
 }
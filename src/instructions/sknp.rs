@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::Read;
 
 /// Represents the SKNP instruction (skips next instruction if the key corresponding to SKNP.0 is
@@ -12,9 +12,10 @@ impl<T> Instruction for SKNP<T>
 where
     T: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         if !state.keyboard.is_key_pressed(self.0.read(state)) {
             state.program_counter += 2;
         }
+        Ok(())
     }
 }
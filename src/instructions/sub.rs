@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
 
 /// Represents the SUB instruction (sets SUB.0 = SUB.0 + SUB.1)
@@ -14,10 +14,11 @@ where
     T: Write<u8> + Read<u8>,
     U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         let (result, carry): (u8, bool) = self.0.read(state).overflowing_sub(self.1.read(state));
-        state.registers.v_registers[0xF] = u8::from(!carry);
         self.0.write(state, result);
+        state.registers.v_registers[0xF] = u8::from(!carry);
+        Ok(())
     }
 }
 
@@ -34,10 +35,11 @@ where
     T: Write<u8> + Read<u8>,
     U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         let (result, carry): (u8, bool) = self.1.read(state).overflowing_sub(self.0.read(state));
-        state.registers.v_registers[0xF] = u8::from(!carry);
         self.0.write(state, result);
+        state.registers.v_registers[0xF] = u8::from(!carry);
+        Ok(())
     }
 }
 
@@ -52,7 +54,7 @@ mod tests {
         state.registers.v_registers[12] = 3;
         state.registers.v_registers[2] = 45;
         let sub = SUB(V(B4(2)), V(B4(12)));
-        sub.execute(&mut state);
+        sub.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[12], 3);
         assert_eq!(state.registers.v_registers[2], 42);
         assert_eq!(state.registers.v_registers[0xF], 1);
@@ -64,7 +66,7 @@ mod tests {
         state.registers.v_registers[12] = 253;
         state.registers.v_registers[3] = 3;
         let subn = SUBN(V(B4(3)), V(B4(12)));
-        subn.execute(&mut state);
+        subn.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[12], 253);
         assert_eq!(state.registers.v_registers[3], 250);
         assert_eq!(state.registers.v_registers[0xF], 1);
@@ -76,7 +78,7 @@ mod tests {
         state.registers.v_registers[12] = 200;
         state.registers.v_registers[11] = 45;
         let sub = SUB(V(B4(11)), V(B4(12)));
-        sub.execute(&mut state);
+        sub.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[12], 200);
         assert_eq!(state.registers.v_registers[11], 101);
         assert_eq!(state.registers.v_registers[0xF], 0);
@@ -88,7 +90,7 @@ mod tests {
         state.registers.v_registers[4] = 3;
         state.registers.v_registers[7] = 252;
         let subn = SUBN(V(B4(7)), V(B4(4)));
-        subn.execute(&mut state);
+        subn.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[4], 3);
         assert_eq!(state.registers.v_registers[7], 7);
         assert_eq!(state.registers.v_registers[0xF], 0);
@@ -1,5 +1,4 @@
-use super::Instruction;
-use super::State;
+use super::{jump_target, ExecutionError, Instruction, State};
 use crate::variables::Read;
 
 /// Represents the SYS instruction (goto a routine at SYS.0)
@@ -7,8 +6,9 @@ use crate::variables::Read;
 pub struct SYS<T: Read<usize>>(pub T);
 
 impl<T: Read<usize>> Instruction for SYS<T> {
-    fn execute(&self, state: &mut State) {
-        state.program_counter = self.0.read(state) - 2;
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
+        state.program_counter = jump_target(state, self.0.read(state));
+        Ok(())
     }
 }
 
@@ -21,7 +21,7 @@ mod tests {
     fn test_sys() {
         let mut state = State::mock(&[]);
         let sys = SYS(B12(B4(0b1011), B4(0b1001), B4(0b0010)));
-        sys.execute(&mut state);
+        sys.execute(&mut state).unwrap();
         state.program_counter += 2;
         assert_eq!(state.program_counter, 0b1011_1001_0010);
     }
@@ -1,4 +1,4 @@
-use super::{Instruction, State};
+use super::{ExecutionError, Instruction, State};
 use crate::variables::{Read, Write};
 
 /// Represents the XOR instruction (sets XOR.0 = XOR.0 ^ XOR.1)
@@ -13,8 +13,9 @@ where
     T: Write<u8> + Read<u8>,
     U: Read<u8>,
 {
-    fn execute(&self, state: &mut State) {
+    fn execute(&self, state: &mut State) -> Result<(), ExecutionError> {
         self.0.write(state, self.0.read(state) ^ self.1.read(state));
+        Ok(())
     }
 }
 
@@ -29,7 +30,7 @@ mod tests {
         state.registers.v_registers[0x0] = 0b1001_1001;
         state.registers.v_registers[0xE] = 0b1010_0011;
         let xor = XOR(V(B4(0x0)), V(B4(0xE)));
-        xor.execute(&mut state);
+        xor.execute(&mut state).unwrap();
         assert_eq!(state.registers.v_registers[0x0], 0b0011_1010);
         assert_eq!(state.registers.v_registers[0xE], 0b1010_0011);
     }
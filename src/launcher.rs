@@ -0,0 +1,348 @@
+use crate::config::Color;
+use crate::database::RomDatabase;
+use crate::rom::{self, Rom, RomError};
+use crate::text::{Canvas, CHAR_ADVANCE, LINE_HEIGHT};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A program in the launcher's list
+#[derive(Debug, Clone, PartialEq)]
+pub struct LauncherEntry {
+    pub path: PathBuf,
+    /// The title from the ROM database, or else the file's name
+    pub title: String,
+    pub description: String,
+    /// The program's name in the archive at `path`, for archives of several programs
+    pub entry: Option<String>,
+}
+
+/// A menu of the programs in a directory to choose one to run
+pub struct Launcher {
+    pub entries: Vec<LauncherEntry>,
+    selected: usize,
+    /// Shown under the list, e.g. when the last program chosen couldn't be loaded
+    pub message: Option<String>,
+    pub pixels: Vec<u8>,
+    /// The directory's entries and the one selected, while an archive's are shown
+    directory: Option<(Vec<LauncherEntry>, usize)>,
+}
+
+impl Launcher {
+    /// The number of characters on a line
+    const COLUMNS: usize = 48;
+
+    /// The width of the menu (in pixels)
+    pub const WIDTH: usize = Self::COLUMNS * CHAR_ADVANCE + 2;
+
+    /// The height of the menu (in pixels)
+    pub const HEIGHT: usize = 24 * LINE_HEIGHT + 2;
+
+    /// The number of entries shown at once
+    pub const VISIBLE: usize = 14;
+
+    const BACKGROUND: Color = Color(16, 16, 16, 255);
+    const TEXT: Color = Color(220, 220, 220, 255);
+    const DIM: Color = Color(120, 120, 120, 255);
+    const HIGHLIGHT: Color = Color(255, 200, 60, 255);
+
+    pub fn new(entries: Vec<LauncherEntry>) -> Self {
+        Launcher {
+            entries,
+            selected: 0,
+            message: None,
+            pixels: vec![0; Self::WIDTH * Self::HEIGHT * 4],
+            directory: None,
+        }
+    }
+
+    /// Lists the programs in a directory by title, with the titles and descriptions the
+    /// database has for them. Source is assembled to be loaded at `origin` to look it up.
+    pub fn scan(directory: &Path, database: &RomDatabase, origin: usize) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for file in fs::read_dir(directory)? {
+            let path = file?.path();
+            let listed = match path.extension().and_then(|x| x.to_str()) {
+                Some(extension) => rom::EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+                None => true,
+            };
+            if !path.is_file() || !listed {
+                continue;
+            }
+            let name = path
+                .file_name()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if name.starts_with('.') {
+                continue;
+            }
+            // Archives with several programs ask which one when they're launched
+            let info = rom::read(&path.to_string_lossy())
+                .and_then(|x| {
+                    Rom::load(&x, &name, origin, &mut |_| {
+                        Err(RomError::new("Several programs"))
+                    })
+                })
+                .ok()
+                .and_then(|x| database.lookup(&x.program).cloned());
+            entries.push(match info {
+                Some(info) => LauncherEntry {
+                    path,
+                    title: info.title,
+                    description: info.description,
+                    entry: None,
+                },
+                None => LauncherEntry {
+                    path,
+                    title: name,
+                    description: String::new(),
+                    entry: None,
+                },
+            });
+        }
+        entries.sort_by_key(|x| x.title.to_lowercase());
+        Ok(Launcher::new(entries))
+    }
+
+    pub fn selected(&self) -> Option<&LauncherEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Loads the selected program. If it's an archive of several programs, lists them in
+    /// place of the directory's to choose from instead, and returns None. Source is
+    /// assembled to be loaded at `origin`.
+    pub fn load_selected(&mut self, origin: usize) -> Result<Option<Rom>, RomError> {
+        let entry = match self.selected() {
+            Some(entry) => entry.clone(),
+            None => return Ok(None),
+        };
+        let bytes = rom::read(&entry.path.to_string_lossy())?;
+        let mut names = None;
+        let rom = Rom::load(
+            &bytes,
+            &entry.path.to_string_lossy(),
+            origin,
+            &mut |entries: &[String]| match &entry.entry {
+                Some(name) => entries.iter().position(|x| x == name).ok_or_else(|| {
+                    RomError::new(&format!("The archive has no entry named {}", name))
+                }),
+                None => {
+                    names = Some(entries.to_vec());
+                    Err(RomError::new("The archive has several programs"))
+                }
+            },
+        );
+        match names {
+            Some(names) => {
+                self.open_archive(&entry.path, names);
+                Ok(None)
+            }
+            None => rom.map(Some),
+        }
+    }
+
+    /// Lists the programs in the archive at `path` in place of the directory's
+    fn open_archive(&mut self, path: &Path, names: Vec<String>) {
+        let entries = names
+            .into_iter()
+            .map(|name| LauncherEntry {
+                path: path.to_path_buf(),
+                title: name.clone(),
+                description: String::new(),
+                entry: Some(name),
+            })
+            .collect();
+        let directory = std::mem::replace(&mut self.entries, entries);
+        self.directory = Some((directory, self.selected));
+        self.selected = 0;
+        self.message = None;
+    }
+
+    /// Goes back from an archive's programs to the directory's, returning false if the
+    /// directory's were already shown
+    pub fn close_archive(&mut self) -> bool {
+        match self.directory.take() {
+            Some((entries, selected)) => {
+                self.entries = entries;
+                self.selected = selected;
+                self.message = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the selection down by `delta` entries, or up if it's negative, stopping at
+    /// the ends of the list
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    /// Redraws the menu
+    pub fn render(&mut self) {
+        let lines = self.lines();
+        let mut canvas = Canvas::new(&mut self.pixels, Self::WIDTH);
+        canvas.fill(Self::BACKGROUND);
+        for (index, (line, color)) in lines.iter().enumerate() {
+            canvas.draw_text(2, 2 + index * LINE_HEIGHT, line, *color);
+        }
+    }
+
+    /// Returns the lines of text in the menu and their colors
+    fn lines(&self) -> Vec<(String, Color)> {
+        let heading = match self.directory {
+            Some(_) => "CHOOSE A PROGRAM FROM THE ARCHIVE",
+            None => "CHOOSE A PROGRAM",
+        };
+        let mut lines = vec![(String::from(heading), Self::TEXT)];
+        lines.push((String::new(), Self::TEXT));
+        if self.entries.is_empty() {
+            lines.push((String::from("NO PROGRAMS FOUND"), Self::DIM));
+        }
+        // Keep the selection in the middle of the list when there's more than fits
+        let first = self
+            .selected
+            .saturating_sub(Self::VISIBLE / 2)
+            .min(self.entries.len().saturating_sub(Self::VISIBLE));
+        for (index, entry) in self.entries.iter().enumerate().skip(first) {
+            if index == first + Self::VISIBLE {
+                break;
+            }
+            let mut title: String = entry.title.chars().take(Self::COLUMNS - 2).collect();
+            if index == self.selected {
+                title.insert_str(0, "> ");
+                lines.push((title, Self::HIGHLIGHT));
+            } else {
+                title.insert_str(0, "  ");
+                lines.push((title, Self::TEXT));
+            }
+        }
+        lines.resize(2 + Self::VISIBLE + 1, (String::new(), Self::TEXT));
+        let details = match (&self.message, self.selected()) {
+            (Some(message), _) => wrap(message, Self::COLUMNS),
+            (None, Some(entry)) => wrap(&entry.description, Self::COLUMNS),
+            (None, None) => Vec::new(),
+        };
+        lines.extend(details.into_iter().take(4).map(|x| (x, Self::DIM)));
+        lines.resize(23, (String::new(), Self::TEXT));
+        let footer = match self.directory {
+            Some(_) => "UP/DOWN: CHOOSE  ENTER: RUN  F2: MENU  ESC: BACK",
+            None => "UP/DOWN: CHOOSE  ENTER: RUN  F2: MENU  ESC: QUIT",
+        };
+        lines.push((String::from(footer), Self::DIM));
+        lines
+    }
+}
+
+/// Splits text into lines of at most `width` characters, breaking between words
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::memory::PROGRAM_START;
+    use std::{env, io::Write};
+
+    fn entry(title: &str) -> LauncherEntry {
+        LauncherEntry {
+            path: PathBuf::from(title),
+            title: String::from(title),
+            description: String::new(),
+            entry: None,
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let launcher =
+            Launcher::scan(Path::new("roms"), &RomDatabase::builtin(), PROGRAM_START).unwrap();
+        let titles: Vec<&str> = launcher.entries.iter().map(|x| x.title.as_str()).collect();
+        assert!(titles.contains(&"Pong"));
+        assert!(!titles.contains(&"database.json"));
+        let mut sorted = titles.clone();
+        sorted.sort_by_key(|x| x.to_lowercase());
+        assert_eq!(titles, sorted);
+        assert_eq!(launcher.selected(), launcher.entries.first());
+    }
+
+    #[test]
+    fn test_move_selection() {
+        let mut launcher = Launcher::new((0..20).map(|x| entry(&x.to_string())).collect());
+        launcher.move_selection(-1);
+        assert_eq!(launcher.selected().unwrap().title, "0");
+        launcher.move_selection(12);
+        assert_eq!(launcher.selected().unwrap().title, "12");
+        let lines = launcher.lines();
+        assert_eq!(lines[2].0, "  5");
+        assert!(lines.iter().any(|(line, _)| line == "> 12"));
+        launcher.move_selection(100);
+        assert_eq!(launcher.selected().unwrap().title, "19");
+        assert_eq!(launcher.lines()[2].0, "  6");
+        assert_eq!(launcher.lines().len() * LINE_HEIGHT + 2, Launcher::HEIGHT);
+    }
+
+    #[test]
+    fn test_load_selected_archive() {
+        let directory = env::temp_dir().join("chip8_test_launcher");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("games.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        for (name, program) in [("pong.ch8", [1, 2]), ("tetris.ch8", [3, 4])] {
+            writer
+                .start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(&program).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut launcher =
+            Launcher::scan(&directory, &RomDatabase::builtin(), PROGRAM_START).unwrap();
+        assert_eq!(launcher.load_selected(PROGRAM_START), Ok(None));
+        let titles: Vec<&str> = launcher.entries.iter().map(|x| x.title.as_str()).collect();
+        assert_eq!(titles, ["pong.ch8", "tetris.ch8"]);
+        assert_eq!(
+            launcher.lines()[23].0,
+            "UP/DOWN: CHOOSE  ENTER: RUN  F2: MENU  ESC: BACK"
+        );
+        launcher.move_selection(1);
+        assert_eq!(
+            launcher
+                .load_selected(PROGRAM_START)
+                .unwrap()
+                .unwrap()
+                .program,
+            [3, 4]
+        );
+        assert!(launcher.close_archive());
+        assert_eq!(launcher.selected().unwrap().title, "games.zip");
+        assert!(!launcher.close_archive());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("Pong for two players.  The left paddle", 16),
+            ["Pong for two", "players. The", "left paddle"]
+        );
+        assert!(wrap("", 16).is_empty());
+    }
+}
@@ -0,0 +1,36 @@
+//! A Chip-8 emulator.
+//!
+//! The `chip8` binary is a thin frontend over this library, which holds the machine
+//! state, the instructions and everything used to present and debug a running program.
+
+pub mod analysis;
+pub mod capture;
+pub mod cheats;
+pub mod chip8;
+pub mod config;
+pub mod coverage;
+pub mod dap;
+pub mod database;
+pub mod emulator;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filters;
+pub mod gdb;
+pub mod instructions;
+pub mod launcher;
+pub mod lint;
+pub mod octo;
+pub mod overlay;
+pub mod palette;
+pub mod profiler;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod recording;
+pub mod rom;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod text;
+pub mod variables;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod window;
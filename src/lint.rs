@@ -0,0 +1,550 @@
+use crate::analysis::{disassemble, Analysis, Block, EdgeKind};
+use crate::chip8::{display::Display, memory::MemoryLayout, quirks::Quirks};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The kinds of problems the linter looks for
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// SYS calls machine code on the original hardware, which can't be emulated
+    MachineCode,
+    /// BNNN adds V0 on most interpreters, but VX on SUPER-CHIP
+    JumpOffset,
+    /// 8XY6 and 8XYE with X≠Y shift VY on the COSMAC VIP, but VX on most others
+    ShiftSource,
+    /// FX55 and FX65 move I on the COSMAC VIP, but not on most others
+    LoadStore,
+    /// Sprites that cross the edge of the screen wrap around here, but are clipped elsewhere
+    SpriteWrap,
+    /// Subroutines nested more than 16 deep overflow the stack
+    StackDepth,
+    /// Writes below the program (usually 0x200), where the font and (on the original
+    /// hardware) the interpreter live
+    FontWrite,
+    /// Instructions from SUPER-CHIP or XO-CHIP, or bytes that aren't instructions at all
+    NotChip8,
+}
+
+impl Rule {
+    fn name(&self) -> &'static str {
+        match self {
+            Rule::MachineCode => "machine-code",
+            Rule::JumpOffset => "jump-offset",
+            Rule::ShiftSource => "shift-source",
+            Rule::LoadStore => "load-store",
+            Rule::SpriteWrap => "sprite-wrap",
+            Rule::StackDepth => "stack-depth",
+            Rule::FontWrite => "font-write",
+            Rule::NotChip8 => "not-chip8",
+        }
+    }
+}
+
+/// Something suspicious about a program
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    /// The address of the instruction it's about
+    pub address: usize,
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:03X}: {} [{}]",
+            self.address,
+            self.message,
+            self.rule.name()
+        )
+    }
+}
+
+/// The quirk profile a program most likely expects, and why
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Suggestion {
+    pub profile: &'static str,
+    pub reason: String,
+}
+
+/// The findings for a program, in address order, and the quirk profile it likely expects
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Lint {
+    pub findings: Vec<Finding>,
+    pub suggestion: Suggestion,
+}
+
+impl Lint {
+    /// Lints the code reached from the start of a program loaded where `layout` says
+    pub fn new(program: &[u8], layout: &MemoryLayout, quirks: &Quirks) -> Self {
+        let analysis = Analysis::new(program, layout, quirks);
+        let origin = layout.program_start;
+        let opcode_at = |address: usize| {
+            let high = program[address - origin];
+            let low = program[address + 1 - origin];
+            u16::from_be_bytes([high, low])
+        };
+        let assembly_at = |address: usize| {
+            let [high, low] = opcode_at(address).to_be_bytes();
+            disassemble((high, low))
+        };
+
+        let mut findings = Vec::new();
+        for block in analysis.blocks.iter() {
+            let mut registers: [Option<u8>; 16] = [None; 16];
+            for (index, line) in block.instructions.iter().enumerate() {
+                let op = opcode_at(line.address);
+                let mut find = |rule, message: String| {
+                    findings.push(Finding {
+                        address: line.address,
+                        rule,
+                        message,
+                    })
+                };
+                let (x, y, n) = (
+                    usize::from(op >> 8 & 0xF),
+                    usize::from(op >> 4 & 0xF),
+                    op & 0xF,
+                );
+                if let Some(extension) = extension(op) {
+                    find(Rule::NotChip8, format!("{} is {}", line.opcode, extension));
+                } else if line.assembly.starts_with("DW") {
+                    find(
+                        Rule::NotChip8,
+                        format!(
+                            "{} is reached as code but isn't an instruction",
+                            line.opcode
+                        ),
+                    );
+                } else if line.assembly.starts_with("SYS") {
+                    find(
+                        Rule::MachineCode,
+                        format!(
+                            "{} calls machine code on the original hardware; it's treated as a jump here",
+                            line.assembly
+                        ),
+                    );
+                } else if op >> 12 == 0xB {
+                    let (here, elsewhere) = if quirks.jump_vx {
+                        (x, String::from("V0 on most other interpreters"))
+                    } else {
+                        (0, format!("V{:X} on SUPER-CHIP", x))
+                    };
+                    find(
+                        Rule::JumpOffset,
+                        format!(
+                            "{} jumps to {:#05X} + V{:X} here, but to {:#05X} + {}",
+                            line.assembly,
+                            op & 0xFFF,
+                            here,
+                            op & 0xFFF,
+                            elsewhere
+                        ),
+                    );
+                } else if op >> 12 == 0x8 && (n == 0x6 || n == 0xE) && x != y {
+                    let message = if quirks.shift_vy {
+                        format!(
+                            "{} shifts V{:X} into V{:X} here, but shifts V{:X} in place on most other interpreters",
+                            line.assembly, y, x, x
+                        )
+                    } else {
+                        format!(
+                            "{} shifts V{:X} here, but shifts V{:X} into V{:X} on the COSMAC VIP",
+                            line.assembly, x, y, x
+                        )
+                    };
+                    find(Rule::ShiftSource, message);
+                } else if op & 0xF0FF == 0xF055 || op & 0xF0FF == 0xF065 {
+                    let rest = &block.instructions[index + 1..];
+                    let reused = rest
+                        .iter()
+                        .map(|x| opcode_at(x.address))
+                        .take_while(|op| op >> 12 != 0xA)
+                        .any(uses_i);
+                    let message = if reused {
+                        "which this block uses afterwards"
+                    } else {
+                        "which doesn't matter unless I is used before it's set again"
+                    };
+                    let difference = if quirks.load_store_increments_i {
+                        "moves I past the registers here, but leaves it on most other interpreters"
+                    } else {
+                        "moves I past the registers on the COSMAC VIP"
+                    };
+                    find(
+                        Rule::LoadStore,
+                        format!("{} {}, {}", line.assembly, difference, message),
+                    );
+                } else if op >> 12 == 0xD {
+                    if let (Some(vx), Some(vy)) = (registers[x], registers[y]) {
+                        let (left, top) = (
+                            usize::from(vx) % Display::WIDTH,
+                            usize::from(vy) % Display::HEIGHT,
+                        );
+                        if left + 8 > Display::WIDTH || top + usize::from(n) > Display::HEIGHT {
+                            find(
+                                Rule::SpriteWrap,
+                                format!(
+                                    "{} draws at ({}, {}), across the edge of the screen; it wraps around here but is clipped on most other interpreters",
+                                    line.assembly, vx, vy
+                                ),
+                            );
+                        }
+                    }
+                }
+                track_registers(op, &mut registers);
+            }
+        }
+
+        for write in analysis.memory_writes.iter() {
+            let start = write.target.start % layout.size;
+            if start < origin {
+                findings.push(Finding {
+                    address: write.address,
+                    rule: Rule::FontWrite,
+                    message: format!(
+                        "{} writes to {:#05X}, below {:#05X} where the font and the original interpreter live",
+                        assembly_at(write.address),
+                        start,
+                        origin
+                    ),
+                });
+            }
+        }
+        findings.extend(stack_depth(&analysis));
+        findings.sort_by_key(|x| x.address);
+
+        let suggestion = Self::suggest(&findings);
+        Lint {
+            findings,
+            suggestion,
+        }
+    }
+
+    /// Returns the findings and suggestion as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Suggests the quirk profile a program expects, from the instructions it uses
+    fn suggest(findings: &[Finding]) -> Suggestion {
+        let has = |rule: Rule| findings.iter().any(|x| x.rule == rule);
+        let extensions = findings
+            .iter()
+            .any(|x| x.rule == Rule::NotChip8 && x.message.contains("SUPER-CHIP"));
+        let (profile, reason) = if extensions {
+            ("super-chip", "it uses SUPER-CHIP instructions")
+        } else if has(Rule::MachineCode) {
+            (
+                "cosmac-vip",
+                "it calls machine code, so it was written for the original hardware",
+            )
+        } else if has(Rule::ShiftSource) || has(Rule::LoadStore) || has(Rule::JumpOffset) {
+            (
+                "default",
+                "it uses instructions that differ between interpreters; try cosmac-vip if it misbehaves",
+            )
+        } else {
+            (
+                "default",
+                "it doesn't use any instructions that differ between interpreters",
+            )
+        };
+        Suggestion {
+            profile,
+            reason: String::from(reason),
+        }
+    }
+}
+
+/// Returns a description of the SUPER-CHIP or XO-CHIP instruction an opcode is, if it is one
+fn extension(op: u16) -> Option<&'static str> {
+    match op {
+        0x00C0..=0x00CF => Some("SUPER-CHIP's scroll down (00CN)"),
+        0x00D0..=0x00DF => Some("XO-CHIP's scroll up (00DN)"),
+        0x00FB => Some("SUPER-CHIP's scroll right (00FB)"),
+        0x00FC => Some("SUPER-CHIP's scroll left (00FC)"),
+        0x00FD => Some("SUPER-CHIP's exit (00FD)"),
+        0x00FE => Some("SUPER-CHIP's low resolution mode (00FE)"),
+        0x00FF => Some("SUPER-CHIP's high resolution mode (00FF)"),
+        0xF000 => Some("XO-CHIP's long load of I (F000 NNNN)"),
+        0xF002 => Some("XO-CHIP's audio pattern load (F002)"),
+        _ => match op & 0xF00F {
+            0xD000 => Some("SUPER-CHIP's 16x16 sprite (DXY0)"),
+            0x5002 => Some("XO-CHIP's register range save (5XY2)"),
+            0x5003 => Some("XO-CHIP's register range load (5XY3)"),
+            _ => match op & 0xF0FF {
+                0xF001 => Some("XO-CHIP's drawing plane selection (FN01)"),
+                0xF030 => Some("SUPER-CHIP's large font (FX30)"),
+                0xF03A => Some("XO-CHIP's audio pitch (FX3A)"),
+                0xF075 => Some("SUPER-CHIP's flag save (FX75)"),
+                0xF085 => Some("SUPER-CHIP's flag load (FX85)"),
+                _ => None,
+            },
+        },
+    }
+}
+
+/// Returns whether an instruction reads or writes I, or memory through it
+fn uses_i(op: u16) -> bool {
+    op >> 12 == 0xD || [0xF01E, 0xF033, 0xF055, 0xF065].contains(&(op & 0xF0FF))
+}
+
+/// Keeps track of the V registers whose values are known within a block
+fn track_registers(op: u16, registers: &mut [Option<u8>; 16]) {
+    let (x, y, nn) = (
+        usize::from(op >> 8 & 0xF),
+        usize::from(op >> 4 & 0xF),
+        op as u8,
+    );
+    match op >> 12 {
+        0x6 => registers[x] = Some(nn),
+        0x7 => registers[x] = registers[x].map(|v| v.wrapping_add(nn)),
+        0x8 => {
+            registers[x] = if op & 0xF == 0 { registers[y] } else { None };
+            registers[0xF] = None;
+        }
+        0xC => registers[x] = None,
+        0xD => registers[0xF] = None,
+        0xF => match op & 0xFF {
+            0x07 | 0x0A => registers[x] = None,
+            0x65 => registers[..=x].iter_mut().for_each(|v| *v = None),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// Finds call chains more than 16 deep, or recursion, which could overflow the stack
+fn stack_depth(analysis: &Analysis) -> Vec<Finding> {
+    // The CALL instructions reached from each piece of code, and what they call
+    let calls = |blocks: Vec<&Block>| -> Vec<(usize, usize)> {
+        blocks
+            .iter()
+            .flat_map(|block| {
+                block
+                    .successors
+                    .iter()
+                    .filter(|edge| edge.kind == EdgeKind::Call)
+                    .map(move |edge| (block.end - 2, edge.to))
+            })
+            .collect()
+    };
+    let callees: BTreeMap<usize, Vec<(usize, usize)>> = analysis
+        .subroutines
+        .iter()
+        .map(|x| (x.entry, calls(analysis.reached_from(x.entry))))
+        .collect();
+
+    /// Returns the deepest chain of calls from a subroutine, or None if it can recurse
+    fn depth(
+        entry: usize,
+        callees: &BTreeMap<usize, Vec<(usize, usize)>>,
+        visiting: &mut BTreeSet<usize>,
+        known: &mut BTreeMap<usize, Option<usize>>,
+    ) -> Option<usize> {
+        if let Some(depth) = known.get(&entry) {
+            return *depth;
+        }
+        if !visiting.insert(entry) {
+            return None;
+        }
+        let mut deepest = Some(0);
+        for (_, callee) in callees.get(&entry).into_iter().flatten() {
+            deepest = match (deepest, depth(*callee, callees, visiting, known)) {
+                (Some(a), Some(b)) => Some(a.max(b + 1)),
+                _ => None,
+            };
+        }
+        visiting.remove(&entry);
+        known.insert(entry, deepest);
+        deepest
+    }
+
+    let mut findings = Vec::new();
+    let mut known = BTreeMap::new();
+    for (address, callee) in calls(analysis.reached_from(analysis.entry)) {
+        let message = match depth(callee, &callees, &mut BTreeSet::new(), &mut known) {
+            None => format!(
+                "CALL {:#05X} can recurse, so the stack can overflow",
+                callee
+            ),
+            Some(depth) if depth + 1 > 16 => format!(
+                "CALL {:#05X} nests {} subroutines deep, but the stack only holds 16",
+                callee,
+                depth + 1
+            ),
+            _ => continue,
+        };
+        findings.push(Finding {
+            address,
+            rule: Rule::StackDepth,
+            message,
+        });
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(program: &[u8]) -> Vec<(usize, Rule)> {
+        Lint::new(program, &MemoryLayout::default(), &Quirks::default())
+            .findings
+            .iter()
+            .map(|x| (x.address, x.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_quirky_instructions() {
+        let program = [
+            0x81, 0x26, // 200: SHR V1, V2
+            0x81, 0x1E, // 202: SHL V1, V1
+            0xA3, 0x00, // 204: LD I, 0x300
+            0xF1, 0x55, // 206: LD [I], V1
+            0xD0, 0x15, // 208: DRW V0, V1, 5
+            0x03, 0x00, // 20A: SYS 0x300
+        ];
+        assert_eq!(
+            rules(&program),
+            vec![
+                (0x200, Rule::ShiftSource),
+                (0x206, Rule::LoadStore),
+                (0x20A, Rule::MachineCode)
+            ]
+        );
+        let lint = Lint::new(&program, &MemoryLayout::default(), &Quirks::default());
+        assert!(lint.findings[1].message.contains("uses afterwards"));
+        assert_eq!(lint.suggestion.profile, "cosmac-vip");
+    }
+
+    #[test]
+    fn test_messages_follow_quirks() {
+        let program = [
+            0x81, 0x26, // 200: SHR V1, V2
+            0xF1, 0x55, // 202: LD [I], V1
+            0xB3, 0x00, // 204: JP V0, 0x300
+        ];
+        let messages = |quirks: &str| -> Vec<String> {
+            Lint::new(
+                &program,
+                &MemoryLayout::default(),
+                &Quirks::profile(quirks).unwrap(),
+            )
+            .findings
+            .into_iter()
+            .map(|x| x.message)
+            .collect()
+        };
+        let default = messages("default");
+        assert!(default[0].contains("shifts V1 here, but shifts V2 into V1 on the COSMAC VIP"));
+        assert!(default[1].contains("moves I past the registers on the COSMAC VIP"));
+        assert!(default[2].contains("jumps to 0x300 + V0 here, but to 0x300 + V3 on SUPER-CHIP"));
+        let vip = messages("cosmac-vip");
+        assert!(vip[0].contains("shifts V2 into V1 here, but shifts V1 in place"));
+        assert!(vip[1].contains("moves I past the registers here, but leaves it"));
+        let schip = messages("super-chip");
+        assert!(schip[2].contains("jumps to 0x300 + V3 here, but to 0x300 + V0 on most"));
+    }
+
+    #[test]
+    fn test_extensions() {
+        let program = [
+            0xD0, 0x10, // 200: 16x16 sprite
+            0x00, 0xFF, // 202: high resolution, which stops the analysis
+            0x5A, 0xB1, // 204: not an instruction, but never reached
+        ];
+        assert_eq!(
+            rules(&program),
+            vec![(0x200, Rule::NotChip8), (0x202, Rule::NotChip8)]
+        );
+        assert_eq!(rules(&program[4..]), vec![(0x200, Rule::NotChip8)]);
+        assert_eq!(
+            Lint::new(&program, &MemoryLayout::default(), &Quirks::default())
+                .suggestion
+                .profile,
+            "super-chip"
+        );
+    }
+
+    #[test]
+    fn test_sprite_wrap() {
+        let program = [
+            0x60, 0x3C, // 200: LD V0, 60
+            0x61, 0x00, // 202: LD V1, 0
+            0xD0, 0x15, // 204: DRW V0, V1, 5 (crosses the right edge)
+            0x70, 0xC4, // 206: ADD V0, 0xC4 (wraps to 0)
+            0xD0, 0x15, // 208: DRW V0, V1, 5
+            0xB2, 0x00, // 20A: JP V0, 0x200
+        ];
+        assert_eq!(
+            rules(&program),
+            vec![(0x204, Rule::SpriteWrap), (0x20A, Rule::JumpOffset)]
+        );
+    }
+
+    #[test]
+    fn test_font_write() {
+        let program = [
+            0xA0, 0x10, // 200: LD I, 0x010
+            0xF0, 0x33, // 202: LD B, V0
+            0x12, 0x02, // 204: JP 0x202
+        ];
+        assert_eq!(rules(&program), vec![(0x202, Rule::FontWrite)]);
+
+        // The same program for the ETI-660, which loads programs at 0x600
+        let program = [
+            0xA4, 0x00, // 600: LD I, 0x400
+            0xF0, 0x33, // 602: LD B, V0
+            0x16, 0x02, // 604: JP 0x602
+        ];
+        let layout = MemoryLayout {
+            program_start: 0x600,
+            ..MemoryLayout::default()
+        };
+        let lint = Lint::new(&program, &layout, &Quirks::default());
+        assert_eq!(lint.findings.len(), 1);
+        assert_eq!(
+            lint.findings[0].to_string(),
+            "602: LD B, V0 writes to 0x400, below 0x600 where the font and the original interpreter live [font-write]"
+        );
+    }
+
+    /// Returns a program that calls `depth` subroutines nested in each other
+    fn nested(depth: u16) -> Vec<u8> {
+        // 200: CALL 0x204, 202: JP 0x202
+        let mut program = vec![0x22, 0x04, 0x12, 0x02];
+        for i in 1..depth {
+            let [high, low] = (0x2204 + i * 4).to_be_bytes();
+            program.extend(&[high, low, 0x00, 0xEE]);
+        }
+        program.extend(&[0x00, 0xEE]);
+        program
+    }
+
+    #[test]
+    fn test_stack_depth() {
+        assert_eq!(rules(&nested(16)), vec![]);
+        assert_eq!(rules(&nested(17)), vec![(0x200, Rule::StackDepth)]);
+        // A subroutine that calls itself
+        assert_eq!(
+            rules(&[0x22, 0x02, 0x22, 0x02, 0x00, 0xEE]),
+            vec![(0x200, Rule::StackDepth), (0x202, Rule::StackDepth)]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let finding = Finding {
+            address: 0x20A,
+            rule: Rule::JumpOffset,
+            message: String::from("JP V0, 0x200 jumps"),
+        };
+        assert_eq!(finding.to_string(), "20A: JP V0, 0x200 jumps [jump-offset]");
+    }
+}
@@ -1,22 +1,51 @@
-mod chip8;
-mod config;
-mod instructions;
-mod variables;
-
+#[cfg(feature = "scripting")]
+use chip8::scripting::Script;
 use chip8::{
-    display::*, keyboard::SDLKeyboard, memory::Memory, registers::Registers, stack::Stack,
-    timers::Timers, State,
+    analysis::Analysis,
+    capture::{timestamped_path, Capturer},
+    cheats::{CheatConsole, Cheats},
+    chip8::{
+        display::*,
+        keyboard::{HeadlessKeyboard, Hotkey, SDLKeyboard},
+        memory::MemoryLayout,
+        quirks::Quirks,
+        State,
+    },
+    config::Config,
+    coverage::Coverage,
+    dap::{self, DapServer},
+    emulator::Emulator,
+    filters::Filter,
+    gdb::GdbServer,
+    launcher::Launcher,
+    lint::Lint,
+    overlay::Overlay,
+    palette::PaletteCycle,
+    profiler::Profiler,
+    rom::{self, Rom, RomError},
+    window::{GameWindow, TEXTURE_FORMAT},
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use sdl2::{
+    event::Event,
+    keyboard::Keycode,
+    pixels::Color,
+    render::{Texture, TextureCreator},
+    Sdl,
 };
-use clap::App;
-use config::Config;
-use instructions::Instruction;
-use sdl2;
+#[cfg(feature = "scripting")]
 use sdl2::{
-    pixels::PixelFormatEnum,
-    render::{Texture, TextureCreator, WindowCanvas},
+    rect::Rect,
+    render::{BlendMode, Canvas},
+    video::Window,
 };
 use std::{
-    fs, thread,
+    fs,
+    io::{self, BufReader},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
     time::{Duration, Instant},
 };
 
@@ -26,11 +55,56 @@ fn main() {
         .author("Theodore Wang")
         .version("v0.0.1")
         .about("A chip8 emulator")
+        .arg(
+            Arg::from_usage("[PROGRAM] 'Set the file containing the chip8 program: a binary, hex text, Octo source (Chip-8 instructions only, without :macro, :calc, :stringmode, :assert or :next), a zip archive or an Octo cartridge, or - for standard input. A directory opens a menu of the programs in it, as does leaving it out when the config file has a rom_directory'"),
+        )
         .args_from_usage(
-            "<PROGRAM>          'Set the file containing the chip8 program'
-            -c, --config=[FILE] 'Apply settings from a config.json file'
+            "-c, --config=[FILE] 'Apply settings from a config.json file'
             -d, --debug         'Print debugging information'
-            -s, --step          'Step through instructions one by one (press the key mapped to one to quit)'",
+            -s, --step          'Step through instructions one by one (press the key mapped to one to quit)'
+            --frames=[COUNT]    'Quit after emulating COUNT frames'
+            --screenshot=[FILE] 'Save the last frame as a PNG'
+            --gif=[FILE]        'Record frames to an animated GIF'
+            --gif-start=[FRAME] 'Frame number at which to start recording the GIF (default: 0)'
+            --gif-length=[COUNT] 'Number of frames to record to the GIF (default: until quitting)'
+            --record=[FILE]     'Record video and audio of every frame to FILE.y4m and FILE.wav'
+            --quirks=[PROFILE]  'Run with a quirk profile: default, cosmac-vip or super-chip (overrides the config file)'
+            --coverage=[FILE]   'Measure which instructions are executed, writing an annotated listing to FILE.lst and an lcov report to FILE.info'
+            --profile=[FILE]    'Profile the program, writing a report to FILE.txt and folded stacks for flamegraphs to FILE.folded'
+            --gdb=[PORT]        'Wait for a GDB connection on PORT and run the program under its control, without a window'
+            --script=[FILE]     'Run a Rhai script with hooks on frames, breakpoints and memory writes'
+            --cheats=[FILE]     'Freeze the values listed in FILE (default: the cheats saved for the program)'
+            --cheat-console     'Read commands from standard input to search memory for values and freeze them'
+            --entry=[NAME]      'Load NAME from a zip archive with several programs, instead of asking which'",
+        )
+        .arg(
+            Arg::from_usage("--headless 'Run without a window or keyboard input'")
+                .requires("frames"),
+        )
+        .arg(Arg::from_usage(
+            "--dap 'Run as a Debug Adapter Protocol server on stdin and stdout, which launches programs itself'",
+        ))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Analyzes a program without running it: its control-flow graph, subroutines, data and unreachable bytes")
+                .args_from_usage(
+                    "<PROGRAM>          'Set the file containing the chip8 program'
+                    --format=[FORMAT]   'Write the analysis as json or dot (default: json)'
+                    -o, --output=[FILE] 'Write the analysis to FILE instead of standard output'
+                    --quirks=[PROFILE]  'Analyze with a quirk profile: default, cosmac-vip or super-chip (overrides the config file)'
+                    --entry=[NAME]      'Load NAME from a zip archive with several programs, instead of asking which'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Reports instructions that behave differently between interpreters or could misbehave, and suggests a quirk profile")
+                .args_from_usage(
+                    "<PROGRAM>          'Set the file containing the chip8 program'
+                    --format=[FORMAT]   'Write the findings as text or json (default: text)'
+                    --quirks=[PROFILE]  'Lint with a quirk profile: default, cosmac-vip or super-chip (overrides the config file)'
+                    --entry=[NAME]      'Load NAME from a zip archive with several programs, instead of asking which'",
+                ),
         )
         .get_matches();
 
@@ -41,93 +115,759 @@ fn main() {
         Default::default()
     };
 
-    // Read program
-    let program: Vec<u8> =
-        fs::read(matches.value_of("PROGRAM").unwrap()).expect("Could not read the chip8 program!");
+    match matches.subcommand() {
+        ("analyze", Some(matches)) => return run_analyze(matches, &config),
+        ("lint", Some(matches)) => return run_lint(matches, &config),
+        _ => {}
+    }
 
-    // Set up SDL
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window(
-            "chip8 emulator",
-            Display::WIDTH as u32 * config.pixel_size,
-            Display::HEIGHT as u32 * config.pixel_size,
+    if matches.is_present("dap") {
+        run_dap(&config, parse_quirks(&matches));
+        return;
+    }
+
+    // Set up screenshots and recordings
+    let mut capturer = Capturer::new(config.pixel_size, config.frames_per_second);
+    if let Some(path) = matches.value_of("gif") {
+        capturer.schedule_gif(
+            path.into(),
+            parse_number(&matches, "gif-start").unwrap_or(0),
+            parse_number(&matches, "gif-length"),
+        );
+    }
+    if let Some(path) = matches.value_of("record") {
+        capturer
+            .record(Path::new(path))
+            .unwrap_or_else(|e| panic!("Could not start recording to {}: {}", path, e));
+    }
+
+    // Run the program, or the launcher's menu of programs to choose from
+    let path = matches
+        .value_of("PROGRAM")
+        .map(String::from)
+        .or_else(|| config.rom_directory.clone())
+        .expect("Give a program to run, or a directory of programs to choose from!");
+    let emulator = if Path::new(&path).is_dir() {
+        if matches.is_present("gdb") || matches.is_present("headless") {
+            panic!(
+                "The launcher needs a window, so give a program to run with --gdb or --headless!"
+            );
+        }
+        match run_launcher(Path::new(&path), &config, &mut capturer, &matches) {
+            Some(emulator) => emulator,
+            None => return,
+        }
+    } else {
+        let Launch {
+            program,
+            config,
+            layout,
+            quirks,
+        } = prepare(load_rom(&path, &config, &matches), &config, &matches)
+            .unwrap_or_else(|e| panic!("Could not load the chip8 program: {}", e));
+        let palette = config.get_palette();
+        let display = Display::new(palette.active(), palette.inactive());
+        if let Some(port) = matches.value_of("gdb") {
+            let mut state =
+                State::with_layout(&program, Box::new(HeadlessKeyboard::new()), display, layout);
+            state.quirks = quirks;
+            config.configure_memory(&mut state.memory);
+            let mut emulator = new_emulator(state, &program, &matches);
+            run_gdb(port, &mut emulator, &config, &mut capturer);
+            emulator
+        } else if matches.is_present("headless") {
+            let mut state =
+                State::with_layout(&program, Box::new(HeadlessKeyboard::new()), display, layout);
+            state.quirks = quirks;
+            config.configure_memory(&mut state.memory);
+            let mut emulator = new_emulator(state, &program, &matches);
+            run_headless(&mut emulator, &config, &mut capturer, &matches);
+            emulator
+        } else {
+            let mut frontend = Frontend::new(&config, false);
+            let (emulator, _) = run_window(
+                &mut frontend,
+                &program,
+                layout,
+                quirks,
+                &config,
+                &mut capturer,
+                &matches,
+            );
+            emulator
+        }
+    };
+
+    if let Some(path) = matches.value_of("screenshot") {
+        if let Err(e) = capturer.screenshot(Path::new(path), &emulator.state.display) {
+            eprintln!("Could not save the screenshot: {}", e);
+        }
+    }
+    if let Err(e) = capturer.finish() {
+        eprintln!("Could not save the recording: {}", e);
+    }
+    if let (Some(path), Some(profiler)) = (matches.value_of("profile"), &emulator.profiler) {
+        if let Err(e) = profiler.save(Path::new(path)) {
+            eprintln!("Could not save the profile: {}", e);
+        }
+    }
+    if let (Some(path), Some(coverage)) = (matches.value_of("coverage"), &emulator.coverage) {
+        println!("{}", coverage.summary());
+        if let Err(e) = coverage.save(Path::new(path)) {
+            eprintln!("Could not save the coverage: {}", e);
+        }
+    }
+    #[cfg(feature = "scripting")]
+    {
+        if let Some(code) = emulator.script.as_ref().and_then(Script::exit_code) {
+            std::process::exit(code);
+        }
+    }
+}
+
+/// Creates an emulator for the state, with the debugging, profiling, coverage and
+/// script asked for
+fn new_emulator(state: State, program: &[u8], matches: &ArgMatches) -> Emulator {
+    let quirks = state.quirks;
+    let layout = state.memory.layout();
+    let mut emulator = Emulator::new(state, matches.is_present("debug"));
+    if matches.is_present("profile") {
+        emulator.profiler = Some(Profiler::new(&layout));
+    }
+    if matches.is_present("coverage") {
+        emulator.coverage = Some(Coverage::new(program, &layout, &quirks));
+    }
+    if let Some(path) = cheats_path(program, matches) {
+        if path.exists() || matches.is_present("cheats") {
+            emulator.cheats = Cheats::load(&path).unwrap_or_else(|e| {
+                panic!("Could not load the cheats from {}: {}", path.display(), e)
+            });
+        }
+    }
+    if let Some(path) = matches.value_of("script") {
+        load_script(&mut emulator, path);
+    }
+    emulator
+}
+
+/// Returns the cheat list given with --cheats, or else the one saved for the program
+fn cheats_path(program: &[u8], matches: &ArgMatches) -> Option<PathBuf> {
+    matches
+        .value_of("cheats")
+        .map(PathBuf::from)
+        .or_else(|| Cheats::default_path(program))
+}
+
+/// Loads a script into the emulator and runs its top level
+#[cfg(feature = "scripting")]
+fn load_script(emulator: &mut Emulator, path: &str) {
+    let mut script = Script::from_file(Path::new(path))
+        .unwrap_or_else(|e| panic!("Could not load the script {}: {}", path, e));
+    let state = &mut emulator.state;
+    let keyboard = std::mem::replace(&mut state.keyboard, Box::new(HeadlessKeyboard::new()));
+    state.keyboard = script.keyboard(keyboard);
+    script.start(state);
+    emulator.script = Some(script);
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_script(_: &mut Emulator, _: &str) {
+    panic!("This chip8 was built without scripting! Please rebuild it with the scripting feature.");
+}
+
+/// Parses the value of a numeric command line option, if it was given
+fn parse_number(matches: &ArgMatches, name: &str) -> Option<u64> {
+    matches.value_of(name).map(|x| {
+        x.parse()
+            .unwrap_or_else(|_| panic!("--{} should be a number, not {}", name, x))
+    })
+}
+
+/// Reads a program in whatever format it's in, panicking if it can't
+fn load_rom(path: &str, config: &Config, matches: &ArgMatches) -> Rom {
+    read_rom(path, config, matches)
+        .unwrap_or_else(|e| panic!("Could not load the chip8 program at {}: {}", path, e))
+}
+
+/// Reads a program in whatever format it's in. For an archive of several programs,
+/// loads the one named by --entry or else asks which. Source is assembled where the
+/// config file's memory layout starts programs, since the database can only be looked up
+/// by the assembled program.
+fn read_rom(path: &str, config: &Config, matches: &ArgMatches) -> Result<Rom, RomError> {
+    let bytes = rom::read(path)?;
+    let mut choose = |entries: &[String]| match matches.value_of("entry") {
+        Some(name) => entries
+            .iter()
+            .position(|x| x == name)
+            .ok_or_else(|| RomError::new(&format!("The archive has no entry named {}", name))),
+        None if path == "-" => Err(RomError::new(
+            "The archive has several programs, so choose one with --entry",
+        )),
+        None => choose_entry(entries),
+    };
+    Rom::load(
+        &bytes,
+        path,
+        config.get_memory_layout(None).program_start,
+        &mut choose,
+    )
+}
+
+/// A program ready to run, and what it runs with
+struct Launch {
+    program: Vec<u8>,
+    config: Config,
+    layout: MemoryLayout,
+    quirks: Quirks,
+}
+
+/// Works out the settings a program runs with: the config file's, overridden by those
+/// it came with and then by the command line's
+fn prepare(rom: Rom, config: &Config, matches: &ArgMatches) -> Result<Launch, String> {
+    let mut config = config.clone();
+    if let Some(options) = &rom.options {
+        config.apply_cartridge(options);
+    }
+    let quirks = parse_quirks(matches).unwrap_or_else(|| config.get_quirks());
+    let layout = config.get_memory_layout(config.get_database().lookup(&rom.program));
+    layout
+        .check(&rom.program, &config.get_font())
+        .map_err(|e| e.to_string())?;
+    Ok(Launch {
+        program: rom.program,
+        config,
+        layout,
+        quirks,
+    })
+}
+
+/// Asks on the terminal which of an archive's programs to load
+fn choose_entry(entries: &[String]) -> Result<usize, RomError> {
+    eprintln!("The archive has several programs:");
+    for (index, entry) in entries.iter().enumerate() {
+        eprintln!("{:>3}. {}", index + 1, entry);
+    }
+    eprint!("Which one should be loaded? ");
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| RomError::new(&e.to_string()))?;
+    match answer.trim().parse::<usize>() {
+        Ok(number) if (1..=entries.len()).contains(&number) => Ok(number - 1),
+        _ => Err(RomError::new(&format!(
+            "{} isn't one of the programs' numbers",
+            answer.trim()
+        ))),
+    }
+}
+
+/// Returns the quirk profile named by the --quirks option, if it was given
+fn parse_quirks(matches: &ArgMatches) -> Option<Quirks> {
+    matches.value_of("quirks").map(|name| {
+        Quirks::profile(name).unwrap_or_else(|| {
+            panic!(
+                "Could not find a quirk profile named {}. Please use one of {:?}!",
+                name,
+                Quirks::PROFILES
+            )
+        })
+    })
+}
+
+/// Loads a program to analyze or lint, with the memory layout and quirks it would
+/// run with
+fn prepare_static(matches: &ArgMatches, config: &Config) -> Launch {
+    let rom = load_rom(matches.value_of("PROGRAM").unwrap(), config, matches);
+    prepare(rom, config, matches)
+        .unwrap_or_else(|e| panic!("Could not load the chip8 program: {}", e))
+}
+
+/// Writes the static analysis of a program as JSON or DOT
+fn run_analyze(matches: &ArgMatches, config: &Config) {
+    let launch = prepare_static(matches, config);
+    let analysis = Analysis::new(&launch.program, &launch.layout, &launch.quirks);
+    let output = match matches.value_of("format").unwrap_or("json") {
+        "json" => analysis.to_json(),
+        "dot" => analysis.to_dot(),
+        format => panic!("--format should be json or dot, not {}", format),
+    };
+    match matches.value_of("output") {
+        Some(file) => fs::write(file, output)
+            .unwrap_or_else(|e| panic!("Could not write the analysis to {}: {}", file, e)),
+        None => print!("{}", output),
+    }
+}
+
+/// Prints what the linter finds in a program, exiting with an error if it finds anything
+fn run_lint(matches: &ArgMatches, config: &Config) {
+    let launch = prepare_static(matches, config);
+    let lint = Lint::new(&launch.program, &launch.layout, &launch.quirks);
+    match matches.value_of("format").unwrap_or("text") {
+        "text" => {
+            for finding in lint.findings.iter() {
+                println!("{}", finding);
+            }
+            println!(
+                "Suggested quirk profile: {} ({})",
+                lint.suggestion.profile, lint.suggestion.reason
+            );
+        }
+        "json" => print!("{}", lint.to_json()),
+        format => panic!("--format should be text or json, not {}", format),
+    }
+    if !lint.findings.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Emulates frames as fast as possible without a window until the frame limit is reached
+fn run_headless(
+    emulator: &mut Emulator,
+    config: &Config,
+    capturer: &mut Capturer,
+    matches: &ArgMatches,
+) {
+    let frames: u64 = parse_number(matches, "frames").unwrap();
+    while emulator.frame < frames && !emulator.should_stop() {
+        for _ in 0..config.ticks_per_frame {
+            if let Err(e) = emulator.step() {
+                eprintln!("The program stopped: {}", e);
+                return;
+            }
+            if emulator.should_stop() {
+                break;
+            }
+        }
+        if let Err(e) = capturer.capture_frame(emulator.frame, &emulator.state) {
+            eprintln!("Could not record the frame: {}", e);
+        }
+        emulator.end_frame();
+    }
+}
+
+/// Waits for a debugger to connect on `port`, then lets it control the emulator until it detaches
+fn run_gdb(port: &str, emulator: &mut Emulator, config: &Config, capturer: &mut Capturer) {
+    let listener = TcpListener::bind((
+        "127.0.0.1",
+        port.parse().expect("--gdb should be a port number"),
+    ))
+    .unwrap_or_else(|e| panic!("Could not listen on port {}: {}", port, e));
+    println!("Waiting for GDB to connect on 127.0.0.1:{}", port);
+    let (stream, address) = listener
+        .accept()
+        .expect("Could not accept the GDB connection!");
+    println!("GDB connected from {}", address);
+    let mut server = GdbServer::new(stream, config.ticks_per_frame);
+    let result = server.serve(emulator, &mut |emulator| {
+        if let Err(e) = capturer.capture_frame(emulator.frame, &emulator.state) {
+            eprintln!("Could not record the frame: {}", e);
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("The GDB connection failed: {}", e);
+    }
+}
+
+/// Lets an editor launch and debug programs over the Debug Adapter Protocol until it disconnects
+fn run_dap(config: &Config, quirks: Option<Quirks>) {
+    let requests = dap::spawn_reader(BufReader::new(io::stdin()));
+    let mut server = DapServer::new(requests, io::stdout(), config.clone(), quirks);
+    if let Err(e) = server.serve() {
+        eprintln!("The debug adapter connection failed: {}", e);
+    }
+}
+
+/// The SDL context and the window, which stay open from one program to the next when
+/// they're chosen from the launcher
+struct Frontend {
+    sdl_context: Sdl,
+    window: GameWindow,
+    /// Whether there's a launcher to go back to
+    launcher: bool,
+}
+
+impl Frontend {
+    fn new(config: &Config, launcher: bool) -> Self {
+        let sdl_context = sdl2::init().unwrap();
+        let video_subsystem = sdl_context.video().unwrap();
+        let window = GameWindow::new(&video_subsystem, config);
+        Frontend {
+            sdl_context,
+            window,
+            launcher,
+        }
+    }
+}
+
+/// Why the window stopped running a program
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum WindowExit {
+    Quit,
+    /// The user asked to go back to the launcher
+    Launcher,
+}
+
+/// Shows the launcher's menu of the programs in a directory and runs the ones chosen
+/// from it until the user quits. Returns the emulator of the last program run, if any.
+fn run_launcher(
+    directory: &Path,
+    config: &Config,
+    capturer: &mut Capturer,
+    matches: &ArgMatches,
+) -> Option<Emulator> {
+    let origin = config.get_memory_layout(None).program_start;
+    let mut launcher =
+        Launcher::scan(directory, &config.get_database(), origin).unwrap_or_else(|e| {
+            panic!(
+                "Could not list the programs in {}: {}",
+                directory.display(),
+                e
+            )
+        });
+    let mut frontend = Frontend::new(config, true);
+    let mut last = None;
+    while choose_program(&mut frontend, &mut launcher) {
+        let launch = match launcher.load_selected(origin) {
+            // An archive of several programs, now listed to choose from
+            Ok(None) => continue,
+            Ok(Some(rom)) => prepare(rom, config, matches),
+            Err(e) => Err(e.to_string()),
+        };
+        let launch = match launch {
+            Ok(launch) => launch,
+            Err(e) => {
+                let path = launcher.selected().unwrap().path.display().to_string();
+                launcher.message = Some(format!("Could not load {}: {}", path, e));
+                continue;
+            }
+        };
+        launcher.message = None;
+        let (mut emulator, exit) = run_window(
+            &mut frontend,
+            &launch.program,
+            launch.layout,
+            launch.quirks,
+            &launch.config,
+            capturer,
+            matches,
+        );
+        // The keyboard holds SDL's event pump, which the menu needs back
+        emulator.state.keyboard = Box::new(HeadlessKeyboard::new());
+        last = Some(emulator);
+        if exit == WindowExit::Quit {
+            break;
+        }
+    }
+    frontend.window.save_geometry();
+    last
+}
+
+/// Shows the launcher's menu until the user chooses a program, returning false if they
+/// quit instead
+fn choose_program(frontend: &mut Frontend, launcher: &mut Launcher) -> bool {
+    let mut events = frontend.sdl_context.event_pump().unwrap();
+    let texture_creator: TextureCreator<_> = frontend.window.canvas.texture_creator();
+    let mut texture: Texture = texture_creator
+        .create_texture_streaming(
+            TEXTURE_FORMAT,
+            Launcher::WIDTH as u32,
+            Launcher::HEIGHT as u32,
         )
-        .position_centered()
-        .build()
-        .unwrap();
-    let event_pump = sdl_context.event_pump().unwrap();
-    let mut canvas: WindowCanvas = window.into_canvas().present_vsync().build().unwrap();
-    let texture_creator: TextureCreator<_> = canvas.texture_creator();
+        .expect("Could not create texture!");
+    let page = Launcher::VISIBLE as isize;
+    loop {
+        let everything = launcher.entries.len() as isize;
+        for event in events.poll_iter() {
+            let keycode = match event {
+                Event::Quit { .. } => return false,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => keycode,
+                _ => continue,
+            };
+            match keycode {
+                // Go back to the directory from an archive, or else quit
+                Keycode::Escape if !launcher.close_archive() => return false,
+                Keycode::Up => launcher.move_selection(-1),
+                Keycode::Down => launcher.move_selection(1),
+                Keycode::PageUp => launcher.move_selection(-page),
+                Keycode::PageDown => launcher.move_selection(page),
+                Keycode::Home => launcher.move_selection(-everything),
+                Keycode::End => launcher.move_selection(everything),
+                Keycode::Return | Keycode::KpEnter if launcher.selected().is_some() => return true,
+                Keycode::F11 => {
+                    if let Err(e) = frontend.window.toggle_fullscreen() {
+                        eprintln!("Could not toggle fullscreen: {}", e);
+                    }
+                }
+                _ => {}
+            }
+        }
+        launcher.render();
+        texture
+            .update(None, &launcher.pixels, Launcher::WIDTH * 4)
+            .expect("Could not update texture!");
+        let viewport = frontend
+            .window
+            .viewport((Launcher::WIDTH as u32, Launcher::HEIGHT as u32));
+        let canvas = &mut frontend.window.canvas;
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas
+            .copy(&texture, None, viewport)
+            .expect("Could not copy texture!");
+        canvas.present();
+        thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+    }
+}
+
+/// Emulates the program in the window until the user quits or goes back to the launcher.
+/// Returns the emulator so the final frame can be inspected.
+fn run_window(
+    frontend: &mut Frontend,
+    program: &[u8],
+    layout: MemoryLayout,
+    quirks: Quirks,
+    config: &Config,
+    capturer: &mut Capturer,
+    matches: &ArgMatches,
+) -> (Emulator, WindowExit) {
+    // Set up SDL
+    let window = &mut frontend.window;
+    let event_pump = frontend.sdl_context.event_pump().unwrap();
+    let texture_creator: TextureCreator<_> = window.canvas.texture_creator();
+    let mut filter: Box<dyn Filter> = config.filter.create(config.persistence);
+    let (texture_width, texture_height) = filter.size();
     let mut texture: Texture = texture_creator
+        .create_texture_streaming(TEXTURE_FORMAT, texture_width as u32, texture_height as u32)
+        .expect("Could not create texture!");
+    let mut overlay = Overlay::new();
+    let mut overlay_texture: Texture = texture_creator
         .create_texture_streaming(
-            PixelFormatEnum::RGBA8888,
-            Display::WIDTH as u32,
-            Display::HEIGHT as u32,
+            TEXTURE_FORMAT,
+            Overlay::WIDTH as u32,
+            Overlay::HEIGHT as u32,
         )
         .expect("Could not create texture!");
+    #[cfg(feature = "scripting")]
+    let mut labels = Labels::new(&texture_creator);
 
     // Initialize state
-    let mut state: State = State {
-        memory: Memory::new(&program),
-        program_counter: chip8::memory::PROGRAM_START,
-        stack: Stack::new(),
-        registers: Registers::new(),
-        timers: Timers::new(),
-        keyboard: Box::new(SDLKeyboard::new(event_pump, config.get_keyboard())),
-        display: Display::new(config.active_color, config.inactive_color),
+    let palette = config.get_palette();
+    let display = Display::new(palette.active(), palette.inactive());
+    let keyboard = Box::new(SDLKeyboard::new(event_pump, config.get_keyboard()));
+    let mut state: State = State::with_layout(program, keyboard, display, layout);
+    state.quirks = quirks;
+    config.configure_memory(&mut state.memory);
+    let mut emulator = new_emulator(state, program, matches);
+    let frame_limit: u64 = parse_number(matches, "frames").unwrap_or(u64::MAX);
+    let mut console = if matches.is_present("cheat-console") {
+        Some(Console::new(cheats_path(program, matches)))
+    } else {
+        None
     };
+    let mut palettes = PaletteCycle::new(palette);
+    let mut exit = WindowExit::Quit;
 
     // Run emulator
     let sleep_duration: Duration = Duration::new(0, 1_000_000_000u32 / config.frames_per_second);
-    'running: while state.program_counter + 1 < chip8::memory::MAX_SIZE {
+    'running: while !emulator.should_stop() && emulator.frame < frame_limit {
         let start: Instant = Instant::now();
-        if state.keyboard.is_quit() {
+        if emulator.state.keyboard.is_quit() {
             break;
         }
+        for hotkey in emulator.state.keyboard.take_hotkeys() {
+            if hotkey == Hotkey::Launcher && frontend.launcher {
+                exit = WindowExit::Launcher;
+                break 'running;
+            }
+            handle_hotkey(
+                hotkey,
+                &mut emulator,
+                capturer,
+                window,
+                &mut palettes,
+                &mut overlay,
+            );
+        }
+
+        if let Some(console) = console.as_mut() {
+            console.run_commands(&mut emulator);
+        }
 
         for _ in 0..config.ticks_per_frame {
-            let pc: usize = state.program_counter;
-            if matches.is_present("debug") {
-                println!("PC: {:?}", state.program_counter);
-                println!("{:?}", state.stack);
-                println!("{:?}", state.registers);
-                println!("{:?}", state.timers);
-                println!("Display:");
-                for row in state.display.pixels.iter() {
-                    println!("{:?}", &row[..]);
-                }
-            }
-            let bytes: (u8, u8) = (state.memory.ram[pc], state.memory.ram[pc + 1]);
-            let instruction: Box<dyn Instruction> = instructions::parse(bytes).unwrap();
-            if matches.is_present("debug") {
-                println!(
-                    "Instruction: {:X} {:X} ({:?})",
-                    bytes.0, bytes.1, instruction
-                );
-                println!("======================================================================");
+            if let Err(e) = emulator.step() {
+                eprintln!("The program stopped: {}", e);
+                break 'running;
             }
-            instruction.execute(&mut state);
-            state.program_counter += 2;
-            if matches.is_present("step") && state.keyboard.wait_for_key_press() == 1 {
+            if matches.is_present("step") && emulator.state.keyboard.wait_for_key_press() == 1 {
                 break 'running;
             }
+            if emulator.should_stop() {
+                break;
+            }
+        }
+        if let Err(e) = capturer.capture_frame(emulator.frame, &emulator.state) {
+            eprintln!("Could not record the frame: {}", e);
         }
         texture
-            .update(None, &state.display.colored_pixels, Display::WIDTH * 4)
+            .update(
+                None,
+                filter.apply(&emulator.state.display),
+                texture_width * 4,
+            )
             .expect("Could not update texture!");
-        canvas.clear();
-        canvas
-            .copy(&texture, None, None)
+        window.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        window.canvas.clear();
+        let viewport = if overlay.visible {
+            let (display_area, panel_area) =
+                Overlay::split(window.viewport(Overlay::combined_size()));
+            overlay.render(&emulator.state);
+            overlay_texture
+                .update(None, &overlay.pixels, Overlay::WIDTH * 4)
+                .expect("Could not update texture!");
+            window
+                .canvas
+                .copy(&overlay_texture, None, panel_area)
+                .expect("Could not copy texture!");
+            display_area
+        } else {
+            window.viewport((Display::WIDTH as u32, Display::HEIGHT as u32))
+        };
+        window
+            .canvas
+            .copy(&texture, None, viewport)
             .expect("Could not copy texture!");
-        canvas.present();
-        state.timers.decrement_timers();
+        #[cfg(feature = "scripting")]
+        {
+            if let Some(script) = &emulator.script {
+                labels.draw(script, &mut window.canvas, viewport);
+            }
+        }
+        window.canvas.present();
+        emulator.end_frame();
         let end: Instant = Instant::now();
         if end - start < sleep_duration {
             thread::sleep(sleep_duration - (end - start));
         }
     }
+    window.save_geometry();
+    (emulator, exit)
+}
+
+/// Runs the cheat commands typed on standard input while the window is open
+struct Console {
+    console: CheatConsole,
+    commands: Receiver<String>,
+}
+
+impl Console {
+    fn new(path: Option<PathBuf>) -> Self {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        println!("{}", CheatConsole::HELP);
+        Console {
+            console: CheatConsole::new(path),
+            commands,
+        }
+    }
+
+    /// Runs the commands typed since the last frame
+    fn run_commands(&mut self, emulator: &mut Emulator) {
+        while let Ok(command) = self.commands.try_recv() {
+            match self
+                .console
+                .run(&command, &mut emulator.state, &mut emulator.cheats)
+            {
+                Ok(output) => println!("{}", output),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+}
+
+/// The text a script draws, laid over the display
+#[cfg(feature = "scripting")]
+struct Labels<'a> {
+    texture: Texture<'a>,
+    pixels: Vec<u8>,
+}
+
+#[cfg(feature = "scripting")]
+impl<'a> Labels<'a> {
+    fn new<T>(texture_creator: &'a TextureCreator<T>) -> Self {
+        let mut texture = texture_creator
+            .create_texture_streaming(
+                TEXTURE_FORMAT,
+                Script::LABELS_WIDTH as u32,
+                Script::LABELS_HEIGHT as u32,
+            )
+            .expect("Could not create texture!");
+        texture.set_blend_mode(BlendMode::Blend);
+        Labels {
+            texture,
+            pixels: vec![0; Script::LABELS_WIDTH * Script::LABELS_HEIGHT * 4],
+        }
+    }
+
+    /// Draws the script's labels over the display in `viewport`
+    fn draw(&mut self, script: &Script, canvas: &mut Canvas<Window>, viewport: Rect) {
+        script.draw_labels(&mut self.pixels);
+        self.texture
+            .update(None, &self.pixels, Script::LABELS_WIDTH * 4)
+            .expect("Could not update texture!");
+        canvas
+            .copy(&self.texture, None, viewport)
+            .expect("Could not copy texture!");
+    }
+}
+
+/// Performs the emulator function bound to a hotkey
+fn handle_hotkey(
+    hotkey: Hotkey,
+    emulator: &mut Emulator,
+    capturer: &mut Capturer,
+    window: &mut GameWindow,
+    palettes: &mut PaletteCycle,
+    overlay: &mut Overlay,
+) {
+    match hotkey {
+        Hotkey::Screenshot => {
+            let path = timestamped_path("png");
+            match capturer.screenshot(&path, &emulator.state.display) {
+                Ok(()) => println!("Saved a screenshot to {}", path.display()),
+                Err(e) => eprintln!("Could not save the screenshot: {}", e),
+            }
+        }
+        Hotkey::ToggleGif => {
+            let path = timestamped_path("gif");
+            match capturer.toggle_gif(&path) {
+                Ok(true) => println!("Recording a GIF to {}", path.display()),
+                Ok(false) => println!("Stopped recording the GIF"),
+                Err(e) => eprintln!("Could not record the GIF: {}", e),
+            }
+        }
+        Hotkey::ToggleFullscreen => {
+            if let Err(e) = window.toggle_fullscreen() {
+                eprintln!("Could not toggle fullscreen: {}", e);
+            }
+        }
+        Hotkey::NextPalette => {
+            let palette = palettes.advance();
+            palette.apply(&mut emulator.state.display);
+            println!("Switched to the {} palette", palette.name);
+        }
+        Hotkey::ToggleOverlay => overlay.toggle(),
+        // Only programs chosen from the launcher can go back to it
+        Hotkey::Launcher => {}
+    }
 }
 
 /*
@@ -0,0 +1,748 @@
+//! Octo cartridges and an assembler for the Chip-8 subset of the Octo language.
+//!
+//! Octo shares programs as "cartridges": GIF images of a label whose palette indices
+//! also carry the program. Each pixel holds two bits of the payload in the low bits of
+//! its index, most significant bits first, across every frame. The payload is a 32-bit
+//! big-endian length followed by that many bytes of UTF-8 JSON, with the program's
+//! source and the options Octo ran it with: `{"program": "...", "options": {...}}`.
+
+use crate::chip8::quirks::Quirks;
+use crate::config::Color;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+
+/// Error for when a cartridge can't be read or its program can't be assembled
+#[derive(Debug, Clone, PartialEq)]
+pub struct OctoError(String);
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The settings Octo saves with a program. Those this emulator has no use for are left out.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CartridgeOptions {
+    /// Instructions per frame
+    pub tickrate: Option<u32>,
+    pub fill_color: Option<Color>,
+    pub background_color: Option<Color>,
+    /// 8XY6 and 8XYE shift VX in place
+    #[serde(default)]
+    pub shift_quirks: bool,
+    /// FX55 and FX65 leave I as it was
+    #[serde(default)]
+    pub load_store_quirks: bool,
+    /// BNNN jumps to NNN + VX
+    #[serde(default)]
+    pub jump_quirks: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF
+    #[serde(default)]
+    pub logic_quirks: bool,
+    /// The name of Octo's font, e.g. "vip"
+    pub font_style: Option<String>,
+}
+
+impl CartridgeOptions {
+    /// The quirks the options turn on
+    pub fn quirks(&self) -> Quirks {
+        Quirks {
+            shift_vy: !self.shift_quirks,
+            load_store_increments_i: !self.load_store_quirks,
+            jump_vx: self.jump_quirks,
+            logic_resets_vf: self.logic_quirks,
+        }
+    }
+
+    /// The name of the built-in font matching Octo's font, if there is one
+    pub fn font(&self) -> Option<&'static str> {
+        match self.font_style.as_deref()? {
+            "vip" => Some("cosmac-vip"),
+            "dream6800" => Some("dream-6800"),
+            "eti660" => Some("eti-660"),
+            "schip" => Some("super-chip"),
+            _ => None,
+        }
+    }
+}
+
+/// A program as Octo shares it
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Cartridge {
+    /// The Octo source
+    pub program: String,
+    #[serde(default)]
+    pub options: CartridgeOptions,
+}
+
+impl Cartridge {
+    /// Reads the program and options out of a cartridge GIF
+    pub fn from_gif(bytes: &[u8]) -> Result<Cartridge, OctoError> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options
+            .read_info(bytes)
+            .map_err(|e| OctoError(format!("Could not read the cartridge: {}", e)))?;
+        let mut payload = Vec::new();
+        while let Some(frame) = decoder
+            .read_next_frame()
+            .map_err(|e| OctoError(format!("Could not read the cartridge: {}", e)))?
+        {
+            for pixels in frame.buffer.chunks_exact(4) {
+                payload.push(pixels.iter().fold(0, |byte, x| (byte << 2) | (x & 3)));
+            }
+        }
+        if payload.len() < 4 {
+            return Err(OctoError("The cartridge holds no program".to_string()));
+        }
+        let size = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+        let json = payload
+            .get(4..4 + size)
+            .ok_or_else(|| OctoError("The cartridge's program is cut off".to_string()))?;
+        serde_json::from_slice(json)
+            .map_err(|e| OctoError(format!("The cartridge's program is invalid: {}", e)))
+    }
+
+    /// Assembles the cartridge's program to be loaded at `origin`
+    pub fn assemble(&self, origin: usize) -> Result<Vec<u8>, OctoError> {
+        assemble(&self.program, origin)
+    }
+}
+
+/// A whitespace-separated word of the source and the line it's on
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+/// Assembles Octo source into a Chip-8 program loaded at `origin`, usually 0x200.
+///
+/// Octo programs start at the label `main`, so the program starts with a jump to it.
+/// SUPER-CHIP and XO-CHIP instructions aren't supported, and neither are the
+/// directives `:macro`, `:calc`, `:stringmode`, `:assert` and `:next`.
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, OctoError> {
+    let tokens = source
+        .lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace().map(move |text| Token {
+                text,
+                line: index + 1,
+            })
+        })
+        .collect();
+    let mut assembler = Assembler::new(tokens, origin);
+    assembler.run()?;
+    Ok(assembler.rom)
+}
+
+struct Assembler<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    /// The address the program is loaded at
+    origin: usize,
+    rom: Vec<u8>,
+    labels: HashMap<&'a str, usize>,
+    constants: HashMap<&'a str, usize>,
+    aliases: HashMap<&'a str, u16>,
+    /// References to labels that aren't defined yet, and where to fill them in
+    fixups: Vec<(&'a str, usize, Token<'a>)>,
+    /// The statement that opened each open block, and the offsets of the jumps to point
+    /// at its `else`, `end` or `again`
+    blocks: Vec<(Token<'a>, Vec<usize>)>,
+    /// Where each open loop starts
+    loops: Vec<usize>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(tokens: Vec<Token<'a>>, origin: usize) -> Self {
+        Assembler {
+            tokens,
+            position: 0,
+            origin,
+            rom: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    /// The address the next byte goes to
+    fn here(&self) -> usize {
+        self.origin + self.rom.len()
+    }
+
+    fn run(&mut self) -> Result<(), OctoError> {
+        let main = Token {
+            text: "main",
+            line: 1,
+        };
+        self.emit_address(0x1000, main)?;
+        while self.position < self.tokens.len() {
+            let token = self.tokens[self.position];
+            self.statement()?;
+            if self.here() > 0x1000 {
+                return Err(error(token, "goes past the end of memory at 0xFFF"));
+            }
+        }
+        if let Some((token, _)) = self.blocks.last() {
+            return Err(error(*token, "isn't closed with end or again"));
+        }
+        if !self.labels.contains_key("main") {
+            return Err(OctoError(String::from("The program has no main label")));
+        }
+        for (name, address, token) in std::mem::take(&mut self.fixups) {
+            let target = *self
+                .labels
+                .get(name)
+                .ok_or_else(|| error(token, "isn't a label"))?;
+            self.patch(address, target, token)?;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, OctoError> {
+        let token = self.tokens.get(self.position).copied().ok_or_else(|| {
+            OctoError(String::from(
+                "The program ends in the middle of a statement",
+            ))
+        })?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|x| x.text)
+    }
+
+    /// Consumes a token that must be `text`
+    fn expect(&mut self, text: &str) -> Result<(), OctoError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(error(token, &format!("was found where {} should be", text)));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.rom.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    /// Emits an instruction with a 12-bit address, filled in later if it's a forward reference
+    fn emit_address(&mut self, opcode: u16, token: Token<'a>) -> Result<(), OctoError> {
+        match self.value(token) {
+            Ok(address) => {
+                let address = fit(address, 0xFFF, token)?;
+                self.emit(opcode | address);
+            }
+            Err(_) if is_name(token.text) => {
+                self.fixups.push((token.text, self.rom.len(), token));
+                self.emit(opcode);
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Fills in the address of the instruction at an offset into the program
+    fn patch(&mut self, offset: usize, address: usize, token: Token) -> Result<(), OctoError> {
+        let address = fit(address, 0xFFF, token)?;
+        self.rom[offset] |= (address >> 8) as u8;
+        self.rom[offset + 1] = (address & 0xFF) as u8;
+        Ok(())
+    }
+
+    /// The value of a number, constant or defined label
+    fn value(&self, token: Token) -> Result<usize, OctoError> {
+        let text = token.text;
+        if let Some(value) = self.constants.get(text).or_else(|| self.labels.get(text)) {
+            return Ok(*value);
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2)
+        } else {
+            digits.parse::<i64>()
+        };
+        let value = parsed.map_err(|_| error(token, "isn't a number or a defined name"))?;
+        // Negative numbers wrap around to bytes, like -1 for 0xFF
+        Ok(if negative {
+            (256 - value).rem_euclid(256) as usize
+        } else {
+            value as usize
+        })
+    }
+
+    fn byte(&mut self) -> Result<u16, OctoError> {
+        let token = self.next()?;
+        let value = self.value(token)?;
+        fit(value, 0xFF, token)
+    }
+
+    fn register(&self, token: Token) -> Option<u16> {
+        if let Some(register) = self.aliases.get(token.text) {
+            return Some(*register);
+        }
+        let lower = token.text.to_ascii_lowercase();
+        let digit = lower.strip_prefix('v')?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u16::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&mut self) -> Result<u16, OctoError> {
+        let token = self.next()?;
+        self.register(token)
+            .ok_or_else(|| error(token, "isn't a register"))
+    }
+
+    fn statement(&mut self) -> Result<(), OctoError> {
+        let token = self.next()?;
+        if let Some(x) = self.register(token) {
+            return self.register_statement(x);
+        }
+        match token.text {
+            ":" => {
+                let name = self.next()?;
+                if self.labels.insert(name.text, self.here()).is_some() {
+                    return Err(error(name, "is already defined"));
+                }
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next()?;
+                let value = self.value(value)?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.expect_register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":org" => {
+                let target = self.next()?;
+                let address = fit(self.value(target)?, 0xFFF, target)? as usize;
+                if address < self.here() {
+                    return Err(error(token, "can't go back to an earlier address"));
+                }
+                self.rom.resize(address - self.origin, 0);
+            }
+            ":byte" => {
+                let value = self.byte()?;
+                self.rom.push(value as u8);
+            }
+            ":unpack" => {
+                let high = self.byte()?;
+                let label = self.next()?;
+                let address = self.value(label)?;
+                let address = fit(address, 0xFFF, label)?;
+                self.emit(0x6000 | (high << 4) | (address >> 8));
+                self.emit(0x6100 | (address & 0xFF));
+            }
+            ":call" => {
+                let address = self.next()?;
+                self.emit_address(0x2000, address)?;
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "jump" => {
+                let address = self.next()?;
+                self.emit_address(0x1000, address)?;
+            }
+            "jump0" => {
+                let address = self.next()?;
+                self.emit_address(0xB000, address)?;
+            }
+            "native" => {
+                let address = self.next()?;
+                self.emit_address(0x0000, address)?;
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let height = self.next()?;
+                let height = fit(self.value(height)?, 0xF, height)?;
+                self.emit(0xD000 | (x << 8) | (y << 4) | height);
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                let opcode = if token.text == "delay" {
+                    0xF015
+                } else {
+                    0xF018
+                };
+                self.emit(opcode | (x << 8));
+            }
+            "i" => self.i_statement()?,
+            "bcd" | "save" | "load" => {
+                let x = self.expect_register()?;
+                if self.peek() == Some("-") {
+                    return Err(error(token, "ranges are XO-CHIP, which isn't supported"));
+                }
+                let opcode = match token.text {
+                    "bcd" => 0xF033,
+                    "save" => 0xF055,
+                    _ => 0xF065,
+                };
+                self.emit(opcode | (x << 8));
+            }
+            "if" => self.if_statement(token)?,
+            "else" => {
+                let (open, jumps) = self.pop_if_block(token)?;
+                let offset = self.rom.len();
+                self.emit(0x1000);
+                self.patch_block(jumps, token)?;
+                self.blocks.push((open, vec![offset]));
+            }
+            "end" => {
+                let (_, jumps) = self.pop_if_block(token)?;
+                self.patch_block(jumps, token)?;
+            }
+            "loop" => {
+                self.loops.push(self.here());
+                self.blocks.push((token, Vec::new()));
+            }
+            "while" => {
+                let (_, opcode) = self.condition()?;
+                let offset = self.rom.len();
+                self.emit(opcode);
+                self.emit(0x1000);
+                self.blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|(open, _)| open.text == "loop")
+                    .ok_or_else(|| error(token, "isn't in a loop"))?
+                    .1
+                    .push(offset + 2);
+            }
+            "again" => {
+                let start = self
+                    .loops
+                    .pop()
+                    .ok_or_else(|| error(token, "isn't in a loop"))?;
+                let (open, jumps) = self.blocks.pop().unwrap();
+                if open.text != "loop" {
+                    return Err(error(open, "isn't closed before again"));
+                }
+                self.emit(0x1000 | fit(start, 0xFFF, token)?);
+                self.patch_block(jumps, token)?;
+            }
+            ":macro" | ":calc" | ":stringmode" | ":assert" | ":next" => {
+                return Err(error(token, "isn't supported"));
+            }
+            "hires" | "lores" | "scroll-down" | "scroll-up" | "scroll-left" | "scroll-right"
+            | "exit" | "plane" | "audio" | "saveflags" | "loadflags" | "pitch" => {
+                return Err(error(
+                    token,
+                    "is a SUPER-CHIP or XO-CHIP instruction, which isn't supported",
+                ));
+            }
+            _ if token.text.starts_with(':') => {
+                return Err(error(token, "isn't a directive"));
+            }
+            _ if is_name(token.text) && !self.constants.contains_key(token.text) => {
+                // A bare label calls it
+                self.emit_address(0x2000, token)?;
+            }
+            _ => {
+                let value = self.value(token)?;
+                self.rom.push(fit(value, 0xFF, token)? as u8);
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the innermost block, which should be an `if ... begin`
+    fn pop_if_block(&mut self, token: Token<'a>) -> Result<(Token<'a>, Vec<usize>), OctoError> {
+        match self.blocks.pop() {
+            Some((open, jumps)) if open.text == "if" => Ok((open, jumps)),
+            _ => Err(error(token, "isn't in an if ... begin block")),
+        }
+    }
+
+    /// Points the jumps of a block that's ending at the current address
+    fn patch_block(&mut self, jumps: Vec<usize>, token: Token<'a>) -> Result<(), OctoError> {
+        let here = self.here();
+        for offset in jumps {
+            self.patch(offset, here, token)?;
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), OctoError> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        if let Some(y) = self.register(operand) {
+            let opcode = match operator.text {
+                ":=" => 0x8000,
+                "|=" => 0x8001,
+                "&=" => 0x8002,
+                "^=" => 0x8003,
+                "+=" => 0x8004,
+                "-=" => 0x8005,
+                ">>=" => 0x8006,
+                "=-" => 0x8007,
+                "<<=" => 0x800E,
+                _ => return Err(error(operator, "isn't an operator between registers")),
+            };
+            self.emit(opcode | (x << 8) | (y << 4));
+            return Ok(());
+        }
+        match (operator.text, operand.text) {
+            (":=", "key") => self.emit(0xF00A | (x << 8)),
+            (":=", "delay") => self.emit(0xF007 | (x << 8)),
+            (":=", "random") => {
+                let mask = self.byte()?;
+                self.emit(0xC000 | (x << 8) | mask);
+            }
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                let value = fit(self.value(operand)?, 0xFF, operand)?;
+                let opcode = match operator.text {
+                    ":=" => 0x6000 | value,
+                    "+=" => 0x7000 | value,
+                    // Subtracting is adding the two's complement
+                    _ => 0x7000 | ((0x100 - value) & 0xFF),
+                };
+                self.emit(opcode | (x << 8));
+            }
+            _ => return Err(error(operand, "isn't something this operator takes")),
+        }
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), OctoError> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        match operator.text {
+            ":=" if operand.text == "hex" => {
+                let x = self.expect_register()?;
+                self.emit(0xF029 | (x << 8));
+            }
+            ":=" if operand.text == "bighex" || operand.text == "long" => {
+                return Err(error(operand, "isn't supported"));
+            }
+            ":=" => self.emit_address(0xA000, operand)?,
+            "+=" => {
+                let x = self
+                    .register(operand)
+                    .ok_or_else(|| error(operand, "isn't a register"))?;
+                self.emit(0xF01E | (x << 8));
+            }
+            _ => return Err(error(operator, "isn't an operator i takes")),
+        }
+        Ok(())
+    }
+
+    /// Reads a condition, returning the instructions that skip when it's false and when it's true
+    fn condition(&mut self) -> Result<(u16, u16), OctoError> {
+        let x = self.expect_register()?;
+        let operator = self.next()?;
+        match operator.text {
+            "key" => return Ok((0xE0A1 | (x << 8), 0xE09E | (x << 8))),
+            "-key" => return Ok((0xE09E | (x << 8), 0xE0A1 | (x << 8))),
+            "==" | "!=" => {}
+            _ => return Err(error(operator, "isn't a supported comparison")),
+        }
+        let operand = self.next()?;
+        let (equal, not_equal) = match self.register(operand) {
+            Some(y) => (0x5000 | (y << 4), 0x9000 | (y << 4)),
+            None => {
+                let value = fit(self.value(operand)?, 0xFF, operand)?;
+                (0x3000 | value, 0x4000 | value)
+            }
+        };
+        let (equal, not_equal) = (equal | (x << 8), not_equal | (x << 8));
+        Ok(if operator.text == "==" {
+            (not_equal, equal)
+        } else {
+            (equal, not_equal)
+        })
+    }
+
+    fn if_statement(&mut self, token: Token<'a>) -> Result<(), OctoError> {
+        let (skip_if_false, skip_if_true) = self.condition()?;
+        let keyword = self.next()?;
+        match keyword.text {
+            "then" => self.emit(skip_if_false),
+            "begin" => {
+                self.emit(skip_if_true);
+                let offset = self.rom.len();
+                self.emit(0x1000);
+                self.blocks.push((token, vec![offset]));
+            }
+            _ => return Err(error(keyword, "was found where then or begin should be")),
+        }
+        Ok(())
+    }
+}
+
+/// Whether a token could be the name of a label
+fn is_name(text: &str) -> bool {
+    text.chars()
+        .next()
+        .is_some_and(|x| x.is_ascii_alphabetic() || x == '_')
+}
+
+/// Checks that a value fits in an instruction's field
+fn fit(value: usize, max: usize, token: Token) -> Result<u16, OctoError> {
+    if value > max {
+        return Err(error(token, &format!("doesn't fit in {:#X}", max)));
+    }
+    Ok(value as u16)
+}
+
+fn error(token: Token, message: &str) -> OctoError {
+    OctoError(format!("Line {}: {} {}", token.line, token.text, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            :alias x v1
+            :const SPEED 2
+            : main
+                x := 0
+                i := dot
+                loop
+                    sprite x v2 1
+                    x += SPEED
+                    draw
+                    if x != 64 then
+                again
+            : draw
+                v0 := key
+                return
+            : dot 0x80
+        ";
+        assert_eq!(
+            assemble(source, 0x200).unwrap(),
+            [
+                0x12, 0x02, // jump main
+                0x61, 0x00, // x := 0
+                0xA2, 0x14, // i := dot
+                0xD1, 0x21, // sprite x v2 1
+                0x71, 0x02, // x += SPEED
+                0x22, 0x10, // draw
+                0x31, 0x40, // if x != 64 then
+                0x12, 0x06, // again
+                0xF0, 0x0A, // v0 := key
+                0x00, 0xEE, // return
+                0x80, // dot
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let source = "
+            : main
+                if v0 == v1 begin
+                    v2 := 1
+                else
+                    v2 -= 1
+                end
+                loop
+                    while v3 key
+                    v4 =- v5
+                again
+        ";
+        assert_eq!(
+            assemble(source, 0x200).unwrap(),
+            [
+                0x12, 0x02, // jump main
+                0x50, 0x10, // skip if v0 == v1
+                0x12, 0x0A, // jump to else
+                0x62, 0x01, // v2 := 1
+                0x12, 0x0C, // jump to end
+                0x72, 0xFF, // v2 -= 1
+                0xE3, 0x9E, // skip if v3 key
+                0x12, 0x14, // jump out of the loop
+                0x84, 0x57, // v4 =- v5
+                0x12, 0x0C, // again
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble(": main\n  v0 := 300", 0x200),
+            Err(OctoError("Line 2: 300 doesn't fit in 0xFF".to_string()))
+        );
+        assert!(assemble(": main hires", 0x200).is_err());
+        assert!(assemble(": main if v0 == 1 begin", 0x200).is_err());
+        assert!(assemble("v0 := 1", 0x200).is_err());
+        assert_eq!(
+            assemble(": main :org 0xFFFFFFFFFF", 0x200),
+            Err(OctoError(
+                "Line 1: 0xFFFFFFFFFF doesn't fit in 0xFFF".to_string()
+            ))
+        );
+        assert!(assemble(": main :org 0xFFE clear clear", 0x200).is_err());
+        assert_eq!(
+            assemble(": main :calc x { 1 + 2 }", 0x200),
+            Err(OctoError("Line 1: :calc isn't supported".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_origin() {
+        let source = ": main clear : spin jump spin :org 0x700 :byte 1";
+        let program = assemble(source, 0x600).unwrap();
+        assert_eq!(&program[..6], [0x16, 0x02, 0x00, 0xE0, 0x16, 0x04]);
+        assert_eq!(program.len(), 0x101);
+        assert!(assemble(source, 0x800).is_err());
+    }
+
+    #[test]
+    fn test_cartridge() {
+        let json = r#"{"program": ": main clear", "options": {"tickrate": 20, "fontStyle": "vip", "shiftQuirks": true}}"#;
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        // Two bits of the payload per pixel, above which Octo keeps the label's colors
+        let mut pixels: Vec<u8> = payload
+            .iter()
+            .flat_map(|byte| (0..4).rev().map(move |x| 0x04 | ((byte >> (x * 2)) & 3)))
+            .collect();
+        pixels.resize(32 * 32, 0);
+        let mut gif = Vec::new();
+        {
+            let palette = [0; 3 * 8];
+            let mut encoder = gif::Encoder::new(&mut gif, 32, 32, &palette).unwrap();
+            let frame = gif::Frame::from_indexed_pixels(32, 32, pixels, None);
+            encoder.write_frame(&frame).unwrap();
+        }
+        let cartridge = Cartridge::from_gif(&gif).unwrap();
+        assert_eq!(cartridge.program, ": main clear");
+        assert_eq!(cartridge.options.tickrate, Some(20));
+        assert_eq!(cartridge.options.font(), Some("cosmac-vip"));
+        assert!(!cartridge.options.quirks().shift_vy);
+        assert_eq!(cartridge.assemble(0x200).unwrap(), [0x12, 0x02, 0x00, 0xE0]);
+        assert!(Cartridge::from_gif(b"GIF89a").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::chip8::{display::Display, State};
+use crate::config::Color;
+use crate::text::{Canvas, CHAR_ADVANCE, LINE_HEIGHT};
+#[cfg(feature = "sdl")]
+use sdl2::rect::Rect;
+
+/// A side panel showing the machine's registers, timers, stack and memory while it runs
+pub struct Overlay {
+    pub pixels: Vec<u8>,
+    pub visible: bool,
+}
+
+impl Overlay {
+    /// The width of the panel (in pixels)
+    pub const WIDTH: usize = 36 * CHAR_ADVANCE;
+
+    /// The height of the panel (in pixels)
+    pub const HEIGHT: usize = 21 * LINE_HEIGHT + 2;
+
+    /// How many panel pixels make up one chip-8 pixel, so the panel and the display
+    /// can be drawn side by side at the same scale
+    pub const SCALE: usize = 4;
+
+    /// The number of bytes on each line of the memory views
+    const BYTES_PER_ROW: usize = 8;
+
+    /// The number of lines in each memory view
+    const MEMORY_ROWS: usize = 3;
+
+    /// The number of stack entries on each line of the stack view
+    const ENTRIES_PER_ROW: usize = 4;
+
+    const BACKGROUND: Color = Color(16, 16, 16, 255);
+    const TEXT: Color = Color(220, 220, 220, 255);
+
+    pub fn new() -> Self {
+        Overlay {
+            pixels: vec![0; Self::WIDTH * Self::HEIGHT * 4],
+            visible: false,
+        }
+    }
+
+    /// Shows the panel if it's hidden and hides it if it's shown
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Returns the (width, height) of the display and the panel side by side, in panel pixels
+    pub fn combined_size() -> (u32, u32) {
+        (
+            (Display::WIDTH * Self::SCALE + Self::WIDTH) as u32,
+            (Display::HEIGHT * Self::SCALE).max(Self::HEIGHT) as u32,
+        )
+    }
+
+    /// Splits an area fitted to `combined_size` into the areas for the display and the panel
+    #[cfg(feature = "sdl")]
+    pub fn split(area: Rect) -> (Rect, Rect) {
+        let (combined_width, combined_height) = Self::combined_size();
+        let display_width = area.width() * (Display::WIDTH * Self::SCALE) as u32 / combined_width;
+        let display_height =
+            area.height() * (Display::HEIGHT * Self::SCALE) as u32 / combined_height;
+        let panel_height = area.height() * Self::HEIGHT as u32 / combined_height;
+        (
+            Rect::new(area.x(), area.y(), display_width, display_height),
+            Rect::new(
+                area.x() + display_width as i32,
+                area.y(),
+                area.width() - display_width,
+                panel_height,
+            ),
+        )
+    }
+
+    /// Redraws the panel with the machine's current state
+    pub fn render(&mut self, state: &State) {
+        let mut canvas = Canvas::new(&mut self.pixels, Self::WIDTH);
+        canvas.fill(Self::BACKGROUND);
+        for (index, line) in Self::lines(state).iter().enumerate() {
+            canvas.draw_text(2, 2 + index * LINE_HEIGHT, line, Self::TEXT);
+        }
+    }
+
+    /// Returns the lines of text shown on the panel
+    fn lines(state: &State) -> Vec<String> {
+        let registers = &state.registers;
+        let stack = state.stack.contents();
+        let mut lines = vec![
+            format!(
+                "PC {:04X}  I {:04X}  SP {:02}",
+                state.program_counter,
+                registers.i_register,
+                stack.len()
+            ),
+            format!(
+                "DT {:02X}  ST {:02X}",
+                state.timers.delay_timer, state.timers.sound_timer
+            ),
+        ];
+        for (row, values) in registers.v_registers.chunks(4).enumerate() {
+            let cells: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(column, value)| format!("V{:X} {:02X}", row * 4 + column, value))
+                .collect();
+            lines.push(cells.join(" "));
+        }
+
+        lines.push(String::from("STACK"));
+        let stack_rows: Vec<String> = stack
+            .chunks(Self::ENTRIES_PER_ROW)
+            .map(|entries| {
+                let cells: Vec<String> = entries.iter().map(|x| format!("{:04X}", x)).collect();
+                cells.join(" ")
+            })
+            .collect();
+        for row in 0..16 / Self::ENTRIES_PER_ROW {
+            lines.push(stack_rows.get(row).cloned().unwrap_or_default());
+        }
+
+        lines.push(String::from("MEMORY AT PC"));
+        lines.extend(Self::memory_view(state, state.program_counter));
+        lines.push(String::from("MEMORY AT I"));
+        lines.extend(Self::memory_view(state, usize::from(registers.i_register)));
+        lines
+    }
+
+    /// Returns hex dump lines of the memory around `address`, marking the line it's on with '>'
+    fn memory_view(state: &State, address: usize) -> Vec<String> {
+        let ram = state.memory.ram();
+        let row_start = address - address % Self::BYTES_PER_ROW;
+        let first = row_start.saturating_sub(Self::BYTES_PER_ROW).min(
+            ram.len()
+                .saturating_sub(Self::MEMORY_ROWS * Self::BYTES_PER_ROW)
+                .next_multiple_of(Self::BYTES_PER_ROW),
+        );
+        (0..Self::MEMORY_ROWS)
+            .map(|row| first + row * Self::BYTES_PER_ROW)
+            .take_while(|start| *start < ram.len())
+            .map(|start| {
+                let end = (start + Self::BYTES_PER_ROW).min(ram.len());
+                let bytes: Vec<String> = ram[start..end]
+                    .iter()
+                    .map(|x| format!("{:02X}", x))
+                    .collect();
+                let marker = if start == row_start { '>' } else { ' ' };
+                format!("{}{:03X} {}", marker, start, bytes.join(" "))
+            })
+            .collect()
+    }
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Overlay::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::memory::MemoryLayout;
+    use crate::text::GLYPH_HEIGHT;
+
+    #[test]
+    fn test_lines() {
+        let mut state = State::mock(&[0xA2, 0x2A]);
+        state.registers.v_registers[0xB] = 0x7F;
+        state.registers.i_register = 0x300;
+        state.stack.push(0x204).unwrap();
+        let lines = Overlay::lines(&state);
+        assert_eq!(lines[0], "PC 0200  I 0300  SP 01");
+        assert_eq!(lines[4], "V8 00 V9 00 VA 00 VB 7F");
+        assert_eq!(lines[7], "0204");
+        assert_eq!(lines[13], ">200 A2 2A 00 00 00 00 00 00");
+        assert_eq!(lines[12], " 1F8 00 00 00 00 00 00 00 00");
+        assert!(lines
+            .iter()
+            .all(|x| x.len() * CHAR_ADVANCE <= Overlay::WIDTH - 2));
+        assert!(lines.len() * LINE_HEIGHT + GLYPH_HEIGHT <= Overlay::HEIGHT);
+    }
+
+    #[test]
+    #[cfg(feature = "sdl")]
+    fn test_split() {
+        let (width, height) = Overlay::combined_size();
+        let (display, panel) = Overlay::split(Rect::new(10, 20, width * 2, height * 2));
+        assert_eq!(display, Rect::new(10, 20, 512, 256));
+        assert_eq!(panel, Rect::new(522, 20, Overlay::WIDTH as u32 * 2, 256));
+    }
+
+    #[test]
+    fn test_memory_view_at_edges() {
+        let state = State::mock(&[]);
+        assert_eq!(
+            Overlay::memory_view(&state, 0)[0],
+            ">000 F0 90 90 90 F0 20 60 20"
+        );
+        let view = Overlay::memory_view(&state, 0xFFF);
+        assert_eq!(view[2], ">FF8 00 00 00 00 00 00 00 00");
+    }
+
+    #[test]
+    fn test_memory_view_layouts() {
+        let layout = |size| MemoryLayout {
+            size,
+            program_start: 0,
+            font_address: 0,
+        };
+        let state = State::mock_with_layout(&[], layout(0x800));
+        let view = Overlay::memory_view(&state, 0xFFF);
+        assert_eq!(view[2], " 7F8 00 00 00 00 00 00 00 00");
+        let state = State::mock_with_layout(&[], layout(0x10000));
+        let view = Overlay::memory_view(&state, 0xFFFF);
+        assert_eq!(view[2], ">FFF8 00 00 00 00 00 00 00 00");
+        let state = State::mock_with_layout(&[], layout(84));
+        let view = Overlay::memory_view(&state, 83);
+        assert_eq!(view.len(), 3);
+        assert_eq!(view[2], ">050 00 00 00 00");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::chip8::display::Display;
+use crate::config::Color;
+
+/// A set of colors to draw the display with: one for the background and one for lit
+/// pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Color; 2],
+}
+
+impl Palette {
+    /// The names of the built-in palettes, in the order they are cycled through
+    pub const PRESETS: [&'static str; 4] = ["green", "amber", "lcd", "high-contrast"];
+
+    /// Creates a palette with one color for inactive pixels and one for active pixels
+    pub fn two_color(name: &str, active: Color, inactive: Color) -> Self {
+        Palette {
+            name: String::from(name),
+            colors: [inactive, active],
+        }
+    }
+
+    /// Returns the built-in palette called `name`, if there is one
+    pub fn preset(name: &str) -> Option<Palette> {
+        let (inactive, active) = match name {
+            // A green phosphor monochrome monitor
+            "green" => ("#0B1A0B", "#33FF66"),
+            // An amber phosphor monochrome monitor
+            "amber" => ("#1A0F00", "#FFB000"),
+            // A reflective green LCD, like early handhelds
+            "lcd" => ("#9BBC0F", "#0F380F"),
+            "high-contrast" => ("#000000", "#FFFFFF"),
+            _ => return None,
+        };
+        Some(Palette::two_color(
+            name,
+            Color::from_hex(active).unwrap(),
+            Color::from_hex(inactive).unwrap(),
+        ))
+    }
+
+    /// Returns the color of pixels that are not lit
+    pub fn inactive(&self) -> Color {
+        self.colors[0]
+    }
+
+    /// Returns the color of lit pixels
+    pub fn active(&self) -> Color {
+        self.colors[1]
+    }
+
+    /// Redraws the display with this palette
+    pub fn apply(&self, display: &mut Display) {
+        display.set_colors(self.active(), self.inactive());
+    }
+}
+
+/// Cycles through a list of palettes, e.g. when a hotkey is pressed
+pub struct PaletteCycle {
+    palettes: Vec<Palette>,
+    current: usize,
+}
+
+impl PaletteCycle {
+    /// Creates a cycle that starts at `first` and continues through the built-in palettes
+    pub fn new(first: Palette) -> Self {
+        let mut palettes = vec![first];
+        for name in Palette::PRESETS.iter() {
+            if palettes[0].name != *name {
+                palettes.push(Palette::preset(name).unwrap());
+            }
+        }
+        PaletteCycle {
+            palettes,
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> &Palette {
+        &self.palettes[self.current]
+    }
+
+    /// Moves on to the next palette, returning it
+    pub fn advance(&mut self) -> &Palette {
+        self.current = (self.current + 1) % self.palettes.len();
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        for name in Palette::PRESETS.iter() {
+            assert_eq!(Palette::preset(name).unwrap().name, *name);
+        }
+        assert_eq!(Palette::preset("nope"), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut display = Display::new(Color::white(), Color::black());
+        display.xor(0, 0, 1);
+        let palette = Palette::preset("amber").unwrap();
+        palette.apply(&mut display);
+        assert_eq!(&display.colored_pixels[..4], &[0xFF, 0xB0, 0x00, 0xFF]);
+        assert_eq!(&display.colored_pixels[4..8], &[0x1A, 0x0F, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut cycle = PaletteCycle::new(Palette::preset("amber").unwrap());
+        assert_eq!(cycle.current().name, "amber");
+        assert_eq!(cycle.advance().name, "green");
+        assert_eq!(cycle.advance().name, "lcd");
+        assert_eq!(cycle.advance().name, "high-contrast");
+        assert_eq!(cycle.advance().name, "amber");
+
+        let custom = Palette::two_color("config", Color::white(), Color::black());
+        let mut cycle = PaletteCycle::new(custom.clone());
+        for _ in 0..Palette::PRESETS.len() {
+            cycle.advance();
+        }
+        assert_eq!(cycle.advance(), &custom);
+    }
+}
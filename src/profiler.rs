@@ -0,0 +1,418 @@
+use crate::analysis::disassemble;
+use crate::chip8::{memory::MemoryLayout, State};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::{fs, io, path::Path};
+
+/// Returns the opcode pattern an opcode belongs to, e.g. `8XY4` for `0x8124`,
+/// or `????` if it isn't an instruction
+pub fn opcode_class(opcode: (u8, u8)) -> &'static str {
+    let (high, low) = opcode;
+    match (high >> 4, low) {
+        (0x0, 0xE0) if high == 0 => "00E0",
+        (0x0, 0xEE) if high == 0 => "00EE",
+        (0x0, _) => "0NNN",
+        (0x1, _) => "1NNN",
+        (0x2, _) => "2NNN",
+        (0x3, _) => "3XNN",
+        (0x4, _) => "4XNN",
+        (0x5, _) if low & 0xF == 0 => "5XY0",
+        (0x6, _) => "6XNN",
+        (0x7, _) => "7XNN",
+        (0x8, _) => match low & 0xF {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "????",
+        },
+        (0x9, _) if low & 0xF == 0 => "9XY0",
+        (0xA, _) => "ANNN",
+        (0xB, _) => "BNNN",
+        (0xC, _) => "CXNN",
+        (0xD, _) => "DXYN",
+        (0xE, 0x9E) => "EX9E",
+        (0xE, 0xA1) => "EXA1",
+        (0xF, 0x07) => "FX07",
+        (0xF, 0x0A) => "FX0A",
+        (0xF, 0x15) => "FX15",
+        (0xF, 0x18) => "FX18",
+        (0xF, 0x1E) => "FX1E",
+        (0xF, 0x29) => "FX29",
+        (0xF, 0x33) => "FX33",
+        (0xF, 0x55) => "FX55",
+        (0xF, 0x65) => "FX65",
+        _ => "????",
+    }
+}
+
+/// How much one subroutine ran, measured in instructions executed
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SubroutineProfile {
+    /// The number of times it was called
+    pub calls: u64,
+    /// Instructions executed in the subroutine itself
+    pub own: u64,
+    /// Instructions executed in the subroutine and everything it called
+    pub total: u64,
+}
+
+/// Counts what a program spends its time on as it runs.
+///
+/// Time is measured in instructions executed, since that's what a program's speed
+/// is limited by at a fixed number of ticks per frame.
+/// Subroutines are followed by watching the stack grow and shrink, so that calls
+/// and returns are paired even if a program manipulates the stack some other way.
+pub struct Profiler {
+    /// The number of times the instruction at each address was executed
+    counts: Vec<u64>,
+    /// The opcode last executed at each address
+    opcodes: Vec<(u8, u8)>,
+    /// The entry addresses of the subroutines currently running, outermost first
+    calls: Vec<usize>,
+    /// The number of instructions executed with each chain of subroutines running
+    stacks: BTreeMap<Vec<usize>, u64>,
+    /// The number of times each subroutine was called
+    subroutine_calls: BTreeMap<usize, u64>,
+    /// Whether the program has polled a running delay timer this frame
+    polling: bool,
+    /// The number of frames the program spent polling a running delay timer
+    pub waiting_frames: u64,
+    /// The number of frames profiled
+    pub frames: u64,
+}
+
+impl Profiler {
+    /// The number of addresses listed in the report
+    const HOTSPOTS: usize = 20;
+
+    /// Profiles a program running in memory laid out as `layout`
+    pub fn new(layout: &MemoryLayout) -> Self {
+        Profiler {
+            counts: vec![0; layout.code().end],
+            opcodes: vec![(0, 0); layout.code().end],
+            calls: Vec::new(),
+            stacks: BTreeMap::new(),
+            subroutine_calls: BTreeMap::new(),
+            polling: false,
+            waiting_frames: 0,
+            frames: 0,
+        }
+    }
+
+    /// Counts the instruction at the program counter, which is about to be executed
+    pub fn before_step(&mut self, state: &State, opcode: (u8, u8)) {
+        let address = state.program_counter % self.counts.len();
+        self.counts[address] += 1;
+        self.opcodes[address] = opcode;
+        match self.stacks.get_mut(&self.calls[..]) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.calls.clone(), 1);
+            }
+        }
+        if opcode_class(opcode) == "FX07" && state.timers.delay_timer > 0 {
+            self.polling = true;
+        }
+    }
+
+    /// Follows the call or return the instruction just executed made, if any
+    pub fn after_step(&mut self, state: &State) {
+        let depth = state.stack.contents().len();
+        self.calls.truncate(depth);
+        if depth > self.calls.len() {
+            let entry = state.program_counter;
+            *self.subroutine_calls.entry(entry).or_insert(0) += 1;
+            self.calls.push(entry);
+        }
+    }
+
+    /// Counts a finished frame
+    pub fn end_frame(&mut self) {
+        if self.polling {
+            self.waiting_frames += 1;
+        }
+        self.polling = false;
+        self.frames += 1;
+    }
+
+    /// Returns the number of instructions executed
+    pub fn instructions(&self) -> u64 {
+        self.stacks.values().sum()
+    }
+
+    /// Returns how many times the instruction at an address was executed
+    pub fn count(&self, address: usize) -> u64 {
+        self.counts[address % self.counts.len()]
+    }
+
+    /// Returns the number of instructions executed of each opcode pattern, e.g. `DXYN`
+    pub fn classes(&self) -> BTreeMap<&'static str, u64> {
+        let mut classes = BTreeMap::new();
+        for (count, opcode) in self.counts.iter().zip(self.opcodes.iter()) {
+            if *count > 0 {
+                *classes.entry(opcode_class(*opcode)).or_insert(0) += count;
+            }
+        }
+        classes
+    }
+
+    /// Returns how much each subroutine that was called ran, by entry address
+    pub fn subroutines(&self) -> BTreeMap<usize, SubroutineProfile> {
+        let mut subroutines: BTreeMap<usize, SubroutineProfile> = self
+            .subroutine_calls
+            .iter()
+            .map(|(entry, calls)| {
+                let profile = SubroutineProfile {
+                    calls: *calls,
+                    ..Default::default()
+                };
+                (*entry, profile)
+            })
+            .collect();
+        for (stack, count) in self.stacks.iter() {
+            if let Some(innermost) = stack.last() {
+                subroutines.entry(*innermost).or_default().own += count;
+            }
+            // Recursive subroutines are only counted once per chain
+            let mut entries = stack.clone();
+            entries.sort_unstable();
+            entries.dedup();
+            for entry in entries {
+                subroutines.entry(entry).or_default().total += count;
+            }
+        }
+        subroutines
+    }
+
+    /// Returns the instructions executed in each chain of subroutines in the folded stack
+    /// format flamegraph tools read, e.g. `main;sub_2A0;sub_300 1234`
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for (stack, count) in self.stacks.iter() {
+            folded.push_str("main");
+            for entry in stack.iter() {
+                write!(folded, ";sub_{:03X}", entry).unwrap();
+            }
+            writeln!(folded, " {}", count).unwrap();
+        }
+        folded
+    }
+
+    /// Returns a human-readable report of where the program spent its time
+    pub fn report(&self) -> String {
+        let instructions = self.instructions();
+        let percent = |count: u64| 100.0 * count as f64 / instructions.max(1) as f64;
+        let mut report = String::new();
+        writeln!(
+            report,
+            "Executed {} instructions over {} frames",
+            instructions, self.frames
+        )
+        .unwrap();
+        writeln!(
+            report,
+            "Frames spent waiting on the delay timer: {} ({:.1}%)",
+            self.waiting_frames,
+            100.0 * self.waiting_frames as f64 / self.frames.max(1) as f64
+        )
+        .unwrap();
+
+        writeln!(report, "\nHottest addresses:").unwrap();
+        let mut addresses: Vec<usize> = (0..self.counts.len())
+            .filter(|x| self.counts[*x] > 0)
+            .collect();
+        addresses.sort_by_key(|x| std::cmp::Reverse(self.counts[*x]));
+        for address in addresses.into_iter().take(Self::HOTSPOTS) {
+            let count = self.counts[address];
+            writeln!(
+                report,
+                "  {:03X}  {:>10}  {:>5.1}%  {}",
+                address,
+                count,
+                percent(count),
+                disassemble(self.opcodes[address])
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nInstructions by opcode:").unwrap();
+        let mut classes: Vec<(&str, u64)> = self.classes().into_iter().collect();
+        classes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        for (class, count) in classes {
+            writeln!(
+                report,
+                "  {}  {:>10}  {:>5.1}%",
+                class,
+                count,
+                percent(count)
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            report,
+            "\nSubroutines (instructions in the subroutine itself, and including what it calls):"
+        )
+        .unwrap();
+        let mut subroutines: Vec<(usize, SubroutineProfile)> =
+            self.subroutines().into_iter().collect();
+        subroutines.sort_by_key(|(_, profile)| std::cmp::Reverse(profile.total));
+        for (entry, profile) in subroutines {
+            writeln!(
+                report,
+                "  {:03X}  {:>8} calls  {:>10} own ({:>5.1}%)  {:>10} total ({:>5.1}%)",
+                entry,
+                profile.calls,
+                profile.own,
+                percent(profile.own),
+                profile.total,
+                percent(profile.total)
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    /// Writes the report to `<path>.txt` and the folded stacks to `<path>.folded`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path.with_extension("txt"), self.report())?;
+        fs::write(path.with_extension("folded"), self.folded())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    /// Runs a program for `frames` frames of `ticks` instructions with a profiler
+    fn profile(program: &[u8], frames: u64, ticks: u64) -> Profiler {
+        profile_with_layout(program, MemoryLayout::default(), frames, ticks)
+    }
+
+    /// Profiles a program like `profile`, in memory laid out as `layout`
+    fn profile_with_layout(
+        program: &[u8],
+        layout: MemoryLayout,
+        frames: u64,
+        ticks: u64,
+    ) -> Profiler {
+        let mut emulator = Emulator::new(State::mock_with_layout(program, layout), false);
+        emulator.profiler = Some(Profiler::new(&layout));
+        for _ in 0..frames {
+            for _ in 0..ticks {
+                emulator.step().unwrap();
+            }
+            emulator.end_frame();
+        }
+        emulator.profiler.unwrap()
+    }
+
+    const PROGRAM: [u8; 12] = [
+        0x22, 0x04, // 200: CALL 0x204
+        0x12, 0x00, // 202: JP 0x200
+        0x22, 0x08, // 204: CALL 0x208
+        0x00, 0xEE, // 206: RET
+        0x60, 0x01, // 208: LD V0, 1
+        0x00, 0xEE, // 20A: RET
+    ];
+
+    #[test]
+    fn test_opcode_class() {
+        assert_eq!(opcode_class((0x00, 0xE0)), "00E0");
+        assert_eq!(opcode_class((0x01, 0xE0)), "0NNN");
+        assert_eq!(opcode_class((0x81, 0x24)), "8XY4");
+        assert_eq!(opcode_class((0x81, 0x28)), "????");
+        assert_eq!(opcode_class((0xD1, 0x25)), "DXYN");
+        assert_eq!(opcode_class((0xF3, 0x65)), "FX65");
+    }
+
+    #[test]
+    fn test_counts() {
+        // Two passes through the program, which executes 6 instructions a pass
+        let profiler = profile(&PROGRAM, 2, 6);
+        assert_eq!(profiler.instructions(), 12);
+        assert_eq!(profiler.count(0x200), 2);
+        assert_eq!(profiler.count(0x20A), 2);
+        assert_eq!(profiler.count(0x20C), 0);
+        let classes = profiler.classes();
+        assert_eq!(classes["2NNN"], 4);
+        assert_eq!(classes["00EE"], 4);
+        assert_eq!(classes["6XNN"], 2);
+        assert_eq!(profiler.frames, 2);
+    }
+
+    #[test]
+    fn test_memory_layouts() {
+        let layout = MemoryLayout {
+            program_start: 0x600,
+            ..MemoryLayout::default()
+        };
+        let program = [0x70, 0x01, 0x16, 0x00]; // 600: ADD V0, 1; 602: JP 0x600
+        let profiler = profile_with_layout(&program, layout, 1, 4);
+        assert_eq!(profiler.count(0x600), 2);
+        assert!(profiler
+            .report()
+            .contains("  602           2   50.0%  JP 0x600\n"));
+
+        // The program counter wraps around memory smaller than it can reach
+        let layout = MemoryLayout {
+            size: 0x400,
+            ..MemoryLayout::default()
+        };
+        let program = [0x16, 0x00]; // 200: JP 0x600, which runs 0x200 again
+        let profiler = profile_with_layout(&program, layout, 1, 2);
+        assert_eq!(profiler.count(0x200), 2);
+        assert_eq!(profiler.count(0x600), 2);
+    }
+
+    #[test]
+    fn test_subroutines() {
+        let profiler = profile(&PROGRAM, 2, 6);
+        let subroutines = profiler.subroutines();
+        assert_eq!(
+            subroutines[&0x204],
+            SubroutineProfile {
+                calls: 2,
+                own: 4,
+                total: 8
+            }
+        );
+        assert_eq!(
+            subroutines[&0x208],
+            SubroutineProfile {
+                calls: 2,
+                own: 4,
+                total: 4
+            }
+        );
+        assert_eq!(
+            profiler.folded(),
+            "main 4\nmain;sub_204 4\nmain;sub_204;sub_208 4\n"
+        );
+    }
+
+    #[test]
+    fn test_waiting_frames() {
+        let program = [
+            0x60, 0x02, // 200: LD V0, 2
+            0xF0, 0x15, // 202: LD DT, V0
+            0xF0, 0x07, // 204: LD V0, DT
+            0x30, 0x00, // 206: SE V0, 0
+            0x12, 0x04, // 208: JP 0x204
+            0x12, 0x00, // 20A: JP 0x200
+        ];
+        // The timer runs out at the end of the second frame, so the third doesn't wait
+        let profiler = profile(&program, 3, 5);
+        assert_eq!(profiler.waiting_frames, 2);
+        assert!(profiler
+            .report()
+            .contains("Frames spent waiting on the delay timer: 2 (66.7%)"));
+    }
+}
@@ -0,0 +1,460 @@
+//! A Python module for scripting the emulator, e.g. to test ROMs with pytest, built with
+//! the `python` feature (see `pyproject.toml`).
+//!
+//! ```python
+//! import chip8
+//! import numpy
+//!
+//! machine = chip8.Machine(open("roms/pong.rom", "rb").read())
+//! machine.hold(0x1, frames=30)
+//! screen = numpy.asarray(machine.pixels)  # 32x64 array of 0s and 1s
+//! assert machine.sprite(20, 0, 5) == chip8.font_sprite(0)
+//! ```
+//!
+//! `tests/python` has pytest tests driving Pong this way.
+
+use crate::chip8::{
+    display::Display,
+    font::Font,
+    keyboard::SharedKeyboard,
+    memory::{Memory, MemoryLayout},
+    quirks::Quirks,
+    State,
+};
+use crate::config::Config;
+use crate::emulator::Emulator;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyBufferError, PyException, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::os::raw::{c_char, c_int, c_void};
+
+create_exception!(
+    chip8,
+    Chip8Error,
+    PyException,
+    "Raised when the program does something the machine can't, e.g. runs an invalid instruction"
+);
+
+/// A read-only copy of the display or memory, which supports the buffer protocol so
+/// `numpy.asarray` and `memoryview` can read it without copying it again
+#[pyclass(frozen, module = "chip8")]
+pub struct Buffer {
+    data: Vec<u8>,
+    shape: Vec<isize>,
+    strides: Vec<isize>,
+}
+
+impl Buffer {
+    fn new(data: Vec<u8>, shape: Vec<isize>) -> Self {
+        // Row-major, with one byte per item
+        let mut strides = vec![1; shape.len()];
+        for axis in (0..shape.len().saturating_sub(1)).rev() {
+            strides[axis] = strides[axis + 1] * shape[axis + 1];
+        }
+        Buffer {
+            data,
+            shape,
+            strides,
+        }
+    }
+}
+
+#[pymethods]
+impl Buffer {
+    /// The size of each dimension, e.g. (32, 64) for the display
+    #[getter]
+    fn shape(&self) -> Vec<isize> {
+        self.shape.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.shape[0] as usize
+    }
+
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("The view is null"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("The buffer is read-only"));
+        }
+        let buffer = slf.get();
+        let view = &mut *view;
+        view.buf = buffer.data.as_ptr() as *mut c_void;
+        view.len = buffer.data.len() as isize;
+        view.readonly = 1;
+        view.itemsize = 1;
+        view.format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            // Unsigned bytes
+            b"B\0".as_ptr() as *mut c_char
+        } else {
+            std::ptr::null_mut()
+        };
+        view.ndim = buffer.shape.len() as c_int;
+        view.shape = if flags & ffi::PyBUF_ND == ffi::PyBUF_ND {
+            buffer.shape.as_ptr() as *mut _
+        } else {
+            std::ptr::null_mut()
+        };
+        view.strides = if flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+            buffer.strides.as_ptr() as *mut _
+        } else {
+            std::ptr::null_mut()
+        };
+        view.suboffsets = std::ptr::null_mut();
+        view.internal = std::ptr::null_mut();
+        // The view keeps the buffer alive until it's released
+        view.obj = slf.into_ptr();
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut ffi::Py_buffer) {}
+}
+
+/// A machine running one program, with its keys held and released by the script
+#[pyclass(unsendable, module = "chip8")]
+pub struct Machine {
+    emulator: Emulator,
+    keys: SharedKeyboard,
+    quirks: Quirks,
+    layout: MemoryLayout,
+    ticks_per_frame: u32,
+}
+
+impl Machine {
+    fn emulator(
+        program: &[u8],
+        keys: &SharedKeyboard,
+        quirks: Quirks,
+        layout: MemoryLayout,
+    ) -> Emulator {
+        let palette = Config::default().get_palette();
+        let display = Display::new(palette.active(), palette.inactive());
+        let mut state = State::with_layout(program, Box::new(keys.clone()), display, layout);
+        state.quirks = quirks;
+        Emulator::new(state, false)
+    }
+
+    fn check_program(program: &[u8], layout: &MemoryLayout) -> PyResult<()> {
+        layout
+            .check(program, &Font::default())
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn check_key(key: u8) -> PyResult<()> {
+        if key >= 16 {
+            return Err(PyValueError::new_err(format!("There's no key {:#X}", key)));
+        }
+        Ok(())
+    }
+
+    /// Returns the memory from `address` to `address + length`, if it's in bounds
+    fn range(&self, address: usize, length: usize) -> PyResult<std::ops::Range<usize>> {
+        match address.checked_add(length) {
+            Some(end) if end <= self.emulator.state.memory.ram().len() => Ok(address..end),
+            _ => Err(PyValueError::new_err(format!(
+                "{} bytes from {:#05X} run past the end of memory",
+                length, address
+            ))),
+        }
+    }
+}
+
+#[pymethods]
+impl Machine {
+    /// Creates a machine with `program` loaded, which executes `ticks_per_frame`
+    /// instructions a frame with the quirks of the `quirks` profile. Memory is
+    /// `memory_size` bytes, with programs loaded at `program_start` and the font at
+    /// `font_address`, which default to 4096, 0x200 and 0.
+    #[new]
+    #[pyo3(signature = (
+        program,
+        quirks = "default",
+        ticks_per_frame = None,
+        memory_size = None,
+        program_start = None,
+        font_address = None
+    ))]
+    fn new(
+        program: &[u8],
+        quirks: &str,
+        ticks_per_frame: Option<u32>,
+        memory_size: Option<usize>,
+        program_start: Option<usize>,
+        font_address: Option<usize>,
+    ) -> PyResult<Self> {
+        let default = MemoryLayout::default();
+        let layout = MemoryLayout {
+            size: memory_size.unwrap_or(default.size),
+            program_start: program_start.unwrap_or(default.program_start),
+            font_address: font_address.unwrap_or(default.font_address),
+        };
+        Self::check_program(program, &layout)?;
+        let quirks = Quirks::profile(quirks).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "There's no quirk profile named {}. Please use one of {:?}",
+                quirks,
+                Quirks::PROFILES
+            ))
+        })?;
+        let keys = SharedKeyboard::new();
+        Ok(Machine {
+            emulator: Self::emulator(program, &keys, quirks, layout),
+            keys,
+            quirks,
+            layout,
+            ticks_per_frame: ticks_per_frame
+                .unwrap_or_else(|| u32::from(Config::default().ticks_per_frame)),
+        })
+    }
+
+    /// Loads a program, resetting the machine
+    fn load(&mut self, program: &[u8]) -> PyResult<()> {
+        Self::check_program(program, &self.layout)?;
+        self.emulator = Self::emulator(program, &self.keys, self.quirks, self.layout);
+        Ok(())
+    }
+
+    /// Holds or releases one of the 16 keys (0-F)
+    fn set_key(&mut self, key: u8, pressed: bool) -> PyResult<()> {
+        Self::check_key(key)?;
+        self.keys.set_key(key, pressed);
+        Ok(())
+    }
+
+    /// Holds a key until it's released
+    fn press(&mut self, key: u8) -> PyResult<()> {
+        self.set_key(key, true)
+    }
+
+    /// Releases a held key
+    fn release(&mut self, key: u8) -> PyResult<()> {
+        self.set_key(key, false)
+    }
+
+    /// Executes one instruction, unless the program is waiting for a key to be held,
+    /// since the script can't be blocked
+    fn step(&mut self) -> PyResult<()> {
+        if self.emulator.is_waiting_for_key() && !self.keys.any_held() {
+            return Ok(());
+        }
+        self.emulator
+            .step()
+            .map_err(|e| Chip8Error::new_err(e.to_string()))
+    }
+
+    /// Emulates `frames` frames, each executing `ticks_per_frame` instructions and then
+    /// counting down the timers
+    #[pyo3(signature = (frames = 1))]
+    fn run_frames(&mut self, frames: u32) -> PyResult<()> {
+        for _ in 0..frames {
+            for _ in 0..self.ticks_per_frame {
+                self.step()?;
+            }
+            self.emulator.end_frame();
+        }
+        Ok(())
+    }
+
+    /// Holds a key for `frames` frames, then releases it
+    #[pyo3(signature = (key, frames = 1))]
+    fn hold(&mut self, key: u8, frames: u32) -> PyResult<()> {
+        self.press(key)?;
+        let result = self.run_frames(frames);
+        self.keys.set_key(key, false);
+        result
+    }
+
+    /// The number of frames emulated since the program was loaded
+    #[getter]
+    fn frame(&self) -> u64 {
+        self.emulator.frame
+    }
+
+    /// The program counter
+    #[getter]
+    fn pc(&self) -> usize {
+        self.emulator.state.program_counter
+    }
+
+    #[setter]
+    fn set_pc(&mut self, pc: usize) -> PyResult<()> {
+        if pc >= self.emulator.state.memory.layout().code().end {
+            return Err(PyValueError::new_err(format!(
+                "The program counter {:#X} is out of memory",
+                pc
+            )));
+        }
+        self.emulator.state.program_counter = pc;
+        Ok(())
+    }
+
+    /// The I register
+    #[getter]
+    fn i(&self) -> u16 {
+        self.emulator.state.registers.i_register
+    }
+
+    #[setter]
+    fn set_i(&mut self, i: u16) {
+        self.emulator.state.registers.i_register = i;
+    }
+
+    /// A copy of V0-VF. Assign all 16 to change them.
+    #[getter]
+    fn v(&self) -> Vec<u8> {
+        self.emulator.state.registers.v_registers.to_vec()
+    }
+
+    #[setter]
+    fn set_v(&mut self, v: Vec<u8>) -> PyResult<()> {
+        if v.len() != 16 {
+            return Err(PyValueError::new_err(format!(
+                "There are 16 V registers, not {}",
+                v.len()
+            )));
+        }
+        self.emulator
+            .state
+            .registers
+            .v_registers
+            .copy_from_slice(&v);
+        Ok(())
+    }
+
+    #[getter]
+    fn delay_timer(&self) -> u8 {
+        self.emulator.state.timers.delay_timer
+    }
+
+    #[setter]
+    fn set_delay_timer(&mut self, value: u8) {
+        self.emulator.state.timers.delay_timer = value;
+    }
+
+    #[getter]
+    fn sound_timer(&self) -> u8 {
+        self.emulator.state.timers.sound_timer
+    }
+
+    #[setter]
+    fn set_sound_timer(&mut self, value: u8) {
+        self.emulator.state.timers.sound_timer = value;
+    }
+
+    /// The return addresses on the stack, innermost last
+    #[getter]
+    fn stack(&self) -> Vec<usize> {
+        self.emulator.state.stack.contents().to_vec()
+    }
+
+    /// Whether the beeper should sound
+    #[getter]
+    fn beeping(&self) -> bool {
+        self.emulator.state.timers.sound_timer > 0
+    }
+
+    /// A copy of the display, 32 rows of 64 pixels, 1 where a pixel is lit
+    #[getter]
+    fn pixels(&self) -> Buffer {
+        let pixels = self.emulator.state.display.pixels.concat();
+        Buffer::new(
+            pixels,
+            vec![Display::HEIGHT as isize, Display::WIDTH as isize],
+        )
+    }
+
+    /// A copy of memory, 4096 bytes unless the machine was given another size
+    #[getter]
+    fn ram(&self) -> Buffer {
+        let ram = self.emulator.state.memory.ram().to_vec();
+        let size = ram.len() as isize;
+        Buffer::new(ram, vec![size])
+    }
+
+    /// Returns `length` bytes of memory starting at `address`
+    fn read_memory<'py>(
+        &self,
+        py: Python<'py>,
+        address: usize,
+        length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let range = self.range(address, length)?;
+        Ok(PyBytes::new(py, &self.emulator.state.memory.ram()[range]))
+    }
+
+    /// Writes `data` to memory starting at `address`
+    fn write_memory(&mut self, address: usize, data: Vec<u8>) -> PyResult<()> {
+        let range = self.range(address, data.len())?;
+        self.emulator.state.memory.ram_mut()[range].copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// Returns the pixels in the `height` rows of 8 starting at (x, y) as a sprite,
+    /// one byte per row, wrapping around the edges like DRW does. Compare it with
+    /// `font_sprite` to check a digit is on the screen.
+    fn sprite<'py>(
+        &self,
+        py: Python<'py>,
+        x: usize,
+        y: usize,
+        height: usize,
+    ) -> Bound<'py, PyBytes> {
+        let pixels = &self.emulator.state.display.pixels;
+        let rows: Vec<u8> = (0..height)
+            .map(|row| {
+                (0..8).fold(0, |byte, column| {
+                    let pixel = pixels[(y + row) % Display::HEIGHT][(x + column) % Display::WIDTH];
+                    byte << 1 | pixel
+                })
+            })
+            .collect();
+        PyBytes::new(py, &rows)
+    }
+
+    /// Saves the whole machine (except the keys held), for `load_state`
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.emulator.state.snapshot())
+    }
+
+    /// Restores the machine to a state saved by `save_state`
+    fn load_state(&mut self, data: &[u8]) -> PyResult<()> {
+        self.emulator
+            .state
+            .restore(data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.quirks = self.emulator.state.quirks;
+        Ok(())
+    }
+}
+
+/// Returns the built-in sprite for a hex digit (0-F), as LD F, VX points I to
+#[pyfunction]
+fn font_sprite<'py>(py: Python<'py>, digit: u8) -> PyResult<Bound<'py, PyBytes>> {
+    if digit >= 16 {
+        return Err(PyValueError::new_err(format!(
+            "There's no sprite for {:#X}",
+            digit
+        )));
+    }
+    let start = usize::from(digit) * 5;
+    Ok(PyBytes::new(py, &Memory::new(&[]).ram()[start..start + 5]))
+}
+
+#[pymodule]
+#[pyo3(name = "chip8")]
+fn chip8_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Machine>()?;
+    m.add_class::<Buffer>()?;
+    m.add_function(wrap_pyfunction!(font_sprite, m)?)?;
+    m.add("Chip8Error", m.py().get_type::<Chip8Error>())?;
+    m.add("WIDTH", Display::WIDTH)?;
+    m.add("HEIGHT", Display::HEIGHT)?;
+    Ok(())
+}
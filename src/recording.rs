@@ -0,0 +1,213 @@
+use crate::capture::{scale, to_rgb, CaptureError};
+use crate::chip8::display::Display;
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// The rate at which the chip-8 timers count down, which is also the
+/// frame rate of recordings regardless of how fast the emulator is running
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+/// The sample rate of recorded audio
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// The number of audio samples recorded per frame
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FRAMES_PER_SECOND) as usize;
+
+/// Returns `path` with `.extension` added after whatever extension it already has
+fn add_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Synthesizes the chip-8 beeper, a square wave that sounds while the sound timer is positive
+pub struct Beeper {
+    /// The frequency of the tone (in Hz)
+    frequency: f64,
+    /// How far through the current period the wave is, from 0 to 1.
+    /// Kept between frames so the tone doesn't click at frame boundaries.
+    phase: f64,
+}
+
+impl Beeper {
+    /// The amplitude of the square wave, a quarter of the loudest possible sample
+    const AMPLITUDE: i16 = i16::MAX / 4;
+
+    pub fn new(frequency: f64) -> Self {
+        Beeper {
+            frequency,
+            phase: 0.0,
+        }
+    }
+
+    /// Returns one frame's worth of samples for a frame with the given sound timer
+    pub fn frame_samples(&mut self, sound_timer: u8) -> [i16; SAMPLES_PER_FRAME] {
+        let mut samples = [0; SAMPLES_PER_FRAME];
+        if sound_timer == 0 {
+            self.phase = 0.0;
+            return samples;
+        }
+        for sample in samples.iter_mut() {
+            *sample = if (2.0 * PI * self.phase).sin() >= 0.0 {
+                Self::AMPLITUDE
+            } else {
+                -Self::AMPLITUDE
+            };
+            self.phase = (self.phase + self.frequency / f64::from(SAMPLE_RATE)).fract();
+        }
+        samples
+    }
+}
+
+/// Converts RGB pixels to the Y, U and V planes of a 4:4:4 image (BT.601, limited range)
+fn to_yuv_planes(rgb: &[u8]) -> [Vec<u8>; 3] {
+    let mut planes = [Vec::new(), Vec::new(), Vec::new()];
+    for pixel in rgb.chunks(3) {
+        let (r, g, b) = (
+            i32::from(pixel[0]),
+            i32::from(pixel[1]),
+            i32::from(pixel[2]),
+        );
+        planes[0].push((((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8);
+        planes[1].push((((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8);
+        planes[2].push((((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8);
+    }
+    planes
+}
+
+/// Records every frame of the display to a Y4M video and the beeper to a WAV file.
+///
+/// Both streams advance by exactly one frame per emulator frame rather than by
+/// wall clock time, so recordings stay in sync when the emulator runs headless
+/// or faster than real time.
+pub struct Recorder {
+    video: BufWriter<File>,
+    audio: BufWriter<File>,
+    beeper: Beeper,
+    pixel_size: usize,
+    /// The number of audio samples written so far
+    samples: u32,
+}
+
+impl Recorder {
+    /// The frequency of the beeper (in Hz)
+    const BEEP_FREQUENCY: f64 = 440.0;
+
+    /// Creates `<path>.y4m` and `<path>.wav`, scaling every chip-8 pixel by `pixel_size`
+    pub fn create(path: &Path, pixel_size: u32) -> Result<Self, CaptureError> {
+        let mut video = BufWriter::new(File::create(add_extension(path, "y4m"))?);
+        writeln!(
+            video,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+            Display::WIDTH as u32 * pixel_size,
+            Display::HEIGHT as u32 * pixel_size,
+            FRAMES_PER_SECOND
+        )?;
+        let mut audio = BufWriter::new(File::create(add_extension(path, "wav"))?);
+        write_wav_header(&mut audio, 0)?;
+        Ok(Recorder {
+            video,
+            audio,
+            beeper: Beeper::new(Self::BEEP_FREQUENCY),
+            pixel_size: pixel_size as usize,
+            samples: 0,
+        })
+    }
+
+    /// Records one frame, given the display and the sound timer during the frame
+    pub fn record_frame(&mut self, display: &Display, sound_timer: u8) -> Result<(), CaptureError> {
+        self.video.write_all(b"FRAME\n")?;
+        for plane in to_yuv_planes(&to_rgb(&display.colored_pixels)).iter() {
+            self.video
+                .write_all(&scale(plane, 1, Display::WIDTH, self.pixel_size))?;
+        }
+        for sample in self.beeper.frame_samples(sound_timer).iter() {
+            self.audio.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += SAMPLES_PER_FRAME as u32;
+        Ok(())
+    }
+
+    /// Flushes both streams and fills in the sizes in the WAV header
+    pub fn finish(mut self) -> Result<(), CaptureError> {
+        self.video.flush()?;
+        self.audio.flush()?;
+        let mut audio = self.audio.into_inner().map_err(|e| e.into_error())?;
+        audio.seek(SeekFrom::Start(0))?;
+        write_wav_header(&mut audio, self.samples)?;
+        Ok(())
+    }
+}
+
+/// Writes the header of a 16-bit mono PCM WAV file holding `samples` samples
+fn write_wav_header<W: Write>(out: &mut W, samples: u32) -> Result<(), CaptureError> {
+    let data_size: u32 = samples * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&1u16.to_le_bytes())?; // Mono
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // Byte rate
+    out.write_all(&2u16.to_le_bytes())?; // Block align
+    out.write_all(&16u16.to_le_bytes())?; // Bits per sample
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Color;
+    use std::{env, fs};
+
+    #[test]
+    fn test_beeper() {
+        let mut beeper = Beeper::new(440.0);
+        assert!(beeper.frame_samples(0).iter().all(|x| *x == 0));
+        let samples = beeper.frame_samples(1);
+        assert!(samples.iter().all(|x| x.abs() == Beeper::AMPLITUDE));
+        assert!(samples.iter().any(|x| *x < 0));
+    }
+
+    #[test]
+    fn test_yuv_planes() {
+        let planes = to_yuv_planes(&[0, 0, 0, 255, 255, 255]);
+        assert_eq!(planes[0], vec![16, 235]);
+        assert_eq!(planes[1], vec![128, 128]);
+        assert_eq!(planes[2], vec![128, 128]);
+    }
+
+    #[test]
+    fn test_recorder() {
+        let display = Display::new(Color::white(), Color::black());
+        let path = env::temp_dir().join("chip8_test_recorder.v1");
+        let mut recorder = Recorder::create(&path, 2).unwrap();
+        recorder.record_frame(&display, 0).unwrap();
+        recorder.record_frame(&display, 3).unwrap();
+        recorder.finish().unwrap();
+
+        let video_path = env::temp_dir().join("chip8_test_recorder.v1.y4m");
+        let audio_path = env::temp_dir().join("chip8_test_recorder.v1.wav");
+        let video = fs::read(&video_path).unwrap();
+        let audio = fs::read(&audio_path).unwrap();
+        fs::remove_file(video_path).unwrap();
+        fs::remove_file(audio_path).unwrap();
+
+        let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+        assert_eq!(&video[..header.len()], &header[..]);
+        assert_eq!(video.len(), header.len() + 2 * (6 + 128 * 64 * 3));
+        assert_eq!(audio.len(), 44 + 2 * SAMPLES_PER_FRAME * 2);
+        assert_eq!(
+            &audio[40..44],
+            &(2 * SAMPLES_PER_FRAME as u32 * 2).to_le_bytes()
+        );
+    }
+}
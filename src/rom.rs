@@ -0,0 +1,317 @@
+use crate::octo::{self, Cartridge, CartridgeOptions};
+use std::{
+    fmt, fs,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+/// Error for when a program can't be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomError(String);
+
+impl RomError {
+    pub fn new(message: &str) -> Self {
+        RomError(String::from(message))
+    }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a file holding a program is, told apart by its contents rather than its name,
+/// except for hex text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The program's bytes as they are
+    Binary,
+    /// The program's bytes as hex digits, e.g. `00E0 A22A`
+    Hex,
+    /// Octo source code, e.g. `: main clear`
+    OctoSource,
+    /// A zip archive with the program in it
+    Zip,
+    /// An Octo cartridge: a GIF with the program's source and options hidden in it
+    Cartridge,
+}
+
+impl Format {
+    /// Tells what the file named `name` is from its contents, or `-` for standard input
+    pub fn detect(bytes: &[u8], name: &str) -> Format {
+        if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Format::Zip
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Format::Cartridge
+        } else if is_hex_text(bytes, name) {
+            Format::Hex
+        } else if is_octo_source(bytes) {
+            Format::OctoSource
+        } else {
+            Format::Binary
+        }
+    }
+}
+
+/// A program, with the settings it came with if it's from an Octo cartridge
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rom {
+    pub program: Vec<u8>,
+    pub options: Option<CartridgeOptions>,
+}
+
+impl Rom {
+    /// Loads a program from the contents of the file named `name` in any format,
+    /// assembling source to be loaded at `origin`. `choose` picks the entry to load from an
+    /// archive with more than one program in it.
+    pub fn load<F>(bytes: &[u8], name: &str, origin: usize, choose: &mut F) -> Result<Rom, RomError>
+    where
+        F: FnMut(&[String]) -> Result<usize, RomError>,
+    {
+        match Format::detect(bytes, name) {
+            Format::Zip => {
+                let (name, entry) = read_zip(bytes, choose)?;
+                Rom::load_unpacked(&entry, &name, origin)
+            }
+            _ => Rom::load_unpacked(bytes, name, origin),
+        }
+    }
+
+    /// Loads a program that isn't in an archive, which it can't be for an archive's entries
+    fn load_unpacked(bytes: &[u8], name: &str, origin: usize) -> Result<Rom, RomError> {
+        match Format::detect(bytes, name) {
+            Format::Binary => Ok(Rom::binary(bytes.to_vec())),
+            Format::Hex => Ok(Rom::binary(parse_hex(bytes).unwrap())),
+            Format::OctoSource => {
+                let source = std::str::from_utf8(bytes).unwrap();
+                let program = octo::assemble(source, origin)
+                    .map_err(|e| RomError(format!("Could not assemble the program: {}", e)))?;
+                Ok(Rom::binary(program))
+            }
+            Format::Zip => Err(RomError::new(
+                "The archive has another archive in it instead of a program",
+            )),
+            Format::Cartridge => {
+                let cartridge = Cartridge::from_gif(bytes).map_err(|e| RomError(e.to_string()))?;
+                let program = cartridge
+                    .assemble(origin)
+                    .map_err(|e| RomError(format!("Could not assemble the cartridge: {}", e)))?;
+                Ok(Rom {
+                    program,
+                    options: Some(cartridge.options),
+                })
+            }
+        }
+    }
+
+    fn binary(program: Vec<u8>) -> Rom {
+        Rom {
+            program,
+            options: None,
+        }
+    }
+}
+
+/// Reads a file, or standard input if the path is `-`
+pub fn read(path: &str) -> Result<Vec<u8>, RomError> {
+    if path == "-" {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| RomError(e.to_string()))?;
+        Ok(bytes)
+    } else {
+        fs::read(path).map_err(|e| RomError(e.to_string()))
+    }
+}
+
+/// Parses text made of pairs of hex digits, which can be separated by whitespace or
+/// commas and start with 0x
+fn parse_hex(bytes: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let mut digits = String::new();
+    for word in text.split(|x: char| x.is_whitespace() || x == ',') {
+        let word = word.strip_prefix("0x").unwrap_or(word);
+        if !word.chars().all(|x| x.is_ascii_hexdigit()) || word.len() % 2 != 0 {
+            return None;
+        }
+        digits.push_str(word);
+    }
+    if digits.is_empty() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|x| u8::from_str_radix(&digits[x..x + 2], 16).ok())
+        .collect()
+}
+
+/// Tells whether a file is hex text. A binary can be made of nothing but the bytes of hex
+/// digits too, so unless the file is named .hex, the digits have to be separated or start
+/// with 0x.
+fn is_hex_text(bytes: &[u8], name: &str) -> bool {
+    parse_hex(bytes).is_some()
+        && (extension(name).as_deref() == Some("hex")
+            || bytes.iter().any(|x| x.is_ascii_whitespace() || *x == b',')
+            || bytes.windows(2).any(|x| x == b"0x"))
+}
+
+/// The extension of a file name, in lower case
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .map(|x| x.to_lowercase())
+}
+
+/// Tells whether text is Octo source, which always has a `main` label
+fn is_octo_source(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).is_ok_and(|text| {
+        let words: Vec<&str> = text
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+            .collect();
+        words.windows(2).any(|x| x == [":", "main"])
+    })
+}
+
+/// The extensions of the files that are taken to be programs, or archives of them
+pub const EXTENSIONS: [&str; 7] = ["ch8", "c8", "rom", "hex", "8o", "gif", "zip"];
+
+/// Reads the name and contents of the program in a zip archive: its only file, else its
+/// only file with a program's extension, else the one `choose` picks
+fn read_zip<F>(bytes: &[u8], choose: &mut F) -> Result<(String, Vec<u8>), RomError>
+where
+    F: FnMut(&[String]) -> Result<usize, RomError>,
+{
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| RomError(e.to_string()))?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let file = archive
+            .by_index(index)
+            .map_err(|e| RomError(e.to_string()))?;
+        if file.is_file() && !file.name().starts_with("__MACOSX/") {
+            files.push(String::from(file.name()));
+        }
+    }
+    let programs: Vec<String> = files
+        .iter()
+        .filter(|x| extension(x).is_some_and(|x| x != "zip" && EXTENSIONS.contains(&x.as_str())))
+        .cloned()
+        .collect();
+    let entries = if programs.is_empty() { files } else { programs };
+    let name = match entries.len() {
+        0 => return Err(RomError::new("The archive is empty")),
+        1 => &entries[0],
+        _ => entries
+            .get(choose(&entries)?)
+            .ok_or_else(|| RomError::new("There's no such entry in the archive"))?,
+    };
+    let mut file = archive.by_name(name).map_err(|e| RomError(e.to_string()))?;
+    let mut entry = Vec::new();
+    file.read_to_end(&mut entry)
+        .map_err(|e| RomError(e.to_string()))?;
+    Ok((name.clone(), entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::memory::PROGRAM_START;
+    use std::io::Write;
+
+    fn no_choice(_: &[String]) -> Result<usize, RomError> {
+        panic!("There should be nothing to choose")
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Format::detect(&[0x00, 0xE0, 0x12, 0x00], "a.ch8"),
+            Format::Binary
+        );
+        assert_eq!(Format::detect(b"00E0 1200\n", "-"), Format::Hex);
+        assert_eq!(Format::detect(b"0x00, 0xE0", "a.txt"), Format::Hex);
+        assert_eq!(Format::detect(b"00E", "a.hex"), Format::Binary);
+        assert_eq!(Format::detect(b"GIF89a...", "a.gif"), Format::Cartridge);
+        assert_eq!(
+            Format::detect(b": main\n  clear # ok", "a"),
+            Format::OctoSource
+        );
+        assert_eq!(Format::detect(b"#: main", "a"), Format::Binary);
+        assert_eq!(
+            Format::detect(&zip(&[("a.ch8", &[1])]), "a.ch8"),
+            Format::Zip
+        );
+    }
+
+    #[test]
+    fn test_detect_hex_digits() {
+        // The bytes of hex digits, like 0x61 for a, are a program as well as hex text
+        assert_eq!(Format::detect(b"6162", "a.ch8"), Format::Binary);
+        assert_eq!(Format::detect(b"6162", "-"), Format::Binary);
+        assert_eq!(Format::detect(b"6162", "a.HEX"), Format::Hex);
+        assert_eq!(Format::detect(b"61 62", "a.ch8"), Format::Hex);
+        assert_eq!(Format::detect(b"0x6162", "a.ch8"), Format::Hex);
+        let rom = Rom::load(b"6162", "a.ch8", PROGRAM_START, &mut no_choice).unwrap();
+        assert_eq!(rom.program, b"6162");
+        let archive = zip(&[("a.hex", b"6162")]);
+        let rom = Rom::load(&archive, "a.zip", PROGRAM_START, &mut no_choice).unwrap();
+        assert_eq!(rom.program, [0x61, 0x62]);
+    }
+
+    #[test]
+    fn test_load_hex() {
+        let rom = Rom::load(b"00E0 a2 2A\n0x12,0x00", "-", PROGRAM_START, &mut no_choice).unwrap();
+        assert_eq!(rom.program, [0x00, 0xE0, 0xA2, 0x2A, 0x12, 0x00]);
+        assert_eq!(rom.options, None);
+    }
+
+    #[test]
+    fn test_load_zip() {
+        let archive = zip(&[("README.txt", b"Pong"), ("games/pong.ch8", &[1, 2])]);
+        assert_eq!(
+            Rom::load(&archive, "a.zip", PROGRAM_START, &mut no_choice)
+                .unwrap()
+                .program,
+            [1, 2]
+        );
+        let archive = zip(&[("b.ch8", b"03 04"), ("a.ch8", &[1])]);
+        let mut choices = Vec::new();
+        let mut choose = |entries: &[String]| {
+            choices.extend_from_slice(entries);
+            Ok(0)
+        };
+        assert_eq!(
+            Rom::load(&archive, "a.zip", PROGRAM_START, &mut choose)
+                .unwrap()
+                .program,
+            [3, 4]
+        );
+        assert_eq!(choices, ["b.ch8", "a.ch8"]);
+        assert!(Rom::load(&archive, "a.zip", PROGRAM_START, &mut |_: &[String]| Ok(2)).is_err());
+        let archive = zip(&[("README.txt", b"Pong"), ("pong.rom", &[5, 6])]);
+        let rom = Rom::load(&archive, "a.zip", PROGRAM_START, &mut no_choice).unwrap();
+        assert_eq!(rom.program, [5, 6]);
+        let archive = zip(&[("game.8o", b": main clear")]);
+        let program = Rom::load(&archive, "a.zip", PROGRAM_START, &mut no_choice)
+            .unwrap()
+            .program;
+        assert_eq!(program, [0x12, 0x02, 0x00, 0xE0]);
+        let archive = zip(&[("inner.zip", &archive)]);
+        assert!(Rom::load(&archive, "a.zip", PROGRAM_START, &mut no_choice).is_err());
+    }
+}
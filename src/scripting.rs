@@ -0,0 +1,534 @@
+//! Rhai scripts that automate runs, built with the `scripting` feature.
+//!
+//! A script's top level runs once when the program is loaded, and can set
+//! breakpoints and watches. Its hooks then run as the program does:
+//!
+//! ```rhai
+//! breakpoint(0x2A2);  // where Pong handles a miss
+//! watch(0x2F3);       // the tens digit of the score, which is the left player's
+//!
+//! fn on_frame() {
+//!     if frame() == 100 { press(1); }
+//!     if frame() == 130 { release(1); }
+//!     text(1, 26, `FRAME ${frame()}`);
+//! }
+//!
+//! fn on_breakpoint(address) {
+//!     this.points = (this.points ?? 0) + 1;
+//!     if this.points == 3 { quit(); }
+//! }
+//!
+//! fn on_write(address, value) {
+//!     print(`${address} = ${value}`);
+//! }
+//! ```
+//!
+//! Functions can't see the script's variables, so hooks keep values between calls
+//! in `this`, a map shared by all of them. Text stays on the screen until other text
+//! is drawn at the same place.
+
+use crate::chip8::{
+    display::Display,
+    keyboard::{HeadlessKeyboard, Hotkey, Keyboard, SharedKeyboard},
+    State,
+};
+use crate::config::Color;
+use crate::overlay::Overlay;
+use crate::text::Canvas;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::rc::Rc;
+use std::{fmt, fs, path::Path};
+
+/// Error for when a script can't be loaded, or fails while running
+#[derive(Debug)]
+pub struct ScriptError(String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(error: Box<EvalAltResult>) -> Self {
+        ScriptError(error.to_string())
+    }
+}
+
+/// Text a script draws over the display
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    /// The position of its top left corner, in chip-8 pixels
+    pub x: usize,
+    pub y: usize,
+    pub text: String,
+}
+
+/// What the script's functions work on. The machine's State is swapped in while the
+/// script runs, and a placeholder is left in its place otherwise.
+struct Context {
+    state: RefCell<State>,
+    keys: SharedKeyboard,
+    breakpoints: RefCell<BTreeSet<usize>>,
+    watches: RefCell<BTreeSet<usize>>,
+    /// The watched addresses the program has written to since the last instruction,
+    /// with the values written, which the memory's watches add to
+    writes: Rc<RefCell<Vec<(usize, u8)>>>,
+    labels: RefCell<Vec<Label>>,
+    frame: Cell<u64>,
+    /// The exit code the script quit with, if it has
+    exit_code: Cell<Option<i32>>,
+}
+
+/// Result of a function called from a script
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Returns a value from a script if it's in `0..limit`
+fn checked(value: i64, limit: usize, name: &str) -> ScriptResult<usize> {
+    if value >= 0 && (value as usize) < limit {
+        Ok(value as usize)
+    } else {
+        Err(format!("{} {:#X} is out of range", name, value).into())
+    }
+}
+
+impl Context {
+    /// Registers the functions scripts call
+    fn register(context: &Rc<Context>, engine: &mut Engine) {
+        let c = context.clone();
+        engine.register_fn("pc", move || c.state.borrow().program_counter as i64);
+        let c = context.clone();
+        engine.register_fn("set_pc", move |address: i64| -> ScriptResult<()> {
+            let code = c.state.borrow().memory.layout().code();
+            c.state.borrow_mut().program_counter = checked(address, code.end, "The address")?;
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("i", move || {
+            i64::from(c.state.borrow().registers.i_register)
+        });
+        let c = context.clone();
+        engine.register_fn("set_i", move |value: i64| -> ScriptResult<()> {
+            c.state.borrow_mut().registers.i_register = checked(value, 0x10000, "I")? as u16;
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("v", move |x: i64| -> ScriptResult<i64> {
+            Ok(i64::from(
+                c.state.borrow().registers.v_registers[checked(x, 16, "The register")?],
+            ))
+        });
+        let c = context.clone();
+        engine.register_fn("set_v", move |x: i64, value: i64| -> ScriptResult<()> {
+            let x = checked(x, 16, "The register")?;
+            c.state.borrow_mut().registers.v_registers[x] = checked(value, 256, "The value")? as u8;
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("delay_timer", move || {
+            i64::from(c.state.borrow().timers.delay_timer)
+        });
+        let c = context.clone();
+        engine.register_fn("set_delay_timer", move |value: i64| -> ScriptResult<()> {
+            c.state.borrow_mut().timers.delay_timer = checked(value, 256, "The value")? as u8;
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("sound_timer", move || {
+            i64::from(c.state.borrow().timers.sound_timer)
+        });
+        let c = context.clone();
+        engine.register_fn("set_sound_timer", move |value: i64| -> ScriptResult<()> {
+            c.state.borrow_mut().timers.sound_timer = checked(value, 256, "The value")? as u8;
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("frame", move || c.frame.get() as i64);
+        let c = context.clone();
+        engine.register_fn("peek", move |address: i64| -> ScriptResult<i64> {
+            let state = c.state.borrow();
+            let address = checked(address, state.memory.ram().len(), "The address")?;
+            Ok(i64::from(state.memory.ram()[address]))
+        });
+        let c = context.clone();
+        engine.register_fn(
+            "poke",
+            move |address: i64, value: i64| -> ScriptResult<()> {
+                let mut state = c.state.borrow_mut();
+                let address = checked(address, state.memory.ram().len(), "The address")?;
+                state.memory.ram_mut()[address] = checked(value, 256, "The value")? as u8;
+                Ok(())
+            },
+        );
+        let c = context.clone();
+        engine.register_fn("press", move |key: i64| -> ScriptResult<()> {
+            c.keys.set_key(checked(key, 16, "The key")? as u8, true);
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("release", move |key: i64| -> ScriptResult<()> {
+            c.keys.set_key(checked(key, 16, "The key")? as u8, false);
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn(
+            "text",
+            move |x: i64, y: i64, text: &str| -> ScriptResult<()> {
+                let x = checked(x, Display::WIDTH, "x")?;
+                let y = checked(y, Display::HEIGHT, "y")?;
+                // Text replaces whatever was drawn at the same place
+                let mut labels = c.labels.borrow_mut();
+                labels.retain(|label| (label.x, label.y) != (x, y));
+                if !text.is_empty() {
+                    labels.push(Label {
+                        x,
+                        y,
+                        text: text.to_string(),
+                    });
+                }
+                Ok(())
+            },
+        );
+        let c = context.clone();
+        engine.register_fn("clear_text", move || c.labels.borrow_mut().clear());
+        let c = context.clone();
+        engine.register_fn("breakpoint", move |address: i64| -> ScriptResult<()> {
+            let code = c.state.borrow().memory.layout().code();
+            let address = checked(address, code.end, "The address")?;
+            c.breakpoints.borrow_mut().insert(address);
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("watch", move |address: i64| -> ScriptResult<()> {
+            let size = c.state.borrow().memory.ram().len();
+            let address = checked(address, size, "The address")?;
+            if c.watches.borrow_mut().insert(address) {
+                let writes = c.writes.clone();
+                c.state
+                    .borrow_mut()
+                    .memory
+                    .watch(address..address + 1, move |address, value| {
+                        writes.borrow_mut().push((address, value))
+                    });
+            }
+            Ok(())
+        });
+        let c = context.clone();
+        engine.register_fn("quit", move || c.exit_code.set(Some(0)));
+        let c = context.clone();
+        engine.register_fn("quit", move |code: i64| c.exit_code.set(Some(code as i32)));
+    }
+}
+
+/// A keyboard whose keys are held if they're held on the real keyboard or by the script
+struct ScriptKeyboard {
+    keyboard: Box<dyn Keyboard>,
+    keys: SharedKeyboard,
+}
+
+impl Keyboard for ScriptKeyboard {
+    fn is_key_pressed(&self, key: u8) -> bool {
+        self.keys.is_key_pressed(key) || self.keyboard.is_key_pressed(key)
+    }
+
+    fn wait_for_key_press(&self) -> u8 {
+        if self.keys.any_held() {
+            self.keys.wait_for_key_press()
+        } else {
+            self.keyboard.wait_for_key_press()
+        }
+    }
+
+    fn is_quit(&self) -> bool {
+        self.keyboard.is_quit()
+    }
+
+    fn take_hotkeys(&self) -> Vec<Hotkey> {
+        self.keyboard.take_hotkeys()
+    }
+}
+
+/// A script loaded into the emulator, whose hooks run at the end of every frame
+/// (`on_frame`), when the program reaches a breakpoint (`on_breakpoint`) and when it
+/// writes to a watched address (`on_write`)
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    context: Rc<Context>,
+    /// The map hooks see as `this`
+    this: Dynamic,
+}
+
+impl Script {
+    /// The size of the image labels are drawn on, which is the display at the
+    /// overlay's scale
+    pub const LABELS_WIDTH: usize = Display::WIDTH * Overlay::SCALE;
+    pub const LABELS_HEIGHT: usize = Display::HEIGHT * Overlay::SCALE;
+
+    /// Compiles a script
+    pub fn new(source: &str) -> Result<Self, ScriptError> {
+        let context = Rc::new(Context {
+            state: RefCell::new(State::new(
+                &[],
+                Box::new(HeadlessKeyboard::new()),
+                Display::new(Color::white(), Color::black()),
+            )),
+            keys: SharedKeyboard::new(),
+            breakpoints: RefCell::new(BTreeSet::new()),
+            watches: RefCell::new(BTreeSet::new()),
+            writes: Rc::new(RefCell::new(Vec::new())),
+            labels: RefCell::new(Vec::new()),
+            frame: Cell::new(0),
+            exit_code: Cell::new(None),
+        });
+        let mut engine = Engine::new();
+        Context::register(&context, &mut engine);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| ScriptError(e.to_string()))?;
+        Ok(Script {
+            engine,
+            ast,
+            scope: Scope::new(),
+            context,
+            this: Dynamic::from_map(Map::new()),
+        })
+    }
+
+    /// Compiles the script in a file
+    pub fn from_file(path: &Path) -> Result<Self, ScriptError> {
+        let source = fs::read_to_string(path).map_err(|e| ScriptError(e.to_string()))?;
+        Self::new(&source)
+    }
+
+    /// Wraps the machine's keyboard so the script can hold keys too
+    pub fn keyboard(&self, keyboard: Box<dyn Keyboard>) -> Box<dyn Keyboard> {
+        Box::new(ScriptKeyboard {
+            keyboard,
+            keys: self.context.keys.clone(),
+        })
+    }
+
+    /// Runs the script's top level, which sets up its breakpoints and watches
+    pub fn start(&mut self, state: &mut State) {
+        let (engine, ast, scope) = (&self.engine, &self.ast, &mut self.scope);
+        Self::with_state(&self.context, state, || {
+            engine
+                .run_ast_with_scope(scope, ast)
+                .map_err(ScriptError::from)
+        });
+    }
+
+    /// Runs the hooks for the instruction just executed: `on_write` for each watched
+    /// address it wrote to, then `on_breakpoint` if the program counter is on a breakpoint
+    pub fn after_step(&mut self, state: &mut State) {
+        let writes: Vec<(usize, u8)> = self.context.writes.borrow_mut().drain(..).collect();
+        for (address, value) in writes {
+            self.call(
+                state,
+                "on_write",
+                vec![(address as i64).into(), i64::from(value).into()],
+            );
+        }
+        let pc = state.program_counter;
+        if self.context.breakpoints.borrow().contains(&pc) {
+            self.call(state, "on_breakpoint", vec![(pc as i64).into()]);
+        }
+    }
+
+    /// Runs the `on_frame` hook at the end of a frame
+    pub fn end_frame(&mut self, state: &mut State, frame: u64) {
+        self.context.frame.set(frame);
+        self.call(state, "on_frame", vec![]);
+    }
+
+    /// Returns the exit code the script quit with (1 if it failed), if it has
+    pub fn exit_code(&self) -> Option<i32> {
+        self.context.exit_code.get()
+    }
+
+    /// Returns the text the script has drawn
+    pub fn labels(&self) -> Vec<Label> {
+        self.context.labels.borrow().clone()
+    }
+
+    /// Draws the labels onto a transparent RGBA image of LABELS_WIDTH by LABELS_HEIGHT,
+    /// to be laid over the display
+    pub fn draw_labels(&self, pixels: &mut [u8]) {
+        let mut canvas = Canvas::new(pixels, Self::LABELS_WIDTH);
+        canvas.fill(Color(0, 0, 0, 0));
+        for label in self.context.labels.borrow().iter() {
+            let (x, y) = (label.x * Overlay::SCALE, label.y * Overlay::SCALE);
+            // A shadow keeps the text readable on lit pixels
+            canvas.draw_text(x + 1, y + 1, &label.text, Color(0, 0, 0, 255));
+            canvas.draw_text(x, y, &label.text, Color(255, 255, 0, 255));
+        }
+    }
+
+    /// Calls a hook, if the script defines it with that many parameters
+    fn call(&mut self, state: &mut State, name: &str, args: Vec<Dynamic>) {
+        if self.exit_code().is_some() {
+            return;
+        }
+        let defined = self
+            .ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == args.len());
+        if !defined {
+            return;
+        }
+        let (engine, ast, scope, this) = (&self.engine, &self.ast, &mut self.scope, &mut self.this);
+        Self::with_state(&self.context, state, || {
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(this);
+            engine
+                .call_fn_with_options::<Dynamic>(options, scope, ast, name, args)
+                .map(|_| ())
+                .map_err(|e| ScriptError(format!("{} failed: {}", name, e)))
+        });
+    }
+
+    /// Swaps the machine's state into the context while `run` runs the script.
+    /// If the script fails, it's reported and the script quits with exit code 1.
+    fn with_state(
+        context: &Context,
+        state: &mut State,
+        run: impl FnOnce() -> Result<(), ScriptError>,
+    ) {
+        std::mem::swap(&mut *context.state.borrow_mut(), state);
+        let result = run();
+        std::mem::swap(&mut *context.state.borrow_mut(), state);
+        if let Err(e) = result {
+            eprintln!("The script failed: {}", e);
+            context.exit_code.set(Some(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::memory::MemoryLayout;
+    use crate::emulator::Emulator;
+
+    const PROGRAM: [u8; 8] = [
+        0x60, 0x07, // 200: LD V0, 7
+        0xA3, 0x00, // 202: LD I, 0x300
+        0xF0, 0x33, // 204: LD B, V0
+        0x12, 0x06, // 206: JP 0x206
+    ];
+
+    /// Runs a script over PROGRAM for `frames` frames of 4 instructions
+    fn run(source: &str, frames: u64) -> Emulator {
+        run_with_layout(source, frames, MemoryLayout::default())
+    }
+
+    /// Runs a script like `run`, with PROGRAM loaded where `layout` says
+    fn run_with_layout(source: &str, frames: u64, layout: MemoryLayout) -> Emulator {
+        let mut script = Script::new(source).unwrap();
+        let mut state = State::mock_with_layout(&PROGRAM, layout);
+        script.start(&mut state);
+        let mut emulator = Emulator::new(state, false);
+        emulator.script = Some(script);
+        while emulator.frame < frames && !emulator.should_stop() {
+            for _ in 0..4 {
+                emulator.step().unwrap();
+            }
+            emulator.end_frame();
+        }
+        emulator
+    }
+
+    #[test]
+    fn test_hooks() {
+        let emulator = run(
+            r#"
+            breakpoint(0x206);
+            watch(0x302);
+            poke(0x400, 1);
+
+            fn on_breakpoint(address) {
+                this.hits = (this.hits ?? 0) + 1;
+                set_v(1, address % 256);
+            }
+
+            fn on_write(address, value) {
+                set_v(2, value);
+            }
+
+            fn on_frame() {
+                set_v(3, this.hits);
+                if frame() == 2 { quit(3); }
+            }
+            "#,
+            10,
+        );
+        let state = &emulator.state;
+        assert_eq!(state.memory.ram()[0x400], 1);
+        assert_eq!(state.registers.v_registers[1], 0x06);
+        // LD B, V0 wrote 0, 0 and 7, and 7 went to 0x302
+        assert_eq!(state.registers.v_registers[2], 7);
+        // The breakpoint was hit by the instruction before it and by each jump
+        assert_eq!(state.registers.v_registers[3], 6);
+        assert_eq!(emulator.frame, 2);
+        assert_eq!(emulator.script.unwrap().exit_code(), Some(3));
+    }
+
+    #[test]
+    fn test_keys_and_labels() {
+        let emulator = run(
+            r#"
+            press(0xA);
+            text(1, 2, "HELLO");
+            text(1, 2, "HI");
+            text(9, 9, "GONE");
+            text(9, 9, "");
+            "#,
+            1,
+        );
+        let script = emulator.script.unwrap();
+        let keyboard = script.keyboard(Box::new(HeadlessKeyboard::new()));
+        assert!(keyboard.is_key_pressed(0xA));
+        assert!(!keyboard.is_key_pressed(0xB));
+        assert_eq!(keyboard.wait_for_key_press(), 0xA);
+        assert_eq!(
+            script.labels(),
+            vec![Label {
+                x: 1,
+                y: 2,
+                text: String::from("HI")
+            }]
+        );
+        let mut pixels = vec![0; Script::LABELS_WIDTH * Script::LABELS_HEIGHT * 4];
+        script.draw_labels(&mut pixels);
+        // The top of the H is lit at (4, 8), and the shadow is one pixel down and right
+        let at = |x: usize, y: usize| &pixels[(y * Script::LABELS_WIDTH + x) * 4..][..4];
+        assert_eq!(at(4, 8), &[255, 255, 0, 255]);
+        assert_eq!(at(5, 13), &[0, 0, 0, 255]);
+        assert_eq!(at(0, 0), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Script::new("fn on_frame( {").is_err());
+        let emulator = run("fn on_frame() { poke(0x1000, 1); }", 5);
+        assert_eq!(emulator.frame, 1);
+        assert_eq!(emulator.script.unwrap().exit_code(), Some(1));
+    }
+
+    #[test]
+    fn test_memory_layouts() {
+        let layout = |size| MemoryLayout {
+            size,
+            ..MemoryLayout::default()
+        };
+        let emulator = run_with_layout("fn on_frame() { poke(0x900, 1); }", 5, layout(0x800));
+        assert_eq!(emulator.script.unwrap().exit_code(), Some(1));
+        let emulator = run_with_layout("fn on_frame() { set_pc(0x900); }", 5, layout(0x800));
+        assert_eq!(emulator.script.unwrap().exit_code(), Some(1));
+        let emulator = run_with_layout("poke(0xFFFF, peek(0x200));", 1, layout(0x10000));
+        assert_eq!(emulator.state.memory.ram()[0xFFFF], 0x60);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::config::Color;
+
+/// The width of a glyph (in pixels)
+pub const GLYPH_WIDTH: usize = 3;
+
+/// The height of a glyph (in pixels)
+pub const GLYPH_HEIGHT: usize = 5;
+
+/// The horizontal distance between the starts of consecutive characters
+pub const CHAR_ADVANCE: usize = GLYPH_WIDTH + 1;
+
+/// The vertical distance between the starts of consecutive lines
+pub const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+/// Returns the rows of the 3x5 glyph for a character, most significant bit on the left.
+/// Lowercase letters are drawn as uppercase, and unknown characters as a filled box.
+pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b111, 0b001, 0b010, 0b000, 0b010],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+        _ => [0b111, 0b111, 0b111, 0b111, 0b111],
+    }
+}
+
+/// An RGBA image that text can be drawn onto
+pub struct Canvas<'a> {
+    pub pixels: &'a mut [u8],
+    pub width: usize,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(pixels: &'a mut [u8], width: usize) -> Self {
+        Canvas { pixels, width }
+    }
+
+    fn height(&self) -> usize {
+        self.pixels.len() / (self.width * 4)
+    }
+
+    /// Sets the pixel at (x, y), ignoring pixels outside of the image
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x >= self.width || y >= self.height() {
+            return;
+        }
+        let start = (y * self.width + x) * 4;
+        self.pixels[start..start + 4].copy_from_slice(&[color.0, color.1, color.2, color.3]);
+    }
+
+    /// Fills the whole image with one color
+    pub fn fill(&mut self, color: Color) {
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&[color.0, color.1, color.2, color.3]);
+        }
+    }
+
+    /// Draws a line of text with its top left corner at (x, y)
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, color: Color) {
+        for (index, c) in text.chars().enumerate() {
+            let left = x + index * CHAR_ADVANCE;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        self.set_pixel(left + column, y + row, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glyph() {
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_ne!(glyph('B'), glyph('8'));
+        assert_ne!(glyph('D'), glyph('0'));
+        assert_eq!(glyph('~'), [0b111; 5]);
+    }
+
+    #[test]
+    fn test_draw_text() {
+        let mut pixels = vec![0; 8 * 6 * 4];
+        let mut canvas = Canvas::new(&mut pixels, 8);
+        canvas.draw_text(1, 1, "1", Color::white());
+        // The top row of "1" is 010, starting one pixel in
+        let lit = |x: usize, y: usize| pixels[(y * 8 + x) * 4] == 255;
+        assert!(!lit(1, 1));
+        assert!(lit(2, 1));
+        assert!(!lit(3, 1));
+        assert!(lit(1, 5));
+        // Text that doesn't fit is clipped
+        let mut canvas = Canvas::new(&mut pixels, 8);
+        canvas.draw_text(6, 4, "88", Color::white());
+    }
+}
@@ -1,6 +1,6 @@
 use super::{Read, State};
 
-/// Represents the location of the sprite for F.0
+/// Represents the location of the sprite for F.0 in the loaded font
 #[derive(Debug)]
 pub struct F<T>(pub T)
 where
@@ -11,13 +11,18 @@ where
     T: Read<u8>,
 {
     fn read(&self, state: &State) -> u16 {
-        u16::from(self.0.read(state)) * 5
+        let font_address = state.memory.layout().font_address;
+        (font_address + usize::from(self.0.read(state)) * state.memory.font().height) as u16
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chip8::{
+        font::Font,
+        memory::{Memory, MemoryLayout},
+    };
     use crate::variables::{nibble::B4, vregister::V};
 
     #[test]
@@ -34,7 +39,35 @@ mod tests {
         ];
         let result = usize::from(f.read(&state));
         for i in 0..5 {
-            assert_eq!(state.memory.ram[result + i], sprite[i]);
+            assert_eq!(state.memory.ram()[result + i], sprite[i]);
         }
     }
+
+    #[test]
+    fn test_read_f_font_address() {
+        let mut state = State::mock(&[]);
+        state.memory = Memory::with_layout(
+            &[],
+            MemoryLayout {
+                font_address: 0x50,
+                ..MemoryLayout::default()
+            },
+        );
+        state.registers.v_registers[3] = 2;
+        assert_eq!(F(V(B4(3))).read(&state), 0x50 + 10);
+    }
+
+    #[test]
+    fn test_read_f_glyph_height() {
+        let mut state = State::mock(&[]);
+        let large = Font::preset("super-chip-large").unwrap();
+        state.memory.load_font(large.clone()).unwrap();
+        state.registers.v_registers[3] = 7;
+        let result = usize::from(F(V(B4(3))).read(&state));
+        assert_eq!(result, 70);
+        assert_eq!(
+            state.memory.ram()[result..result + 10],
+            large.bytes()[70..80]
+        );
+    }
 }
@@ -2,21 +2,28 @@ use super::{Read, State, Write};
 
 /// Struct representing the memory at a location at AT.0 + AT.1
 /// The first field represents the starting position. The second represents the offset
+/// Locations past the end of memory lead wherever the memory's wrapping policy says
 #[derive(Debug)]
 pub struct AT<T: Read<usize>>(pub T, pub usize);
 
+impl<T: Read<usize>> AT<T> {
+    fn location(&self, state: &State) -> usize {
+        self.0.read(state) + self.1
+    }
+}
+
 /// We should be able to read a u8 from memory
 impl<T: Read<usize>> Read<u8> for AT<T> {
     fn read(&self, state: &State) -> u8 {
-        state.memory.ram[self.0.read(state) + self.1]
+        state.memory.read(self.location(state))
     }
 }
 
 /// We should be able to write a u8 to memory
 impl<T: Read<usize>> Write<u8> for AT<T> {
     fn write(&self, state: &mut State, val: u8) {
-        let location: usize = self.0.read(state);
-        state.memory.ram[location + self.1] = val;
+        let location: usize = self.location(state);
+        state.memory.write(location, val);
     }
 }
 
@@ -29,7 +36,7 @@ mod tests {
     fn test_read_u8() {
         let at = AT(B12(B4(0b1001), B4(0b0100), B4(0b0010)), 0);
         let mut state = State::mock(&[]);
-        state.memory.ram[0b1001_0100_0010] = 43;
+        state.memory.ram_mut()[0b1001_0100_0010] = 43;
         assert_eq!(at.read(&state), 43);
     }
 
@@ -39,6 +46,15 @@ mod tests {
         let mut state = State::mock(&[]);
         state.registers.i_register = 1403;
         at.write(&mut state, 76);
-        assert_eq!(state.memory.ram[1403], 76);
+        assert_eq!(state.memory.ram()[1403], 76);
+    }
+
+    #[test]
+    fn test_wraps_around() {
+        let mut state = State::mock(&[]);
+        state.registers.i_register = 0xFFFF;
+        AT(I, 2).write(&mut state, 12);
+        assert_eq!(state.memory.ram()[0x001], 12);
+        assert_eq!(AT(I, 2).read(&state), 12);
     }
 }
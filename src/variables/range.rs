@@ -49,6 +49,6 @@ mod tests {
         let v: Vec<AT<I>> = (0usize..5).map(|x| AT(I, x)).collect();
         let entries: Vec<u8> = vec![32, 44, 2, 9, 65];
         RANGE(v).write(&mut state, entries.clone());
-        assert_eq!(state.memory.ram[540..545].to_vec(), entries);
+        assert_eq!(state.memory.ram()[540..545].to_vec(), entries);
     }
 }
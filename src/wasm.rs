@@ -0,0 +1,191 @@
+//! A JavaScript API for running programs in the browser, built with the `wasm` feature.
+//!
+//! The page drives the emulator: it calls `frame` sixty times a second, draws the
+//! `framebuffer` and passes key presses on with `setKey`, e.g.
+//!
+//! ```js
+//! const chip8 = new Chip8(10);
+//! chip8.load(new Uint8Array(await (await fetch("pong.rom")).arrayBuffer()));
+//! setInterval(() => {
+//!     chip8.frame();
+//!     context.putImageData(new ImageData(new Uint8ClampedArray(chip8.framebuffer()), 64, 32), 0, 0);
+//! }, 1000 / 60);
+//! ```
+
+use crate::chip8::{
+    display::Display, font::Font, keyboard::SharedKeyboard, memory::MemoryLayout, quirks::Quirks,
+    State,
+};
+use crate::config::Config;
+use crate::emulator::Emulator;
+use crate::palette::Palette;
+use wasm_bindgen::prelude::*;
+
+/// Seeds the random number generator RND uses, e.g. with `Date.now()`.
+/// Until then, programs see the same random numbers every time they're run.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn seed(seed: f64) {
+    crate::instructions::seed_random(seed as u64);
+}
+
+/// An emulator running one program at a time
+#[wasm_bindgen]
+pub struct Chip8 {
+    emulator: Emulator,
+    keys: SharedKeyboard,
+    ticks_per_frame: u32,
+    quirks: Quirks,
+    layout: MemoryLayout,
+    palette: Palette,
+}
+
+#[wasm_bindgen]
+impl Chip8 {
+    /// Creates an emulator with no program loaded, which executes `ticks_per_frame`
+    /// instructions a frame
+    #[wasm_bindgen(constructor)]
+    pub fn new(ticks_per_frame: u32) -> Chip8 {
+        let keys = SharedKeyboard::new();
+        let palette = Config::default().get_palette();
+        Chip8 {
+            emulator: Self::emulator(
+                &[],
+                &keys,
+                Quirks::default(),
+                MemoryLayout::default(),
+                &palette,
+            ),
+            keys,
+            ticks_per_frame,
+            quirks: Quirks::default(),
+            layout: MemoryLayout::default(),
+            palette,
+        }
+    }
+
+    fn emulator(
+        program: &[u8],
+        keys: &SharedKeyboard,
+        quirks: Quirks,
+        layout: MemoryLayout,
+        palette: &Palette,
+    ) -> Emulator {
+        let display = Display::new(palette.active(), palette.inactive());
+        let mut state = State::with_layout(program, Box::new(keys.clone()), display, layout);
+        state.quirks = quirks;
+        Emulator::new(state, false)
+    }
+
+    /// Loads a program, resetting the machine
+    pub fn load(&mut self, program: &[u8]) -> Result<(), JsValue> {
+        self.layout
+            .check(program, &Font::default())
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.emulator =
+            Self::emulator(program, &self.keys, self.quirks, self.layout, &self.palette);
+        Ok(())
+    }
+
+    /// Sets how memory is laid out for programs loaded afterwards: `size` bytes of it
+    /// (up to 0x10000), with programs loaded at `program_start` and the font at
+    /// `font_address`
+    #[wasm_bindgen(js_name = setMemoryLayout)]
+    pub fn set_memory_layout(
+        &mut self,
+        size: usize,
+        program_start: usize,
+        font_address: usize,
+    ) -> Result<(), JsValue> {
+        let layout = MemoryLayout {
+            size,
+            program_start,
+            font_address,
+        };
+        layout
+            .check(&[], &Font::default())
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.layout = layout;
+        Ok(())
+    }
+
+    /// Switches to a quirk profile (default, cosmac-vip or super-chip)
+    #[wasm_bindgen(js_name = setQuirks)]
+    pub fn set_quirks(&mut self, profile: &str) -> Result<(), JsValue> {
+        self.quirks = Quirks::profile(profile).ok_or_else(|| {
+            JsValue::from_str(&format!(
+                "There's no quirk profile named {}. Please use one of {:?}",
+                profile,
+                Quirks::PROFILES
+            ))
+        })?;
+        self.emulator.state.quirks = self.quirks;
+        Ok(())
+    }
+
+    /// Switches to one of the preset palettes, e.g. gameboy
+    #[wasm_bindgen(js_name = setPalette)]
+    pub fn set_palette(&mut self, name: &str) -> Result<(), JsValue> {
+        self.palette = Palette::preset(name)
+            .ok_or_else(|| JsValue::from_str(&format!("There's no palette named {}", name)))?;
+        self.palette.apply(&mut self.emulator.state.display);
+        Ok(())
+    }
+
+    /// Executes one instruction, unless the program is waiting for a key to be held,
+    /// since the page can't be blocked
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        if self.emulator.is_waiting_for_key() && !self.keys.any_held() {
+            return Ok(());
+        }
+        self.emulator
+            .step()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Emulates a frame: executes `ticks_per_frame` instructions, then counts down the timers
+    pub fn frame(&mut self) -> Result<(), JsValue> {
+        for _ in 0..self.ticks_per_frame {
+            self.step()?;
+        }
+        self.emulator.end_frame();
+        Ok(())
+    }
+
+    /// Returns the display as RGBA pixels, row by row, ready for an `ImageData`
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.emulator.state.display.colored_pixels.to_vec()
+    }
+
+    /// Returns the display as one byte per pixel, 1 where it's lit
+    pub fn pixels(&self) -> Vec<u8> {
+        self.emulator.state.display.pixels.concat()
+    }
+
+    /// Holds or releases one of the 16 keys (0-F)
+    #[wasm_bindgen(js_name = setKey)]
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keys.set_key(key, pressed);
+    }
+
+    /// Returns whether the beeper should sound this frame
+    pub fn beeping(&self) -> bool {
+        self.emulator.state.timers.sound_timer > 0
+    }
+
+    /// The number of frames emulated since the program was loaded
+    #[wasm_bindgen(getter, js_name = frameCount)]
+    pub fn frame_count(&self) -> f64 {
+        self.emulator.frame as f64
+    }
+
+    /// The width of the display, in pixels
+    pub fn width() -> usize {
+        Display::WIDTH
+    }
+
+    /// The height of the display, in pixels
+    pub fn height() -> usize {
+        Display::HEIGHT
+    }
+}
@@ -0,0 +1,312 @@
+#[cfg(feature = "sdl")]
+use crate::chip8::display::Display;
+use crate::config;
+#[cfg(feature = "sdl")]
+use crate::config::Config;
+#[cfg(feature = "sdl")]
+use sdl2::{
+    pixels::PixelFormatEnum,
+    rect::Rect,
+    render::WindowCanvas,
+    video::{FullscreenType, WindowPos},
+    VideoSubsystem,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The pixel format of the textures the display and panels are drawn to. Their pixels
+/// are the bytes `[r, g, b, a]`, which SDL's packed formats read as ABGR8888 on
+/// little-endian machines and RGBA8888 on big-endian ones.
+#[cfg(feature = "sdl")]
+pub const TEXTURE_FORMAT: PixelFormatEnum = PixelFormatEnum::RGBA32;
+
+/// How the display is scaled to fit the window
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Scaling {
+    /// As large as possible while keeping the display's aspect ratio
+    #[default]
+    Aspect,
+    /// As large as possible while keeping every chip-8 pixel the same whole number of pixels
+    Integer,
+    /// Fill the window, even if that distorts the display
+    Stretch,
+}
+
+#[cfg(feature = "sdl")]
+impl Scaling {
+    /// Returns the area of a `window` sized window to draw a `content` sized image in.
+    /// Any area left over is split evenly on either side as black bars.
+    pub fn fit(self, window: (u32, u32), content: (u32, u32)) -> Rect {
+        let (window_width, window_height) = window;
+        let (content_width, content_height) = content;
+        let (width, height) = match self {
+            Scaling::Stretch => (window_width, window_height),
+            Scaling::Aspect => {
+                // Compare window_width / window_height with content_width / content_height
+                if u64::from(window_width) * u64::from(content_height)
+                    > u64::from(window_height) * u64::from(content_width)
+                {
+                    (
+                        window_height * content_width / content_height,
+                        window_height,
+                    )
+                } else {
+                    (window_width, window_width * content_height / content_width)
+                }
+            }
+            Scaling::Integer => {
+                let scale = (window_width / content_width)
+                    .min(window_height / content_height)
+                    .max(1);
+                (content_width * scale, content_height * scale)
+            }
+        };
+        Rect::new(
+            (window_width as i32 - width as i32) / 2,
+            (window_height as i32 - height as i32) / 2,
+            width,
+            height,
+        )
+    }
+}
+
+/// The position and size of the window, remembered between sessions
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+}
+
+impl WindowGeometry {
+    /// Returns where the window geometry is saved: `chip8-rust/window.json`
+    /// in the user's config directory, if there is one
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config::config_dir()?.join("window.json"))
+    }
+
+    /// Reads the geometry saved at `path`, if it can be read
+    pub fn load(path: &Path) -> Option<Self> {
+        let file = fs::File::open(path).ok()?;
+        serde_json::from_reader(file).ok()
+    }
+
+    /// Saves the geometry to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// The emulator's SDL window, which can be resized and made fullscreen
+#[cfg(feature = "sdl")]
+pub struct GameWindow {
+    pub canvas: WindowCanvas,
+    scaling: Scaling,
+    /// The geometry of the window the last time it wasn't fullscreen
+    windowed: WindowGeometry,
+}
+
+#[cfg(feature = "sdl")]
+impl GameWindow {
+    /// Opens the window where it was at the end of the last session,
+    /// or in the middle of the screen if there wasn't one
+    pub fn new(video_subsystem: &VideoSubsystem, config: &Config) -> Self {
+        let saved: Option<WindowGeometry> =
+            WindowGeometry::default_path().and_then(|x| WindowGeometry::load(&x));
+        let (width, height) = match saved {
+            Some(geometry) => (geometry.width, geometry.height),
+            None => (
+                Display::WIDTH as u32 * config.pixel_size,
+                Display::HEIGHT as u32 * config.pixel_size,
+            ),
+        };
+        let mut builder = video_subsystem.window("chip8 emulator", width, height);
+        builder.resizable();
+        match saved {
+            Some(geometry) => builder.position(geometry.x, geometry.y),
+            None => builder.position_centered(),
+        };
+        let window = builder.build().unwrap();
+        let mut canvas: WindowCanvas = window.into_canvas().present_vsync().build().unwrap();
+        let (x, y) = canvas.window().position();
+        let windowed = WindowGeometry {
+            x,
+            y,
+            width,
+            height,
+            fullscreen: false,
+        };
+        if saved.map(|x| x.fullscreen) == Some(true) {
+            canvas
+                .window_mut()
+                .set_fullscreen(FullscreenType::Desktop)
+                .unwrap_or_else(|e| eprintln!("Could not go fullscreen: {}", e));
+        }
+        GameWindow {
+            canvas,
+            scaling: config.scaling,
+            windowed,
+        }
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.canvas.window().fullscreen_state() != FullscreenType::Off
+    }
+
+    /// Switches between fullscreen and windowed mode
+    pub fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        if self.is_fullscreen() {
+            let window = self.canvas.window_mut();
+            window.set_fullscreen(FullscreenType::Off)?;
+            window.set_position(
+                WindowPos::Positioned(self.windowed.x),
+                WindowPos::Positioned(self.windowed.y),
+            );
+            window
+                .set_size(self.windowed.width, self.windowed.height)
+                .map_err(|e| e.to_string())
+        } else {
+            self.windowed = self.geometry();
+            self.canvas
+                .window_mut()
+                .set_fullscreen(FullscreenType::Desktop)
+        }
+    }
+
+    /// Returns the current geometry of the window.
+    /// When fullscreen, this is the geometry it will go back to.
+    pub fn geometry(&self) -> WindowGeometry {
+        if self.is_fullscreen() {
+            return WindowGeometry {
+                fullscreen: true,
+                ..self.windowed
+            };
+        }
+        let window = self.canvas.window();
+        let (x, y) = window.position();
+        let (width, height) = window.size();
+        WindowGeometry {
+            x,
+            y,
+            width,
+            height,
+            fullscreen: false,
+        }
+    }
+
+    /// Saves the geometry of the window so the next session can start with it
+    pub fn save_geometry(&self) {
+        if let Some(path) = WindowGeometry::default_path() {
+            if let Err(e) = self.geometry().save(&path) {
+                eprintln!("Could not save the window geometry to {:?}: {}", path, e);
+            }
+        }
+    }
+
+    /// Returns the area of the window a `content` sized image should be drawn in
+    pub fn viewport(&self, content: (u32, u32)) -> Rect {
+        let output_size = self
+            .canvas
+            .output_size()
+            .unwrap_or_else(|_| self.canvas.window().size());
+        self.scaling.fit(output_size, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "sdl")]
+    use crate::{config::Color, filters::FilterKind, palette::Palette};
+    use std::env;
+
+    /// Reads a pixel the way SDL does: as a 32-bit number in the machine's byte order,
+    /// with the channels from the most significant bits down in the order `format` names
+    #[cfg(feature = "sdl")]
+    fn unpack(format: PixelFormatEnum, bytes: &[u8]) -> Color {
+        let packed = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let [first, second, third, fourth] = packed.to_be_bytes();
+        match format {
+            PixelFormatEnum::RGBA8888 => Color(first, second, third, fourth),
+            PixelFormatEnum::ABGR8888 => Color(fourth, third, second, first),
+            _ => panic!("Unexpected texture format"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sdl")]
+    fn test_texture_format() {
+        let palette = Palette::preset("amber").unwrap();
+        let mut display = Display::new(palette.active(), palette.inactive());
+        display.xor(0, 0, 1);
+        let mut filter = FilterKind::None.create(0.0);
+        let pixels = filter.apply(&display);
+        assert_eq!(unpack(TEXTURE_FORMAT, &pixels[..4]), palette.active());
+        assert_eq!(unpack(TEXTURE_FORMAT, &pixels[4..8]), palette.inactive());
+    }
+
+    #[test]
+    #[cfg(feature = "sdl")]
+    fn test_fit_aspect() {
+        // Wider than the display: bars on the left and right
+        assert_eq!(
+            Scaling::Aspect.fit((1000, 320), (64, 32)),
+            Rect::new(180, 0, 640, 320)
+        );
+        // Taller than the display: bars on the top and bottom
+        assert_eq!(
+            Scaling::Aspect.fit((640, 500), (64, 32)),
+            Rect::new(0, 90, 640, 320)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sdl")]
+    fn test_fit_integer() {
+        assert_eq!(
+            Scaling::Integer.fit((700, 400), (64, 32)),
+            Rect::new(30, 40, 640, 320)
+        );
+        // Never smaller than one pixel per chip-8 pixel
+        assert_eq!(
+            Scaling::Integer.fit((10, 10), (64, 32)),
+            Rect::new(-27, -11, 64, 32)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sdl")]
+    fn test_fit_stretch() {
+        assert_eq!(
+            Scaling::Stretch.fit((300, 700), (64, 32)),
+            Rect::new(0, 0, 300, 700)
+        );
+    }
+
+    #[test]
+    fn test_geometry_save_load() {
+        let path = env::temp_dir()
+            .join("chip8_test_geometry")
+            .join("window.json");
+        let geometry = WindowGeometry {
+            x: 10,
+            y: -20,
+            width: 800,
+            height: 400,
+            fullscreen: true,
+        };
+        geometry.save(&path).unwrap();
+        assert_eq!(WindowGeometry::load(&path), Some(geometry));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(WindowGeometry::load(&path), None);
+    }
+}
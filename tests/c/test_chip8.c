@@ -0,0 +1,89 @@
+/* Exercises the C API from C. tests/ffi.rs builds and runs it against the library. */
+
+#include "chip8.h"
+
+#include <stdio.h>
+#include <string.h>
+
+#define CHECK(condition)                                                   \
+    do {                                                                   \
+        if (!(condition)) {                                                \
+            fprintf(stderr, "%s:%d: %s failed\n", __FILE__, __LINE__, #condition); \
+            return 1;                                                      \
+        }                                                                  \
+    } while (0)
+
+static const uint8_t ROM[] = {
+    0x60, 0x05, /* 200: LD V0, 5 */
+    0xF0, 0x15, /* 202: LD DT, V0 */
+    0xA2, 0x10, /* 204: LD I, 0x210 */
+    0xD0, 0x01, /* 206: DRW V0, V0, 1 */
+    0xF1, 0x0A, /* 208: LD V1, K */
+    0x12, 0x0A, /* 20A: JP 0x20A */
+    0x00, 0x00,
+    0x00, 0x00,
+    0x80,       /* 210: sprite with its leftmost pixel lit */
+};
+
+int main(void) {
+    Chip8Machine *machine = chip8_new();
+    CHECK(machine != NULL);
+    CHECK(chip8_load_rom(machine, ROM, sizeof ROM) == CHIP8_STATUS_OK);
+    CHECK(chip8_set_quirks(machine, "cosmac-vip") == CHIP8_STATUS_OK);
+
+    /* Runs until LD V1, K, which doesn't block without a key held */
+    CHECK(chip8_run_cycles(machine, 20) == CHIP8_STATUS_OK);
+    CHECK(chip8_end_frame(machine) == CHIP8_STATUS_OK);
+    Chip8Registers registers;
+    CHECK(chip8_get_registers(machine, &registers) == CHIP8_STATUS_OK);
+    CHECK(registers.pc == 0x208);
+    CHECK(registers.i == 0x210);
+    CHECK(registers.delay_timer == 4);
+    const uint8_t *framebuffer = chip8_framebuffer(machine);
+    CHECK(framebuffer[5 * 64 + 5] == 1);
+    CHECK(framebuffer[5 * 64 + 6] == 0);
+
+    CHECK(chip8_set_key(machine, 0xC, true) == CHIP8_STATUS_OK);
+    CHECK(chip8_run_cycles(machine, 1) == CHIP8_STATUS_OK);
+    CHECK(chip8_get_registers(machine, &registers) == CHIP8_STATUS_OK);
+    CHECK(registers.pc == 0x20A && registers.v[1] == 0xC);
+
+    /* Saves the state, changes the machine and restores it */
+    size_t size = chip8_state_size(machine);
+    uint8_t state[8192];
+    CHECK(size <= sizeof state);
+    CHECK(chip8_save_state(machine, state, sizeof state) == CHIP8_STATUS_OK);
+    registers.v[1] = 0;
+    registers.pc = 0x200;
+    CHECK(chip8_set_registers(machine, &registers) == CHIP8_STATUS_OK);
+    const uint8_t patch[] = {0xAB, 0xCD};
+    CHECK(chip8_write_memory(machine, 0x300, patch, sizeof patch) == CHIP8_STATUS_OK);
+    uint8_t read[2];
+    CHECK(chip8_read_memory(machine, 0x300, read, sizeof read) == CHIP8_STATUS_OK);
+    CHECK(memcmp(read, patch, sizeof patch) == 0);
+    CHECK(chip8_load_state(machine, state, size) == CHIP8_STATUS_OK);
+    CHECK(chip8_get_registers(machine, &registers) == CHIP8_STATUS_OK);
+    CHECK(registers.pc == 0x20A && registers.v[1] == 0xC);
+    CHECK(chip8_read_memory(machine, 0x300, read, sizeof read) == CHIP8_STATUS_OK);
+    CHECK(read[0] == 0 && read[1] == 0);
+
+    /* Failures leave a message behind */
+    CHECK(chip8_last_error(machine) == NULL);
+    CHECK(chip8_read_memory(machine, 0xFFF, read, sizeof read) == CHIP8_STATUS_OUT_OF_RANGE);
+    CHECK(chip8_last_error(machine) != NULL);
+    CHECK(chip8_load_state(machine, state, 3) == CHIP8_STATUS_INVALID_STATE);
+    CHECK(strstr(chip8_last_error(machine), "snapshot") != NULL);
+    CHECK(chip8_set_quirks(machine, "nonsense") == CHIP8_STATUS_UNKNOWN_PROFILE);
+    CHECK(chip8_run_cycles(NULL, 1) == CHIP8_STATUS_NULL_POINTER);
+    CHECK(chip8_set_memory_layout(machine, 0x800, 0x200, 0x7F0) == CHIP8_STATUS_INVALID_LAYOUT);
+
+    /* ROMs can be loaded elsewhere, e.g. at 0x600 for the ETI-660 */
+    CHECK(chip8_set_memory_layout(machine, 0x1000, 0x600, 0) == CHIP8_STATUS_OK);
+    CHECK(chip8_load_rom(machine, ROM, sizeof ROM) == CHIP8_STATUS_OK);
+    CHECK(chip8_get_registers(machine, &registers) == CHIP8_STATUS_OK);
+    CHECK(registers.pc == 0x600);
+
+    chip8_free(machine);
+    puts("All C API checks passed");
+    return 0;
+}
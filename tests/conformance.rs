@@ -0,0 +1,85 @@
+//! Runs the test ROMs in `roms/` headlessly and compares their final display with
+//! golden images checked in to `tests/golden/`.
+//!
+//! To add a test ROM, copy it into `roms/`, add a `conformance_test!` line at the bottom
+//! of this file and run `UPDATE_GOLDEN=1 cargo test --test conformance` to record its
+//! golden image. Make sure the recorded image shows what the ROM should before committing it.
+
+use chip8::chip8::{display::Display, keyboard::HeadlessKeyboard, memory::MAX_SIZE, State};
+use chip8::{config::Color, emulator::Emulator};
+use std::{env, fs, path::Path};
+
+/// The number of instructions executed each frame, i.e. between timer decrements
+const TICKS_PER_FRAME: u64 = 9;
+
+/// Runs a ROM from `roms/` for `cycles` instructions, or until it runs off the end of memory
+fn run(rom: &str, cycles: u64) -> Emulator {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms").join(rom);
+    let program = fs::read(&path).unwrap_or_else(|e| panic!("Could not read {:?}: {}", path, e));
+    let display = Display::new(Color::white(), Color::black());
+    let state = State::new(&program, Box::new(HeadlessKeyboard::new()), display);
+    let mut emulator = Emulator::new(state, false);
+    for cycle in 1..=cycles {
+        if emulator.state.program_counter + 1 >= MAX_SIZE {
+            break;
+        }
+        emulator
+            .step()
+            .unwrap_or_else(|e| panic!("{} stopped: {}", rom, e));
+        if cycle % TICKS_PER_FRAME == 0 {
+            emulator.end_frame();
+        }
+    }
+    emulator
+}
+
+/// Draws the display as text, with `#` for lit pixels and `.` for unlit ones
+fn render(display: &Display) -> String {
+    let mut result = String::new();
+    for row in display.pixels.iter() {
+        result.extend(row.iter().map(|x| if *x == 1 { '#' } else { '.' }));
+        result.push('\n');
+    }
+    result
+}
+
+/// Checks the display after running a ROM matches its golden image,
+/// or records the golden image if `UPDATE_GOLDEN` is set
+fn check(rom: &str, cycles: u64) {
+    let actual = render(&run(rom, cycles).state.display);
+    let name = Path::new(rom).with_extension("txt");
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|e| {
+        panic!(
+            "Could not read the golden image {:?} ({}). Run with UPDATE_GOLDEN=1 to record it.",
+            golden, e
+        )
+    });
+    assert!(
+        actual == expected,
+        "The display after running {} doesn't match {:?}.\nExpected:\n{}\nActual:\n{}",
+        rom,
+        golden,
+        expected,
+        actual
+    );
+}
+
+macro_rules! conformance_test {
+    ($name:ident, $rom:expr, $cycles:expr) => {
+        #[test]
+        fn $name() {
+            check($rom, $cycles);
+        }
+    };
+}
+
+conformance_test!(bc_test, "BC_test.ch8", 2000);
+conformance_test!(test_opcode, "test_opcode.ch8", 2000);
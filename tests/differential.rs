@@ -0,0 +1,57 @@
+//! Checks the emulator against the reference machine in `reference/mod.rs`: every opcode
+//! from a random state, and every instruction the bundled ROMs execute.
+//! The fuzz targets in `fuzz/` run the same check on inputs chosen by the fuzzer.
+
+mod reference;
+
+use chip8::chip8::{display::Display, keyboard::HeadlessKeyboard, State};
+use chip8::{config::Color, emulator::Emulator};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use reference::{check_step, emulator_from_bytes};
+use std::{env, fs, path::Path};
+
+/// The number of random states each opcode is executed from
+const STATES_PER_OPCODE: usize = 1;
+
+#[test]
+fn test_every_opcode() {
+    let mut rng = StdRng::seed_from_u64(0xC8);
+    let mut data = vec![0u8; 128];
+    for opcode in 0..=0xFFFFu16 {
+        for _ in 0..STATES_PER_OPCODE {
+            rng.fill(&mut data[..]);
+            data[..2].copy_from_slice(&opcode.to_be_bytes());
+            let mut emulator = emulator_from_bytes(&data);
+            if let Err(difference) = check_step(&mut emulator) {
+                panic!("Opcode {:04X} from {:02X?}:\n{}", opcode, data, difference);
+            }
+        }
+    }
+}
+
+/// Runs a ROM from `roms/` for `cycles` instructions, checking each one
+fn check_rom(rom: &str, cycles: u64) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms").join(rom);
+    let program = fs::read(&path).unwrap_or_else(|e| panic!("Could not read {:?}: {}", path, e));
+    let display = Display::new(Color::white(), Color::black());
+    let state = State::new(&program, Box::new(HeadlessKeyboard::new()), display);
+    let mut emulator = Emulator::new(state, false);
+    for cycle in 1..=cycles {
+        if emulator.is_finished() {
+            break;
+        }
+        if let Err(difference) = check_step(&mut emulator) {
+            panic!("{} at cycle {}:\n{}", rom, cycle, difference);
+        }
+        if cycle % 9 == 0 {
+            emulator.end_frame();
+        }
+    }
+}
+
+#[test]
+fn test_roms() {
+    for rom in &["BC_test.ch8", "test_opcode.ch8", "pong.rom"] {
+        check_rom(rom, 2000);
+    }
+}
@@ -0,0 +1,52 @@
+//! Compiles `tests/c/test_chip8.c` against the generated header and the library,
+//! then runs it. Needs the `ffi` feature and a C compiler (`cc`, or `$CC`).
+
+#![cfg(feature = "ffi")]
+
+use std::{env, path::Path, process::Command};
+
+#[test]
+fn test_c_program() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // The shared library's name has no hash in it, so builds with other features
+    // overwrite it. It's built in its own target directory to be sure it has the API.
+    let target = root.join("target").join("ffi");
+    let status = Command::new(env!("CARGO"))
+        .current_dir(root)
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            "ffi",
+        ])
+        .env("CARGO_TARGET_DIR", &target)
+        .status()
+        .expect("Could not run cargo");
+    assert!(status.success(), "Could not build the library");
+    let library = target.join("debug");
+
+    let program = library.join("test_chip8");
+    let compiler = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let status = Command::new(compiler)
+        .arg(root.join("tests/c/test_chip8.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&library)
+        .args(["-lchip8", "-o"])
+        .arg(&program)
+        .status()
+        .expect("Could not run the C compiler");
+    assert!(status.success(), "Could not compile the C test program");
+
+    let output = Command::new(&program)
+        .env("LD_LIBRARY_PATH", &library)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "The C test program failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,467 @@
+//! Property tests for the semantics of every opcode.
+//!
+//! Each property runs one opcode from arbitrary machine states (registers, I, memory,
+//! stack, timers, display, pressed keys and quirks) and checks the whole machine afterwards
+//! against a specification of the opcode's effect, so anything the opcode shouldn't touch
+//! is checked too.
+
+mod reference;
+
+use chip8::chip8::{
+    display::Display,
+    keyboard::{Hotkey, Keyboard},
+    memory::MAX_SIZE,
+    quirks::Quirks,
+    State,
+};
+use chip8::{config::Color, emulator::Emulator};
+use proptest::{collection::vec, prelude::*};
+use reference::Machine;
+
+/// A keyboard with a fixed set of keys held down, one bit per key
+struct HeldKeys(u16);
+
+impl Keyboard for HeldKeys {
+    fn is_key_pressed(&self, key: u8) -> bool {
+        key < 16 && self.0 & (1 << key) != 0
+    }
+
+    /// Resolves to the lowest held key, or key 0 if none are held
+    fn wait_for_key_press(&self) -> u8 {
+        (0..16).find(|key| self.is_key_pressed(*key)).unwrap_or(0)
+    }
+
+    fn is_quit(&self) -> bool {
+        false
+    }
+
+    fn take_hotkeys(&self) -> Vec<Hotkey> {
+        Vec::new()
+    }
+}
+
+/// Everything needed to put the emulator in an arbitrary state
+#[derive(Debug, Clone)]
+struct Setup {
+    v: [u8; 16],
+    i: u16,
+    pc: usize,
+    stack: Vec<usize>,
+    dt: u8,
+    st: u8,
+    /// Repeated to fill memory
+    ram: Vec<u8>,
+    /// The rows of the display, one bit per pixel
+    rows: [u64; 32],
+    keys: u16,
+    quirks: Quirks,
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    any::<[bool; 4]>().prop_map(
+        |[shift_vy, load_store_increments_i, jump_vx, logic_resets_vf]| Quirks {
+            shift_vy,
+            load_store_increments_i,
+            jump_vx,
+            logic_resets_vf,
+        },
+    )
+}
+
+prop_compose! {
+    fn setup()(
+        v in any::<[u8; 16]>(),
+        i in any::<u16>(),
+        pc in 0..MAX_SIZE - 1,
+        stack in vec(0..MAX_SIZE, 0..=16),
+        (dt, st) in any::<(u8, u8)>(),
+        ram in vec(any::<u8>(), 256),
+        rows in any::<[u64; 32]>(),
+        keys in any::<u16>(),
+        quirks in quirks(),
+    ) -> Setup {
+        Setup { v, i, pc, stack, dt, st, ram, rows, keys, quirks }
+    }
+}
+
+/// Runs `opcode` from the state described by `setup`.
+/// Returns the machine before and after, or None after if the opcode failed.
+fn run(setup: &Setup, opcode: u16) -> (Machine, Option<Machine>) {
+    let display = Display::new(Color::white(), Color::black());
+    let mut state = State::new(&[], Box::new(HeldKeys(setup.keys)), display);
+    state.registers.v_registers = setup.v;
+    state.registers.i_register = setup.i;
+    state.program_counter = setup.pc;
+    for address in setup.stack.iter() {
+        state.stack.push(*address).unwrap();
+    }
+    state.timers.delay_timer = setup.dt;
+    state.timers.sound_timer = setup.st;
+    for (byte, value) in state
+        .memory
+        .ram_mut()
+        .iter_mut()
+        .zip(setup.ram.iter().cycle())
+    {
+        *byte = *value;
+    }
+    state.memory.ram_mut()[setup.pc..setup.pc + 2].copy_from_slice(&opcode.to_be_bytes());
+    for (y, row) in setup.rows.iter().enumerate() {
+        for x in 0..Display::WIDTH {
+            state.display.xor(x, y, (row >> x & 1) as u8);
+        }
+    }
+    state.quirks = setup.quirks;
+
+    let before = Machine::from_state(&state);
+    let mut emulator = Emulator::new(state, false);
+    let result = emulator.step();
+    let after = Machine::from_state(&emulator.state);
+    if result.is_err() {
+        assert_eq!(after, before, "A failed opcode changed the state");
+        return (before, None);
+    }
+    (before, Some(after))
+}
+
+/// Returns the machine after an opcode that only moves on to the next instruction and makes
+/// the changes in `effect`
+fn expect(before: &Machine, effect: impl FnOnce(&mut Machine)) -> Option<Machine> {
+    let mut expected = before.clone();
+    expected.pc += 2;
+    effect(&mut expected);
+    Some(expected)
+}
+
+/// Returns the machine after an opcode that skips the next instruction if `condition` holds
+fn skip_if(before: &Machine, condition: bool) -> Option<Machine> {
+    expect(before, |m| {
+        if condition {
+            m.pc += 2
+        }
+    })
+}
+
+/// Returns the machine after a jump to `address`
+fn jump(before: &Machine, address: usize) -> Option<Machine> {
+    let mut expected = before.clone();
+    expected.pc = address % MAX_SIZE;
+    Some(expected)
+}
+
+/// Returns the machine after an 8XY1, 8XY2 or 8XY3 opcode that sets VX to `result`
+fn logic(before: &Machine, quirks: Quirks, x: usize, result: u8) -> Option<Machine> {
+    expect(before, |m| {
+        m.v[x] = result;
+        if quirks.logic_resets_vf {
+            m.v[0xF] = 0;
+        }
+    })
+}
+
+/// Returns the machine after an 8XYN opcode that sets VX to `result` and then VF to `flag`
+fn arithmetic(before: &Machine, x: usize, result: u8, flag: bool) -> Option<Machine> {
+    expect(before, |m| {
+        m.v[x] = result;
+        m.v[0xF] = flag as u8;
+    })
+}
+
+fn opcode(prefix: u16, x: usize, y: usize, suffix: u16) -> u16 {
+    prefix << 12 | (x as u16) << 8 | (y as u16) << 4 | suffix
+}
+
+proptest! {
+    #[test]
+    fn cls(setup in setup()) {
+        let (before, after) = run(&setup, 0x00E0);
+        prop_assert_eq!(after, expect(&before, |m| m.screen = [[0; 64]; 32]));
+    }
+
+    #[test]
+    fn ret(setup in setup()) {
+        let (before, after) = run(&setup, 0x00EE);
+        let expected = before.stack.last().map(|top| {
+            let mut expected = before.clone();
+            expected.stack.pop();
+            expected.pc = top + 2;
+            expected
+        });
+        prop_assert_eq!(after, expected);
+    }
+
+    #[test]
+    fn sys(setup in setup(), nnn in 0..0x1000usize) {
+        prop_assume!(nnn != 0x0E0 && nnn != 0x0EE);
+        let (before, after) = run(&setup, nnn as u16);
+        prop_assert_eq!(after, jump(&before, nnn));
+    }
+
+    #[test]
+    fn jp(setup in setup(), nnn in 0..0x1000usize) {
+        let (before, after) = run(&setup, 0x1000 | nnn as u16);
+        prop_assert_eq!(after, jump(&before, nnn));
+    }
+
+    #[test]
+    fn call(setup in setup(), nnn in 0..0x1000usize) {
+        let (before, after) = run(&setup, 0x2000 | nnn as u16);
+        let expected = if before.stack.len() == 16 {
+            None
+        } else {
+            let mut expected = jump(&before, nnn).unwrap();
+            expected.stack.push(before.pc);
+            Some(expected)
+        };
+        prop_assert_eq!(after, expected);
+    }
+
+    #[test]
+    fn se_byte(setup in setup(), x in 0..16usize, nn: u8) {
+        let (before, after) = run(&setup, opcode(0x3, x, 0, 0) | u16::from(nn));
+        prop_assert_eq!(after, skip_if(&before, before.v[x] == nn));
+    }
+
+    #[test]
+    fn sne_byte(setup in setup(), x in 0..16usize, nn: u8) {
+        let (before, after) = run(&setup, opcode(0x4, x, 0, 0) | u16::from(nn));
+        prop_assert_eq!(after, skip_if(&before, before.v[x] != nn));
+    }
+
+    #[test]
+    fn se_register(setup in setup(), x in 0..16usize, y in 0..16usize, n in 0..16u16) {
+        let (before, after) = run(&setup, opcode(0x5, x, y, n));
+        let expected = if n == 0 { skip_if(&before, before.v[x] == before.v[y]) } else { None };
+        prop_assert_eq!(after, expected);
+    }
+
+    #[test]
+    fn sne_register(setup in setup(), x in 0..16usize, y in 0..16usize, n in 0..16u16) {
+        let (before, after) = run(&setup, opcode(0x9, x, y, n));
+        let expected = if n == 0 { skip_if(&before, before.v[x] != before.v[y]) } else { None };
+        prop_assert_eq!(after, expected);
+    }
+
+    #[test]
+    fn ld_byte(setup in setup(), x in 0..16usize, nn: u8) {
+        let (before, after) = run(&setup, opcode(0x6, x, 0, 0) | u16::from(nn));
+        prop_assert_eq!(after, expect(&before, |m| m.v[x] = nn));
+    }
+
+    #[test]
+    fn add_byte(setup in setup(), x in 0..16usize, nn: u8) {
+        // Never sets the carry flag
+        let (before, after) = run(&setup, opcode(0x7, x, 0, 0) | u16::from(nn));
+        prop_assert_eq!(after, expect(&before, |m| m.v[x] = before.v[x].wrapping_add(nn)));
+    }
+
+    #[test]
+    fn ld_register(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x0));
+        prop_assert_eq!(after, expect(&before, |m| m.v[x] = before.v[y]));
+    }
+
+    #[test]
+    fn or(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x1));
+        prop_assert_eq!(after, logic(&before, setup.quirks, x, before.v[x] | before.v[y]));
+    }
+
+    #[test]
+    fn and(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x2));
+        prop_assert_eq!(after, logic(&before, setup.quirks, x, before.v[x] & before.v[y]));
+    }
+
+    #[test]
+    fn xor(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x3));
+        prop_assert_eq!(after, logic(&before, setup.quirks, x, before.v[x] ^ before.v[y]));
+    }
+
+    #[test]
+    fn add_register(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x4));
+        let sum = u16::from(before.v[x]) + u16::from(before.v[y]);
+        prop_assert_eq!(after, arithmetic(&before, x, sum as u8, sum > 0xFF));
+    }
+
+    #[test]
+    fn sub(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        // VF is set when there's no borrow
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x5));
+        let (vx, vy) = (before.v[x], before.v[y]);
+        prop_assert_eq!(after, arithmetic(&before, x, vx.wrapping_sub(vy), vx >= vy));
+    }
+
+    #[test]
+    fn subn(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x7));
+        let (vx, vy) = (before.v[x], before.v[y]);
+        prop_assert_eq!(after, arithmetic(&before, x, vy.wrapping_sub(vx), vy >= vx));
+    }
+
+    #[test]
+    fn shr(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0x6));
+        let source = before.v[if setup.quirks.shift_vy { y } else { x }];
+        prop_assert_eq!(after, arithmetic(&before, x, source >> 1, source & 1 == 1));
+    }
+
+    #[test]
+    fn shl(setup in setup(), x in 0..16usize, y in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0x8, x, y, 0xE));
+        let source = before.v[if setup.quirks.shift_vy { y } else { x }];
+        prop_assert_eq!(after, arithmetic(&before, x, source << 1, source & 0x80 != 0));
+    }
+
+    #[test]
+    fn invalid_arithmetic(
+        setup in setup(),
+        x in 0..16usize,
+        y in 0..16usize,
+        n in prop::sample::select(vec![0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xF]),
+    ) {
+        let (_, after) = run(&setup, opcode(0x8, x, y, n));
+        prop_assert_eq!(after, None);
+    }
+
+    #[test]
+    fn ld_i(setup in setup(), nnn in 0..0x1000u16) {
+        let (before, after) = run(&setup, 0xA000 | nnn);
+        prop_assert_eq!(after, expect(&before, |m| m.i = nnn));
+    }
+
+    #[test]
+    fn jp_offset(setup in setup(), nnn in 0..0x1000usize) {
+        let (before, after) = run(&setup, 0xB000 | nnn as u16);
+        let offset = before.v[if setup.quirks.jump_vx { nnn >> 8 } else { 0 }];
+        prop_assert_eq!(after, jump(&before, nnn + usize::from(offset)));
+    }
+
+    #[test]
+    fn rnd(setup in setup(), x in 0..16usize, nn: u8) {
+        let (before, after) = run(&setup, opcode(0xC, x, 0, 0) | u16::from(nn));
+        let after = after.unwrap();
+        prop_assert_eq!(after.v[x] & !nn, 0);
+        prop_assert_eq!(Some(after.clone()), expect(&before, |m| m.v[x] = after.v[x]));
+    }
+
+    #[test]
+    fn drw(setup in setup(), x in 0..16usize, y in 0..16usize, n in 0..16u16) {
+        let (before, after) = run(&setup, opcode(0xD, x, y, n));
+        let expected = expect(&before, |m| {
+            let (left, top) = (usize::from(before.v[x]), usize::from(before.v[y]));
+            let mut collision = false;
+            for row in 0..usize::from(n) {
+                let sprite = before.ram[(usize::from(before.i) + row) % MAX_SIZE];
+                for column in 0..8 {
+                    let pixel = &mut m.screen[(top + row) % 32][(left + column) % 64];
+                    let bit = sprite >> (7 - column) & 1;
+                    collision |= *pixel & bit == 1;
+                    *pixel ^= bit;
+                }
+            }
+            m.v[0xF] = collision as u8;
+        });
+        prop_assert_eq!(after, expected);
+    }
+
+    #[test]
+    fn skp(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xE, x, 0x9, 0xE));
+        let pressed = before.v[x] < 16 && setup.keys >> before.v[x] & 1 == 1;
+        prop_assert_eq!(after, skip_if(&before, pressed));
+    }
+
+    #[test]
+    fn sknp(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xE, x, 0xA, 0x1));
+        let pressed = before.v[x] < 16 && setup.keys >> before.v[x] & 1 == 1;
+        prop_assert_eq!(after, skip_if(&before, !pressed));
+    }
+
+    #[test]
+    fn ld_from_delay_timer(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xF, x, 0x0, 0x7));
+        prop_assert_eq!(after, expect(&before, |m| m.v[x] = before.dt));
+    }
+
+    #[test]
+    fn ld_key(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xF, x, 0x0, 0xA));
+        let key = (0..16).find(|key| setup.keys >> key & 1 == 1).unwrap_or(0);
+        prop_assert_eq!(after, expect(&before, |m| m.v[x] = key));
+    }
+
+    #[test]
+    fn ld_timers(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xF, x, 0x1, 0x5));
+        prop_assert_eq!(after, expect(&before, |m| m.dt = before.v[x]));
+        let (before, after) = run(&setup, opcode(0xF, x, 0x1, 0x8));
+        prop_assert_eq!(after, expect(&before, |m| m.st = before.v[x]));
+    }
+
+    #[test]
+    fn add_i(setup in setup(), x in 0..16usize) {
+        // Never sets the carry flag
+        let (before, after) = run(&setup, opcode(0xF, x, 0x1, 0xE));
+        prop_assert_eq!(after, expect(&before, |m| m.i = before.i.wrapping_add(u16::from(before.v[x]))));
+    }
+
+    #[test]
+    fn ld_font(setup in setup(), x in 0..16usize) {
+        // The built-in digit sprites are 5 bytes each, starting at address 0
+        let (before, after) = run(&setup, opcode(0xF, x, 0x2, 0x9));
+        prop_assert_eq!(after, expect(&before, |m| m.i = u16::from(before.v[x]) * 5));
+    }
+
+    #[test]
+    fn bcd(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xF, x, 0x3, 0x3));
+        let value = before.v[x];
+        prop_assert_eq!(after, expect(&before, |m| {
+            for (offset, digit) in [value / 100, value / 10 % 10, value % 10].iter().enumerate() {
+                m.ram[(usize::from(before.i) + offset) % MAX_SIZE] = *digit;
+            }
+        }));
+    }
+
+    #[test]
+    fn store(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xF, x, 0x5, 0x5));
+        prop_assert_eq!(after, expect(&before, |m| {
+            for register in 0..=x {
+                m.ram[(usize::from(before.i) + register) % MAX_SIZE] = before.v[register];
+            }
+            if setup.quirks.load_store_increments_i {
+                m.i = before.i.wrapping_add(x as u16 + 1);
+            }
+        }));
+    }
+
+    #[test]
+    fn load(setup in setup(), x in 0..16usize) {
+        let (before, after) = run(&setup, opcode(0xF, x, 0x6, 0x5));
+        prop_assert_eq!(after, expect(&before, |m| {
+            for register in 0..=x {
+                m.v[register] = before.ram[(usize::from(before.i) + register) % MAX_SIZE];
+            }
+            if setup.quirks.load_store_increments_i {
+                m.i = before.i.wrapping_add(x as u16 + 1);
+            }
+        }));
+    }
+
+    #[test]
+    fn invalid_e_and_f(setup in setup(), prefix in 0xE..=0xFu16, x in 0..16usize, nn: u8) {
+        let valid: &[u8] = if prefix == 0xE {
+            &[0x9E, 0xA1]
+        } else {
+            &[0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65]
+        };
+        prop_assume!(!valid.contains(&nn));
+        let (_, after) = run(&setup, opcode(prefix, x, 0, 0) | u16::from(nn));
+        prop_assert_eq!(after, None);
+    }
+}
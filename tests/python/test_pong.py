@@ -0,0 +1,151 @@
+"""Drives roms/pong.rom through the Python bindings.
+
+Build the module into a virtualenv with maturin, then run pytest:
+
+    pip install maturin pytest numpy
+    maturin develop
+    pytest tests/python
+"""
+
+from pathlib import Path
+
+import pytest
+
+import chip8
+
+ROM = Path(__file__).parents[2] / "roms" / "pong.rom"
+
+# Pong waits this many frames before serving the ball
+SERVE_DELAY = 0x60
+
+# Where the scores are drawn: the left player's on the left, the right player's on the right
+LEFT_SCORE = (20, 0)
+RIGHT_SCORE = (41, 0)
+
+# The paddles' columns, and the registers holding their top rows
+LEFT_PADDLE, VB = 2, 0xB
+RIGHT_PADDLE, VD = 63, 0xD
+
+
+@pytest.fixture
+def machine():
+    return chip8.Machine(ROM.read_bytes())
+
+
+def run_until(machine, condition, frames):
+    """Runs a frame at a time until condition(machine) holds, for at most `frames` frames.
+    Pong erases and redraws its sprites, so the screen is only sometimes complete."""
+    for _ in range(frames):
+        if condition(machine):
+            return True
+        machine.run_frames()
+    return condition(machine)
+
+
+def shows_score(machine, left, right):
+    return machine.sprite(*LEFT_SCORE, 5) == chip8.font_sprite(left) and machine.sprite(
+        *RIGHT_SCORE, 5
+    ) == chip8.font_sprite(right)
+
+
+def test_starts_at_nil_nil(machine):
+    machine.run_frames(10)
+    assert shows_score(machine, 0, 0)
+
+
+def test_paddles_start_in_the_middle(machine):
+    machine.run_frames(10)
+    pixels = memoryview(machine.pixels)
+    for column in (LEFT_PADDLE, RIGHT_PADDLE):
+        assert [row for row in range(chip8.HEIGHT) if pixels[row, column]] == list(range(12, 18))
+
+
+def test_holding_1_moves_the_left_paddle_up(machine):
+    machine.run_frames(SERVE_DELAY + 5)
+    top = machine.v[VB]
+    machine.hold(0x1, frames=3)
+    assert machine.v[VB] < top
+    # The paddle wraps around to the bottom of the screen
+    machine.hold(0x1, frames=30)
+    assert machine.v[VB] > top
+
+
+def test_holding_d_moves_the_right_paddle_down(machine):
+    machine.run_frames(SERVE_DELAY + 5)
+    top = machine.v[VD]
+    machine.hold(0xD, frames=3)
+    assert machine.v[VD] > top
+    assert machine.v[VB] == 12
+
+
+def dodge(machine):
+    """Moves the right paddle away from the ball for a frame"""
+    ball, paddle = machine.v[7], machine.v[VD]
+    machine.set_key(0xC, 0 <= ball - paddle < 9)
+    machine.set_key(0xD, -5 < ball - paddle < 0)
+    machine.run_frames()
+
+
+def test_the_left_player_scores_when_the_right_misses(machine):
+    machine.run_frames(SERVE_DELAY)
+    for _ in range(1000):
+        if machine.v[0xE] != 0:
+            break
+        dodge(machine)
+    machine.release(0xC)
+    machine.release(0xD)
+    # The score is BCD, with the left player's in the tens
+    assert machine.v[0xE] == 10
+    assert run_until(machine, lambda m: shows_score(m, 1, 0), frames=5)
+
+
+def test_save_and_load_state(machine):
+    machine.run_frames(SERVE_DELAY + 20)
+    state = machine.save_state()
+    registers, pixels = machine.v, bytes(machine.pixels)
+    machine.hold(0x4, frames=20)
+    machine.load_state(state)
+    assert machine.v == registers
+    assert bytes(machine.pixels) == pixels
+    with pytest.raises(ValueError):
+        machine.load_state(state[1:])
+
+
+def test_memory(machine):
+    assert machine.read_memory(0x200, 4) == ROM.read_bytes()[:4]
+    assert machine.read_memory(0, 5) == chip8.font_sprite(0)
+    # An invalid instruction, where the program starts
+    machine.write_memory(0x200, b"\x50\x01")
+    machine.pc = 0x200
+    with pytest.raises(chip8.Chip8Error):
+        machine.step()
+    with pytest.raises(ValueError):
+        machine.read_memory(0xFFF, 2)
+    with pytest.raises(ValueError):
+        machine.press(0x10)
+
+
+def test_memory_layout():
+    machine = chip8.Machine(ROM.read_bytes(), memory_size=0x800, program_start=0x600)
+    assert machine.pc == 0x600
+    assert machine.read_memory(0x600, 4) == ROM.read_bytes()[:4]
+    assert len(machine.ram) == 0x800
+    with pytest.raises(ValueError):
+        machine.read_memory(0x7FF, 2)
+    with pytest.raises(ValueError):
+        machine.pc = 0x800
+    with pytest.raises(ValueError):
+        chip8.Machine(ROM.read_bytes(), memory_size=0x200)
+
+
+def test_numpy_views(machine):
+    numpy = pytest.importorskip("numpy")
+    machine.run_frames(10)
+    screen = numpy.asarray(machine.pixels)
+    assert screen.shape == (chip8.HEIGHT, chip8.WIDTH)
+    assert screen.dtype == numpy.uint8
+    assert screen[12:18, LEFT_PADDLE].all()
+    assert not screen[:, 30].any()
+    ram = numpy.frombuffer(machine.ram, dtype=numpy.uint8)
+    assert len(ram) == 4096
+    assert bytes(ram[0x200:0x204]) == ROM.read_bytes()[:4]
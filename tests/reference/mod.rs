@@ -0,0 +1,335 @@
+//! A deliberately simple reference implementation of the Chip-8 instruction set, used to
+//! differentially test the emulator. It's written straight from the spec as one big match
+//! on the opcode and shares no code with the emulator, so a mistake in one is unlikely to
+//! be repeated in the other.
+//!
+//! It follows the emulator's choices where the spec leaves room for them: BNNN adds V0,
+//! 8XY6 and 8XYE shift VX, FX55 and FX65 leave I alone, SYS jumps, addresses wrap around
+//! memory, and there is no keyboard (FX0A reads key 0).
+//!
+//! This is shared by the tests and the fuzz targets, so it only depends on the emulator's
+//! public API.
+
+#![allow(dead_code)]
+
+use chip8::chip8::{display::Display, keyboard::HeadlessKeyboard, State};
+use chip8::config::Color;
+use chip8::emulator::Emulator;
+
+const MEMORY_SIZE: usize = 4096;
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const STACK_SIZE: usize = 16;
+
+/// A Chip-8 machine, as plain data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Machine {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: usize,
+    pub stack: Vec<usize>,
+    pub dt: u8,
+    pub st: u8,
+    pub ram: Vec<u8>,
+    pub screen: [[u8; WIDTH]; HEIGHT],
+    /// The register and mask of the last random number, which can't be predicted
+    pub random: Option<(usize, u8)>,
+}
+
+impl Machine {
+    /// Copies the state of the emulator
+    pub fn from_state(state: &State) -> Machine {
+        Machine {
+            v: state.registers.v_registers,
+            i: state.registers.i_register,
+            pc: state.program_counter,
+            stack: state.stack.contents().to_vec(),
+            dt: state.timers.delay_timer,
+            st: state.timers.sound_timer,
+            ram: state.memory.ram().to_vec(),
+            screen: state.display.pixels,
+            random: None,
+        }
+    }
+
+    fn memory(&self, offset: usize) -> u8 {
+        self.ram[(usize::from(self.i) + offset) % MEMORY_SIZE]
+    }
+
+    fn jump(&mut self, address: usize) {
+        self.pc = address % MEMORY_SIZE;
+    }
+
+    /// Executes the instruction at the program counter.
+    /// Returns false, leaving the machine as it was, if it can't be executed.
+    pub fn step(&mut self) -> bool {
+        if self.pc + 1 >= MEMORY_SIZE {
+            return false;
+        }
+        let opcode = u16::from(self.ram[self.pc]) << 8 | u16::from(self.ram[self.pc + 1]);
+        let x = usize::from(opcode >> 8 & 0xF);
+        let y = usize::from(opcode >> 4 & 0xF);
+        let n = usize::from(opcode & 0xF);
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = usize::from(opcode & 0xFFF);
+        let next = self.pc + 2;
+        self.random = None;
+
+        match opcode >> 12 {
+            0x0 if opcode == 0x00E0 => {
+                self.screen = [[0; WIDTH]; HEIGHT];
+                self.pc = next;
+            }
+            0x0 if opcode == 0x00EE => match self.stack.pop() {
+                Some(address) => self.pc = address + 2,
+                None => return false,
+            },
+            0x0 => self.jump(nnn),
+            0x1 => self.jump(nnn),
+            0x2 => {
+                if self.stack.len() == STACK_SIZE {
+                    return false;
+                }
+                self.stack.push(self.pc);
+                self.jump(nnn);
+            }
+            0x3 => self.pc = if self.v[x] == nn { next + 2 } else { next },
+            0x4 => self.pc = if self.v[x] != nn { next + 2 } else { next },
+            0x5 if n == 0 => {
+                self.pc = if self.v[x] == self.v[y] {
+                    next + 2
+                } else {
+                    next
+                }
+            }
+            0x6 => {
+                self.v[x] = nn;
+                self.pc = next;
+            }
+            0x7 => {
+                self.v[x] = self.v[x].wrapping_add(nn);
+                self.pc = next;
+            }
+            0x8 => {
+                let (vx, vy) = (self.v[x], self.v[y]);
+                let (result, flag) = match n {
+                    0x0 => (vy, None),
+                    0x1 => (vx | vy, None),
+                    0x2 => (vx & vy, None),
+                    0x3 => (vx ^ vy, None),
+                    0x4 => {
+                        let sum = u16::from(vx) + u16::from(vy);
+                        (sum as u8, Some((sum > 0xFF) as u8))
+                    }
+                    0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+                    0x6 => (vx >> 1, Some(vx & 1)),
+                    0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+                    0xE => (vx << 1, Some(vx >> 7)),
+                    _ => return false,
+                };
+                self.v[x] = result;
+                if let Some(flag) = flag {
+                    self.v[0xF] = flag;
+                }
+                self.pc = next;
+            }
+            0x9 if n == 0 => {
+                self.pc = if self.v[x] != self.v[y] {
+                    next + 2
+                } else {
+                    next
+                }
+            }
+            0xA => {
+                self.i = nnn as u16;
+                self.pc = next;
+            }
+            0xB => self.jump(nnn + usize::from(self.v[0])),
+            0xC => {
+                // The emulator's random number is patched in by `check`
+                self.random = Some((x, nn));
+                self.pc = next;
+            }
+            0xD => {
+                let (left, top) = (usize::from(self.v[x]), usize::from(self.v[y]));
+                let mut collision = 0;
+                for row in 0..n {
+                    let sprite = self.memory(row);
+                    for column in 0..8 {
+                        if sprite & (0x80 >> column) == 0 {
+                            continue;
+                        }
+                        let pixel = &mut self.screen[(top + row) % HEIGHT][(left + column) % WIDTH];
+                        collision |= *pixel;
+                        *pixel ^= 1;
+                    }
+                }
+                self.v[0xF] = collision;
+                self.pc = next;
+            }
+            // No keys are ever pressed
+            0xE if nn == 0x9E => self.pc = next,
+            0xE if nn == 0xA1 => self.pc = next + 2,
+            0xF => {
+                match nn {
+                    0x07 => self.v[x] = self.dt,
+                    0x0A => self.v[x] = 0,
+                    0x15 => self.dt = self.v[x],
+                    0x18 => self.st = self.v[x],
+                    0x1E => self.i = self.i.wrapping_add(u16::from(self.v[x])),
+                    0x29 => self.i = u16::from(self.v[x]) * 5,
+                    0x33 => {
+                        let value = self.v[x];
+                        for (offset, digit) in [value / 100, value / 10 % 10, value % 10]
+                            .iter()
+                            .enumerate()
+                        {
+                            let address = (usize::from(self.i) + offset) % MEMORY_SIZE;
+                            self.ram[address] = *digit;
+                        }
+                    }
+                    0x55 => {
+                        for register in 0..=x {
+                            let address = (usize::from(self.i) + register) % MEMORY_SIZE;
+                            self.ram[address] = self.v[register];
+                        }
+                    }
+                    0x65 => {
+                        for register in 0..=x {
+                            self.v[register] = self.memory(register);
+                        }
+                    }
+                    _ => return false,
+                }
+                self.pc = next;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Checks the emulator's state matches this machine, returning the differences
+    pub fn check(&self, state: &State) -> Result<(), String> {
+        let mut expected = self.clone();
+        if let Some((register, mask)) = self.random {
+            let actual = state.registers.v_registers[register];
+            if actual & !mask != 0 {
+                return Err(format!(
+                    "V{:X} = {:#04X} isn't masked by {:#04X}",
+                    register, actual, mask
+                ));
+            }
+            expected.v[register] = actual;
+        }
+        let mut actual = Machine::from_state(state);
+        actual.random = expected.random;
+        if actual == expected {
+            return Ok(());
+        }
+        let mut differences = Vec::new();
+        let mut compare = |name: &str, expected: String, actual: String| {
+            if expected != actual {
+                differences.push(format!("{}: expected {}, got {}", name, expected, actual));
+            }
+        };
+        compare(
+            "V",
+            format!("{:02X?}", expected.v),
+            format!("{:02X?}", actual.v),
+        );
+        compare(
+            "I",
+            format!("{:#X}", expected.i),
+            format!("{:#X}", actual.i),
+        );
+        compare(
+            "PC",
+            format!("{:#X}", expected.pc),
+            format!("{:#X}", actual.pc),
+        );
+        compare(
+            "stack",
+            format!("{:X?}", expected.stack),
+            format!("{:X?}", actual.stack),
+        );
+        compare("DT", expected.dt.to_string(), actual.dt.to_string());
+        compare("ST", expected.st.to_string(), actual.st.to_string());
+        for (address, (expected, actual)) in expected.ram.iter().zip(actual.ram.iter()).enumerate()
+        {
+            compare(
+                &format!("memory at {:#X}", address),
+                expected.to_string(),
+                actual.to_string(),
+            );
+        }
+        for (y, (expected, actual)) in expected.screen.iter().zip(actual.screen.iter()).enumerate()
+        {
+            compare(
+                &format!("display row {}", y),
+                format!("{:?}", &expected[..]),
+                format!("{:?}", &actual[..]),
+            );
+        }
+        Err(differences.join("\n"))
+    }
+}
+
+/// Builds an emulator in an arbitrary state from fuzzer input: the opcode to run, then
+/// the registers, program counter, stack and timers, with the rest of the bytes filling
+/// memory from I onwards and lighting up display pixels
+pub fn emulator_from_bytes(data: &[u8]) -> Emulator {
+    let mut bytes = data.iter().cloned().chain(std::iter::repeat(0));
+    let mut next = || bytes.next().unwrap();
+    let display = Display::new(Color::white(), Color::black());
+    let mut state = State::new(&[], Box::new(HeadlessKeyboard::new()), display);
+
+    let opcode = (next(), next());
+    for register in state.registers.v_registers.iter_mut() {
+        *register = next();
+    }
+    state.registers.i_register = u16::from_be_bytes([next(), next()]);
+    state.program_counter = usize::from(u16::from_be_bytes([next(), next()])) % MEMORY_SIZE;
+    for _ in 0..usize::from(next()) % (STACK_SIZE + 1) {
+        let address = usize::from(u16::from_be_bytes([next(), next()])) % MEMORY_SIZE;
+        state.stack.push(address).unwrap();
+    }
+    state.timers.delay_timer = next();
+    state.timers.sound_timer = next();
+
+    let rest = data.get(27 + 2 * STACK_SIZE..).unwrap_or(&[]);
+    for (offset, byte) in rest.iter().enumerate() {
+        let address = (usize::from(state.registers.i_register) + offset) % MEMORY_SIZE;
+        state.memory.ram_mut()[address] = *byte;
+    }
+    for (index, byte) in rest.iter().take(WIDTH * HEIGHT / 8).enumerate() {
+        for bit in 0..8 {
+            let pixel = index * 8 + bit;
+            state
+                .display
+                .xor(pixel % WIDTH, pixel / WIDTH, (byte >> (7 - bit)) & 1);
+        }
+    }
+    if state.program_counter + 1 < MEMORY_SIZE {
+        state.memory.ram_mut()[state.program_counter] = opcode.0;
+        state.memory.ram_mut()[state.program_counter + 1] = opcode.1;
+    }
+    Emulator::new(state, false)
+}
+
+/// Runs one instruction on both the emulator and the reference machine, checking they
+/// agree on whether it fails and on the state afterwards
+pub fn check_step(emulator: &mut Emulator) -> Result<(), String> {
+    let mut machine = Machine::from_state(&emulator.state);
+    let before = format!("{:?}", machine.pc);
+    let expected_ok = machine.step();
+    let result = emulator.step();
+    if result.is_ok() != expected_ok {
+        return Err(format!(
+            "At PC {}, the emulator returned {:?} but the reference machine {}",
+            before,
+            result,
+            if expected_ok { "succeeded" } else { "failed" }
+        ));
+    }
+    machine.check(&emulator.state)
+}
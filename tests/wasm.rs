@@ -0,0 +1,116 @@
+//! Runs the JavaScript API built with the `wasm` feature under a headless JavaScript runtime:
+//!
+//! ```sh
+//! rustup target add wasm32-unknown-unknown
+//! cargo install wasm-bindgen-cli
+//! cargo test --target wasm32-unknown-unknown --no-default-features --features wasm --test wasm
+//! ```
+//!
+//! `wasm-bindgen-test-runner` runs the tests in Node, or in a headless browser if
+//! `CHROMEDRIVER` or `GECKODRIVER` is set.
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use chip8::wasm::{seed, Chip8};
+use wasm_bindgen_test::*;
+
+/// Returns the first `rows` rows of lit pixels, as strings of # and .
+fn rows(chip8: &Chip8, rows: usize) -> Vec<String> {
+    chip8
+        .pixels()
+        .chunks(Chip8::width())
+        .take(rows)
+        .map(|row| {
+            row[..8]
+                .iter()
+                .map(|x| if *x == 1 { '#' } else { '.' })
+                .collect()
+        })
+        .collect()
+}
+
+#[wasm_bindgen_test]
+fn test_frame() {
+    let mut chip8 = Chip8::new(10);
+    chip8
+        .load(&[
+            0x60, 0x00, // 200: LD V0, 0
+            0xF0, 0x29, // 202: LD F, V0
+            0xD0, 0x05, // 204: DRW V0, V0, 5
+            0x12, 0x06, // 206: JP 0x206
+        ])
+        .unwrap();
+    chip8.frame().unwrap();
+    assert_eq!(chip8.frame_count(), 1.0);
+    assert_eq!(rows(&chip8, 2), vec!["####....", "#..#...."]);
+    assert_eq!(
+        chip8.framebuffer().len(),
+        Chip8::width() * Chip8::height() * 4
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_wait_for_key() {
+    let mut chip8 = Chip8::new(10);
+    chip8
+        .load(&[
+            0xF1, 0x0A, // 200: LD V1, K
+            0xF1, 0x29, // 202: LD F, V1
+            0xD0, 0x05, // 204: DRW V0, V0, 5
+            0x12, 0x06, // 206: JP 0x206
+        ])
+        .unwrap();
+    chip8.frame().unwrap();
+    assert_eq!(rows(&chip8, 1), vec!["........"]);
+    chip8.set_key(0x7, true);
+    chip8.frame().unwrap();
+    // The sprite for 7 is F0 10 20 40 40
+    assert_eq!(rows(&chip8, 2), vec!["####....", "...#...."]);
+}
+
+#[wasm_bindgen_test]
+fn test_memory_layout() {
+    let mut chip8 = Chip8::new(10);
+    assert!(chip8.set_memory_layout(0x800, 0x200, 0x7F0).is_err());
+    chip8.set_memory_layout(0x800, 0x600, 0).unwrap();
+    assert!(chip8.load(&[0; 0x201]).is_err());
+    chip8
+        .load(&[
+            0x60, 0x00, // 600: LD V0, 0
+            0xF0, 0x29, // 602: LD F, V0
+            0xD0, 0x05, // 604: DRW V0, V0, 5
+            0x16, 0x06, // 606: JP 0x606
+        ])
+        .unwrap();
+    chip8.frame().unwrap();
+    assert_eq!(rows(&chip8, 2), vec!["####....", "#..#...."]);
+}
+
+#[wasm_bindgen_test]
+fn test_seed() {
+    let program = [
+        0xC0, 0x0F, // 200: RND V0, 0x0F
+        0xF0, 0x29, // 202: LD F, V0
+        0xD1, 0x15, // 204: DRW V1, V1, 5
+        0x12, 0x06, // 206: JP 0x206
+    ];
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        seed(8.0);
+        let mut chip8 = Chip8::new(10);
+        chip8.load(&program).unwrap();
+        chip8.frame().unwrap();
+        runs.push(chip8.pixels());
+    }
+    assert_eq!(runs[0], runs[1]);
+}
+
+#[wasm_bindgen_test]
+fn test_errors() {
+    let mut chip8 = Chip8::new(10);
+    assert!(chip8.load(&[0; 4000]).is_err());
+    assert!(chip8.set_quirks("nope").is_err());
+    assert!(chip8.set_quirks("cosmac-vip").is_ok());
+    // An invalid opcode stops the frame
+    chip8.load(&[0xFF, 0xFF]).unwrap();
+    assert!(chip8.frame().is_err());
+}